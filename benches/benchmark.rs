@@ -11,7 +11,10 @@ use criterion::Criterion;
 
 use wyrm::nn::lstm;
 use wyrm::nn::xavier_normal;
-use wyrm::{DataInput, HogwildParameter, ParameterNode, SGD};
+use wyrm::{
+    exp_slice, parallel_threshold, set_parallel_threshold, sigmoid_slice, tanh_slice, DataInput,
+    GraphArena, HogwildParameter, IndexInputNode, InputNode, ParameterNode, Variable, SGD,
+};
 
 fn bench_node_reuse(c: &mut Criterion) {
     c.bench_function("node_reuse", |b| {
@@ -55,6 +58,260 @@ fn bench_matrix_multiply(c: &mut Criterion) {
     });
 }
 
+fn bench_dot_backward(c: &mut Criterion) {
+    c.bench_function("dot_forward_backward_256x512x256", |b| {
+        let x = ParameterNode::new(xavier_normal(256, 512));
+        let y = ParameterNode::new(xavier_normal(512, 256));
+        let mut z = x.dot(&y);
+
+        b.iter(|| {
+            z.forward();
+            z.backward(1.0);
+            z.zero_gradient();
+        })
+    });
+}
+
+fn bench_graph_rebuild(c: &mut Criterion) {
+    // A graph of about a hundred nodes, rebuilt for every example.
+    fn rebuild(x: &Variable<ParameterNode>, weights: &Variable<ParameterNode>) {
+        let mut hidden = x.clone().boxed();
+
+        for _ in 0..24 {
+            hidden = (hidden.dot(weights) + hidden.clone()).tanh().boxed();
+        }
+
+        let mut loss = hidden.square().scalar_sum();
+        loss.forward();
+        loss.backward(1.0);
+    }
+
+    c.bench_function("graph_rebuild", |b| {
+        let x = ParameterNode::new(xavier_normal(16, 32));
+        let weights = ParameterNode::new(xavier_normal(32, 32));
+
+        b.iter(|| {
+            rebuild(&x, &weights);
+            x.zero_gradient();
+            weights.zero_gradient();
+        })
+    });
+
+    c.bench_function("graph_rebuild_arena", |b| {
+        let x = ParameterNode::new(xavier_normal(16, 32));
+        let weights = ParameterNode::new(xavier_normal(32, 32));
+        let arena = GraphArena::new();
+        let _guard = arena.enter();
+
+        b.iter(|| {
+            rebuild(&x, &weights);
+            x.zero_gradient();
+            weights.zero_gradient();
+        })
+    });
+}
+
+fn bench_wide_activations(c: &mut Criterion) {
+    // Element-wise kernels over 4M elements, serial by default and split
+    // across the thread pool with a threshold set.
+    for &(name, threshold) in &[
+        ("wide_activations_1024x4096", None),
+        ("wide_activations_1024x4096_parallel", Some(1 << 20)),
+    ] {
+        c.bench_function(name, move |b| {
+            let x = ParameterNode::new(xavier_normal(1024, 4096));
+            let mut z = (x.tanh() + x.sigmoid()).relu().scalar_sum();
+
+            let default = parallel_threshold();
+
+            if let Some(threshold) = threshold {
+                set_parallel_threshold(threshold);
+            }
+
+            b.iter(|| {
+                z.forward();
+                z.backward(1.0);
+                z.zero_gradient();
+            });
+
+            set_parallel_threshold(default);
+        });
+    }
+}
+
+fn bench_embedding_dot(c: &mut Criterion) {
+    let run = |c: &mut Criterion, name: &str, linearize: bool| {
+        c.bench_function(name, move |b| {
+            let dim = 16;
+
+            let u_embedding = ParameterNode::new(xavier_normal(100, dim));
+            let v_embedding = ParameterNode::new(xavier_normal(100, dim));
+            let u_index = IndexInputNode::new(&[0][..]);
+            let v_index = IndexInputNode::new(&[0][..]);
+
+            let mut loss = u_embedding
+                .index(&u_index)
+                .vector_dot(&v_embedding.index(&v_index))
+                .sigmoid()
+                .scalar_sum();
+            if linearize {
+                assert!(loss.linearize());
+            }
+
+            let mut i = 0;
+
+            b.iter(|| {
+                u_index.set_value(i % 100);
+                v_index.set_value((i * 7) % 100);
+                i += 1;
+
+                loss.forward();
+                loss.backward(1.0);
+                loss.zero_gradient();
+            })
+        });
+    };
+
+    run(c, "embedding_dot_recursive", false);
+    run(c, "embedding_dot_linearized", true);
+}
+
+fn bench_mul_add(c: &mut Criterion) {
+    // The fused node skips the intermediate product of the composed
+    // nodes, saving a value buffer per node.
+    let run = |c: &mut Criterion, name: &str, fused: bool| {
+        c.bench_function(name, move |b| {
+            let (rows, cols) = (64, 256);
+
+            let x = ParameterNode::new(xavier_normal(rows, cols));
+            let w = ParameterNode::new(xavier_normal(rows, cols));
+            let bias = ParameterNode::new(xavier_normal(rows, cols));
+
+            let mut loss = if fused {
+                x.mul_add(&w, &bias).sigmoid().scalar_sum().boxed()
+            } else {
+                (x.clone() * w.clone() + bias.clone())
+                    .sigmoid()
+                    .scalar_sum()
+                    .boxed()
+            };
+
+            b.iter(|| {
+                loss.forward();
+                loss.backward(1.0);
+                loss.zero_gradient();
+            })
+        });
+    };
+
+    run(c, "mul_add_composed", false);
+    run(c, "mul_add_fused", true);
+}
+
+fn bench_compiled_inference(c: &mut Criterion) {
+    // A small MLP evaluated through the training graph and through
+    // the compiled inference graph.
+    let run = |c: &mut Criterion, name: &str, compiled: bool| {
+        c.bench_function(name, move |b| {
+            let x = InputNode::new(xavier_normal(1, 32));
+            let w1 = ParameterNode::new(xavier_normal(32, 64));
+            let w2 = ParameterNode::new(xavier_normal(64, 10));
+            let mut output = x.dot(&w1).add_bias().relu().dot(&w2).softmax();
+            let mut graph = output.compile_inference(&[("x", &x)]).unwrap();
+            let input = xavier_normal(1, 32);
+
+            if compiled {
+                b.iter(|| graph.run(&[("x", &input)])[(0, 0)])
+            } else {
+                b.iter(|| {
+                    x.set_value(&input);
+                    output.forward();
+                    output.zero_gradient();
+                    output.value()[(0, 0)]
+                })
+            }
+        });
+    };
+
+    run(c, "inference_forward", false);
+    run(c, "inference_compiled", true);
+}
+
+fn bench_softmax(c: &mut Criterion) {
+    c.bench_function("softmax_10k", |b| {
+        let x = ParameterNode::new(xavier_normal(1, 10_000));
+        let y = InputNode::new(xavier_normal(1, 10_000));
+        let mut loss = (x.softmax() * y).scalar_sum();
+
+        b.iter(|| {
+            loss.forward();
+            loss.backward(1.0);
+            loss.zero_gradient();
+        })
+    });
+}
+
+fn bench_activation_kernels(c: &mut Criterion) {
+    // The vectorized kernels against the standard library over
+    // a 1024-element slice.
+    let input = xavier_normal(1, 1024) * 5.0;
+    let xs = input.as_slice().unwrap().to_vec();
+
+    let scalar = xs.clone();
+    c.bench_function("exp_scalar", move |b| {
+        let mut ys = scalar.clone();
+        b.iter(|| {
+            for (y, x) in ys.iter_mut().zip(scalar.iter()) {
+                *y = x.exp();
+            }
+            ys[0]
+        })
+    });
+    let vector = xs.clone();
+    c.bench_function("exp_slice", move |b| {
+        let mut ys = vector.clone();
+        b.iter(|| {
+            exp_slice(&mut ys, &vector);
+            ys[0]
+        })
+    });
+    let scalar = xs.clone();
+    c.bench_function("tanh_scalar", move |b| {
+        let mut ys = scalar.clone();
+        b.iter(|| {
+            for (y, x) in ys.iter_mut().zip(scalar.iter()) {
+                *y = x.tanh();
+            }
+            ys[0]
+        })
+    });
+    let vector = xs.clone();
+    c.bench_function("tanh_slice", move |b| {
+        let mut ys = vector.clone();
+        b.iter(|| {
+            tanh_slice(&mut ys, &vector);
+            ys[0]
+        })
+    });
+    let scalar = xs.clone();
+    c.bench_function("sigmoid_scalar", move |b| {
+        let mut ys = scalar.clone();
+        b.iter(|| {
+            for (y, x) in ys.iter_mut().zip(scalar.iter()) {
+                *y = 1.0 / (1.0 + (-x).exp());
+            }
+            ys[0]
+        })
+    });
+    c.bench_function("sigmoid_slice", move |b| {
+        let mut ys = xs.clone();
+        b.iter(|| {
+            sigmoid_slice(&mut ys, &xs);
+            ys[0]
+        })
+    });
+}
+
 // fn bench_sofmax_exp_sum(b: &mut Criterion) {
 //     c.bench_function("bench_softmax_exp_sum", |b| {
 //         let x = vec![1.0; 32];
@@ -242,5 +499,18 @@ fn bench_lstm(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_node_reuse, bench_matrix_multiply, bench_lstm);
+criterion_group!(
+    benches,
+    bench_node_reuse,
+    bench_matrix_multiply,
+    bench_dot_backward,
+    bench_graph_rebuild,
+    bench_wide_activations,
+    bench_embedding_dot,
+    bench_mul_add,
+    bench_compiled_inference,
+    bench_softmax,
+    bench_activation_kernels,
+    bench_lstm
+);
 criterion_main!(benches);
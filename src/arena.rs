@@ -0,0 +1,169 @@
+//! Pools of node buffers, reused by graphs rebuilt for every example.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+use Arr;
+
+/// Counts of the buffers handed out and taken back by a `GraphArena`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ArenaStats {
+    /// Buffers freshly allocated because none of the right shape
+    /// was pooled.
+    pub allocated: usize,
+    /// Buffers handed out from the pool.
+    pub reused: usize,
+    /// Buffers returned to the pool by dropped or resized nodes.
+    pub returned: usize,
+}
+
+#[derive(Debug, Default)]
+struct Pool {
+    buffers: HashMap<(usize, usize), Vec<Arr>>,
+    stats: ArenaStats,
+}
+
+/// A pool of value and gradient buffers keyed by shape.
+///
+/// Models that rebuild their graph for every example, as with
+/// variable-length inputs, spend much of their time allocating the
+/// same buffers over and over. While an arena is entered on a thread,
+/// nodes built there take their buffers from it, and nodes dropped
+/// there return them, so that the next graph reuses them.
+///
+/// Most elementwise, activation, dot and sum nodes return their
+/// buffers; other nodes take buffers from the arena but free them
+/// as usual. Buffers are zeroed when handed out, and never shared
+/// between live nodes.
+///
+/// ```rust
+/// # use wyrm::*;
+/// let arena = GraphArena::new();
+/// let _guard = arena.enter();
+///
+/// let x = ParameterNode::new(nn::xavier_normal(4, 8));
+///
+/// for _ in 0..3 {
+///     let mut loss = x.tanh().square().scalar_sum();
+///     loss.forward();
+///     loss.backward(1.0);
+///     loss.zero_gradient();
+/// }
+///
+/// assert!(arena.stats().reused > 0);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct GraphArena {
+    pool: Rc<RefCell<Pool>>,
+}
+
+impl GraphArena {
+    /// Create an empty arena.
+    pub fn new() -> Self {
+        GraphArena::default()
+    }
+
+    /// Make this the arena of the current thread until the returned
+    /// guard is dropped, when the previously entered arena, if any,
+    /// is restored.
+    ///
+    /// Graphs built while the arena is entered should also be dropped
+    /// while it is, or their buffers are freed rather than returned.
+    pub fn enter(&self) -> ArenaGuard {
+        let previous = ARENA.with(|arena| arena.borrow_mut().replace(self.clone()));
+
+        ArenaGuard { previous: previous }
+    }
+
+    /// Counts of the buffers handed out and returned so far.
+    pub fn stats(&self) -> ArenaStats {
+        self.pool.borrow().stats
+    }
+
+    /// The number of buffers waiting in the pool.
+    pub fn pooled(&self) -> usize {
+        self.pool
+            .borrow()
+            .buffers
+            .values()
+            .map(|buffers| buffers.len())
+            .sum()
+    }
+
+    /// Free all pooled buffers.
+    pub fn clear(&self) {
+        self.pool.borrow_mut().buffers.clear();
+    }
+
+    fn take(&self, shape: (usize, usize)) -> Arr {
+        let mut pool = self.pool.borrow_mut();
+
+        match pool
+            .buffers
+            .get_mut(&shape)
+            .and_then(|buffers| buffers.pop())
+        {
+            Some(mut buffer) => {
+                pool.stats.reused += 1;
+                buffer.fill(0.0);
+                buffer
+            }
+            None => {
+                pool.stats.allocated += 1;
+                Arr::zeros(shape)
+            }
+        }
+    }
+
+    fn give(&self, buffer: Arr) {
+        let mut pool = self.pool.borrow_mut();
+
+        pool.stats.returned += 1;
+        pool.buffers.entry(buffer.dim()).or_default().push(buffer);
+    }
+}
+
+/// Keeps a `GraphArena` entered on the current thread; see
+/// `GraphArena::enter`.
+#[derive(Debug)]
+pub struct ArenaGuard {
+    previous: Option<GraphArena>,
+}
+
+impl Drop for ArenaGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        ARENA.with(|arena| *arena.borrow_mut() = previous);
+    }
+}
+
+thread_local! {
+    /// The arena entered on this thread, if any.
+    static ARENA: RefCell<Option<GraphArena>> = RefCell::new(None);
+}
+
+fn active() -> Option<GraphArena> {
+    ARENA
+        .try_with(|arena| arena.borrow().clone())
+        .unwrap_or(None)
+}
+
+/// A zeroed buffer of the given shape, from the entered arena if any.
+pub(crate) fn zeros(shape: (usize, usize)) -> Arr {
+    match active() {
+        Some(arena) => arena.take(shape),
+        None => Arr::zeros(shape),
+    }
+}
+
+/// Return `buffer` to the entered arena, if any, leaving it empty.
+pub(crate) fn recycle(buffer: &mut Arr) {
+    if buffer.is_empty() {
+        return;
+    }
+
+    if let Some(arena) = active() {
+        arena.give(mem::replace(buffer, Arr::zeros((0, 0))));
+    }
+}
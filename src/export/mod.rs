@@ -0,0 +1,66 @@
+//! Exporting trained graphs for use outside of wyrm.
+//!
+//! Graphs can be exported to [ONNX](https://onnx.ai) for inference
+//! with runtimes such as onnxruntime:
+//!
+//! ```rust
+//! # extern crate wyrm;
+//! # use wyrm::*;
+//! # fn main() {
+//! # let path = std::env::temp_dir().join("wyrm_export_doctest.onnx");
+//! let x = InputNode::new(nn::xavier_normal(1, 4));
+//! let weights = ParameterNode::new(nn::xavier_normal(4, 2));
+//! let y = x.dot(&weights).sigmoid();
+//!
+//! export::to_onnx(&y, &[("x", x.graph_node())], &path).unwrap();
+//! # std::fs::remove_file(&path).unwrap();
+//! # }
+//! ```
+use std::error;
+use std::fmt;
+
+mod onnx;
+mod protobuf;
+
+pub use self::onnx::to_onnx;
+
+/// Errors arising when exporting a graph.
+#[derive(Debug)]
+pub enum Error {
+    /// Failure writing the exported file.
+    Io(::std::io::Error),
+    /// The graph contains operations that cannot be exported.
+    UnsupportedOps(Vec<String>),
+    /// A named input is not an input node of the graph.
+    InvalidInput(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(f, "I/O error: {}", err),
+            Error::UnsupportedOps(ref ops) => {
+                write!(f, "Graph contains unsupported operations: {}.", ops.join(", "))
+            }
+            Error::InvalidInput(ref name) => {
+                write!(f, "Input {:?} is not an input node of the graph.", name)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(_) => "I/O error",
+            Error::UnsupportedOps(_) => "unsupported operations",
+            Error::InvalidInput(_) => "invalid input",
+        }
+    }
+}
+
+impl From<::std::io::Error> for Error {
+    fn from(err: ::std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,407 @@
+//! Export to the ONNX format.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use super::protobuf::Message;
+use super::Error;
+use nodes::{node_id, topological_order, Attribute, GraphNode, NodeValue};
+use {Float, Node, Variable};
+
+const IR_VERSION: i64 = 6;
+const OPSET_VERSION: i64 = 11;
+
+/// ONNX element type of `Float` values.
+#[cfg(not(wyrm_f64))]
+const FLOAT: i64 = 1;
+#[cfg(wyrm_f64)]
+const FLOAT: i64 = 11;
+const INT64: i64 = 7;
+
+const FLOAT_BYTES: usize = ::std::mem::size_of::<Float>();
+
+const ATTRIBUTE_INT: i64 = 2;
+
+/// Name of the ONNX operator implementing a wyrm operation.
+fn onnx_op(op: &str) -> Option<&'static str> {
+    Some(match op {
+        "Add" | "Bias" => "Add",
+        "Sub" => "Sub",
+        "Mul" => "Mul",
+        "Div" => "Div",
+        "Dot" => "MatMul",
+        "Neg" => "Neg",
+        "Exp" => "Exp",
+        "Log" => "Log",
+        "Sigmoid" => "Sigmoid",
+        "Tanh" => "Tanh",
+        "Relu" => "Relu",
+        "Softmax" => "Softmax",
+        "LogSoftmaxRows" => "LogSoftmax",
+        "Transpose" => "Transpose",
+        "Concatenate" => "Concat",
+        "Index" => "Gather",
+        _ => return None,
+    })
+}
+
+fn is_leaf(op: &str) -> bool {
+    op == "Input" || op == "IndexInput" || op == "Parameter"
+}
+
+fn value_info(name: &str, elem_type: i64, dims: &[usize]) -> Message {
+    let shape = dims.iter().fold(Message::new(), |shape, &dim| {
+        shape.message(1, &Message::new().int64(1, dim as i64))
+    });
+    let tensor_type = Message::new().int64(1, elem_type).message(2, &shape);
+
+    Message::new()
+        .string(1, name)
+        .message(2, &Message::new().message(1, &tensor_type))
+}
+
+fn float_tensor(name: &str, node: &GraphNode) -> Message {
+    let value = node.value_array().unwrap();
+    let mut data = Vec::with_capacity(FLOAT_BYTES * value.len());
+
+    for &x in value.iter() {
+        let bits = x.to_bits() as u64;
+        data.extend((0..FLOAT_BYTES).map(|byte| (bits >> (8 * byte)) as u8));
+    }
+
+    Message::new()
+        .int64(1, value.rows() as i64)
+        .int64(1, value.cols() as i64)
+        .int64(2, FLOAT)
+        .string(8, name)
+        .bytes(9, &data)
+}
+
+fn index_tensor(name: &str, node: &GraphNode) -> Message {
+    let indices = node.value_indices().unwrap();
+    let mut data = Vec::with_capacity(8 * indices.len());
+
+    for &idx in &indices {
+        data.extend((0..8).map(|byte| (idx as u64 >> (8 * byte)) as u8));
+    }
+
+    Message::new()
+        .int64(1, indices.len() as i64)
+        .int64(2, INT64)
+        .string(8, name)
+        .bytes(9, &data)
+}
+
+/// Encode the graph ending at `root` as an ONNX model.
+fn model(root: &GraphNode, inputs: &[(&str, &GraphNode)]) -> Result<Vec<u8>, Error> {
+    let order = topological_order(root);
+
+    let mut unsupported: Vec<String> = order
+        .iter()
+        .map(|node| node.description().op)
+        .filter(|&op| !is_leaf(op) && onnx_op(op).is_none())
+        .map(|op| op.to_owned())
+        .collect();
+    unsupported.sort();
+    unsupported.dedup();
+
+    if !unsupported.is_empty() {
+        return Err(Error::UnsupportedOps(unsupported));
+    }
+
+    let input_names: HashMap<_, _> = inputs
+        .iter()
+        .map(|&(name, node)| (node_id(node), name))
+        .collect();
+
+    for &(name, node) in inputs {
+        let op = node.description().op;
+        let in_graph = order.iter().any(|&x| node_id(x) == node_id(node));
+
+        if !in_graph || (op != "Input" && op != "IndexInput") {
+            return Err(Error::InvalidInput(name.to_owned()));
+        }
+    }
+
+    let mut names: HashMap<_, String> = HashMap::new();
+    let mut graph = Message::new().string(2, "wyrm");
+    let mut graph_inputs = Vec::new();
+
+    for (idx, &node) in order.iter().enumerate() {
+        let description = node.description();
+        let is_root = idx == order.len() - 1;
+
+        let name = match input_names.get(&node_id(node)) {
+            Some(&name) => name.to_owned(),
+            None if is_root => "output".to_owned(),
+            None => format!("{}_{}", description.op, idx),
+        };
+
+        match description.op {
+            "Input" | "IndexInput" if input_names.contains_key(&node_id(node)) => {
+                graph_inputs.push(match node.value_indices() {
+                    Some(indices) => value_info(&name, INT64, &[indices.len()]),
+                    None => {
+                        let (rows, cols) = node.value_shape();
+                        value_info(&name, FLOAT, &[rows, cols])
+                    }
+                });
+            }
+            "IndexInput" => {
+                graph = graph.message(5, &index_tensor(&name, node));
+            }
+            "Input" | "Parameter" => {
+                graph = graph.message(5, &float_tensor(&name, node));
+            }
+            op => {
+                let mut onnx_node = Message::new();
+
+                for operand in &description.operands {
+                    onnx_node = onnx_node.string(1, &names[&node_id(*operand)]);
+                }
+
+                onnx_node = onnx_node
+                    .string(2, &name)
+                    .string(3, &name)
+                    .string(4, onnx_op(op).unwrap());
+
+                // wyrm's softmax normalizes over the whole matrix, which
+                // opset 11 expresses by coercing the input to a single row;
+                // its row-wise log-softmax coerces each row separately.
+                let axis = match op {
+                    "Softmax" => Some(0),
+                    "LogSoftmaxRows" => Some(1),
+                    "Concatenate" => match description.get_attribute("axis") {
+                        Some(Attribute::Int(axis)) => Some(axis),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+
+                if let Some(axis) = axis {
+                    let attribute = Message::new()
+                        .string(1, "axis")
+                        .int64(3, axis)
+                        .int64(20, ATTRIBUTE_INT);
+                    onnx_node = onnx_node.message(5, &attribute);
+                }
+
+                graph = graph.message(1, &onnx_node);
+            }
+        }
+
+        names.insert(node_id(node), name);
+    }
+
+    for input in &graph_inputs {
+        graph = graph.message(11, input);
+    }
+
+    let (rows, cols) = root.value_shape();
+    graph = graph.message(12, &value_info("output", FLOAT, &[rows, cols]));
+
+    Ok(Message::new()
+        .int64(1, IR_VERSION)
+        .string(2, "wyrm")
+        .message(7, &graph)
+        .message(8, &Message::new().string(1, "").int64(2, OPSET_VERSION))
+        .into_bytes())
+}
+
+/// Export the inference graph ending at `output` to an ONNX model at `path`.
+///
+/// Nodes given in `inputs` become named graph inputs. All other input
+/// nodes are exported as constants, and parameters as initializers.
+/// All shapes are fixed to those of the current node values.
+pub fn to_onnx<T, P>(
+    output: &Variable<T>,
+    inputs: &[(&str, &GraphNode)],
+    path: P,
+) -> Result<(), Error>
+where
+    T: Node,
+    T::Value: NodeValue,
+    P: AsRef<Path>,
+{
+    let model = model(output.graph_node(), inputs)?;
+    File::create(path)?.write_all(&model)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::protobuf::decode::Fields;
+    use super::*;
+    use nn::xavier_normal;
+    use {IndexInputNode, InputNode, ParameterNode};
+
+    fn op_types<'a>(graph: &Fields<'a>) -> Vec<&'a str> {
+        graph
+            .all(1)
+            .iter()
+            .map(|node| node.message().get(4).string())
+            .collect()
+    }
+
+    fn dims(fields: &Fields) -> Vec<i64> {
+        fields.all(1).iter().map(|dim| dim.int()).collect()
+    }
+
+    fn value_info_dims(value_info: &Fields) -> Vec<i64> {
+        let tensor_type = value_info.get(2).message().get(1).message();
+        tensor_type
+            .get(2)
+            .message()
+            .all(1)
+            .iter()
+            .map(|dim| dim.message().get(1).int())
+            .collect()
+    }
+
+    /// Check that every node only consumes inputs, initializers
+    /// or outputs of earlier nodes.
+    fn assert_topologically_sorted(graph: &Fields) {
+        let mut defined: Vec<_> = graph
+            .all(5)
+            .iter()
+            .map(|tensor| tensor.message().get(8).string())
+            .chain(graph.all(11).iter().map(|input| input.message().get(1).string()))
+            .collect();
+
+        for node in graph.all(1) {
+            let node = node.message();
+            for input in node.all(1) {
+                assert!(defined.contains(&input.string()), "{} undefined", input.string());
+            }
+            defined.push(node.get(2).string());
+        }
+    }
+
+    #[test]
+    fn export_mlp() {
+        let x = InputNode::new(xavier_normal(1, 4));
+        let hidden = x
+            .dot(&ParameterNode::new(xavier_normal(4, 8)))
+            .add_bias()
+            .relu();
+        let y = hidden.dot(&ParameterNode::new(xavier_normal(8, 3))).softmax();
+
+        let bytes = model(y.graph_node(), &[("x", x.graph_node())]).unwrap();
+        let model = Fields::decode(&bytes);
+
+        assert_eq!(model.get(1).int(), IR_VERSION);
+        assert_eq!(model.get(8).message().get(2).int(), OPSET_VERSION);
+
+        let graph = model.get(7).message();
+
+        assert_eq!(
+            op_types(&graph),
+            vec!["MatMul", "Add", "Relu", "MatMul", "Softmax"]
+        );
+
+        let initializers = graph.all(5);
+        assert_eq!(initializers.len(), 3);
+        let mut shapes: Vec<_> = initializers
+            .iter()
+            .map(|tensor| {
+                let tensor = tensor.message();
+                assert_eq!(tensor.get(2).int(), FLOAT);
+                assert_eq!(
+                    tensor.get(9).bytes().len() as i64,
+                    FLOAT_BYTES as i64 * dims(&tensor).iter().product::<i64>()
+                );
+                dims(&tensor)
+            })
+            .collect();
+        shapes.sort();
+        assert_eq!(shapes, vec![vec![1, 8], vec![4, 8], vec![8, 3]]);
+
+        let inputs = graph.all(11);
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].message().get(1).string(), "x");
+        assert_eq!(value_info_dims(&inputs[0].message()), vec![1, 4]);
+
+        let output = graph.get(12).message();
+        assert_eq!(output.get(1).string(), "output");
+        assert_eq!(value_info_dims(&output), vec![1, 3]);
+
+        assert_topologically_sorted(&graph);
+
+        let nodes = graph.all(1);
+        let softmax = nodes[nodes.len() - 1].message();
+        assert_eq!(softmax.get(2).string(), "output");
+        let axis = softmax.get(5).message();
+        assert_eq!(axis.get(1).string(), "axis");
+        assert_eq!(axis.get(3).int(), 0);
+    }
+
+    #[test]
+    fn export_embedding_model() {
+        let u_embedding = ParameterNode::new(xavier_normal(10, 5));
+        let v_embedding = ParameterNode::new(xavier_normal(20, 5));
+
+        let u_index = IndexInputNode::new(&[0, 1, 2]);
+        let v_index = IndexInputNode::new(&[3, 4]);
+
+        let u = u_embedding.index(&u_index);
+        let v = v_embedding.index(&v_index);
+        let y = u.dot(&v.t()).sigmoid();
+
+        let bytes = model(
+            y.graph_node(),
+            &[("u", u_index.graph_node()), ("v", v_index.graph_node())],
+        ).unwrap();
+        let graph = Fields::decode(&bytes).get(7).message();
+
+        assert_eq!(
+            op_types(&graph),
+            vec!["Gather", "Gather", "Transpose", "MatMul", "Sigmoid"]
+        );
+
+        let mut shapes: Vec<_> = graph
+            .all(5)
+            .iter()
+            .map(|tensor| dims(&tensor.message()))
+            .collect();
+        shapes.sort();
+        assert_eq!(shapes, vec![vec![10, 5], vec![20, 5]]);
+
+        let inputs = graph.all(11);
+        let names: Vec<_> = inputs
+            .iter()
+            .map(|input| input.message().get(1).string())
+            .collect();
+        assert_eq!(names, vec!["u", "v"]);
+
+        for (input, &len) in inputs.iter().zip(&[3, 2]) {
+            let input = input.message();
+            let tensor_type = input.get(2).message().get(1).message();
+            assert_eq!(tensor_type.get(1).int(), INT64);
+            assert_eq!(value_info_dims(&input), vec![len]);
+        }
+
+        assert_eq!(value_info_dims(&graph.get(12).message()), vec![3, 2]);
+        assert_topologically_sorted(&graph);
+    }
+
+    #[test]
+    fn unsupported_ops() {
+        let x = InputNode::new(xavier_normal(1, 4));
+        let y = x.square().sigmoid().entropy();
+
+        match model(y.graph_node(), &[("x", x.graph_node())]) {
+            Err(Error::UnsupportedOps(ops)) => assert_eq!(ops, vec!["Entropy", "Square"]),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let unrelated = InputNode::new(xavier_normal(1, 4));
+        let y = x.sigmoid();
+
+        match model(y.graph_node(), &[("x", unrelated.graph_node())]) {
+            Err(Error::InvalidInput(name)) => assert_eq!(name, "x"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
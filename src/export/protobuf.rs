@@ -0,0 +1,175 @@
+//! Minimal protocol buffer encoding, sufficient for writing ONNX models.
+
+const VARINT: u32 = 0;
+const LENGTH_DELIMITED: u32 = 2;
+
+/// An encoded protocol buffer message.
+#[derive(Debug, Default)]
+pub struct Message {
+    bytes: Vec<u8>,
+}
+
+impl Message {
+    pub fn new() -> Self {
+        Message::default()
+    }
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(u64::from(field << 3 | wire_type));
+    }
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+    /// Append an `int64` (or `int32`/enum) field.
+    pub fn int64(mut self, field: u32, value: i64) -> Self {
+        self.key(field, VARINT);
+        self.varint(value as u64);
+        self
+    }
+    /// Append a `bytes` field.
+    pub fn bytes(mut self, field: u32, value: &[u8]) -> Self {
+        self.key(field, LENGTH_DELIMITED);
+        self.varint(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+        self
+    }
+    /// Append a `string` field.
+    pub fn string(self, field: u32, value: &str) -> Self {
+        self.bytes(field, value.as_bytes())
+    }
+    /// Append an embedded message field.
+    pub fn message(self, field: u32, value: &Message) -> Self {
+        self.bytes(field, &value.bytes)
+    }
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Decoding of encoded messages, used to check the output in tests.
+#[cfg(test)]
+pub mod decode {
+    /// A decoded field value.
+    #[derive(Debug, PartialEq)]
+    pub enum Value<'a> {
+        Varint(u64),
+        Fixed32(u32),
+        Bytes(&'a [u8]),
+    }
+
+    impl<'a> Value<'a> {
+        pub fn int(&self) -> i64 {
+            match *self {
+                Value::Varint(value) => value as i64,
+                _ => panic!("Not a varint: {:?}", self),
+            }
+        }
+        pub fn bytes(&self) -> &'a [u8] {
+            match *self {
+                Value::Bytes(value) => value,
+                _ => panic!("Not length-delimited: {:?}", self),
+            }
+        }
+        pub fn string(&self) -> &'a str {
+            ::std::str::from_utf8(self.bytes()).unwrap()
+        }
+        pub fn message(&self) -> Fields<'a> {
+            Fields::decode(self.bytes())
+        }
+    }
+
+    /// The fields of a decoded message, in order.
+    #[derive(Debug)]
+    pub struct Fields<'a>(pub Vec<(u32, Value<'a>)>);
+
+    fn varint(bytes: &[u8], position: &mut usize) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = bytes[*position];
+            *position += 1;
+            value |= u64::from(byte & 0x7F) << shift;
+            shift += 7;
+
+            if byte < 0x80 {
+                return value;
+            }
+        }
+    }
+
+    impl<'a> Fields<'a> {
+        pub fn decode(bytes: &'a [u8]) -> Self {
+            let mut fields = Vec::new();
+            let mut position = 0;
+
+            while position < bytes.len() {
+                let key = varint(bytes, &mut position);
+                let field = (key >> 3) as u32;
+
+                let value = match key & 7 {
+                    0 => Value::Varint(varint(bytes, &mut position)),
+                    2 => {
+                        let len = varint(bytes, &mut position) as usize;
+                        position += len;
+                        Value::Bytes(&bytes[position - len..position])
+                    }
+                    5 => {
+                        position += 4;
+                        let b = &bytes[position - 4..position];
+                        Value::Fixed32(
+                            u32::from(b[0])
+                                | u32::from(b[1]) << 8
+                                | u32::from(b[2]) << 16
+                                | u32::from(b[3]) << 24,
+                        )
+                    }
+                    wire_type => panic!("Unexpected wire type {}", wire_type),
+                };
+
+                fields.push((field, value));
+            }
+
+            Fields(fields)
+        }
+        /// All values of a (repeated) field.
+        pub fn all(&self, field: u32) -> Vec<&Value<'a>> {
+            self.0
+                .iter()
+                .filter(|&&(number, _)| number == field)
+                .map(|&(_, ref value)| value)
+                .collect()
+        }
+        /// The single value of a field.
+        pub fn get(&self, field: u32) -> &Value<'a> {
+            let values = self.all(field);
+            assert_eq!(values.len(), 1, "Field {} is not present once.", field);
+            values[0]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode::*;
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let inner = Message::new().string(1, "inner");
+        let bytes = Message::new()
+            .int64(1, 300)
+            .int64(2, -1)
+            .message(4, &inner)
+            .into_bytes();
+
+        let fields = Fields::decode(&bytes);
+
+        assert_eq!(fields.get(1), &Value::Varint(300));
+        assert_eq!(fields.get(2).int(), -1);
+        assert_eq!(fields.get(4).message().get(1).string(), "inner");
+    }
+}
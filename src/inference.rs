@@ -0,0 +1,316 @@
+//! Inference-only evaluation of trained graphs.
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use ndarray::Axis;
+
+use nodes::{
+    add_bias, column_wise_stack, fit_shape, guarded_div, log_softmax, log_softmax_rows, reduce,
+    row_wise_stack, softmax, InputNode, Node, Reduction,
+};
+use numerics::{self, ArraySlice, ArraySliceMut};
+use {Arr, Float, Variable};
+
+/// An operation of a compiled graph, computing the value of a node
+/// from the values of its operands.
+#[derive(Clone, Debug)]
+pub(crate) enum Op {
+    Add,
+    Sub,
+    Mul,
+    /// Division, guarding denominators smaller than the epsilon.
+    Div(Float),
+    Bias,
+    Dot,
+    Neg,
+    Exp,
+    Log,
+    Tanh,
+    Sigmoid,
+    Relu,
+    Square,
+    Softmax,
+    LogSoftmax,
+    LogSoftmaxRows,
+    Sum(Reduction),
+    Transpose,
+    Concatenate(Axis),
+    /// Gather the given rows of the operand.
+    Index(Vec<usize>),
+}
+
+impl Op {
+    fn is_elementwise(&self) -> bool {
+        match *self {
+            Op::Add | Op::Sub | Op::Mul | Op::Div(_) => true,
+            _ => false,
+        }
+    }
+
+    fn compute(&self, dest: &mut Arr, buffers: &[Arr], operands: &[usize]) {
+        let operand = |idx: usize| &buffers[operands[idx]];
+
+        match *self {
+            Op::Add => {
+                fit_shape(dest, operand(0).dim());
+                numerics::map_assign_binary(dest, operand(0), operand(1), |x, y| x + y);
+            }
+            Op::Sub => {
+                fit_shape(dest, operand(0).dim());
+                numerics::sub(operand(0), operand(1), dest);
+            }
+            Op::Mul => {
+                fit_shape(dest, operand(0).dim());
+                numerics::mul(operand(0), operand(1), dest);
+            }
+            Op::Div(epsilon) => {
+                fit_shape(dest, operand(0).dim());
+                guarded_div(dest, operand(0), operand(1), epsilon);
+            }
+            Op::Bias => add_bias(dest, operand(0), operand(1)),
+            Op::Dot => {
+                fit_shape(dest, (operand(0).rows(), operand(1).cols()));
+                numerics::mat_mul(1.0, operand(0), operand(1), 0.0, dest);
+            }
+            Op::Neg => map(dest, operand(0), |x| -x),
+            Op::Exp => map_slice(dest, operand(0), numerics::exp_slice),
+            Op::Log => map(dest, operand(0), numerics::ln),
+            Op::Tanh => map_slice(dest, operand(0), numerics::tanh_slice),
+            Op::Sigmoid => map_slice(dest, operand(0), numerics::sigmoid_slice),
+            Op::Relu => map(dest, operand(0), |x| if x < 0.0 { 0.0 } else { x }),
+            Op::Square => map(dest, operand(0), |x| x.powi(2)),
+            Op::Softmax => softmax(dest, operand(0)),
+            Op::LogSoftmax => log_softmax(dest, operand(0)),
+            Op::LogSoftmaxRows => log_softmax_rows(dest, operand(0)),
+            Op::Sum(reduction) => {
+                fit_shape(dest, (1, 1));
+                dest[(0, 0)] = reduce(operand(0), reduction);
+            }
+            Op::Transpose => {
+                fit_shape(dest, (operand(0).cols(), operand(0).rows()));
+                dest.assign(&operand(0).t());
+            }
+            Op::Concatenate(axis) => {
+                let (lhs, rhs) = (operand(0), operand(1));
+
+                if axis == Axis(0) {
+                    fit_shape(dest, (lhs.rows() + rhs.rows(), lhs.cols()));
+                    row_wise_stack(dest, lhs, rhs);
+                } else {
+                    fit_shape(dest, (lhs.rows(), lhs.cols() + rhs.cols()));
+                    column_wise_stack(dest, lhs, rhs);
+                }
+            }
+            Op::Index(ref indices) => {
+                fit_shape(dest, (indices.len(), operand(0).cols()));
+
+                for (&idx, mut row) in indices.iter().zip(dest.genrows_mut()) {
+                    row.assign(&operand(0).row(idx));
+                }
+            }
+        }
+    }
+}
+
+fn map<F: Fn(Float) -> Float + Sync>(dest: &mut Arr, operand: &Arr, func: F) {
+    fit_shape(dest, operand.dim());
+    numerics::map_assign(dest, operand, func);
+}
+
+fn map_slice(dest: &mut Arr, operand: &Arr, kernel: fn(&mut [Float], &[Float])) {
+    fit_shape(dest, operand.dim());
+    kernel(dest.fast_slice_mut(), operand.fast_slice());
+}
+
+#[derive(Clone, Debug)]
+struct Step {
+    op: Op,
+    operands: Vec<usize>,
+    dest: usize,
+}
+
+/// A graph compiled for inference: a flat sequence of operations over
+/// pre-sized buffers, without pass counters, gradients or reference
+/// counting. Parameters are copied when compiling, so later training
+/// of the original graph does not affect the compiled one.
+///
+/// Compiled graphs are `Send` and `Clone`: give every serving thread
+/// its own copy.
+///
+/// ```rust
+/// # use wyrm::*;
+/// let x = InputNode::new(Arr::zeros((1, 4)));
+/// let weights = ParameterNode::new(nn::xavier_normal(4, 2));
+/// let mut y = x.dot(&weights).sigmoid();
+///
+/// let mut graph = y.compile_inference(&[("x", &x)]).unwrap();
+///
+/// let batch = nn::xavier_normal(8, 4);
+/// x.set_value(&batch);
+/// y.forward();
+///
+/// assert_eq!(graph.run(&[("x", &batch)]), &*y.value());
+/// ```
+#[derive(Clone, Debug)]
+pub struct InferenceGraph {
+    buffers: Vec<Arr>,
+    fills: Vec<Option<Float>>,
+    steps: Vec<Step>,
+    inputs: Vec<(String, usize)>,
+    output: usize,
+}
+
+impl InferenceGraph {
+    /// Set the named inputs to the given values, which may have any
+    /// number of rows, and compute the output. Inputs that are not
+    /// given keep their last value.
+    ///
+    /// Panics if a name is not one of the graph's inputs, or if a value
+    /// has the wrong number of columns.
+    pub fn run(&mut self, inputs: &[(&str, &Arr)]) -> &Arr {
+        for &(name, value) in inputs {
+            let idx = self
+                .inputs
+                .iter()
+                .find(|&&(ref input, _)| input == name)
+                .map(|&(_, idx)| idx)
+                .unwrap_or_else(|| panic!("{:?} is not an input of the graph.", name));
+
+            let buffer = &mut self.buffers[idx];
+            assert_eq!(
+                buffer.cols(),
+                value.cols(),
+                "Input {:?} must have {} columns.",
+                name,
+                buffer.cols()
+            );
+
+            fit_shape(buffer, value.dim());
+            buffer.assign(value);
+        }
+
+        for step in &self.steps {
+            if step.op.is_elementwise() {
+                let (lhs, rhs) = (step.operands[0], step.operands[1]);
+                fit_rows(&mut self.buffers, &self.fills, lhs, rhs);
+                fit_rows(&mut self.buffers, &self.fills, rhs, lhs);
+            }
+
+            // Steps are compiled after their operands, so the buffers
+            // of the operands always precede the destination.
+            let (operands, rest) = self.buffers.split_at_mut(step.dest);
+            step.op.compute(&mut rest[0], operands, &step.operands);
+        }
+
+        &self.buffers[self.output]
+    }
+}
+
+/// Resize the constant in `buffer`, if it is one, to the rows of `other`,
+/// as `InputNode::fit_rows` does.
+fn fit_rows(buffers: &mut [Arr], fills: &[Option<Float>], buffer: usize, other: usize) {
+    if let Some(fill) = fills[buffer] {
+        let rows = buffers[other].rows();
+        let cols = buffers[buffer].cols();
+
+        if buffers[buffer].rows() != rows {
+            buffers[buffer] = Arr::from_elem((rows, cols), fill);
+        }
+    }
+}
+
+/// Compiles the nodes of a graph into an `InferenceGraph`.
+pub struct InferenceBuilder {
+    buffers: Vec<Arr>,
+    fills: Vec<Option<Float>>,
+    steps: Vec<Step>,
+    inputs: HashMap<*const u8, String>,
+    compiled: HashMap<*const u8, usize>,
+}
+
+impl InferenceBuilder {
+    fn new(inputs: &[(&str, &Variable<InputNode>)]) -> Self {
+        InferenceBuilder {
+            buffers: Vec::new(),
+            fills: Vec::new(),
+            steps: Vec::new(),
+            inputs: inputs
+                .iter()
+                .map(|&(name, input)| (input.node.address(), name.to_owned()))
+                .collect(),
+            compiled: HashMap::new(),
+        }
+    }
+
+    /// The buffer holding the value of `node`. Nodes used several
+    /// times in the graph are only compiled once.
+    pub(crate) fn compile<N>(&mut self, node: &Rc<N>) -> Result<usize, String>
+    where
+        N: Node<Value = Arr, InputGradient = Arr>,
+    {
+        let id = node.address();
+
+        if let Some(&buffer) = self.compiled.get(&id) {
+            return Ok(buffer);
+        }
+
+        let buffer = node.compile(self)?;
+        self.compiled.insert(id, buffer);
+
+        Ok(buffer)
+    }
+
+    /// A buffer holding `value`: the value of an input or a parameter
+    /// when the graph is compiled. Buffers with a `fill` are constants
+    /// resized to match the rows of the operands they combine with.
+    pub(crate) fn constant(&mut self, value: Arr, fill: Option<Float>) -> usize {
+        self.buffers.push(value);
+        self.fills.push(fill);
+        self.buffers.len() - 1
+    }
+
+    /// A step computing `op` over `operands`, into a buffer of the
+    /// shape of `value`.
+    pub(crate) fn step(&mut self, op: Op, operands: Vec<usize>, value: &Arr) -> usize {
+        let dest = self.constant(Arr::zeros(value.dim()), None);
+        self.steps.push(Step {
+            op: op,
+            operands: operands,
+            dest: dest,
+        });
+        dest
+    }
+
+    fn finish(mut self, output: usize) -> Result<InferenceGraph, String> {
+        let mut inputs = Vec::new();
+
+        for (address, name) in self.inputs.drain() {
+            match self.compiled.get(&address) {
+                Some(&buffer) => inputs.push((name, buffer)),
+                None => return Err(format!("Input {:?} is not part of the graph.", name)),
+            }
+        }
+
+        Ok(InferenceGraph {
+            buffers: self.buffers,
+            fills: self.fills,
+            steps: self.steps,
+            inputs: inputs,
+            output: output,
+        })
+    }
+}
+
+/// Compile the graph ending at `root`; see `Variable::compile_inference`.
+pub(crate) fn compile<T>(
+    root: &Variable<T>,
+    inputs: &[(&str, &Variable<InputNode>)],
+) -> Result<InferenceGraph, String>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    let mut builder = InferenceBuilder::new(inputs);
+    let output = builder.compile(&root.node)?;
+
+    builder.finish(output)
+}
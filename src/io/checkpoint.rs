@@ -0,0 +1,285 @@
+//! Checkpoints bundling everything needed to resume training.
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use bincode;
+
+use super::{shape, Error};
+use nodes::HogwildParameter;
+
+/// Version of the checkpoint format written by this version of the crate.
+pub const CHECKPOINT_VERSION: u32 = 2;
+
+/// A training checkpoint: named parameters together with their
+/// optimizer state, the number of steps taken and the seeds of
+/// the random number generators used in training.
+///
+/// ```rust
+/// # extern crate wyrm;
+/// # use std::sync::Arc;
+/// # use wyrm::*;
+/// # use wyrm::io::Checkpoint;
+/// # fn main() {
+/// # let path = std::env::temp_dir().join("wyrm_checkpoint_doctest.bin");
+/// let weights = Arc::new(HogwildParameter::new(nn::xavier_normal(10, 4)));
+///
+/// Checkpoint::new(50, vec![42])
+///     .parameter("weights", &weights)
+///     .save(&path)
+///     .unwrap();
+///
+/// let checkpoint = Checkpoint::load(&path).unwrap();
+/// let weights = ParameterNode::shared(checkpoint.get_parameter("weights", (10, 4)).unwrap());
+/// assert_eq!(checkpoint.step, 50);
+/// # std::fs::remove_file(&path).unwrap();
+/// # }
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    version: u32,
+    /// Number of optimization steps taken.
+    pub step: u64,
+    /// Seeds of the random number generators used in training.
+    pub rng_seeds: Vec<u64>,
+    parameters: Vec<(String, Arc<HogwildParameter>)>,
+}
+
+impl Checkpoint {
+    /// Create a checkpoint at `step`.
+    pub fn new(step: u64, rng_seeds: Vec<u64>) -> Self {
+        Checkpoint {
+            version: CHECKPOINT_VERSION,
+            step: step,
+            rng_seeds: rng_seeds,
+            parameters: Vec::new(),
+        }
+    }
+
+    /// Add a named parameter. Its values and optimizer state are
+    /// captured when the checkpoint is saved.
+    pub fn parameter(mut self, name: &str, parameter: &Arc<HogwildParameter>) -> Self {
+        self.parameters.push((name.to_owned(), Arc::clone(parameter)));
+        self
+    }
+
+    /// Retrieve a parameter, checking that it has the expected shape.
+    pub fn get_parameter(
+        &self,
+        name: &str,
+        expected_shape: (usize, usize),
+    ) -> Result<Arc<HogwildParameter>, Error> {
+        let parameter = self
+            .parameters
+            .iter()
+            .find(|&&(ref parameter_name, _)| parameter_name == name)
+            .map(|&(_, ref parameter)| parameter)
+            .ok_or_else(|| Error::MissingParameter(name.to_owned()))?;
+
+        let found = shape(parameter);
+
+        if found != expected_shape {
+            return Err(Error::ShapeMismatch {
+                name: name.to_owned(),
+                expected: expected_shape,
+                found: found,
+            });
+        }
+
+        Ok(Arc::clone(parameter))
+    }
+
+    /// Save the checkpoint to `path`. The checkpoint is written to
+    /// a temporary file first and then renamed, so that an existing
+    /// checkpoint is never left partially overwritten.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut temporary_name = path
+            .file_name()
+            .ok_or_else(|| {
+                Error::Io(::std::io::Error::new(
+                    ::std::io::ErrorKind::InvalidInput,
+                    "checkpoint path has no file name",
+                ))
+            })?
+            .to_owned();
+        temporary_name.push(".tmp");
+        let temporary_path = path.with_file_name(temporary_name);
+
+        {
+            let file = File::create(&temporary_path)?;
+            let mut writer = BufWriter::new(&file);
+            bincode::serialize_into(&mut writer, self)?;
+            writer.flush()?;
+            drop(writer);
+            file.sync_all()?;
+        }
+
+        fs::rename(&temporary_path, path)?;
+
+        Ok(())
+    }
+
+    /// Load a checkpoint saved with `Checkpoint::save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+
+        // The version is the leading field, and can be read
+        // before attempting to decode the rest.
+        let version: u32 = bincode::deserialize(&bytes)?;
+
+        if version != CHECKPOINT_VERSION {
+            return Err(Error::VersionMismatch {
+                expected: CHECKPOINT_VERSION,
+                found: version,
+            });
+        }
+
+        let checkpoint: Checkpoint = bincode::deserialize(&bytes)?;
+
+        for (idx, &(ref name, _)) in checkpoint.parameters.iter().enumerate() {
+            if checkpoint.parameters[..idx]
+                .iter()
+                .any(|&(ref other, _)| other == name)
+            {
+                return Err(Error::Corrupted(format!("duplicate parameter {:?}", name)));
+            }
+        }
+
+        Ok(checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Deref;
+
+    use rand::prng::XorShiftRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use nn;
+    use optim::{Adam, Optimizer, SGD};
+    use {DataInput, InputNode, ParameterNode};
+
+    fn temp_path(name: &str) -> ::std::path::PathBuf {
+        ::std::env::temp_dir().join(format!("wyrm_{}_{}", ::std::process::id(), name))
+    }
+
+    /// The data for each step is drawn from a generator seeded
+    /// with the run seed and the step number.
+    fn batch(seed: u64, step: u64) -> (::Arr, ::Arr) {
+        let mut bytes = [0; 16];
+        for (idx, byte) in bytes.iter_mut().enumerate() {
+            *byte = ((seed ^ step.rotate_left(32)) >> (8 * (idx % 8))) as u8 ^ idx as u8;
+        }
+        let mut rng = XorShiftRng::from_seed(bytes);
+
+        let x = nn::uniform(8, 3, -1.0, 1.0, &mut rng);
+        let y = x.dot(&::ndarray::arr2(&[[1.0], [-2.0], [0.5]]));
+
+        (x, y)
+    }
+
+    /// Train for steps `start..end` and return the final parameters.
+    fn train(
+        weights: Arc<HogwildParameter>,
+        seed: u64,
+        start: u64,
+        end: u64,
+        use_adam: bool,
+    ) -> Arc<HogwildParameter> {
+        let w = ParameterNode::shared(weights.clone());
+        let x = InputNode::new(::Arr::zeros((8, 3)));
+        let y = InputNode::new(::Arr::zeros((8, 1)));
+        let mut loss = (x.dot(&w) - y.clone()).square();
+
+        let optimizer: Box<Optimizer> = if use_adam {
+            Box::new(Adam::new(loss.parameters()).learning_rate(0.05))
+        } else {
+            Box::new(SGD::new(loss.parameters()).learning_rate(0.01))
+        };
+
+        for step in start..end {
+            let (x_value, y_value) = batch(seed, step);
+            x.set_value(&x_value);
+            y.set_value(&y_value);
+
+            loss.forward();
+            loss.backward(1.0);
+            optimizer.step();
+            loss.zero_gradient();
+        }
+
+        weights
+    }
+
+    fn resume_matches_uninterrupted_run(use_adam: bool) {
+        let seed = 1234;
+        let initial = HogwildParameter::new(nn::xavier_normal(3, 1));
+
+        let uninterrupted = train(Arc::new(initial.clone()), seed, 0, 100, use_adam);
+
+        let path = temp_path(&format!("checkpoint_{}.bin", use_adam));
+        {
+            let weights = train(Arc::new(initial.clone()), seed, 0, 50, use_adam);
+            Checkpoint::new(50, vec![seed])
+                .parameter("weights", &weights)
+                .save(&path)
+                .unwrap();
+        }
+
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(checkpoint.step, 50);
+        let resumed = train(
+            checkpoint.get_parameter("weights", (3, 1)).unwrap(),
+            checkpoint.rng_seeds[0],
+            checkpoint.step,
+            100,
+            use_adam,
+        );
+
+        assert_eq!(resumed.value(), uninterrupted.value());
+        assert_eq!(
+            resumed.moments.borrow().deref(),
+            uninterrupted.moments.borrow().deref()
+        );
+        assert_eq!(
+            resumed.squared_gradients.borrow().deref(),
+            uninterrupted.squared_gradients.borrow().deref()
+        );
+    }
+
+    #[test]
+    fn resume_sgd() {
+        resume_matches_uninterrupted_run(false);
+    }
+
+    #[test]
+    fn resume_adam() {
+        resume_matches_uninterrupted_run(true);
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let path = temp_path("checkpoint_version.bin");
+        let mut checkpoint = Checkpoint::new(0, Vec::new());
+        checkpoint.version = CHECKPOINT_VERSION + 1;
+        checkpoint.save(&path).unwrap();
+
+        let result = Checkpoint::load(&path);
+        ::std::fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(Error::VersionMismatch { expected, found }) => {
+                assert_eq!(expected, CHECKPOINT_VERSION);
+                assert_eq!(found, CHECKPOINT_VERSION + 1);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Loading pretrained embeddings from GloVe and word2vec text files.
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use super::Error;
+use nn::xavier_normal;
+use {Arr, Float};
+
+/// Embeddings loaded from a text file.
+#[derive(Debug)]
+pub struct TextEmbeddings {
+    /// The embedding matrix, with one row per vocabulary index.
+    pub embeddings: Arr,
+    /// Number of vocabulary entries found in the file.
+    pub matched: usize,
+    /// Number of lines that could not be parsed and were skipped.
+    pub malformed: usize,
+}
+
+/// Load pretrained embeddings in the text format used by GloVe
+/// and word2vec (`token v1 v2 ... vd`, one token per line).
+///
+/// Row `vocab[token]` of the returned matrix holds the vector of
+/// `token`; rows of tokens absent from the file are initialized
+/// with `xavier_normal` noise. The file is read line by line.
+/// A first line of exactly two integers is taken to be a word2vec
+/// `count dim` header and skipped. Other lines with the wrong number
+/// of values or unparseable values are skipped and counted as malformed.
+pub fn load_text_embeddings<R: BufRead>(
+    mut reader: R,
+    vocab: &HashMap<String, usize>,
+    dim: usize,
+) -> Result<TextEmbeddings, Error> {
+    let rows = vocab.values().max().map(|&idx| idx + 1).unwrap_or(0);
+    let mut embeddings = xavier_normal(rows, dim);
+    let mut seen = vec![false; rows];
+
+    let mut matched = 0;
+    let mut malformed = 0;
+
+    let mut line = Vec::new();
+    let mut values = Vec::with_capacity(dim);
+    let mut first_line = true;
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        let line = match ::std::str::from_utf8(&line) {
+            Ok(line) => line.trim(),
+            Err(_) => {
+                malformed += 1;
+                continue;
+            }
+        };
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if first_line {
+            first_line = false;
+
+            // Checked before parsing vectors: with `dim == 1`, a
+            // header would also parse as a token and its value.
+            if is_header(line) {
+                continue;
+            }
+        }
+
+        let mut fields = line.split_whitespace();
+        let token = fields.next().unwrap();
+
+        values.clear();
+        let mut valid = true;
+        for field in fields {
+            match field.parse::<Float>() {
+                Ok(value) => values.push(value),
+                Err(_) => {
+                    valid = false;
+                    break;
+                }
+            }
+        }
+
+        if !valid || values.len() != dim {
+            malformed += 1;
+            continue;
+        }
+
+        if let Some(&idx) = vocab.get(token) {
+            embeddings
+                .subview_mut(::ndarray::Axis(0), idx)
+                .as_slice_mut()
+                .unwrap()
+                .copy_from_slice(&values);
+
+            if !seen[idx] {
+                seen[idx] = true;
+                matched += 1;
+            }
+        }
+    }
+
+    Ok(TextEmbeddings {
+        embeddings: embeddings,
+        matched: matched,
+        malformed: malformed,
+    })
+}
+
+/// Whether `line` is a word2vec `count dim` header.
+fn is_header(line: &str) -> bool {
+    let fields: Vec<_> = line.split_whitespace().collect();
+
+    fields.len() == 2 && fields.iter().all(|field| field.parse::<usize>().is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_text_embeddings() {
+        let data = "3 2\n\
+                    the 0.5 -1.0\n\
+                    cat 1.5 2.5\n\
+                    dog 1.0\n\
+                    \n\
+                    bird 0.1 0.2\n\
+                    mouse x 0.2\n\
+                    sat 3.0 4.0 5.0\n";
+
+        let vocab: HashMap<String, usize> = ["the", "cat", "sat", "mat"]
+            .iter()
+            .enumerate()
+            .map(|(idx, token)| (token.to_string(), idx))
+            .collect();
+
+        let loaded = super::load_text_embeddings(data.as_bytes(), &vocab, 2).unwrap();
+
+        assert_eq!(loaded.embeddings.shape(), &[4, 2]);
+        assert_eq!(loaded.matched, 2);
+        // "dog", "mouse" and "sat".
+        assert_eq!(loaded.malformed, 3);
+
+        assert_eq!(loaded.embeddings.row(0).to_vec(), vec![0.5, -1.0]);
+        assert_eq!(loaded.embeddings.row(1).to_vec(), vec![1.5, 2.5]);
+        assert!(loaded.embeddings.row(3).iter().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn load_single_dimension_embeddings() {
+        let data = "2 1\n\
+                    the 0.5\n\
+                    cat 1.5\n";
+
+        let vocab: HashMap<String, usize> = ["the", "cat"]
+            .iter()
+            .enumerate()
+            .map(|(idx, token)| (token.to_string(), idx))
+            .collect();
+
+        let loaded = super::load_text_embeddings(data.as_bytes(), &vocab, 1).unwrap();
+
+        assert_eq!(loaded.matched, 2);
+        assert_eq!(loaded.malformed, 0);
+        assert_eq!(loaded.embeddings.column(0).to_vec(), vec![0.5, 1.5]);
+    }
+}
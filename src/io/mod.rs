@@ -0,0 +1,354 @@
+//! Saving and loading model parameters.
+//!
+//! Parameters are stored by name, together with their shapes and all
+//! optimizer buffers, so that training can be resumed after loading:
+//!
+//! ```rust
+//! # extern crate wyrm;
+//! # use std::sync::Arc;
+//! # use wyrm::*;
+//! # fn main() {
+//! # let path = std::env::temp_dir().join("wyrm_io_doctest.bin");
+//! let embedding = Arc::new(HogwildParameter::new(nn::xavier_normal(10, 4)));
+//!
+//! io::save_parameters(&path, &[("embedding", &embedding)]).unwrap();
+//!
+//! let parameters = io::load_parameters(&path).unwrap();
+//! let embedding = ParameterNode::shared(io::parameter(&parameters, "embedding", (10, 4)).unwrap());
+//! # std::fs::remove_file(&path).unwrap();
+//! # }
+//! ```
+//!
+//! Parameters can also be exchanged with numpy via `to_npy`, `from_npy`
+//! and `to_npz`, and embeddings initialized from GloVe or word2vec text
+//! files with `load_text_embeddings`. A `Checkpoint` additionally records
+//! the training step and random seeds needed to resume training.
+//!
+//! Single parameters can be converted to and from bytes with
+//! `HogwildParameter::to_bytes` and `HogwildParameter::from_bytes`, for
+//! use with custom storage backends.
+//!
+//! Large parameters, such as pretrained embedding tables, can be written
+//! in a raw binary format with `to_raw` and memory-mapped back with
+//! `HogwildParameter::from_mmap`, avoiding serialization overhead, either
+//! read-only or privately for training.
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use bincode;
+
+use nodes::HogwildParameter;
+
+mod checkpoint;
+mod embeddings;
+mod npy;
+mod raw;
+
+pub use self::checkpoint::{Checkpoint, CHECKPOINT_VERSION};
+pub use self::embeddings::{load_text_embeddings, TextEmbeddings};
+pub use self::npy::{from_npy, read_npy, to_npy, to_npz, write_npy};
+pub use self::raw::{to_raw, MapMode};
+
+pub(crate) use self::raw::MappedValue;
+
+/// Errors arising when saving or loading parameters.
+#[derive(Debug)]
+pub enum Error {
+    /// Failure reading or writing the underlying file.
+    Io(::std::io::Error),
+    /// The file could not be decoded.
+    Serialization(bincode::Error),
+    /// The file decoded, but its contents are inconsistent.
+    Corrupted(String),
+    /// The file is valid, but holds data in a format that is not supported.
+    UnsupportedFormat(String),
+    /// The file was written by an incompatible version of the format.
+    VersionMismatch { expected: u32, found: u32 },
+    /// A parameter is not present in the loaded set.
+    MissingParameter(String),
+    /// A parameter does not have the shape the model expects.
+    ShapeMismatch {
+        name: String,
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(f, "I/O error: {}", err),
+            Error::Serialization(ref err) => write!(f, "Unable to decode parameters: {}", err),
+            Error::Corrupted(ref msg) => write!(f, "Corrupted parameters: {}", msg),
+            Error::UnsupportedFormat(ref msg) => write!(f, "Unsupported format: {}", msg),
+            Error::VersionMismatch { expected, found } => write!(
+                f,
+                "File has format version {}, expected version {}.",
+                found, expected
+            ),
+            Error::MissingParameter(ref name) => write!(f, "Parameter {:?} not found.", name),
+            Error::ShapeMismatch {
+                ref name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Parameter {:?} has shape {:?}, expected {:?}.",
+                name, found, expected
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(_) => "I/O error",
+            Error::Serialization(_) => "unable to decode parameters",
+            Error::Corrupted(_) => "corrupted parameters",
+            Error::UnsupportedFormat(_) => "unsupported format",
+            Error::VersionMismatch { .. } => "format version mismatch",
+            Error::MissingParameter(_) => "parameter not found",
+            Error::ShapeMismatch { .. } => "parameter shape mismatch",
+        }
+    }
+}
+
+impl From<::std::io::Error> for Error {
+    fn from(err: ::std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(err: bincode::Error) -> Self {
+        Error::Serialization(err)
+    }
+}
+
+#[derive(Serialize)]
+struct NamedParameterRef<'a> {
+    name: &'a str,
+    shape: (usize, usize),
+    parameter: &'a HogwildParameter,
+}
+
+#[derive(Deserialize)]
+struct NamedParameter {
+    name: String,
+    shape: (usize, usize),
+    parameter: HogwildParameter,
+}
+
+fn shape(parameter: &HogwildParameter) -> (usize, usize) {
+    parameter.shape()
+}
+
+/// Save named parameters to a file at `path`.
+pub fn save_parameters<P: AsRef<Path>>(
+    path: P,
+    parameters: &[(&str, &Arc<HogwildParameter>)],
+) -> Result<(), Error> {
+    let named: Vec<_> = parameters
+        .iter()
+        .map(|&(name, parameter)| NamedParameterRef {
+            name: name,
+            shape: shape(parameter),
+            parameter: parameter,
+        })
+        .collect();
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    bincode::serialize_into(&mut writer, &named)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Load parameters saved with `save_parameters`, keyed by name.
+///
+/// Use `parameter` to retrieve them with a shape check before
+/// wiring them into `ParameterNode::shared`.
+pub fn load_parameters<P: AsRef<Path>>(
+    path: P,
+) -> Result<HashMap<String, Arc<HogwildParameter>>, Error> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+
+    let named: Vec<NamedParameter> = bincode::deserialize(&bytes)?;
+    let mut parameters = HashMap::with_capacity(named.len());
+
+    for NamedParameter {
+        name,
+        shape: stored_shape,
+        parameter,
+    } in named
+    {
+        if shape(&parameter) != stored_shape {
+            return Err(Error::Corrupted(format!(
+                "parameter {:?} is recorded as {:?} but holds {:?}",
+                name,
+                stored_shape,
+                shape(&parameter)
+            )));
+        }
+
+        if parameters.insert(name.clone(), Arc::new(parameter)).is_some() {
+            return Err(Error::Corrupted(format!("duplicate parameter {:?}", name)));
+        }
+    }
+
+    Ok(parameters)
+}
+
+/// Retrieve a loaded parameter, checking that it has the expected shape.
+pub fn parameter(
+    parameters: &HashMap<String, Arc<HogwildParameter>>,
+    name: &str,
+    expected_shape: (usize, usize),
+) -> Result<Arc<HogwildParameter>, Error> {
+    let parameter = parameters
+        .get(name)
+        .ok_or_else(|| Error::MissingParameter(name.to_owned()))?;
+
+    let found = shape(parameter);
+
+    if found != expected_shape {
+        return Err(Error::ShapeMismatch {
+            name: name.to_owned(),
+            expected: expected_shape,
+            found: found,
+        });
+    }
+
+    Ok(Arc::clone(parameter))
+}
+
+impl HogwildParameter {
+    /// Serialize the parameter, together with its optimizer buffers and
+    /// update count, to bytes, for storing it outside of a parameter file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Serializing to memory cannot fail.")
+    }
+
+    /// Deserialize a parameter from bytes written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Deref;
+    use std::path::PathBuf;
+
+    use super::*;
+    use nn::xavier_normal;
+    use optim::{Adam, Optimizer};
+    use {DataInput, InputNode, ParameterNode};
+
+    fn temp_path(name: &str) -> PathBuf {
+        ::std::env::temp_dir().join(format!("wyrm_{}_{}.bin", name, ::std::process::id()))
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("round_trip");
+
+        let embedding = Arc::new(HogwildParameter::new(xavier_normal(10, 4)));
+        let dense = Arc::new(HogwildParameter::new(xavier_normal(4, 1)));
+
+        save_parameters(&path, &[("embedding", &embedding), ("dense", &dense)]).unwrap();
+        let loaded = load_parameters(&path).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded["embedding"].value(), embedding.value());
+        assert_eq!(loaded["dense"].value(), dense.value());
+
+        match parameter(&loaded, "dense", (4, 2)) {
+            Err(Error::ShapeMismatch {
+                expected, found, ..
+            }) => {
+                assert_eq!(expected, (4, 2));
+                assert_eq!(found, (4, 1));
+            }
+            _ => panic!("Shape mismatch not detected."),
+        }
+
+        match parameter(&loaded, "missing", (4, 1)) {
+            Err(Error::MissingParameter(name)) => assert_eq!(name, "missing"),
+            _ => panic!("Missing parameter not detected."),
+        }
+    }
+
+    #[test]
+    fn trained_model_round_trip() {
+        let path = temp_path("trained_model_round_trip");
+
+        let weights = Arc::new(HogwildParameter::new(xavier_normal(3, 1)));
+        let x = InputNode::new(xavier_normal(1, 3));
+        let y = InputNode::new(xavier_normal(1, 1));
+
+        let y_hat = x.dot(&ParameterNode::shared(weights.clone()));
+        let mut loss = (y.clone() - y_hat.clone()).square();
+        let optimizer = Adam::new(loss.parameters());
+
+        for _ in 0..10 {
+            let x_value = xavier_normal(1, 3);
+            y.set_value(x_value.scalar_sum());
+            x.set_value(&x_value);
+
+            loss.forward();
+            loss.backward(1.0);
+            optimizer.step();
+            loss.zero_gradient();
+        }
+
+        save_parameters(&path, &[("weights", &weights)]).unwrap();
+        let loaded = load_parameters(&path).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        let loaded_weights = parameter(&loaded, "weights", (3, 1)).unwrap();
+        assert_eq!(loaded_weights.squared_gradients(), weights.squared_gradients());
+
+        let reloaded_y_hat = x.dot(&ParameterNode::shared(loaded_weights));
+
+        y_hat.forward();
+        reloaded_y_hat.forward();
+
+        assert_eq!(y_hat.value().deref(), reloaded_y_hat.value().deref());
+    }
+
+    #[test]
+    fn corrupted_file() {
+        let path = temp_path("corrupted_file");
+
+        let embedding = Arc::new(HogwildParameter::new(xavier_normal(10, 4)));
+        save_parameters(&path, &[("embedding", &embedding)]).unwrap();
+
+        let mut bytes = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut bytes).unwrap();
+
+        // Truncated file.
+        File::create(&path)
+            .unwrap()
+            .write_all(&bytes[..bytes.len() / 2])
+            .unwrap();
+        assert!(load_parameters(&path).is_err());
+
+        // Garbage.
+        File::create(&path)
+            .unwrap()
+            .write_all(&[0xff; 64])
+            .unwrap();
+        assert!(load_parameters(&path).is_err());
+
+        ::std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,446 @@
+//! Reading and writing numpy `.npy` and `.npz` files.
+//!
+//! Only little-endian `Float` (`'<f4'`, or `'<f8'` with the `wyrm_f64`
+//! cfg), C-ordered, two-dimensional arrays are supported; anything
+//! else is rejected with an error rather than being silently reinterpreted.
+use std::cmp;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::Error;
+use nodes::HogwildParameter;
+use {Arr, Float};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+const ALIGNMENT: usize = 64;
+/// Elements allocated up front when reading an array.
+const MAX_PREALLOCATED: usize = 1 << 16;
+
+#[cfg(not(wyrm_f64))]
+const DESCR: &str = "<f4";
+#[cfg(wyrm_f64)]
+const DESCR: &str = "<f8";
+const FLOAT_BYTES: usize = ::std::mem::size_of::<Float>();
+
+fn u16_le(bytes: &[u8]) -> usize {
+    bytes[0] as usize | (bytes[1] as usize) << 8
+}
+
+fn u32_le(bytes: &[u8]) -> usize {
+    u16_le(&bytes[..2]) | u16_le(&bytes[2..]) << 16
+}
+
+fn push_u16_le(dest: &mut Vec<u8>, value: u16) {
+    dest.push(value as u8);
+    dest.push((value >> 8) as u8);
+}
+
+fn push_u32_le(dest: &mut Vec<u8>, value: u32) {
+    push_u16_le(dest, value as u16);
+    push_u16_le(dest, (value >> 16) as u16);
+}
+
+fn float_le(bytes: &[u8]) -> Float {
+    let bits = bytes
+        .iter()
+        .rev()
+        .fold(0u64, |bits, &byte| bits << 8 | u64::from(byte));
+
+    Float::from_bits(bits as _)
+}
+
+fn push_float_le(dest: &mut Vec<u8>, value: Float) {
+    let bits = value.to_bits() as u64;
+    dest.extend((0..FLOAT_BYTES).map(|byte| (bits >> (8 * byte)) as u8));
+}
+
+/// Return the value following `'key':` in a numpy header dictionary.
+fn header_field<'a>(header: &'a str, key: &str) -> Result<&'a str, Error> {
+    let pattern = format!("'{}':", key);
+    let start = header
+        .find(&pattern)
+        .ok_or_else(|| Error::UnsupportedFormat(format!("header is missing {:?}", key)))?
+        + pattern.len();
+
+    Ok(header[start..].trim_start())
+}
+
+fn parse_header(header: &str) -> Result<(usize, usize), Error> {
+    let descr = header_field(header, "descr")?;
+    if !descr.starts_with(&format!("'{}'", DESCR)) {
+        let descr = descr.split(',').next().unwrap_or(descr);
+        return Err(Error::UnsupportedFormat(format!(
+            "dtype {} is not supported, expected '{}'",
+            descr, DESCR
+        )));
+    }
+
+    let fortran_order = header_field(header, "fortran_order")?;
+    if fortran_order.starts_with("True") {
+        return Err(Error::UnsupportedFormat(
+            "Fortran-ordered arrays are not supported, expected C order".to_owned(),
+        ));
+    } else if !fortran_order.starts_with("False") {
+        return Err(Error::UnsupportedFormat(
+            "unreadable fortran_order field".to_owned(),
+        ));
+    }
+
+    let shape = header_field(header, "shape")?;
+    let shape = &shape[..shape.find(')').unwrap_or(0)];
+    let dimensions = shape
+        .trim_start_matches('(')
+        .split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| Error::UnsupportedFormat(format!("unreadable shape ({})", shape)))?;
+
+    match dimensions[..] {
+        [rows, cols] => Ok((rows, cols)),
+        _ => Err(Error::UnsupportedFormat(format!(
+            "{}-dimensional arrays are not supported, expected 2 dimensions",
+            dimensions.len()
+        ))),
+    }
+}
+
+/// Read an array in the `.npy` format.
+pub fn read_npy<R: Read>(reader: &mut R) -> Result<Arr, Error> {
+    let mut preamble = [0; 8];
+    reader.read_exact(&mut preamble)?;
+
+    if &preamble[..6] != MAGIC {
+        return Err(Error::Corrupted("not a .npy file".to_owned()));
+    }
+
+    let header_len = match preamble[6] {
+        1 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16_le(&len)
+        }
+        2 => {
+            let mut len = [0; 4];
+            reader.read_exact(&mut len)?;
+            u32_le(&len)
+        }
+        version => {
+            return Err(Error::UnsupportedFormat(format!(
+                ".npy version {} is not supported",
+                version
+            )))
+        }
+    };
+
+    let mut header = vec![0; header_len];
+    reader.read_exact(&mut header)?;
+    let header = String::from_utf8(header)
+        .map_err(|_| Error::Corrupted("header is not valid text".to_owned()))?;
+
+    let (rows, cols) = parse_header(&header)?;
+
+    let len = rows
+        .checked_mul(cols)
+        .ok_or_else(|| Error::Corrupted(format!("shape ({}, {}) is too large", rows, cols)))?;
+
+    // The header is not trusted to size the buffer: it only
+    // grows past this as the data is actually read.
+    let mut data = Vec::with_capacity(cmp::min(len, MAX_PREALLOCATED));
+    let mut buffer = [0; FLOAT_BYTES];
+
+    for _ in 0..len {
+        reader.read_exact(&mut buffer)?;
+        data.push(float_le(&buffer));
+    }
+
+    Arr::from_shape_vec((rows, cols), data).map_err(|err| Error::Corrupted(format!("{}", err)))
+}
+
+/// Write an array in the `.npy` (version 1.0) format.
+pub fn write_npy<W: Write>(writer: &mut W, value: &Arr) -> Result<(), Error> {
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        DESCR,
+        value.rows(),
+        value.cols()
+    );
+
+    // Pad so that the data starts on an aligned boundary, as numpy does.
+    let unpadded_len = MAGIC.len() + 2 + 2 + header.len() + 1;
+    let padding = (ALIGNMENT - unpadded_len % ALIGNMENT) % ALIGNMENT;
+    header.extend((0..padding).map(|_| ' '));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 4 + header.len() + FLOAT_BYTES * value.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[1, 0]);
+    push_u16_le(&mut bytes, header.len() as u16);
+    bytes.extend_from_slice(header.as_bytes());
+
+    for &x in value.iter() {
+        push_float_le(&mut bytes, x);
+    }
+
+    writer.write_all(&bytes)?;
+
+    Ok(())
+}
+
+/// Read a `.npy` file into an array.
+pub fn from_npy<P: AsRef<Path>>(path: P) -> Result<Arr, Error> {
+    read_npy(&mut BufReader::new(File::open(path)?))
+}
+
+/// Write the value of a parameter to a `.npy` file.
+pub fn to_npy<P: AsRef<Path>>(parameter: &HogwildParameter, path: P) -> Result<(), Error> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_npy(&mut writer, &parameter.full_value())?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+/// Write the values of named parameters into an uncompressed `.npz`
+/// bundle, loadable with `numpy.load`. Each parameter is stored
+/// under its name.
+pub fn to_npz<P: AsRef<Path>>(parameters: &[(&str, &HogwildParameter)], path: P) -> Result<(), Error> {
+    // DOS date for 1980-01-01, the earliest representable.
+    let (time, date) = (0, (1 << 5) | 1);
+
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+
+    for &(name, parameter) in parameters {
+        let name = format!("{}.npy", name);
+
+        let mut data = Vec::new();
+        write_npy(&mut data, &parameter.full_value())?;
+        let crc = crc32(&data);
+        let offset = archive.len() as u32;
+
+        // Local file header.
+        push_u32_le(&mut archive, 0x0403_4b50);
+        push_u16_le(&mut archive, 20);
+        push_u16_le(&mut archive, 0);
+        push_u16_le(&mut archive, 0);
+        push_u16_le(&mut archive, time);
+        push_u16_le(&mut archive, date);
+        push_u32_le(&mut archive, crc);
+        push_u32_le(&mut archive, data.len() as u32);
+        push_u32_le(&mut archive, data.len() as u32);
+        push_u16_le(&mut archive, name.len() as u16);
+        push_u16_le(&mut archive, 0);
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(&data);
+
+        // Central directory entry.
+        push_u32_le(&mut directory, 0x0201_4b50);
+        push_u16_le(&mut directory, 20);
+        push_u16_le(&mut directory, 20);
+        push_u16_le(&mut directory, 0);
+        push_u16_le(&mut directory, 0);
+        push_u16_le(&mut directory, time);
+        push_u16_le(&mut directory, date);
+        push_u32_le(&mut directory, crc);
+        push_u32_le(&mut directory, data.len() as u32);
+        push_u32_le(&mut directory, data.len() as u32);
+        push_u16_le(&mut directory, name.len() as u16);
+        push_u16_le(&mut directory, 0);
+        push_u16_le(&mut directory, 0);
+        push_u16_le(&mut directory, 0);
+        push_u16_le(&mut directory, 0);
+        push_u32_le(&mut directory, 0);
+        push_u32_le(&mut directory, offset);
+        directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = archive.len() as u32;
+    archive.extend_from_slice(&directory);
+
+    // End of central directory record.
+    push_u32_le(&mut archive, 0x0605_4b50);
+    push_u16_le(&mut archive, 0);
+    push_u16_le(&mut archive, 0);
+    push_u16_le(&mut archive, parameters.len() as u16);
+    push_u16_le(&mut archive, parameters.len() as u16);
+    push_u32_le(&mut archive, directory.len() as u32);
+    push_u32_le(&mut archive, directory_offset);
+    push_u16_le(&mut archive, 0);
+
+    File::create(path)?.write_all(&archive)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+
+    use super::*;
+    use nn::xavier_normal;
+
+    /// The bytes `np.save` writes for `np.arange(6, dtype='<f4').reshape(2, 3) / 4`.
+    #[cfg(not(wyrm_f64))]
+    const FIXTURE: &[u8] = include_bytes!("fixture.npy");
+
+    fn temp_path(name: &str) -> ::std::path::PathBuf {
+        ::std::env::temp_dir().join(format!("wyrm_{}_{}", ::std::process::id(), name))
+    }
+
+    #[test]
+    #[cfg(not(wyrm_f64))]
+    fn npy_fixture() {
+        let value = read_npy(&mut &FIXTURE[..]).unwrap();
+
+        assert_eq!(value, arr2(&[[0.0, 0.25, 0.5], [0.75, 1.0, 1.25]]));
+
+        let mut written = Vec::new();
+        write_npy(&mut written, &value).unwrap();
+        assert_eq!(&written[..], FIXTURE);
+    }
+
+    #[test]
+    fn npy_round_trip() {
+        let path = temp_path("round_trip.npy");
+        let parameter = HogwildParameter::new(xavier_normal(7, 3));
+
+        to_npy(&parameter, &path).unwrap();
+        let value = from_npy(&path).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(&value, parameter.value());
+    }
+
+    fn sample() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_npy(&mut bytes, &arr2(&[[0.0, 0.25, 0.5], [0.75, 1.0, 1.25]])).unwrap();
+        bytes
+    }
+
+    fn with_header(header: &str) -> Vec<u8> {
+        let mut bytes = sample();
+        let original = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': (2, 3), }}",
+            DESCR
+        );
+        let start = 10;
+
+        let mut replacement = header.as_bytes().to_vec();
+        replacement.extend((0..original.len() - header.len()).map(|_| b' '));
+        bytes[start..start + original.len()].copy_from_slice(&replacement);
+
+        bytes
+    }
+
+    fn raw_header(header: &str) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        push_u16_le(&mut bytes, header.len() as u16);
+        bytes.extend_from_slice(header.as_bytes());
+
+        bytes
+    }
+
+    fn error_message(bytes: &[u8]) -> String {
+        match read_npy(&mut &bytes[..]) {
+            Err(err) => format!("{}", err),
+            Ok(_) => panic!("Invalid file accepted."),
+        }
+    }
+
+    #[test]
+    fn npy_rejects_mismatches() {
+        let message = error_message(&with_header(
+            "{'descr': '<i8', 'fortran_order': False, 'shape': (2, 3), }",
+        ));
+        assert!(message.contains("'<i8'"), message);
+
+        let message = error_message(&with_header(
+            "{'descr': '>f4', 'fortran_order': False, 'shape': (2, 3), }",
+        ));
+        assert!(message.contains("'>f4'"), message);
+
+        let message = error_message(&with_header(&format!(
+            "{{'descr': '{}', 'fortran_order': True, 'shape': (2, 3), }}",
+            DESCR
+        )));
+        assert!(message.contains("Fortran"), message);
+
+        let message = error_message(&with_header(&format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': (6,), }}",
+            DESCR
+        )));
+        assert!(message.contains("1-dimensional"), message);
+
+        let message = error_message(&raw_header(&format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': (4294967296, 4294967296), }}",
+            DESCR
+        )));
+        assert!(message.contains("too large"), message);
+
+        // A shape promising more data than there is fails
+        // without allocating for it.
+        let bytes = raw_header(&format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': (100000000, 100000000), }}",
+            DESCR
+        ));
+        assert!(read_npy(&mut &bytes[..]).is_err());
+
+        let sample = sample();
+        assert!(read_npy(&mut &sample[..sample.len() - 1]).is_err());
+        assert!(read_npy(&mut &b"not a numpy file"[..]).is_err());
+    }
+
+    #[test]
+    fn npz_archive() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let path = temp_path("archive.npz");
+        let first = HogwildParameter::new(xavier_normal(2, 3));
+        let second = HogwildParameter::new(xavier_normal(4, 1));
+
+        to_npz(&[("first", &first), ("second", &second)], &path).unwrap();
+
+        let mut bytes = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut bytes).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        // Walk the local file headers.
+        let mut offset = 0;
+        for &(name, parameter) in &[("first.npy", &first), ("second.npy", &second)] {
+            assert_eq!(u32_le(&bytes[offset..]), 0x0403_4b50);
+            let crc = u32_le(&bytes[offset + 14..]) as u32;
+            let size = u32_le(&bytes[offset + 18..]);
+            let name_len = u16_le(&bytes[offset + 26..]);
+            assert_eq!(&bytes[offset + 30..offset + 30 + name_len], name.as_bytes());
+
+            let data = &bytes[offset + 30 + name_len..offset + 30 + name_len + size];
+            assert_eq!(crc32(data), crc);
+            assert_eq!(&read_npy(&mut &data[..]).unwrap(), parameter.value());
+
+            offset += 30 + name_len + size;
+        }
+
+        // Followed by the central directory.
+        assert_eq!(u32_le(&bytes[offset..]), 0x0201_4b50);
+        assert_eq!(u32_le(&bytes[bytes.len() - 22..]), 0x0605_4b50);
+        assert_eq!(u16_le(&bytes[bytes.len() - 12..]), 2);
+    }
+}
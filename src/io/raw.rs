@@ -0,0 +1,332 @@
+//! Raw binary parameter files, which can be memory-mapped.
+//!
+//! The format is a 32-byte header followed by the parameter values
+//! as contiguous, row-major, little-endian `Float`s. The header holds
+//! the magic string `WYRMRAW1`, the number of rows and columns as
+//! little-endian `u64`s, and the width of each value in bytes as a
+//! little-endian `u64`.
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::slice;
+use std::sync::Arc;
+
+use memmap::{Mmap, MmapMut, MmapOptions};
+
+use super::Error;
+use nodes::HogwildParameter;
+use {Arr, Float};
+
+const MAGIC: &[u8] = b"WYRMRAW1";
+const HEADER_LEN: usize = 32;
+const FLOAT_BYTES: usize = ::std::mem::size_of::<Float>();
+
+fn u64_le(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0u64, |value, &byte| value << 8 | u64::from(byte))
+}
+
+fn push_u64_le(dest: &mut Vec<u8>, value: u64) {
+    dest.extend((0..8).map(|byte| (value >> (8 * byte)) as u8));
+}
+
+/// Write the value of `parameter` to `path` in the raw format
+/// read by `HogwildParameter::from_mmap`.
+pub fn to_raw<P: AsRef<Path>>(parameter: &HogwildParameter, path: P) -> Result<(), Error> {
+    let value = parameter.full_value();
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    push_u64_le(&mut header, value.rows() as u64);
+    push_u64_le(&mut header, value.cols() as u64);
+    push_u64_le(&mut header, FLOAT_BYTES as u64);
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&header)?;
+
+    let mut bytes = Vec::with_capacity(FLOAT_BYTES);
+    for &x in value.iter() {
+        bytes.clear();
+        push_u64_le(&mut bytes, x.to_bits() as u64);
+        writer.write_all(&bytes[..FLOAT_BYTES])?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn parse_header(header: &[u8]) -> Result<(usize, usize), Error> {
+    if header.len() < HEADER_LEN || &header[..MAGIC.len()] != MAGIC {
+        return Err(Error::UnsupportedFormat(
+            "not a raw wyrm parameter file".to_owned(),
+        ));
+    }
+
+    let rows = u64_le(&header[8..16]) as usize;
+    let cols = u64_le(&header[16..24]) as usize;
+    let width = u64_le(&header[24..32]) as usize;
+
+    if width != FLOAT_BYTES {
+        return Err(Error::UnsupportedFormat(format!(
+            "values are {} bytes wide, expected {}",
+            width, FLOAT_BYTES
+        )));
+    }
+
+    Ok((rows, cols))
+}
+
+/// How `HogwildParameter::from_mmap` maps a raw parameter file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapMode {
+    /// Map the file read-only. The parameter is frozen: it is not
+    /// given gradients, and optimizers leave it untouched.
+    ReadOnly,
+    /// Map the file privately, copy-on-write, so that the parameter
+    /// can be trained. Pages are copied as they are first updated,
+    /// and updates are never written back to the file.
+    ReadWrite,
+}
+
+#[derive(Debug)]
+enum Mapping {
+    /// Shared between clones, as it is never written to.
+    Frozen(Arc<Mmap>),
+    Private(RefCell<MmapMut>),
+}
+
+/// Parameter values read and updated in place in a memory-mapped
+/// raw parameter file.
+#[derive(Debug)]
+pub(crate) struct MappedValue {
+    shape: (usize, usize),
+    mapping: Mapping,
+}
+
+impl MappedValue {
+    pub(crate) fn shape(&self) -> (usize, usize) {
+        self.shape
+    }
+
+    pub(crate) fn is_frozen(&self) -> bool {
+        match self.mapping {
+            Mapping::Frozen(_) => true,
+            Mapping::Private(_) => false,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self.mapping {
+            Mapping::Frozen(ref mmap) => mmap,
+            Mapping::Private(ref mmap) => unsafe { &*mmap.as_ptr() },
+        }
+    }
+
+    fn values(&self) -> &[Float] {
+        // Maps are page-aligned, and the header keeps the values aligned.
+        unsafe {
+            slice::from_raw_parts(
+                self.bytes()[HEADER_LEN..].as_ptr() as *const Float,
+                self.shape.0 * self.shape.1,
+            )
+        }
+    }
+
+    pub(crate) fn row(&self, row: usize) -> &[Float] {
+        let cols = self.shape.1;
+        &self.values()[row * cols..(row + 1) * cols]
+    }
+
+    pub(crate) unsafe fn row_mut(&self, row: usize) -> &mut [Float] {
+        let mmap = match self.mapping {
+            Mapping::Frozen(_) => panic!("Frozen parameters cannot be updated."),
+            Mapping::Private(ref mmap) => &mut *mmap.as_ptr(),
+        };
+
+        let cols = self.shape.1;
+        let values = mmap[HEADER_LEN..].as_mut_ptr() as *mut Float;
+
+        slice::from_raw_parts_mut(values.offset((row * cols) as isize), cols)
+    }
+
+    pub(crate) fn to_arr(&self) -> Arr {
+        Arr::from_shape_vec(self.shape, self.values().to_vec()).unwrap()
+    }
+}
+
+/// Frozen values share the map; private ones are copied into an
+/// anonymous map, so that the clones are updated independently.
+impl Clone for MappedValue {
+    fn clone(&self) -> Self {
+        let mapping = match self.mapping {
+            Mapping::Frozen(ref mmap) => Mapping::Frozen(Arc::clone(mmap)),
+            Mapping::Private(_) => {
+                let bytes = self.bytes();
+                let mut mmap = MmapMut::map_anon(bytes.len()).expect("Unable to allocate a map.");
+                mmap.copy_from_slice(bytes);
+
+                Mapping::Private(RefCell::new(mmap))
+            }
+        };
+
+        MappedValue {
+            shape: self.shape,
+            mapping: mapping,
+        }
+    }
+}
+
+impl HogwildParameter {
+    /// Load a parameter from a raw file written by `io::to_raw`.
+    ///
+    /// The parameter is backed by the memory-mapped file, so that a
+    /// large embedding table is paged in as its rows are used rather
+    /// than copied up front. `MapMode::ReadOnly` gives a frozen
+    /// parameter; `MapMode::ReadWrite` maps the file privately, and
+    /// the optimizer buffers are only allocated if an optimizer is
+    /// used to train the parameter.
+    ///
+    /// Like half-precision parameters, mapped parameters can only be
+    /// used through `Variable::index`. Values are mapped as they are
+    /// stored, so this is only supported on little-endian targets.
+    pub fn from_mmap<P: AsRef<Path>>(path: P, mode: MapMode) -> Result<Self, Error> {
+        if cfg!(target_endian = "big") {
+            return Err(Error::UnsupportedFormat(
+                "raw parameter files can only be mapped on little-endian targets".to_owned(),
+            ));
+        }
+
+        let file = File::open(path)?;
+        let mapping = match mode {
+            MapMode::ReadOnly => Mapping::Frozen(Arc::new(unsafe { Mmap::map(&file)? })),
+            MapMode::ReadWrite => {
+                Mapping::Private(RefCell::new(unsafe { MmapOptions::new().map_copy(&file)? }))
+            }
+        };
+
+        let mut value = MappedValue {
+            shape: (0, 0),
+            mapping: mapping,
+        };
+
+        let (rows, cols) = parse_header(value.bytes())?;
+        let data_len = value.bytes().len() - HEADER_LEN;
+
+        if data_len != rows * cols * FLOAT_BYTES {
+            return Err(Error::Corrupted(format!(
+                "expected {} values for a {:?} parameter, found {} bytes",
+                rows * cols,
+                (rows, cols),
+                data_len
+            )));
+        }
+
+        value.shape = (rows, cols);
+
+        Ok(HogwildParameter::mapped(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::ops::Deref;
+
+    use ndarray::Axis;
+
+    use super::*;
+    use nn::xavier_normal;
+    use optim::{Adam, Optimizer};
+    use {IndexInputNode, ParameterNode};
+
+    #[test]
+    fn round_trip() {
+        let path = ::std::env::temp_dir().join(format!("wyrm_raw_{}.bin", ::std::process::id()));
+
+        let parameter = HogwildParameter::new(xavier_normal(5, 3));
+        to_raw(&parameter, &path).unwrap();
+
+        let loaded = Arc::new(HogwildParameter::from_mmap(&path, MapMode::ReadWrite).unwrap());
+        assert!(!loaded.is_frozen());
+        assert_eq!(loaded.full_value().deref(), parameter.value());
+        assert_eq!(loaded.optimizer_buffer_len(), 0);
+
+        let embedding = ParameterNode::shared(Arc::clone(&loaded));
+        assert_eq!(embedding.summary().optimizer_bytes, 0);
+
+        // Training allocates the buffers and updates the rows in place.
+        let index = IndexInputNode::new(&[1, 3]);
+        let rows = embedding.index(&index);
+        let mut loss = rows.square().scalar_sum();
+        let optimizer = Adam::new(loss.parameters());
+        loss.forward();
+        assert_eq!(
+            rows.value().deref(),
+            &parameter.value().select(Axis(0), &[1, 3])
+        );
+        loss.backward(1.0);
+        optimizer.step();
+        assert_eq!(embedding.summary().optimizer_bytes, 2 * 5 * 3 * FLOAT_BYTES);
+
+        let updated = loaded.full_value();
+        for row in 0..5 {
+            let changed = updated.subview(Axis(0), row) != parameter.value().subview(Axis(0), row);
+            assert_eq!(changed, row == 1 || row == 3);
+        }
+
+        // Clones are updated independently.
+        let clone = (*loaded).clone();
+        unsafe { loaded.update_row(0, |row| row[0] += 1.0) };
+        assert_eq!(clone.full_value().deref(), updated.deref());
+
+        // The updates are private to the map.
+        let frozen = HogwildParameter::from_mmap(&path, MapMode::ReadOnly).unwrap();
+        assert!(frozen.is_frozen());
+        assert_eq!(frozen.full_value().deref(), parameter.value());
+
+        // Truncated files are rejected.
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        match HogwildParameter::from_mmap(&path, MapMode::ReadOnly) {
+            Err(Error::Corrupted(_)) => {}
+            other => panic!("Truncated file not detected: {:?}", other),
+        }
+
+        fs::write(&path, b"not a parameter file, but long enough").unwrap();
+        match HogwildParameter::from_mmap(&path, MapMode::ReadOnly) {
+            Err(Error::UnsupportedFormat(_)) => {}
+            other => panic!("Bad header not detected: {:?}", other),
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn frozen_parameters_are_not_trained() {
+        let path =
+            ::std::env::temp_dir().join(format!("wyrm_raw_frozen_{}.bin", ::std::process::id()));
+
+        let parameter = HogwildParameter::new(xavier_normal(4, 2));
+        to_raw(&parameter, &path).unwrap();
+
+        let frozen = Arc::new(HogwildParameter::from_mmap(&path, MapMode::ReadOnly).unwrap());
+        let embedding = ParameterNode::shared(Arc::clone(&frozen));
+        let index = IndexInputNode::new(&[0, 2]);
+        let mut loss = embedding.index(&index).square().scalar_sum();
+        assert!(!loss.needs_gradient());
+
+        let optimizer = Adam::new(loss.parameters());
+        loss.forward();
+        loss.backward(1.0);
+        optimizer.step();
+
+        assert_eq!(frozen.full_value().deref(), parameter.value());
+        assert_eq!(frozen.optimizer_buffer_len(), 0);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
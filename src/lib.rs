@@ -22,7 +22,7 @@
 //! # extern crate wyrm;
 //! # use wyrm::*;
 //! # fn random_matrix(rows: usize, cols: usize) -> Arr {
-//! #      Arr::zeros((rows, cols)).map(|_| rand::random::<f32>())
+//! #      Arr::zeros((rows, cols)).map(|_| rand::random::<Float>())
 //! # }
 //! # fn main() {
 //! let slope = ParameterNode::new(random_matrix(1, 1));
@@ -45,7 +45,7 @@
 //! # use wyrm::*;
 //! # use wyrm::optim::*;
 //! # fn random_matrix(rows: usize, cols: usize) -> Arr {
-//! #      Arr::zeros((rows, cols)).map(|_| rand::random::<f32>())
+//! #      Arr::zeros((rows, cols)).map(|_| rand::random::<Float>())
 //! # }
 //! # fn main() {
 //! # let slope = ParameterNode::new(random_matrix(1, 1));
@@ -58,7 +58,7 @@
 //! let mut optimizer = SGD::new(loss.parameters()).learning_rate(0.1);
 //!
 //! for _ in 0..num_epochs {
-//!     let x_value: f32 = rand::random();
+//!     let x_value: Float = rand::random();
 //!     let y_value = 3.0 * x_value + 5.0;
 //!
 //!     // You can re-use the computation graph
@@ -87,7 +87,7 @@
 //! # use wyrm::*;
 //! # use wyrm::optim::*;
 //! # fn random_matrix(rows: usize, cols: usize) -> Arr {
-//! #      Arr::zeros((rows, cols)).map(|_| rand::random::<f32>())
+//! #      Arr::zeros((rows, cols)).map(|_| rand::random::<Float>())
 //! # }
 //! # fn main() {
 //! let slope_param = Arc::new(HogwildParameter::new(random_matrix(1, 1)));
@@ -107,7 +107,7 @@
 //!            let optimizer = SGD::new(loss.parameters()).learning_rate(0.1);
 //!
 //!            for _ in 0..num_epochs {
-//!                let x_value: f32 = rand::random();
+//!                let x_value: Float = rand::random();
 //!                let y_value = 3.0 * x_value + 5.0;
 //!
 //!                x.set_value(x_value);
@@ -123,6 +123,50 @@
 //! # }
 //! ```
 //!
+//! ## Several losses
+//!
+//! Losses sharing part of a graph, such as two heads on one encoder, can be trained
+//! together. Forward every loss before backpropagating any of them: the gradients of
+//! the losses then add up in the shared nodes, which backpropagate once, when the
+//! backward pass of the last loss reaches them. The losses can be backpropagated in
+//! any order and with different weights. Zero the gradients of every loss once the
+//! iteration is over, in any order. Forwarding a loss after another loss sharing its
+//! nodes was backpropagated panics, as the shared nodes would pass gradients on twice.
+//!
+//! ```rust
+//! # extern crate rand;
+//! # extern crate wyrm;
+//! # use wyrm::*;
+//! # use wyrm::optim::*;
+//! # fn random_matrix(rows: usize, cols: usize) -> Arr {
+//! #      Arr::zeros((rows, cols)).map(|_| rand::random::<Float>())
+//! # }
+//! # fn main() {
+//! let x = InputNode::new(random_matrix(4, 3));
+//! let encoder = ParameterNode::new(random_matrix(3, 5));
+//! let head_a = ParameterNode::new(random_matrix(5, 1));
+//! let head_b = ParameterNode::new(random_matrix(5, 2));
+//!
+//! let hidden = x.dot(&encoder).tanh();
+//! let mut loss_a = hidden.dot(&head_a).square().scalar_sum();
+//! let mut loss_b = hidden.dot(&head_b).sigmoid().scalar_sum();
+//!
+//! let parameters = vec![encoder.clone(), head_a.clone(), head_b.clone()];
+//! let mut optimizer = SGD::new(parameters).learning_rate(0.1);
+//!
+//! for _ in 0..10 {
+//!     loss_a.forward();
+//!     loss_b.forward();
+//!     loss_a.backward(1.0);
+//!     loss_b.backward(0.5);
+//!
+//!     optimizer.step();
+//!     loss_a.zero_gradient();
+//!     loss_b.zero_gradient();
+//! }
+//! # }
+//! ```
+//!
 //! ## BLAS support
 //! You should enable BLAS support to get (much) better performance out of matrix-multiplication-heavy
 //! workloads. To do so, add the following to your `Cargo.toml`:
@@ -137,10 +181,40 @@
 //!
 //! Enable the `fast-math` option to use fast approximations to transcendental functions.
 //! This should give substantial speed gains in networks that are `exp`, `ln`, or `tanh`-heavy.
+//!
+//! ## Hogwild diagnostics
+//!
+//! Enable the `instrumentation` option to record how many optimizer updates are applied
+//! to each row of a `HogwildParameter`, available via `HogwildParameter::update_histogram`.
+//! Hot rows are a sign of update contention. The counters compile away when the option is off.
+//!
+//! ## Non-finite value guard
+//!
+//! Enable the `nan-guard` option to check the values and gradients of every node for
+//! infinities and NaNs as the forward and backward passes go through it. The checks panic
+//! naming the pass and the node where the non-finite values first appear: its operation
+//! and attributes, and its name if it was given one with `Variable::named`. The checks
+//! read every value and gradient in the graph, so only enable the option for debugging.
+//! They compile away when the option is off.
+//!
+//! ## Double precision
+//!
+//! All values and gradients are `f32` by default. Build with the `wyrm_f64` cfg
+//! (`RUSTFLAGS="--cfg wyrm_f64" cargo build`) to make `Float` (and so `Arr`)
+//! double precision instead, for example for scientific fitting problems or
+//! tighter gradient checks. Note that the `fast-math` approximations are still
+//! evaluated in single precision.
+//!
+//! This is a cfg rather than a Cargo feature because it changes public types:
+//! features are unified across a dependency graph, so a crate asking for `f64`
+//! would silently change `Float` for every other crate using wyrm. A cfg is set
+//! for the whole build by whoever runs it.
 #![cfg_attr(feature = "cargo-clippy", allow(unreadable_literal, redundant_field_names))]
 #[macro_use]
 extern crate serde_derive;
 
+extern crate bincode;
+extern crate memmap;
 extern crate serde;
 
 extern crate ndarray;
@@ -151,26 +225,58 @@ extern crate smallvec;
 #[macro_use]
 extern crate itertools;
 
-/// Alias for a `f32` `ndarray` matrix.
-pub type Arr = ndarray::Array2<f32>;
+#[cfg(test)]
+extern crate serde_json;
+
+/// Scalar type of all values and gradients: `f32`, or `f64`
+/// when built with the `wyrm_f64` cfg.
+#[cfg(not(wyrm_f64))]
+pub type Float = f32;
+/// Scalar type of all values and gradients: `f32`, or `f64`
+/// when built with the `wyrm_f64` cfg.
+#[cfg(wyrm_f64)]
+pub type Float = f64;
+
+/// Alias for a `Float` `ndarray` matrix.
+pub type Arr = ndarray::Array2<Float>;
 
 use std::cell::RefCell;
 use std::clone::Clone;
-use std::ops::{Add, Deref, Div, Mul, Neg, Sub};
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::{Add, Deref, Div, Mul, Neg, Range, Sub};
 use std::rc::Rc;
 
+use ndarray::ArrayView2;
+
+mod arena;
+pub mod export;
 mod fast_approx;
+mod inference;
+pub mod io;
 pub mod nn;
 mod nodes;
 mod numerics;
 pub mod optim;
+mod summary;
+pub mod testing;
 
 use nodes::*;
 
-pub use nodes::{Bor, HogwildParameter, IndexInputNode, InputNode, Node, ParameterNode};
-pub use numerics::simd_dot;
-
-fn clamp(x: f32, min: f32, max: f32) -> f32 {
+pub use nodes::{
+    inference_mode, is_inference, set_stability, stability, ArgmaxGradient, Attribute, Bor,
+    CloneBuilder, CloneMode, Description, DuplicateSubgraph, GraphError, GraphNode,
+    HogwildParameter, IndexInputNode, InferenceGuard, InputNode, Node, NodeValue, Padding,
+    ParameterNode, Reduction, ShapeError, StabilityConfig,
+};
+pub use arena::{ArenaGuard, ArenaStats, GraphArena};
+pub use inference::InferenceGraph;
+pub use numerics::{
+    exp_slice, parallel_threshold, set_parallel_threshold, sigmoid_slice, simd_dot, tanh_slice,
+};
+pub use summary::{FlopCount, FlopProfile, ModelSummary, NodeCount, ParameterSummary};
+
+fn clamp(x: Float, min: Float, max: Float) -> Float {
     if x > max {
         max
     } else if x < min {
@@ -187,12 +293,13 @@ pub trait DataInput<T> {
 }
 
 fn merge_parameters(xs: &[Rc<ParameterNode>], ys: &[Rc<ParameterNode>]) -> Vec<Rc<ParameterNode>> {
-    let mut unique_params: Vec<_> = xs.iter().chain(ys.iter()).cloned().collect();
-
-    unique_params.sort_unstable_by_key(|x| x.deref() as *const ParameterNode);
-    unique_params.dedup_by_key(|x| (*x).deref() as *const ParameterNode);
+    let mut seen = HashSet::new();
 
-    unique_params
+    xs.iter()
+        .chain(ys.iter())
+        .filter(|x| seen.insert(&***x as *const ParameterNode))
+        .cloned()
+        .collect()
 }
 
 /// Handle to a node in the computation graph. The underlying nodes
@@ -206,6 +313,7 @@ where
     node: Rc<T>,
     grad: Option<RefCell<Arr>>,
     parameters: Vec<Rc<ParameterNode>>,
+    schedule: Option<Schedule>,
 }
 
 impl<T: Node> Clone for Variable<T> {
@@ -214,10 +322,59 @@ impl<T: Node> Clone for Variable<T> {
             node: Rc::clone(&self.node),
             grad: None,
             parameters: self.parameters.clone(),
+            schedule: None,
         }
     }
 }
 
+/// Number of leading entries shown when displaying a variable.
+const DISPLAY_ENTRIES: usize = 6;
+
+/// Shows the shape, range and mean of the current value of an array
+/// variable and its first few entries, or the indices of an index
+/// variable. Run the forward pass first to show an up-to-date value.
+impl<T> fmt::Display for Variable<T>
+where
+    T: Node,
+    T::Value: NodeValue,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = self.value();
+
+        if let Some(indices) = value.to_indices() {
+            return write!(f, "Indices {:?}", indices);
+        }
+
+        let array = match value.to_array() {
+            Some(array) => array,
+            None => return write!(f, "Value of shape {:?}", value.value_shape()),
+        };
+
+        write!(f, "Arr{:?}", array.dim())?;
+        if array.is_empty() {
+            return write!(f, " []");
+        }
+
+        let (min, max) = array
+            .iter()
+            .fold((Float::MAX, Float::MIN), |(min, max), &x| (min.min(x), max.max(x)));
+        let mean = array.scalar_sum() / array.len() as Float;
+        write!(f, " {{ min: {}, max: {}, mean: {}, values: [", min, max, mean)?;
+
+        for (idx, x) in array.iter().take(DISPLAY_ENTRIES).enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", x)?;
+        }
+        if array.len() > DISPLAY_ENTRIES {
+            write!(f, ", ...")?;
+        }
+
+        write!(f, "] }}")
+    }
+}
+
 impl<T> Variable<T>
 where
     T: Node,
@@ -227,74 +384,452 @@ where
             node: node,
             grad: None,
             parameters: parameters,
+            schedule: None,
         }
     }
     /// Get the value of the node.
     pub fn value(&self) -> Bor<T::Value> {
         self.node.value()
     }
-    /// Run the forward pass through the subgraph terminating at this node,
-    /// recursing through the ancestor nodes.
-    pub fn forward(&self) {
-        self.node.forward()
+    /// Return an owned copy of the value of the node.
+    pub fn value_clone(&self) -> T::Value
+    where
+        T::Value: Clone,
+    {
+        self.node.value().clone()
     }
     /// Zero the gradients. Must be called after a backward step or whenever inputs change.
+    ///
+    /// This also clears the cached forward pass, so it can be called after a
+    /// forward pass without a backward pass, for example after evaluation.
     pub fn zero_gradient(&self) {
-        self.node.zero_gradient();
+        match self.schedule {
+            Some(ref schedule) => schedule.zero_gradient(),
+            None => self.node.zero_gradient(),
+        }
     }
 
     pub fn needs_gradient(&self) -> bool {
         self.node.needs_gradient()
     }
 
-    /// Return the parameters of the graph.
+    /// Return the parameters of the graph, in order of first use.
+    ///
+    /// Parameter nodes used more than once, as in weight tying,
+    /// are only returned once.
     pub fn parameters(&self) -> Vec<Variable<ParameterNode>> {
-        let mut unique_params = self.parameters.clone();
-        unique_params.sort_unstable_by_key(|x| x.deref() as *const ParameterNode);
-        unique_params.dedup_by_key(|x| (*x).deref() as *const ParameterNode);
+        let mut seen = HashSet::new();
 
-        unique_params
+        self.parameters
             .iter()
+            .filter(|x| seen.insert(&***x as *const ParameterNode))
             .map(|x| Variable::new(Rc::clone(x), Vec::new()))
             .collect()
     }
 }
 
+impl<T> Variable<T>
+where
+    T: Node,
+    T::Value: NodeValue,
+{
+    /// Run the forward pass through the subgraph terminating at this node,
+    /// recursing through the ancestor nodes, or following the order
+    /// computed by `linearize`.
+    ///
+    /// Panics if the graph was backpropagated since its gradients were
+    /// last zeroed, as when calling `forward` after `backward` without
+    /// `zero_gradient` in between: its nodes would pass their gradients
+    /// on a second time. Forwarding again before backpropagating only
+    /// returns the cached values.
+    pub fn forward(&self) {
+        match self.schedule {
+            Some(ref schedule) => schedule.forward(),
+            None => {
+                self.node.forward();
+
+                // Operands come first in topological order, so the first
+                // node found is where the non-finite values appear.
+                #[cfg(feature = "nan-guard")]
+                for node in topological_order(self.graph_node()) {
+                    check_finite_value(node);
+                }
+            }
+        }
+    }
+
+    /// Return an object-safe view of the node, used to inspect
+    /// the structure of the graph.
+    pub fn graph_node(&self) -> &GraphNode {
+        &*self.node
+    }
+
+    /// Name the variable's node, so that diagnostics such as the
+    /// `nan-guard` checks can refer to it.
+    ///
+    /// Panics if the node is a leaf, such as an input or a parameter:
+    /// only operations can be named.
+    pub fn named(self, name: &'static str) -> Self {
+        self.node
+            .pass_counter()
+            .expect("Only operations can be named.")
+            .set_name(name);
+        self
+    }
+
+    /// Check the graph terminating at this node for inconsistent state:
+    /// pass counters that disagree with how the nodes are used, gradient
+    /// buffers that do not match the shapes of their operands, and
+    /// parameters that are missing from `parameters` or still hold
+    /// gradients before the graph is evaluated. Reach for this when
+    /// training misbehaves.
+    ///
+    /// Nodes shared with the graphs of other losses are forwarded once
+    /// for each loss: check such graphs together with `validate_with`.
+    pub fn validate(&self) -> Result<(), GraphError> {
+        self.validate_with(&[])
+    }
+
+    /// Like `validate`, but check the graph together with the graphs
+    /// terminating at `others` that share its nodes, as when training
+    /// several losses. Only the parameters of this graph are checked
+    /// against `parameters`.
+    pub fn validate_with(&self, others: &[&GraphNode]) -> Result<(), GraphError> {
+        validate_graph(self.graph_node(), others, &self.parameters)
+    }
+
+    /// Summarize the graph terminating at this node: its operations,
+    /// parameters, and estimated memory use.
+    pub fn summary(&self) -> ModelSummary {
+        ModelSummary::new(self.graph_node())
+    }
+
+    /// Estimate the FLOPs of a forward pass through the graph terminating
+    /// at this node, by operation. Shared nodes are counted once.
+    pub fn profile_flops(&self) -> FlopProfile {
+        FlopProfile::new(self.graph_node())
+    }
+
+    /// Flatten the graph terminating at this node into topological order,
+    /// so that `forward`, `backward` and `zero_gradient` visit each node
+    /// once in that order instead of recursing through the graph. This
+    /// saves the bookkeeping of recursive passes in small graphs that are
+    /// run many times.
+    ///
+    /// Returns `false`, leaving the passes recursive, if the graph contains
+    /// nodes that do not support linearized passes. The order is only kept
+    /// by this handle: clones and variables built on top of it recurse
+    /// until they are linearized themselves.
+    pub fn linearize(&mut self) -> bool {
+        self.schedule = Schedule::new(&*self.node);
+        self.schedule.is_some()
+    }
+}
+
 pub type BoxedNode = Rc<Node<Value = Arr, InputGradient = Arr>>;
 
 impl<T> Variable<T>
 where
     T: Node<Value = Arr, InputGradient = Arr>,
 {
+    /// Run the forward pass and return an owned copy of the value, for
+    /// collecting outputs without holding a borrow of the graph.
+    ///
+    /// Like `forward`, this reuses the cached pass until `zero_gradient`
+    /// is called, so repeated calls only copy the value.
+    pub fn forward_to_array(&self) -> Arr {
+        self.forward();
+        self.value().clone()
+    }
+
+    /// Return the single element of a `(1, 1)` value, such as a loss.
+    /// Panics, reporting the actual shape, if the value has any other
+    /// shape.
+    ///
+    /// Unlike `forward_to_array`, this does not run the forward pass, so
+    /// it can be called between `backward` and `zero_gradient`.
+    pub fn to_scalar(&self) -> Float {
+        match self.try_to_scalar() {
+            Some(scalar) => scalar,
+            None => panic!("Expected a scalar of shape (1, 1), got {:?}.", self.value().dim()),
+        }
+    }
+
+    /// Return the single element of a `(1, 1)` value, or `None` if the
+    /// value has any other shape.
+    pub fn try_to_scalar(&self) -> Option<Float> {
+        let value = self.value();
+
+        if value.dim() == (1, 1) {
+            Some(value[(0, 0)])
+        } else {
+            None
+        }
+    }
+
     /// Box the variable, erasing its specific type. Use to manage the complexity
-    /// of variable types in deep computation graphs.
-    pub fn boxed(&self) -> Variable<Rc<Node<Value = Arr, InputGradient = Arr>>> {
+    /// of variable types in deep computation graphs, or to give variables built
+    /// in different ways, such as layers with and without an activation, a
+    /// common type.
+    ///
+    /// Boxed variables keep their parameters and support the same operations
+    /// as any other variable, except that their graphs are not linearized.
+    pub fn boxed(&self) -> Variable<BoxedNode> {
         Variable::new(
             Rc::new(self.node.clone() as Rc<Node<Value = Arr, InputGradient = Arr>>),
             self.parameters.clone(),
         )
     }
 
+    /// Use the value of this variable without backpropagating through it,
+    /// for example to compute targets from a target network. The result
+    /// carries none of this variable's parameters.
+    pub fn stop_gradient(&self) -> Variable<StopGradientNode<T>> {
+        Variable::new(
+            Rc::new(StopGradientNode::new(Rc::clone(&self.node))),
+            Vec::new(),
+        )
+    }
+
+    /// Pass this variable through a node that calls `hook` on the gradient
+    /// flowing back through it, once the gradients from all of its
+    /// consumers have been summed and before it is passed on, for example
+    /// to log or rescale it. Only gradients flowing through the returned
+    /// variable are hooked.
+    ///
+    /// Further hooks added to the returned variable with
+    /// `add_gradient_hook` run after this one, in the order they are added.
+    pub fn register_gradient_hook<F>(&self, hook: F) -> Variable<HookNode<T>>
+    where
+        F: Fn(&mut Arr) + 'static,
+    {
+        let node = HookNode::new(Rc::clone(&self.node));
+        node.add_hook(hook);
+
+        Variable::new(Rc::new(node), self.parameters.clone())
+    }
+
+    /// Pass this variable through a node that calls `hook` on its value
+    /// each time the forward pass computes it, for example to log
+    /// activations. Cached forward passes do not call the hook again.
+    pub fn register_forward_hook<F>(&self, hook: F) -> Variable<ForwardHookNode<T>>
+    where
+        F: Fn(&Arr) + 'static,
+    {
+        Variable::new(
+            Rc::new(ForwardHookNode::new(Rc::clone(&self.node), hook)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Pass this variable through a node that copies its value into the
+    /// returned buffer each time the forward pass computes it, to inspect
+    /// intermediate activations after the pass.
+    pub fn capture(&self) -> (Variable<ForwardHookNode<T>>, Rc<RefCell<Arr>>) {
+        let buffer = Rc::new(RefCell::new(Arr::zeros((0, 0))));
+        let dest = Rc::clone(&buffer);
+
+        let variable = self.register_forward_hook(move |value| {
+            let mut dest = dest.borrow_mut();
+            fit_shape(&mut dest, value.dim());
+            dest.assign(value);
+        });
+
+        (variable, buffer)
+    }
+
+    /// Pass this variable through unchanged, but rescale the gradient
+    /// flowing back through it to an L2 norm of `target_norm`, for example
+    /// to balance the gradients of several loss heads where they merge.
+    /// Zero gradients are passed on as they are.
+    pub fn normalize_gradient(&self, target_norm: Float) -> Variable<GradNormNode<T>> {
+        Variable::new(
+            Rc::new(GradNormNode::new(Rc::clone(&self.node), target_norm)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Build the forward-mode tangent of this variable: a variable whose
+    /// value is the directional derivative of this variable's value, as
+    /// each parameter in `directions` moves along its direction. Other
+    /// parameters are held fixed.
+    ///
+    /// The tangent is an ordinary graph sharing this variable's nodes, so
+    /// running the backward pass on the tangent of a scalar loss yields
+    /// Hessian-vector products in the parameters' gradients. Zero the
+    /// gradients of the tangent before running passes on this variable.
+    ///
+    /// Only graphs of `Add`, `Sub`, `Neg`, `Mul`, `Dot`, `Square`, `Sum`,
+    /// `Sigmoid`, `Tanh`, `Relu` and `StopGradient` nodes are supported;
+    /// other operations produce an error naming them.
+    pub fn tangent(
+        &self,
+        directions: &[(&Variable<ParameterNode>, &Arr)],
+    ) -> Result<Variable<Rc<Node<Value = Arr, InputGradient = Arr>>>, String> {
+        let tangent = TangentBuilder::new(directions)
+            .tangent(&self.node)?
+            .unwrap_or_else(|| InputNode::new(self.value().deref() * 0.0).boxed());
+
+        Ok(Variable::new(tangent.node, self.parameters.clone()))
+    }
+
+    /// Compile the graph ending at this variable into an inference-only
+    /// evaluator, which computes the same values as `forward` without
+    /// the bookkeeping needed for training. The values of `inputs` are
+    /// set by name in `InferenceGraph::run`; other inputs, including index
+    /// inputs, and parameters keep the values they have now.
+    ///
+    /// Nodes that do not implement `Node::compile` produce an error
+    /// naming their operation.
+    pub fn compile_inference(
+        &self,
+        inputs: &[(&str, &Variable<InputNode>)],
+    ) -> Result<InferenceGraph, String> {
+        inference::compile(self, inputs)
+    }
+
+    /// Build a structural copy of this variable's graph with its own
+    /// parameters, for example as the target network in Q-learning or
+    /// a member of a population. Parameters are initialized according
+    /// to `mode`, and input nodes are shared with this graph, so setting
+    /// an input feeds both: use `CloneBuilder` to copy inputs as well.
+    ///
+    /// Nodes that do not implement `Node::clone_architecture` produce an
+    /// error naming their operation.
+    pub fn clone_architecture(
+        &self,
+        mode: CloneMode,
+    ) -> Result<Variable<Rc<Node<Value = Arr, InputGradient = Arr>>>, String> {
+        CloneBuilder::new(mode).clone_variable(self)
+    }
+
+    /// Find subgraphs of this variable's graph that compute the same
+    /// value from the same operands, such as `h.sigmoid()` built twice,
+    /// and are therefore forwarded and backpropagated more than once.
+    /// Of nested duplicates, only the largest are reported.
+    ///
+    /// ```rust
+    /// # use wyrm::*;
+    /// let x = InputNode::new(Arr::zeros((1, 3)));
+    /// let w = ParameterNode::new(nn::xavier_normal(3, 2));
+    /// let h = x.dot(&w);
+    /// let y = h.sigmoid() * h.sigmoid();
+    ///
+    /// let duplicates = y.find_duplicate_subgraphs();
+    /// assert_eq!(duplicates.len(), 1);
+    /// assert_eq!(duplicates[0].op, "Sigmoid");
+    /// assert_eq!(duplicates[0].copies, 2);
+    /// ```
+    pub fn find_duplicate_subgraphs(&self) -> Vec<DuplicateSubgraph> {
+        find_duplicates(self.graph_node())
+    }
+
+    /// Build a copy of this variable's graph in which duplicated subgraphs,
+    /// as reported by `find_duplicate_subgraphs`, are computed once. The
+    /// copy trains the same parameter nodes and reads the same inputs.
+    ///
+    /// Supports the same operations as `clone_architecture`.
+    pub fn dedup(&self) -> Result<Variable<Rc<Node<Value = Arr, InputGradient = Arr>>>, String> {
+        CloneBuilder::new(CloneMode::ShareParameters)
+            .deduplicate(true)
+            .clone_variable(self)
+    }
+
     /// Run the backward pass through the subgraph terminating at this node.
-    /// The weight parameter scales the gradients.
-    pub fn backward(&mut self, weight: f32) {
-        let val = self.node.value();
+    /// The weight parameter scales the gradients. Outputs with more than
+    /// one element receive `weight` as the gradient of every element, as
+    /// if their sum were taken: see `backward_with` to seed each element.
+    ///
+    /// Call `zero_gradient` before forwarding the graph again, or the
+    /// next `forward` panics. Panics if the graph was built in inference
+    /// mode: see `inference_mode`.
+    pub fn backward(&mut self, weight: Float) {
+        {
+            let val = self.node.value();
+            let mut grad = self.grad
+                .get_or_insert_with(|| RefCell::new(val.map(|_| weight)))
+                .borrow_mut();
 
-        self.grad
-            .get_or_insert_with(|| RefCell::new(val.map(|_| weight)))
-            .borrow_mut()
-            .as_slice_mut()
-            .unwrap()
-            .iter_mut()
-            .for_each(|x| *x = weight);
+            fit_shape(&mut grad, val.dim());
+            grad.fill(weight);
+        }
+
+        self.backward_seeded();
+    }
+
+    /// Like `backward`, but seed the backward pass with `seed` as the
+    /// gradient of the output, for example to weight the per-example
+    /// losses of an `(n, 1)` output, or to backpropagate a loss computed
+    /// outside the graph.
+    ///
+    /// Panics if `seed` does not have the shape of the value, and if
+    /// the graph was built in inference mode.
+    pub fn backward_with(&mut self, seed: &Arr) {
+        {
+            let val = self.node.value();
+            assert_eq!(
+                seed.dim(),
+                val.dim(),
+                "The seed gradient must have the shape of the output."
+            );
+
+            let mut grad = self.grad
+                .get_or_insert_with(|| RefCell::new(seed.clone()))
+                .borrow_mut();
+
+            fit_shape(&mut grad, seed.dim());
+            grad.assign(seed);
+        }
+
+        self.backward_seeded();
+    }
+
+    /// Run the backward pass from the gradient held in `self.grad`.
+    fn backward_seeded(&mut self) {
+        assert!(
+            self.parameters.iter().all(|parameter| !parameter.inference),
+            "Cannot backpropagate through a graph built in inference mode."
+        );
 
         if let Some(ref grad) = self.grad {
-            self.node.backward(&grad.borrow());
+            match self.schedule {
+                Some(ref schedule) => {
+                    self.node.accumulate(&grad.borrow());
+                    schedule.backward();
+                }
+                None => {
+                    self.node.backward(&grad.borrow());
+
+                    // Gradients flow back from the nodes using an operand,
+                    // so the first node found walking the topological order
+                    // backwards is where the non-finite gradients appear.
+                    #[cfg(feature = "nan-guard")]
+                    for node in topological_order(self.graph_node()).into_iter().rev() {
+                        check_finite_gradients(node);
+                    }
+                }
+            }
         }
     }
 
+    /// Like `backward`, but only accumulate gradients into `parameters`,
+    /// for example to fine-tune part of a model. The other parameters of
+    /// the graph are still used in the forward pass, and their gradients
+    /// are left as they were.
+    pub fn backward_for(&mut self, weight: Float, parameters: &[Variable<ParameterNode>]) {
+        let _disabled = DisabledGradients::new(
+            self.parameters
+                .iter()
+                .filter(|x| !parameters.iter().any(|parameter| Rc::ptr_eq(&parameter.node, x)))
+                .cloned()
+                .collect(),
+        );
+
+        self.backward(weight);
+    }
+
     /// Clip the value. Useful for clipping losses.
-    pub fn clip(&self, min: f32, max: f32) {
+    pub fn clip(&self, min: Float, max: Float) {
         let bor_value = self.node.value();
         let value: &Arr = bor_value.deref();
         let value = unsafe { &mut *(value as *const Arr as *mut Arr) };
@@ -316,8 +851,15 @@ where
 
     /// Sum this variable.
     pub fn scalar_sum(&self) -> Variable<SumNode<T>> {
+        self.scalar_sum_ordered(Reduction::Unordered)
+    }
+
+    /// Sum this variable, adding up its elements in the order given by
+    /// `reduction`. Use `Reduction::Sequential` or `Reduction::Pairwise`
+    /// when results need to be bit-for-bit reproducible.
+    pub fn scalar_sum_ordered(&self, reduction: Reduction) -> Variable<SumNode<T>> {
         Variable::new(
-            Rc::new(SumNode::new(Rc::clone(&self.node))),
+            Rc::new(SumNode::new(Rc::clone(&self.node), reduction)),
             self.parameters.clone(),
         )
     }
@@ -330,6 +872,19 @@ where
         )
     }
 
+    /// Compute the log-barrier `-sum(ln(x))` of this variable, for keeping
+    /// its elements positive in constrained optimization. The result is
+    /// a scalar.
+    ///
+    /// Non-positive elements do not produce NaNs: each contributes a
+    /// large finite penalty to the value and a zero gradient.
+    pub fn log_barrier(&self) -> Variable<LogBarrierNode<T>> {
+        Variable::new(
+            Rc::new(LogBarrierNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
     /// Take the tanh of this variable.
     pub fn tanh(&self) -> Variable<TanhNode<T>> {
         Variable::new(
@@ -338,6 +893,15 @@ where
         )
     }
 
+    /// Compute the tanh-shrink `x - tanh(x)` of this variable, whose
+    /// derivative `tanh(x)^2` vanishes near zero.
+    pub fn tanh_shrink(&self) -> Variable<TanhShrinkNode<T>> {
+        Variable::new(
+            Rc::new(TanhShrinkNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
     /// Transpose this variable.
     pub fn t(&self) -> Variable<TransposeNode<T>> {
         Variable::new(
@@ -354,812 +918,4477 @@ where
         )
     }
 
-    /// Compute the softmax of this variable.
-    pub fn softmax(&self) -> Variable<SoftmaxNode<T>> {
+    /// Exponentiate `base` to the power of this variable, element-wise.
+    /// The base must be positive.
+    pub fn exp_base(&self, base: Float) -> Variable<ExpBaseNode<T>> {
         Variable::new(
-            Rc::new(SoftmaxNode::new(Rc::clone(&self.node))),
+            Rc::new(ExpBaseNode::new(Rc::clone(&self.node), base)),
             self.parameters.clone(),
         )
     }
 
-    /// Compute the log-softmax of this variable.
-    pub fn log_softmax(&self) -> Variable<LogSoftmaxNode<T>> {
+    /// Compute the pseudo-Huber function `delta^2 * (sqrt(1 + (x / delta)^2) - 1)`
+    /// element-wise: a smooth approximation to `|x|` that is quadratic
+    /// near zero. Delta must be positive.
+    pub fn pseudo_huber(&self, delta: Float) -> Variable<PseudoHuberNode<T>> {
         Variable::new(
-            Rc::new(LogSoftmaxNode::new(Rc::clone(&self.node))),
+            Rc::new(PseudoHuberNode::new(Rc::clone(&self.node), delta)),
             self.parameters.clone(),
         )
     }
 
-    /// Compute the sigmoid of this variable.
-    pub fn sigmoid(&self) -> Variable<SigmoidNode<T>> {
+    /// Compute the soft-threshold `sign(x) * max(|x| - lambda, 0)`
+    /// element-wise, shrinking values towards zero by `lambda`. Lambda
+    /// must be non-negative.
+    pub fn soft_threshold(&self, lambda: Float) -> Variable<SoftThresholdNode<T>> {
         Variable::new(
-            Rc::new(SigmoidNode::new(Rc::clone(&self.node))),
+            Rc::new(SoftThresholdNode::new(Rc::clone(&self.node), lambda)),
             self.parameters.clone(),
         )
     }
 
-    /// Compute the ReLU of this variable.
-    pub fn relu(&self) -> Variable<ReluNode<T>> {
+    /// Compute `min(x, ceiling)` element-wise, but backpropagate as if
+    /// this were the identity: unlike a masking minimum, values clipped
+    /// to the ceiling still receive their gradient.
+    pub fn min_scalar_ste(&self, ceiling: Float) -> Variable<ScalarMinSteNode<T>> {
         Variable::new(
-            Rc::new(ReluNode::new(Rc::clone(&self.node))),
+            Rc::new(ScalarMinSteNode::new(Rc::clone(&self.node), ceiling)),
             self.parameters.clone(),
         )
     }
 
-    /// Compute the row-wise vector dot product of LHS and RHS.
-    pub fn vector_dot<S>(&self, other: &Variable<S>) -> Variable<VectorDotNode<T, S>>
+    /// Apply `function` element-wise, backpropagating through its
+    /// `derivative`, for activations without a dedicated node. `name`
+    /// identifies the operation in `Debug` output and graph summaries.
+    ///
+    /// ```rust
+    /// # extern crate wyrm;
+    /// # extern crate ndarray;
+    /// # use wyrm::*;
+    /// # fn main() {
+    /// let x = ParameterNode::new(ndarray::arr2(&[[1.0, -2.0]]));
+    /// let mut y = x.map("cube", |v| v * v * v, |v| 3.0 * v * v).scalar_sum();
+    ///
+    /// y.forward();
+    /// y.backward(1.0);
+    ///
+    /// assert_eq!(y.to_scalar(), -7.0);
+    /// assert_eq!(x.gradient(), ndarray::arr2(&[[3.0, 12.0]]));
+    /// # }
+    /// ```
+    pub fn map<F, D>(&self, name: &'static str, function: F, derivative: D) -> Variable<MapNode<T>>
     where
-        S: Node<Value = Arr, InputGradient = Arr>,
+        F: Fn(Float) -> Float + 'static,
+        D: Fn(Float) -> Float + 'static,
     {
         Variable::new(
-            Rc::new(VectorDotNode::new(
-                Rc::clone(&self.node),
-                Rc::clone(&other.node),
-            )),
-            merge_parameters(&self.parameters, &other.parameters),
+            Rc::new(MapNode::new(Rc::clone(&self.node), name, function, derivative)),
+            self.parameters.clone(),
         )
     }
 
-    /// Compute the matrix multiplication of LHS and RHS.
-    pub fn dot<S>(&self, other: &Variable<S>) -> Variable<DotNode<T, S>>
-    where
-        S: Node<Value = Arr, InputGradient = Arr>,
-    {
+    /// Subtract the maximum of each row from that row, as softmax does
+    /// for numerical stability.
+    pub fn subtract_row_max(&self) -> Variable<SubtractRowMaxNode<T>> {
         Variable::new(
-            Rc::new(DotNode::new(Rc::clone(&self.node), Rc::clone(&other.node))),
-            merge_parameters(&self.parameters, &other.parameters),
+            Rc::new(SubtractRowMaxNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
         )
     }
 
-    /// Stack/concatenate LHS and RHS, either row-wise (`ndarray::Axis(0)`) or
-    /// column-wise (`ndarray::Axis(1)`).
-    pub fn stack<S>(
-        &self,
-        other: &Variable<S>,
-        axis: ndarray::Axis,
-    ) -> Variable<ConcatenateNode<T, S>>
-    where
-        S: Node<Value = Arr, InputGradient = Arr>,
-    {
+    /// Replace each row of scores with a one-hot row selecting its
+    /// maximum, as in a hard attention readout. With
+    /// `ArgmaxGradient::Blocked`, the result carries none of this
+    /// variable's parameters, as with `stop_gradient`.
+    pub fn one_hot_argmax(&self, gradient: ArgmaxGradient) -> Variable<OneHotArgmaxNode<T>> {
+        let parameters = match gradient {
+            ArgmaxGradient::Blocked => Vec::new(),
+            ArgmaxGradient::StraightThrough => self.parameters.clone(),
+        };
+
         Variable::new(
-            Rc::new(ConcatenateNode::new(
-                Rc::clone(&self.node),
-                Rc::clone(&other.node),
-                axis,
-            )),
-            merge_parameters(&self.parameters, &other.parameters),
+            Rc::new(OneHotArgmaxNode::new(Rc::clone(&self.node), gradient)),
+            parameters,
         )
     }
-}
-
-impl Variable<ParameterNode> {
-    /// Return the (dense) gradient value of this node.
-    pub fn dense_gradient(&self) -> Option<Arr> {
-        match self.node.gradient.borrow().dense_gradient {
-            Some(ref gradients) => Some(gradients.clone()),
-            None => None,
-        }
-    }
 
-    /// Return the (dense) gradient value of this node.
-    fn sparse_gradient(&self) -> SparseGradientStore {
-        self.node.gradient.borrow().sparse_gradient.clone()
+    /// Compute the log-probability of the `chosen` index of each row
+    /// under the softmax of that row, as an `(n, 1)` column: the
+    /// log-likelihood term of REINFORCE, for actions sampled with
+    /// `sample_categorical`. The chosen indices are treated as a constant.
+    pub fn log_prob(&self, chosen: &Variable<IndexInputNode>) -> Variable<LogProbNode<T>> {
+        Variable::new(
+            Rc::new(LogProbNode::new(Rc::clone(&self.node), Rc::clone(&chosen.node))),
+            self.parameters.clone(),
+        )
     }
 
-    /// Row-wise indexing of this parameter node. Primiarily used
-    /// to implement embedding layers.
-    pub fn index(&self, index: &Variable<IndexInputNode>) -> Variable<IndexNode<ParameterNode>> {
+    /// Sample one index per row of these logits, from the categorical
+    /// distribution given by the softmax of the row, as when choosing
+    /// the actions of a policy. The sampling is not differentiable: the
+    /// result has no parameters.
+    ///
+    /// The indices can be copied into an `IndexInputNode` to compute
+    /// their log-probabilities. As with evaluation, zero the sample's
+    /// gradient before forwarding a loss that shares its logits.
+    pub fn sample_categorical(&self) -> Variable<CategoricalSampleNode<T>> {
         Variable::new(
-            Rc::new(IndexNode::new(
-                Rc::clone(&self.node),
-                Rc::clone(&index.node),
-            )),
-            merge_parameters(&self.parameters, &index.parameters),
+            Rc::new(CategoricalSampleNode::new(Rc::clone(&self.node))),
+            Vec::new(),
         )
     }
-}
 
-impl<T> Variable<nn::losses::SparseCategoricalCrossentropyNode<T>>
-where
-    T: Node<Value = Arr, InputGradient = Arr>,
-{
-    /// Return the log-softmax predictions from a sparse categorical
-    /// cross-entropy node.
+    /// Compute the softmax of this variable.
+    pub fn softmax(&self) -> Variable<SoftmaxNode<T>> {
+        Variable::new(
+            Rc::new(SoftmaxNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the log-softmax of this variable.
+    pub fn log_softmax(&self) -> Variable<LogSoftmaxNode<T>> {
+        Variable::new(
+            Rc::new(LogSoftmaxNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the log-softmax of each row of this variable, as for
+    /// a batch of logits.
+    pub fn log_softmax_rows(&self) -> Variable<LogSoftmaxRowsNode<T>> {
+        Variable::new(
+            Rc::new(LogSoftmaxRowsNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the softmax of each row of these logits after adding the
+    /// `(1, classes)` constant `log_prior` to every row, as in logit
+    /// adjustment for long-tailed classification, where the prior holds
+    /// the log class frequencies. Setting the prior's value changes the
+    /// adjustment of the next forward pass.
     ///
-    /// Calling `.value()` on the node returns the value of the loss;
-    /// this function allows getting the predictins with low overhead.
-    pub fn predictions(&self) -> Bor<Arr> {
-        self.node.predictions()
+    /// Panics, when built and in every forward pass, if the prior does not
+    /// have one entry per column of the logits.
+    pub fn logit_adjusted_softmax(
+        &self,
+        log_prior: &Variable<InputNode>,
+    ) -> Variable<LogitAdjustedSoftmaxNode<T>> {
+        Variable::new(
+            Rc::new(LogitAdjustedSoftmaxNode::new(
+                Rc::clone(&self.node),
+                Rc::clone(&log_prior.node),
+            )),
+            self.parameters.clone(),
+        )
     }
-}
 
-impl<'value> DataInput<&'value Arr> for Variable<ParameterNode> {
-    fn set_value(&self, value: &Arr) {
-        let param_value = unsafe { &mut *(self.node.value.deref().value.as_ptr()) };
-        param_value.assign(value)
+    /// Compute `x[pos] - logsumexp(x[negs])` for each row of these
+    /// logits, where row `i` has the positive column `positives[i]` and
+    /// the negative columns `negatives[i]`. The result is an `(n, 1)`
+    /// column.
+    ///
+    /// This is the core of InfoNCE-style contrastive losses: maximizing
+    /// it pushes the positive logit up and the negatives down. The
+    /// indices are treated as constants.
+    pub fn log_ratio(
+        &self,
+        positives: &[usize],
+        negatives: &[Vec<usize>],
+    ) -> Variable<LogRatioNode<T>> {
+        Variable::new(
+            Rc::new(LogRatioNode::new(
+                Rc::clone(&self.node),
+                positives,
+                negatives,
+            )),
+            self.parameters.clone(),
+        )
     }
-}
 
-impl<'value> DataInput<&'value Arr> for Variable<InputNode> {
-    fn set_value(&self, value: &Arr) {
-        self.node.value.borrow_mut().assign(value);
+    /// Compute the sigmoid of this variable.
+    pub fn sigmoid(&self) -> Variable<SigmoidNode<T>> {
+        Variable::new(
+            Rc::new(SigmoidNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
     }
-}
 
-impl DataInput<f32> for Variable<InputNode> {
-    fn set_value(&self, value: f32) {
-        self.node.value.borrow_mut()[(0, 0)] = value;
+    /// Compute `lower + (upper - lower) * sigmoid(x)` element-wise, to
+    /// constrain outputs to the range `(lower, upper)`. Lower must be
+    /// below upper.
+    pub fn bounded_sigmoid(&self, lower: Float, upper: Float) -> Variable<BoundedSigmoidNode<T>> {
+        Variable::new(
+            Rc::new(BoundedSigmoidNode::new(Rc::clone(&self.node), lower, upper)),
+            self.parameters.clone(),
+        )
     }
-}
 
-impl<'value> DataInput<&'value [usize]> for Variable<IndexInputNode> {
-    fn set_value(&self, value: &[usize]) {
-        let mut node_value = self.node.value.borrow_mut();
-        node_value.clear();
-        node_value.extend_from_slice(value);
+    /// Compute `sigmoid(x - a) - sigmoid(x - b)` element-wise: a smooth
+    /// bump, close to one for `a < x < b` and to zero elsewhere, for
+    /// gating on a range of values. `a` must be below `b`.
+    pub fn bump(&self, a: Float, b: Float) -> Variable<BumpNode<T>> {
+        Variable::new(
+            Rc::new(BumpNode::new(Rc::clone(&self.node), a, b)),
+            self.parameters.clone(),
+        )
     }
-}
 
-impl DataInput<usize> for Variable<IndexInputNode> {
-    fn set_value(&self, value: usize) {
-        let mut node_value = self.node.value.borrow_mut();
-        node_value.clear();
-        node_value.push(value);
-    }
-}
+    /// Compute the ReLU of this variable.
+    pub fn relu(&self) -> Variable<ReluNode<T>> {
+        Variable::new(
+            Rc::new(ReluNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the softsign, `x / (1 + |x|)`, of this variable.
+    pub fn softsign(&self) -> Variable<SoftsignNode<T>> {
+        Variable::new(
+            Rc::new(SoftsignNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the hard-swish, `x * clamp(x + 3, 0, 6) / 6`, of this variable.
+    pub fn hard_swish(&self) -> Variable<HardSwishNode<T>> {
+        Variable::new(
+            Rc::new(HardSwishNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the SELU of this variable, element-wise, with the
+    /// constants of self-normalizing networks: `alpha = 1.6733` and
+    /// `scale = 1.0507`.
+    pub fn selu(&self) -> Variable<SeluNode<T>> {
+        Variable::new(
+            Rc::new(SeluNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the error function of this variable, element-wise.
+    pub fn erf(&self) -> Variable<ErfNode<T>> {
+        Variable::new(
+            Rc::new(ErfNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the GeGLU of this `(n, 2d)` variable: the GELU of its
+    /// first `d` columns, multiplied by its last `d` columns. The
+    /// result is `(n, d)`.
+    pub fn geglu(&self) -> Variable<GeGluNode<T>> {
+        Variable::new(
+            Rc::new(GeGluNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the entropy of each row of this variable, interpreted as
+    /// a probability distribution. The result is an `(n, 1)` column.
+    pub fn entropy(&self) -> Variable<EntropyNode<T>> {
+        Variable::new(
+            Rc::new(EntropyNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the smooth maximum `ln(mean(exp(beta * x))) / beta` of each
+    /// row of this variable, for differentiable pooling. The result is an
+    /// `(n, 1)` column, approaching the row maximum as `beta` grows and the
+    /// row mean as it shrinks. Beta must be positive.
+    pub fn soft_max_pool(&self, beta: Float) -> Variable<SoftMaxPoolNode<T>> {
+        Variable::new(
+            Rc::new(SoftMaxPoolNode::new(Rc::clone(&self.node), beta)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the `(n, n)` matrix of KL divergences `KL(p_i || p_j)`
+    /// between every pair of rows of this `(n, d)` variable, each row
+    /// interpreted as a probability distribution. Probabilities are
+    /// clamped away from zero before taking logarithms.
+    ///
+    /// Both passes take `O(n^2 d)` time and the result takes `O(n^2)`
+    /// memory, so this is only suitable for small batches.
+    pub fn row_kl_matrix(&self) -> Variable<RowKlMatrixNode<T>> {
+        Variable::new(
+            Rc::new(RowKlMatrixNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the softmax focal loss of these logits against the
+    /// class indices in `target`, `-alpha * (1 - p_t)^gamma * log(p_t)`.
+    ///
+    /// With `gamma = 0` and `alpha = 1` this is the sparse categorical
+    /// cross-entropy. The target is treated as a constant.
+    pub fn focal_loss(
+        &self,
+        target: &Variable<IndexInputNode>,
+        gamma: Float,
+        alpha: Float,
+    ) -> Variable<nn::losses::FocalLossNode<T>> {
+        nn::losses::focal_loss(self, target, gamma, alpha)
+    }
+
+    /// Compute the `(d, d)` covariance matrix of the columns
+    /// of this `(n, d)` variable.
+    pub fn covariance(&self) -> Variable<CovarianceNode<T>> {
+        Variable::new(
+            Rc::new(CovarianceNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Hinge loss on the standard deviations of the columns of this
+    /// `(n, d)` variable over its rows: the mean over columns of
+    /// `max(0, gamma - sqrt(var + epsilon))`. Used as in VICReg to keep
+    /// embeddings from collapsing, alongside a penalty on the
+    /// off-diagonal entries of the `covariance`.
+    ///
+    /// Panics if `epsilon` is not positive.
+    pub fn variance_hinge(&self, gamma: Float, epsilon: Float) -> Variable<VarianceHingeNode<T>> {
+        Variable::new(
+            Rc::new(VarianceHingeNode::new(Rc::clone(&self.node), gamma, epsilon)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Standardize each column of this `(n, d)` variable to zero mean and
+    /// unit variance over its rows, as in the z-score. Unlike batch
+    /// normalization, there are no learnable parameters or running
+    /// statistics.
+    pub fn standardize(&self) -> Variable<StandardizeNode<T>> {
+        Variable::new(
+            Rc::new(StandardizeNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Normalize each column of this variable by the running mean and
+    /// variance of all the rows it has held over the forward passes, as
+    /// for streaming inputs fed a row at a time. See `RunningNormNode`
+    /// for how the statistics are updated.
+    ///
+    /// ```rust
+    /// # use wyrm::*;
+    /// let x = InputNode::new(Arr::zeros((1, 2)));
+    /// let y = x.running_norm();
+    ///
+    /// for row in &[[1.0, 10.0], [3.0, 30.0]] {
+    ///     x.set_value(&row[..]);
+    ///     y.forward();
+    ///     y.zero_gradient();
+    /// }
+    ///
+    /// assert_eq!(y.count(), 2);
+    /// assert_eq!(y.running_mean().row(0).to_vec(), vec![2.0, 20.0]);
+    /// ```
+    pub fn running_norm(&self) -> Variable<RunningNormNode<T>> {
+        Variable::new(
+            Rc::new(RunningNormNode::new(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Add a learnable bias vector to every row of this variable.
+    /// The bias is initialized to zeros and is available through
+    /// `Variable::bias` as well as among the graph's parameters.
+    pub fn add_bias(&self) -> Variable<BiasNode<T>> {
+        let bias = ParameterNode::new(Arr::zeros((1, self.value().cols())));
+
+        Variable::new(
+            Rc::new(BiasNode::new(Rc::clone(&self.node), Rc::clone(&bias.node))),
+            merge_parameters(&self.parameters, &bias.parameters),
+        )
+    }
+
+    /// Mix this variable with `other` as `s * self + (1 - s) * other`,
+    /// where `s = sigmoid(alpha)` for the scalar parameter `alpha`, as in
+    /// a learnable skip connection. The mixing coefficient stays in
+    /// `[0, 1]` throughout training.
+    pub fn convex_combine<S>(
+        &self,
+        other: &Variable<S>,
+        alpha: &Variable<ParameterNode>,
+    ) -> Variable<ConvexCombineNode<T, S>>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Variable::new(
+            Rc::new(ConvexCombineNode::new(
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+                Rc::clone(&alpha.node),
+            )),
+            merge_parameters(
+                &merge_parameters(&self.parameters, &other.parameters),
+                &alpha.parameters,
+            ),
+        )
+    }
+
+    /// Compute `sum_j w_j x_ij^2` for each row of this `(n, d)` variable
+    /// as an `(n, 1)` column, for the `(1, d)` learnable feature
+    /// `weights`: a squared norm under a diagonal metric.
+    pub fn weighted_sq_norm(
+        &self,
+        weights: &Variable<ParameterNode>,
+    ) -> Variable<WeightedSqNormNode<T>> {
+        Variable::new(
+            Rc::new(WeightedSqNormNode::new(
+                Rc::clone(&self.node),
+                Rc::clone(&weights.node),
+            )),
+            merge_parameters(&self.parameters, &weights.parameters),
+        )
+    }
+
+    /// Compute the row-wise vector dot product of LHS and RHS.
+    pub fn vector_dot<S>(&self, other: &Variable<S>) -> Variable<VectorDotNode<T, S>>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        self.try_vector_dot(other).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `vector_dot`, but returns an error if the shapes differ.
+    pub fn try_vector_dot<S>(
+        &self,
+        other: &Variable<S>,
+    ) -> Result<Variable<VectorDotNode<T, S>>, ShapeError>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Ok(Variable::new(
+            Rc::new(VectorDotNode::try_new(
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+            )?),
+            merge_parameters(&self.parameters, &other.parameters),
+        ))
+    }
+
+    /// Compute `sqrt(lhs^2 + rhs^2)` element-wise, without overflow
+    /// for large inputs. LHS and RHS must have the same shape.
+    pub fn hypot<S>(&self, other: &Variable<S>) -> Variable<HypotNode<T, S>>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        self.try_hypot(other).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `hypot`, but returns an error if the shapes differ.
+    pub fn try_hypot<S>(&self, other: &Variable<S>) -> Result<Variable<HypotNode<T, S>>, ShapeError>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Ok(Variable::new(
+            Rc::new(HypotNode::try_new(
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+            )?),
+            merge_parameters(&self.parameters, &other.parameters),
+        ))
+    }
+
+    /// Compute the `(n, m)` matrix of cosine similarities between the rows
+    /// of this `(n, d)` variable and the rows of an `(m, d)` variable.
+    pub fn cosine_matrix<S>(&self, other: &Variable<S>) -> Variable<CosineMatrixNode<T, S>>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        self.try_cosine_matrix(other)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `cosine_matrix`, but returns an error if the numbers of
+    /// columns differ.
+    pub fn try_cosine_matrix<S>(
+        &self,
+        other: &Variable<S>,
+    ) -> Result<Variable<CosineMatrixNode<T, S>>, ShapeError>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Ok(Variable::new(
+            Rc::new(CosineMatrixNode::try_new(
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+            )?),
+            merge_parameters(&self.parameters, &other.parameters),
+        ))
+    }
+
+    /// Compute the matrix multiplication of LHS and RHS.
+    pub fn dot<S>(&self, other: &Variable<S>) -> Variable<DotNode<T, S>>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        self.try_dot(other).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `dot`, but returns an error if the number of columns of
+    /// LHS differs from the number of rows of RHS.
+    pub fn try_dot<S>(&self, other: &Variable<S>) -> Result<Variable<DotNode<T, S>>, ShapeError>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Ok(Variable::new(
+            Rc::new(DotNode::try_new(
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+            )?),
+            merge_parameters(&self.parameters, &other.parameters),
+        ))
+    }
+
+    /// Add RHS to LHS element-wise, returning an error if the shapes differ.
+    pub fn try_add<S>(&self, other: &Variable<S>) -> Result<Variable<AddNode<T, S>>, ShapeError>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Ok(Variable::new(
+            Rc::new(AddNode::try_new(
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+            )?),
+            merge_parameters(&self.parameters, &other.parameters),
+        ))
+    }
+
+    /// Subtract RHS from LHS element-wise, returning an error if the
+    /// shapes differ.
+    pub fn try_sub<S>(&self, other: &Variable<S>) -> Result<Variable<SubNode<T, S>>, ShapeError>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Ok(Variable::new(
+            Rc::new(SubNode::try_new(
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+            )?),
+            merge_parameters(&self.parameters, &other.parameters),
+        ))
+    }
+
+    /// Multiply LHS and RHS element-wise, returning an error if the
+    /// shapes differ.
+    pub fn try_mul<S>(&self, other: &Variable<S>) -> Result<Variable<MulNode<T, S>>, ShapeError>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Ok(Variable::new(
+            Rc::new(MulNode::try_new(
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+            )?),
+            merge_parameters(&self.parameters, &other.parameters),
+        ))
+    }
+
+    /// Compute `self * other + addend` element-wise in a single node,
+    /// saving the intermediate product of separate `Mul` and `Add` nodes.
+    pub fn mul_add<S, A>(
+        &self,
+        other: &Variable<S>,
+        addend: &Variable<A>,
+    ) -> Variable<FmaNode<T, S, A>>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+        A: Node<Value = Arr, InputGradient = Arr>,
+    {
+        self.try_mul_add(other, addend)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `mul_add`, but returns an error if the shapes differ.
+    pub fn try_mul_add<S, A>(
+        &self,
+        other: &Variable<S>,
+        addend: &Variable<A>,
+    ) -> Result<Variable<FmaNode<T, S, A>>, ShapeError>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+        A: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Ok(Variable::new(
+            Rc::new(FmaNode::try_new(
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+                Rc::clone(&addend.node),
+            )?),
+            merge_parameters(
+                &merge_parameters(&self.parameters, &other.parameters),
+                &addend.parameters,
+            ),
+        ))
+    }
+
+    /// Divide LHS by RHS element-wise, returning an error if the
+    /// shapes differ.
+    pub fn try_div<S>(&self, other: &Variable<S>) -> Result<Variable<DivNode<T, S>>, ShapeError>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Ok(Variable::new(
+            Rc::new(DivNode::try_new(
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+            )?),
+            merge_parameters(&self.parameters, &other.parameters),
+        ))
+    }
+
+    /// Convolve the rows of this `(time, in_features)` variable with
+    /// `weights`, a `(kernel_size * in_features, out_features)` flattened
+    /// kernel, moving `stride` rows at a time. See `Conv1dNode` for
+    /// the weight layout.
+    pub fn conv1d(
+        &self,
+        weights: &Variable<ParameterNode>,
+        kernel_size: usize,
+        stride: usize,
+        padding: Padding,
+    ) -> Variable<Conv1dNode<T>> {
+        Variable::new(
+            Rc::new(Conv1dNode::new(
+                Rc::clone(&self.node),
+                Rc::clone(&weights.node),
+                kernel_size,
+                stride,
+                padding,
+            )),
+            merge_parameters(&self.parameters, &weights.parameters),
+        )
+    }
+
+    /// Take the maximum of each column over windows of `window_size`
+    /// rows, moving `stride` rows at a time.
+    pub fn max_pool1d(
+        &self,
+        window_size: usize,
+        stride: usize,
+        padding: Padding,
+    ) -> Variable<MaxPool1dNode<T>> {
+        Variable::new(
+            Rc::new(MaxPool1dNode::new(
+                Rc::clone(&self.node),
+                window_size,
+                stride,
+                padding,
+            )),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Average each column over windows of `window_size` rows,
+    /// moving `stride` rows at a time.
+    pub fn avg_pool1d(
+        &self,
+        window_size: usize,
+        stride: usize,
+        padding: Padding,
+    ) -> Variable<AvgPool1dNode<T>> {
+        Variable::new(
+            Rc::new(AvgPool1dNode::new(
+                Rc::clone(&self.node),
+                window_size,
+                stride,
+                padding,
+            )),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Smooth each column down the row (time) axis by leaky integration,
+    /// `y_t = alpha * y_{t-1} + (1 - alpha) * x_t` with `y_{-1} = 0`.
+    ///
+    /// This is a first-order IIR filter: a lightweight, parameter-free
+    /// alternative to a recurrent layer for smoothing signals.
+    pub fn iir(&self, alpha: Float) -> Variable<IirNode<T>> {
+        Variable::new(
+            Rc::new(IirNode::new(Rc::clone(&self.node), alpha)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Stack/concatenate LHS and RHS, either row-wise (`ndarray::Axis(0)`) or
+    /// column-wise (`ndarray::Axis(1)`).
+    pub fn stack<S>(
+        &self,
+        other: &Variable<S>,
+        axis: ndarray::Axis,
+    ) -> Variable<ConcatenateNode<T, S>>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        self.try_stack(other, axis).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `stack`, but returns an error if the operands' lengths
+    /// along the other axis differ.
+    pub fn try_stack<S>(
+        &self,
+        other: &Variable<S>,
+        axis: ndarray::Axis,
+    ) -> Result<Variable<ConcatenateNode<T, S>>, ShapeError>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Ok(Variable::new(
+            Rc::new(ConcatenateNode::try_new(
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+                axis,
+            )?),
+            merge_parameters(&self.parameters, &other.parameters),
+        ))
+    }
+
+    /// Split the columns of this variable at `split_points`, the inverse
+    /// of column-wise `stack`. Splitting a `(n, 6)` variable at `&[2, 4]`
+    /// yields three `(n, 2)` pieces.
+    ///
+    /// The gradients of the pieces are combined before being
+    /// backpropagated into this variable.
+    pub fn split(&self, split_points: &[usize]) -> Vec<Variable<SplitViewNode<T>>> {
+        SplitNode::new(Rc::clone(&self.node), split_points)
+            .into_iter()
+            .map(|piece| Variable::new(Rc::new(piece), self.parameters.clone()))
+            .collect()
+    }
+}
+
+impl Variable<ParameterNode> {
+    /// Return the (dense) gradient value of this node.
+    pub fn dense_gradient(&self) -> Option<Arr> {
+        match self.node.gradient.borrow().dense_gradient {
+            Some(ref gradients) => Some(gradients.clone()),
+            None => None,
+        }
+    }
+
+    /// Return the gradient accumulated by this node, with the rows of
+    /// any sparse gradients added to the dense gradient.
+    ///
+    /// This is only meaningful between a backward pass and the next
+    /// call to `zero_gradient`.
+    pub fn gradient(&self) -> Arr {
+        self.node.gradient.borrow().folded_gradient()
+    }
+
+    /// Clamp the accumulated gradient of this node to `[min, max]`,
+    /// element-wise. Call between the backward pass and the optimizer
+    /// step.
+    pub fn clip_gradient_values(&self, min: Float, max: Float) {
+        self.node.gradient.borrow_mut().clamp(min, max);
+    }
+
+    /// Rescale the accumulated gradient of this node so that its L2 norm,
+    /// including sparse rows, is at most `max_norm`. Returns the norm
+    /// before clipping. Call between the backward pass and the optimizer
+    /// step; see `optim::clip_gradient_norm` to clip several parameters
+    /// by their joint norm.
+    pub fn clip_gradient_norm(&self, max_norm: Float) -> Float {
+        optim::clip_gradient_norm(::std::slice::from_ref(self), max_norm)
+    }
+
+    /// Return the sparse gradients accumulated by this node, as pairs of
+    /// row indices and the gradients of those rows. Sparse gradients
+    /// are produced by `Variable::index`.
+    ///
+    /// This is only meaningful between a backward pass and the next
+    /// call to `zero_gradient`.
+    pub fn sparse_gradient(&self) -> Vec<(Vec<usize>, Arr)> {
+        self.node.gradient.borrow().sparse_entries().to_vec()
+    }
+
+    /// Row-wise indexing of this parameter node. Primiarily used
+    /// to implement embedding layers.
+    pub fn index(&self, index: &Variable<IndexInputNode>) -> Variable<IndexNode<ParameterNode>> {
+        Variable::new(
+            Rc::new(IndexNode::new(
+                Rc::clone(&self.node),
+                Rc::clone(&index.node),
+            )),
+            merge_parameters(&self.parameters, &index.parameters),
+        )
+    }
+}
+
+impl<T> Variable<BiasNode<T>>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    /// Return the bias parameter of this node.
+    pub fn bias(&self) -> Variable<ParameterNode> {
+        let bias = Rc::clone(self.node.bias());
+
+        Variable::new(Rc::clone(&bias), vec![bias])
+    }
+}
+
+impl<T> Variable<HookNode<T>>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    /// Add a gradient hook to this node, to run after the hooks added
+    /// before it. See `register_gradient_hook`.
+    pub fn add_gradient_hook<F>(&self, hook: F)
+    where
+        F: Fn(&mut Arr) + 'static,
+    {
+        self.node.add_hook(hook);
+    }
+}
+
+impl<T> Variable<CategoricalSampleNode<T>>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    /// Return the probabilities the indices were sampled from, as of
+    /// the last forward pass.
+    pub fn probabilities(&self) -> Bor<Arr> {
+        self.node.probabilities()
+    }
+
+    /// Reseed the random number generator, making the samples of the
+    /// following forward passes reproducible.
+    pub fn set_seed(&self, seed: u64) {
+        self.node.set_seed(seed)
+    }
+}
+
+impl<T> Variable<RunningNormNode<T>>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    /// The number of rows seen by the evaluated forward passes.
+    pub fn count(&self) -> usize {
+        self.node.count()
+    }
+
+    /// The running mean of each column, as a `(1, d)` array.
+    pub fn running_mean(&self) -> Arr {
+        self.node.mean()
+    }
+
+    /// The running variance of each column, normalized by the number
+    /// of rows, as a `(1, d)` array.
+    pub fn running_variance(&self) -> Arr {
+        self.node.variance()
+    }
+
+    /// Forget the rows seen so far, as when the stream changes.
+    pub fn reset_statistics(&self) {
+        self.node.reset()
+    }
+}
+
+impl<T> Variable<nn::losses::SparseCategoricalCrossentropyNode<T>>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    /// Return the log-softmax predictions from a sparse categorical
+    /// cross-entropy node.
+    ///
+    /// Calling `.value()` on the node returns the value of the loss;
+    /// this function allows getting the predictins with low overhead.
+    pub fn predictions(&self) -> Bor<Arr> {
+        self.node.predictions()
+    }
+}
+
+impl<T> Variable<nn::losses::FocalLossNode<T>>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    /// Return the log-softmax predictions from a focal loss node.
+    pub fn predictions(&self) -> Bor<Arr> {
+        self.node.predictions()
+    }
+}
+
+impl<'value> DataInput<&'value Arr> for Variable<ParameterNode> {
+    fn set_value(&self, value: &Arr) {
+        let param_value = unsafe { &mut *(self.node.value.deref().value.as_ptr()) };
+        param_value.assign(value)
+    }
+}
+
+impl<'value> DataInput<&'value Arr> for Variable<InputNode> {
+    /// Copy values into the input, as with an `ArrayView2`.
+    fn set_value(&self, value: &Arr) {
+        self.set_value(value.view());
+    }
+}
+
+impl<'value> DataInput<&'value [Float]> for Variable<InputNode> {
+    /// Copy row-major values into the input, which must hold
+    /// exactly as many values.
+    fn set_value(&self, value: &[Float]) {
+        let mut node_value = self.node.value.borrow_mut();
+
+        assert!(
+            node_value.len() == value.len(),
+            "Expected {} values for an input of shape {:?}, got {}.",
+            node_value.len(),
+            node_value.dim(),
+            value.len()
+        );
+
+        node_value.as_slice_mut().unwrap().copy_from_slice(value);
+    }
+}
+
+impl<'value> DataInput<ArrayView2<'value, Float>> for Variable<InputNode> {
+    /// Copy a view into the input, which must have the same number of
+    /// columns. The number of rows may change, as when the batch size
+    /// does: the rest of the graph is resized on the next forward pass.
+    fn set_value(&self, value: ArrayView2<'value, Float>) {
+        let mut node_value = self.node.value.borrow_mut();
+
+        assert!(
+            node_value.cols() == value.cols(),
+            "Expected a view of shape {:?}, got {:?}.",
+            node_value.dim(),
+            value.dim()
+        );
+
+        fit_shape(&mut node_value, value.dim());
+        node_value.assign(&value);
+    }
+}
+
+impl DataInput<Float> for Variable<InputNode> {
+    fn set_value(&self, value: Float) {
+        self.node.value.borrow_mut()[(0, 0)] = value;
+    }
+}
+
+impl<'value> DataInput<&'value [usize]> for Variable<IndexInputNode> {
+    fn set_value(&self, value: &[usize]) {
+        let mut node_value = self.node.value.borrow_mut();
+        node_value.clear();
+        node_value.extend_from_slice(value);
+    }
+}
+
+impl<'value> DataInput<&'value [u32]> for Variable<IndexInputNode> {
+    fn set_value(&self, value: &[u32]) {
+        let mut node_value = self.node.value.borrow_mut();
+        node_value.clear();
+        node_value.extend(value.iter().map(|&idx| idx as usize));
+    }
+}
+
+impl DataInput<Range<usize>> for Variable<IndexInputNode> {
+    fn set_value(&self, value: Range<usize>) {
+        let mut node_value = self.node.value.borrow_mut();
+        node_value.clear();
+        node_value.extend(value);
+    }
+}
+
+impl DataInput<usize> for Variable<IndexInputNode> {
+    fn set_value(&self, value: usize) {
+        let mut node_value = self.node.value.borrow_mut();
+        node_value.clear();
+        node_value.push(value);
+    }
+}
+
+macro_rules! impl_arithmetic_op {
+    ($trait:ident, $fn:ident, $try_fn:ident, $node:ident) => {
+        impl<LHS, RHS> $trait<Variable<RHS>> for Variable<LHS>
+        where
+            RHS: Node<Value = Arr, InputGradient = Arr>,
+            LHS: Node<Value = Arr, InputGradient = Arr>,
+        {
+            type Output = Variable<$node<LHS, RHS>>;
+            fn $fn(self, other: Variable<RHS>) -> Self::Output {
+                self.$try_fn(&other).unwrap_or_else(|error| panic!("{}", error))
+            }
+        }
+
+        /// The constant will be broadcast to have the same shape
+        /// as the LHS.
+        impl<LHS> $trait<Float> for Variable<LHS>
+        where
+            LHS: Node<Value = Arr, InputGradient = Arr>,
+        {
+            type Output = Variable<$node<LHS, InputNode>>;
+            fn $fn(self, other: Float) -> Self::Output {
+                let constant = InputNode::constant(self.value().dim(), other);
+
+                Variable::new(
+                    Rc::new($node::new(self.node, constant.node)),
+                    merge_parameters(&self.parameters, &constant.parameters),
+                )
+            }
+        }
+
+        /// The constant will be broadcast to have the same shape
+        /// as the RHS.
+        impl<RHS> $trait<Variable<RHS>> for Float
+        where
+            RHS: Node<Value = Arr, InputGradient = Arr>,
+        {
+            type Output = Variable<$node<InputNode, RHS>>;
+            fn $fn(self, other: Variable<RHS>) -> Self::Output {
+                let constant = InputNode::constant(other.value().dim(), self);
+
+                Variable::new(
+                    Rc::new($node::new(constant.node, other.node)),
+                    merge_parameters(&constant.parameters, &other.parameters),
+                )
+            }
+        }
+
+        /// Operating on references shares the operands' nodes,
+        /// exactly as operating on clones does.
+        impl<'a, 'b, LHS, RHS> $trait<&'b Variable<RHS>> for &'a Variable<LHS>
+        where
+            RHS: Node<Value = Arr, InputGradient = Arr>,
+            LHS: Node<Value = Arr, InputGradient = Arr>,
+        {
+            type Output = Variable<$node<LHS, RHS>>;
+            fn $fn(self, other: &Variable<RHS>) -> Self::Output {
+                $trait::$fn(self.clone(), other.clone())
+            }
+        }
+
+        impl<'a, LHS, RHS> $trait<Variable<RHS>> for &'a Variable<LHS>
+        where
+            RHS: Node<Value = Arr, InputGradient = Arr>,
+            LHS: Node<Value = Arr, InputGradient = Arr>,
+        {
+            type Output = Variable<$node<LHS, RHS>>;
+            fn $fn(self, other: Variable<RHS>) -> Self::Output {
+                $trait::$fn(self.clone(), other)
+            }
+        }
+
+        impl<'b, LHS, RHS> $trait<&'b Variable<RHS>> for Variable<LHS>
+        where
+            RHS: Node<Value = Arr, InputGradient = Arr>,
+            LHS: Node<Value = Arr, InputGradient = Arr>,
+        {
+            type Output = Variable<$node<LHS, RHS>>;
+            fn $fn(self, other: &Variable<RHS>) -> Self::Output {
+                $trait::$fn(self, other.clone())
+            }
+        }
+
+        impl<'a, LHS> $trait<Float> for &'a Variable<LHS>
+        where
+            LHS: Node<Value = Arr, InputGradient = Arr>,
+        {
+            type Output = Variable<$node<LHS, InputNode>>;
+            fn $fn(self, other: Float) -> Self::Output {
+                $trait::$fn(self.clone(), other)
+            }
+        }
+
+        impl<'b, RHS> $trait<&'b Variable<RHS>> for Float
+        where
+            RHS: Node<Value = Arr, InputGradient = Arr>,
+        {
+            type Output = Variable<$node<InputNode, RHS>>;
+            fn $fn(self, other: &Variable<RHS>) -> Self::Output {
+                $trait::$fn(self, other.clone())
+            }
+        }
+    };
+}
+
+impl_arithmetic_op!(Add, add, try_add, AddNode);
+impl_arithmetic_op!(Sub, sub, try_sub, SubNode);
+impl_arithmetic_op!(Mul, mul, try_mul, MulNode);
+impl_arithmetic_op!(Div, div, try_div, DivNode);
+
+impl<T> Neg for Variable<T>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Output = Variable<NegNode<T>>;
+    fn neg(self) -> Self::Output {
+        Variable::new(Rc::new(NegNode::new(self.node)), self.parameters.clone())
+    }
+}
+
+impl<'a, T> Neg for &'a Variable<T>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Output = Variable<NegNode<T>>;
+    fn neg(self) -> Self::Output {
+        -self.clone()
+    }
+}
+
+/// Compute finite difference gradient estimates of the output variable
+/// with respect to the input. Use to verify correctness of gradient
+/// computations; `testing::check_gradients` additionally reports the
+/// worst mismatch.
+pub fn finite_difference<T>(
+    input: &mut Variable<ParameterNode>,
+    output: &mut Variable<T>,
+) -> (Arr, Arr)
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    testing::gradients(input, output, 0.5e-4)
+}
+
+/// Approximate the Jacobian-vector product `J·v` of the output variable
+/// with respect to the inputs, where each input is paired with its part
+/// of the direction `v`. The result has the shape of the output.
+///
+/// The product is estimated by central finite differences, evaluating the
+/// output with every input moved by `epsilon` times its direction both
+/// ways, so it costs two forward passes regardless of the number of
+/// inputs. No backward pass is run, and gradients accumulated before the
+/// call are kept; the inputs are then restored and the output evaluated
+/// at them again.
+///
+/// The truncation error grows with `epsilon^2` and the curvature of the
+/// output, vanishing for linear (and bilinear) maps, while the rounding
+/// error grows as `epsilon` shrinks: in single precision, do not expect
+/// more than about three significant digits.
+pub fn jvp<T>(
+    inputs: &[(&Variable<ParameterNode>, &Arr)],
+    output: &mut Variable<T>,
+    epsilon: Float,
+) -> Arr
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    let initial_inputs: Vec<Arr> = inputs
+        .iter()
+        .map(|&(input, direction)| {
+            assert_eq!(
+                input.value().dim(),
+                direction.dim(),
+                "Direction must have the shape of the input."
+            );
+            input.value().clone()
+        })
+        .collect();
+
+    // Forward passes are only re-run once the graph is zeroed, which
+    // would also zero the parameter gradients: set them aside meanwhile.
+    let parameters = output.parameters();
+    let gradients: Vec<_> = parameters
+        .iter()
+        .map(|parameter| {
+            let shape = parameter.node.value.shape();
+            parameter
+                .node
+                .gradient
+                .replace(GradientAccumulator::new(shape))
+        })
+        .collect();
+
+    let mut evaluate = |step: Float| {
+        for (&(input, direction), initial_input) in inputs.iter().zip(&initial_inputs) {
+            input.set_value(&(initial_input + &(direction * step)));
+        }
+        output.zero_gradient();
+        output.forward();
+        output.value().clone()
+    };
+
+    let positive = evaluate(epsilon);
+    let negative = evaluate(-epsilon);
+
+    for (&(input, _), initial_input) in inputs.iter().zip(&initial_inputs) {
+        input.set_value(initial_input);
+    }
+    output.zero_gradient();
+    output.forward();
+
+    for (parameter, gradient) in parameters.iter().zip(gradients) {
+        parameter.node.gradient.replace(gradient);
+    }
+
+    (positive - negative) / (2.0 * epsilon)
+}
+
+/// Assert two arrays are within `tol` of each other.
+pub fn assert_close(x: &Arr, y: &Arr, tol: Float) {
+    assert!(
+        x.all_close(y, tol),
+        "{:#?} not within {} of {:#?}",
+        x,
+        tol,
+        y
+    );
+}
+
+#[cfg(test)]
+mod tests {
+
+    use ndarray::{arr2, Axis, Slice};
+
+    use optim::{Adagrad, Optimizer, SGD};
+    use rand::distributions::{Distribution, Uniform};
+    use rand::Rng;
+    use rayon::prelude::*;
+    use std::cell::Cell;
+    use std::sync::Arc;
+
+    use super::*;
+    use testing::check_gradients;
+
+    const EPSILON: Float = 0.5e-4;
+    #[cfg(not(wyrm_f64))]
+    const TOLERANCE: Float = 0.05;
+    #[cfg(wyrm_f64)]
+    const TOLERANCE: Float = 1e-5;
+
+    fn random_matrix(rows: usize, cols: usize) -> Arr {
+        nn::xavier_normal(rows, cols)
+    }
+
+    fn random_index(rows: usize) -> usize {
+        Uniform::new(0, rows).sample(&mut rand::thread_rng())
+    }
+
+    #[test]
+    fn test_constant_sub() {
+        let x = ParameterNode::new(Arr::zeros((10, 10)) + 1.0);
+        let mut y = (1.0 - x.clone()) * 2.0;
+
+        assert_eq!(y.value().scalar_sum(), 0.0);
+        y.zero_gradient();
+        y.forward();
+        y.backward(1.0);
+        assert_eq!(y.value().scalar_sum(), 0.0);
+
+        check_gradients(&mut y, &x, EPSILON, TOLERANCE).unwrap();
+    }
+
+    #[test]
+    fn parameter_deduplication() {
+        let x = ParameterNode::new(random_matrix(1, 1));
+        let y = ParameterNode::new(random_matrix(1, 1));
+
+        let z = x + y;
+        let z = z.clone() + z.clone();
+
+        assert_eq!(z.parameters().len(), 2);
+    }
+
+    #[test]
+    fn add_finite_difference() {
+        let x = ParameterNode::new(random_matrix(1, 1));
+        let y = ParameterNode::new(random_matrix(1, 1));
+        let mut z = x.clone() + y.clone() + x.clone() + x.clone();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+        check_gradients(&mut z, &y, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn sub_finite_difference() {
+        let x = ParameterNode::new(random_matrix(1, 1));
+        let y = ParameterNode::new(random_matrix(1, 1));
+        let z = x.clone() - (y.clone() - x.clone());
+        let mut z = z.clone() * 2.0 + z.clone().sigmoid();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+        check_gradients(&mut z, &y, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn mul_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 10));
+        let y = ParameterNode::new(random_matrix(10, 10));
+        let z = x.clone() * y.clone();
+        let mut z = z.clone() + z.clone();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+        check_gradients(&mut z, &y, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn mul_add_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let y = ParameterNode::new(random_matrix(10, 5));
+        let c = ParameterNode::new(random_matrix(10, 5));
+        let z = x.mul_add(&y, &c);
+        let mut z = z.clone() + z.clone();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+        check_gradients(&mut z, &y, EPSILON, TOLERANCE).unwrap();
+        check_gradients(&mut z, &c, EPSILON, TOLERANCE).unwrap();
+
+        // Matches the composed nodes, with one value buffer fewer.
+        let mut fused = x.mul_add(&y, &c).scalar_sum();
+        let mut composed = (x.clone() * y.clone() + c.clone()).scalar_sum();
+
+        fused.forward();
+        composed.forward();
+        assert_close(&fused.value(), &composed.value(), 1e-5);
+
+        fused.backward(1.0);
+        let gradients: Vec<_> = fused.parameters().iter().map(|p| p.gradient()).collect();
+        fused.zero_gradient();
+        composed.backward(1.0);
+        for (fused, composed) in gradients.iter().zip(composed.parameters()) {
+            assert_close(fused, &composed.gradient(), 1e-5);
+        }
+
+        assert_eq!(
+            composed.summary().value_bytes - fused.summary().value_bytes,
+            10 * 5 * ::std::mem::size_of::<Float>()
+        );
+    }
+    #[test]
+    fn div_finite_difference() {
+        let x = ParameterNode::new(random_matrix(1, 1));
+        let y = ParameterNode::new(random_matrix(1, 1));
+        let mut z = (x.clone() + x.clone()) / y.clone();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn vector_dot_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let y = ParameterNode::new(random_matrix(10, 5));
+        let z = x.vector_dot(&y);
+        let mut z = z.clone() + z.clone();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        check_gradients(&mut z, &y, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn weighted_sq_norm_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let w = ParameterNode::new(random_matrix(1, 5));
+        let z = x.weighted_sq_norm(&w);
+        let mut z = z.clone() + z.clone();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+        check_gradients(&mut z, &w, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn weighted_sq_norm_with_unit_weights() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let w = ParameterNode::new(Arr::ones((1, 5)));
+
+        let mut weighted = x.weighted_sq_norm(&w);
+        weighted.forward();
+        weighted.backward(1.0);
+        let weighted_gradient = x.dense_gradient().unwrap();
+        weighted.zero_gradient();
+
+        let mut plain = x.vector_dot(&x);
+        plain.forward();
+        plain.backward(1.0);
+
+        assert_close(weighted.value().deref(), plain.value().deref(), 1e-5);
+        assert_close(&weighted_gradient, &x.dense_gradient().unwrap(), 1e-5);
+    }
+    #[test]
+    fn dot_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let y = ParameterNode::new(random_matrix(5, 10));
+        let mut z = (x.clone() + x.clone()).dot(&y);
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        check_gradients(&mut z, &y, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn dot_gradients_match_transposed_products() {
+        let x = ParameterNode::new(random_matrix(12, 9));
+        let y = ParameterNode::new(random_matrix(9, 10));
+        let gradient = random_matrix(12, 10);
+        let mut z = (x.dot(&y) * InputNode::new(gradient.clone())).scalar_sum();
+
+        z.forward();
+        z.backward(1.0);
+
+        let mut x_gradient = Arr::zeros((12, 9));
+        let mut y_gradient = Arr::zeros((9, 10));
+        numerics::mat_mul(1.0, &gradient, &y.value().t(), 0.0, &mut x_gradient);
+        numerics::mat_mul(1.0, &x.value().t(), &gradient, 0.0, &mut y_gradient);
+
+        assert_eq!(x.dense_gradient().unwrap(), x_gradient);
+        assert_eq!(y.dense_gradient().unwrap(), y_gradient);
+    }
+    #[test]
+    fn dot_accumulation_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let y = ParameterNode::new(random_matrix(5, 10));
+        let z = x.clone().dot(&y);
+        let mut v = z.clone() * z.clone();
+
+        check_gradients(&mut v, &x, EPSILON, TOLERANCE).unwrap();
+
+        check_gradients(&mut v, &y, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn square_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = x.square();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn ln_finite_difference() {
+        let x = ParameterNode::new(random_matrix(2, 2));
+        let mut z = (x.clone() + x.clone()).exp().ln();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn tanh_finite_difference() {
+        let x = ParameterNode::new(random_matrix(2, 2));
+        let mut z = (x.clone() + x.clone()).tanh();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn tanh_shrink_finite_difference() {
+        let x = ParameterNode::new(random_matrix(3, 4).map(|x| 2.0 * x));
+        let mut z = x.tanh_shrink() * 3.0;
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        // The local derivative is tanh(x)^2.
+        let mut z = x.tanh_shrink().scalar_sum();
+        z.forward();
+        z.backward(1.0);
+
+        assert_close(&x.tanh_shrink().value(), &x.value().map(|x| x - x.tanh()), 1e-5);
+        assert_close(&x.gradient(), &x.value().map(|x| x.tanh().powi(2)), 1e-5);
+
+        // Small inputs keep their relative precision.
+        let xs = [1e-4, -1e-3, 0.01, 0.1, -0.3, 0.49, 0.51, -1.0, 4.0];
+        let x = InputNode::new(Arr::from_shape_vec((1, xs.len()), xs.to_vec()).unwrap());
+        for (&x, &y) in xs.iter().zip(x.tanh_shrink().value().iter()) {
+            let expected = x as f64 - (x as f64).tanh();
+            let error = ((y as f64 - expected) / expected).abs();
+            assert!(error < 1e-5, "{}: {}", x, y);
+        }
+    }
+    #[test]
+    fn sum_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).scalar_sum();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE * 2.0).unwrap();
+    }
+    #[test]
+    fn squared_sum_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = x.square().scalar_sum();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn transpose_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).t();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn exp_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).exp();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn erf_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).erf();
+
+        // The forward pass approximates erf to within 1.5e-7, and the
+        // slope of that error puts its finite differences up to about
+        // 2e-5 away from the exact derivative: within the single but not
+        // the double precision tolerance.
+        let tolerance = if cfg!(wyrm_f64) { 1e-4 } else { TOLERANCE };
+        check_gradients(&mut z, &x, EPSILON, tolerance).unwrap();
+    }
+    #[test]
+    fn geglu_finite_difference() {
+        let x = ParameterNode::new(random_matrix(5, 6));
+        let mut z = (x.clone() + x.clone()).geglu();
+
+        assert_eq!(z.value().dim(), (5, 3));
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn geglu_value() {
+        let x = ParameterNode::new(arr2(&[[0.0, 1.0, 2.0, 3.0], [-1.0, 2.0, 0.5, -2.0]]));
+        let z = x.geglu();
+        z.forward();
+
+        let gelu = |x: Float| 0.5 * x * (1.0 + (0.797_884_6 * (x + 0.044_715 * x.powi(3))).tanh());
+        let expected = arr2(&[
+            [gelu(0.0) * 2.0, gelu(1.0) * 3.0],
+            [gelu(-1.0) * 0.5, gelu(2.0) * -2.0],
+        ]);
+        assert_close(z.value().deref(), &expected, 1e-5);
+    }
+    #[test]
+    #[should_panic(expected = "GeGLU requires an even number of columns, got 3.")]
+    fn geglu_odd_columns() {
+        ParameterNode::new(random_matrix(2, 3)).geglu();
+    }
+    #[test]
+    fn exp_base_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).exp_base(2.0);
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn pseudo_huber_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).pseudo_huber(0.5);
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        // Quadratic near zero, linear far from it.
+        let x = InputNode::new(arr2(&[[0.0, 1e-3, 100.0, -100.0]]));
+        let value = x.pseudo_huber(1.0).value().clone();
+        assert_close(&value, &arr2(&[[0.0, 0.5e-6, 99.005, 99.005]]), 1e-3);
+    }
+    #[test]
+    fn soft_threshold_finite_difference() {
+        let x = ParameterNode::new(arr2(&[
+            [-2.0, -0.6, -0.4, 0.0],
+            [0.4, 0.6, 2.0, 0.1],
+        ]));
+        let mut z = x.soft_threshold(0.5) * 2.0;
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        // No gradient at the breakpoints themselves.
+        let x = ParameterNode::new(arr2(&[[-0.5, 0.5, 0.75]]));
+        let mut z = x.soft_threshold(0.5).scalar_sum();
+        z.forward();
+        z.backward(1.0);
+
+        assert_close(&x.soft_threshold(0.5).value(), &arr2(&[[0.0, 0.0, 0.25]]), 1e-6);
+        assert_eq!(x.gradient(), arr2(&[[0.0, 0.0, 1.0]]));
+    }
+    #[test]
+    fn min_scalar_ste_passes_gradient_through() {
+        let x = ParameterNode::new(arr2(&[[-1.0, 0.5, 2.0, 3.0]]));
+        let weights = InputNode::new(arr2(&[[1.0, 2.0, 3.0, 4.0]]));
+
+        let mut ste = (x.min_scalar_ste(1.0) * weights.clone()).scalar_sum();
+        ste.forward();
+        ste.backward(1.0);
+        let ste_gradient = x.gradient();
+        ste.zero_gradient();
+
+        // A masking minimum agrees in the forward pass, but the clipped
+        // values get no gradient.
+        let masking = x.map("Min", |v| v.min(1.0), |v| if v < 1.0 { 1.0 } else { 0.0 });
+        let mut masked = (masking * weights.clone()).scalar_sum();
+        masked.forward();
+        masked.backward(1.0);
+
+        assert_eq!(x.min_scalar_ste(1.0).value().deref(), &arr2(&[[-1.0, 0.5, 1.0, 1.0]]));
+        assert_eq!(ste.value().deref(), masked.value().deref());
+        assert_eq!(ste_gradient, arr2(&[[1.0, 2.0, 3.0, 4.0]]));
+        assert_eq!(x.gradient(), arr2(&[[1.0, 2.0, 0.0, 0.0]]));
+    }
+    #[test]
+    fn map_finite_difference() {
+        // A custom activation: the bipolar sigmoid `2 / (1 + exp(-x)) - 1`.
+        let bipolar = |x: Float| 2.0 / (1.0 + (-x).exp()) - 1.0;
+        let x = ParameterNode::new(random_matrix(3, 4));
+        let mut z = x
+            .map("BipolarSigmoid", bipolar, move |x| 0.5 * (1.0 - bipolar(x).powi(2)))
+            .square();
+
+        assert_close(z.value().deref(), &x.value().map(|&x| bipolar(x).powi(2)), 1e-6);
+        assert!(format!("{:?}", z).contains("BipolarSigmoid"));
+        assert!(z.summary().nodes.iter().any(|node| node.op == "BipolarSigmoid"));
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn one_hot_argmax_modes() {
+        let scores = arr2(&[[0.5, 2.0, -1.0], [3.0, 3.0, 1.0]]);
+        let upstream = InputNode::new(arr2(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]));
+
+        let x = ParameterNode::new(scores.clone());
+        let mut z = (x.one_hot_argmax(ArgmaxGradient::StraightThrough) * upstream.clone())
+            .scalar_sum();
+
+        z.forward();
+        // Ties go to the first maximum.
+        assert_eq!(z.to_scalar(), 2.0 + 4.0);
+
+        // Only the selected scores receive their gradient.
+        z.backward(1.0);
+        assert_eq!(x.gradient(), arr2(&[[0.0, 2.0, 0.0], [4.0, 0.0, 0.0]]));
+        z.zero_gradient();
+
+        let selection = x.one_hot_argmax(ArgmaxGradient::Blocked);
+        assert!(selection.parameters().is_empty());
+        assert_eq!(*selection.value(), arr2(&[[0.0, 1.0, 0.0], [1.0, 0.0, 0.0]]));
+
+        let mut z = (x.one_hot_argmax(ArgmaxGradient::Blocked) + x.clone()).scalar_sum();
+        z.forward();
+        z.backward(1.0);
+        assert_eq!(x.gradient(), Arr::ones((2, 3)));
+    }
+    #[test]
+    fn subtract_row_max_finite_difference() {
+        let x = ParameterNode::new(arr2(&[[0.3, -1.2, 0.8], [2.0, 0.5, -0.4]]));
+        let weights = InputNode::new(arr2(&[[1.0, 2.0, 3.0], [-1.0, 0.5, 2.0]]));
+        let mut z = (x.subtract_row_max() * weights.clone()).scalar_sum();
+
+        assert_close(
+            x.subtract_row_max().value().deref(),
+            &arr2(&[[-0.5, -2.0, 0.0], [0.0, -1.5, -2.4]]),
+            1e-6,
+        );
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        // The row maxima also receive minus the row's total gradient.
+        z.forward();
+        z.backward(1.0);
+        assert_close(
+            &x.gradient(),
+            &arr2(&[[1.0, 2.0, -3.0], [-2.5, 0.5, 2.0]]),
+            1e-6,
+        );
+    }
+    #[test]
+    fn convex_combine_finite_difference() {
+        let a = ParameterNode::new(random_matrix(4, 3));
+        let b = ParameterNode::new(random_matrix(4, 3));
+        let alpha = ParameterNode::new(arr2(&[[0.3]]));
+        let weights = InputNode::new(random_matrix(4, 3));
+        let mut z = (a.clone() * a.clone()).convex_combine(&b.sigmoid(), &alpha) * weights;
+
+        assert_eq!(z.parameters().len(), 3);
+        check_gradients(&mut z, &a, EPSILON, TOLERANCE).unwrap();
+        check_gradients(&mut z, &b, EPSILON, TOLERANCE).unwrap();
+        check_gradients(&mut z, &alpha, EPSILON, TOLERANCE).unwrap();
+
+        // An alpha of zero mixes the operands equally.
+        let alpha = ParameterNode::new(arr2(&[[0.0]]));
+        let z = InputNode::new(arr2(&[[1.0, 2.0]])).convex_combine(
+            &InputNode::new(arr2(&[[3.0, 0.0]])),
+            &alpha,
+        );
+        assert_close(z.value().deref(), &arr2(&[[2.0, 1.0]]), 1e-6);
+    }
+    #[test]
+    fn display_values() {
+        let x = InputNode::new(arr2(&[[1.0, -2.0, 3.0], [0.5, 4.0, 5.5]]));
+        assert_eq!(
+            format!("{}", x),
+            "Arr(2, 3) { min: -2, max: 5.5, mean: 2, values: [1, -2, 3, 0.5, 4, 5.5] }"
+        );
+
+        let y = x.dot(&InputNode::new(Arr::ones((3, 4)))) * 0.5;
+        y.forward();
+        assert_eq!(
+            format!("{}", y),
+            "Arr(2, 4) { min: 1, max: 5, mean: 3, values: [1, 1, 1, 1, 5, 5, ...] }"
+        );
+        assert_eq!(y.value_clone(), y.value().clone());
+
+        let empty = InputNode::new(Arr::zeros((0, 3)));
+        assert_eq!(format!("{}", empty), "Arr(0, 3) []");
+
+        let index = IndexInputNode::new(&[0, 3, 2]);
+        assert_eq!(format!("{}", index), "Indices [0, 3, 2]");
+    }
+    #[test]
+    fn forward_to_array_outputs() {
+        let x = InputNode::new(random_matrix(2, 3));
+        let w = ParameterNode::new(random_matrix(3, 4));
+        let y = x.dot(&w).tanh();
+
+        let mut outputs = Vec::new();
+
+        for _ in 0..3 {
+            x.set_value(&random_matrix(2, 3));
+            outputs.push(y.forward_to_array());
+            assert_eq!(y.forward_to_array(), outputs[outputs.len() - 1]);
+            y.zero_gradient();
+        }
+
+        assert_eq!(outputs.len(), 3);
+        assert!(outputs[0] != outputs[1]);
+        assert!(outputs[1] != outputs[2]);
+
+        for output in &outputs {
+            assert_eq!(output.dim(), (2, 4));
+        }
+    }
+    #[test]
+    fn changing_batch_size() {
+        let w = ParameterNode::new(random_matrix(4, 8));
+        let v = ParameterNode::new(random_matrix(8, 2));
+        let build = |x: &Variable<InputNode>, y: &Variable<InputNode>| {
+            let hidden = x.dot(&w).tanh();
+            let output = (1.0 - hidden.dot(&v).sigmoid()) * 0.5;
+
+            (output - y.clone()).square().scalar_sum()
+        };
+
+        let x = InputNode::new(random_matrix(32, 4));
+        let y = InputNode::new(random_matrix(32, 2));
+        let mut loss = build(&x, &y);
+
+        loss.forward();
+        loss.backward(1.0);
+        loss.zero_gradient();
+
+        // A smaller final batch runs through the same graph.
+        let (x_value, y_value) = (random_matrix(7, 4), random_matrix(7, 2));
+        x.set_value(&x_value);
+        y.set_value(&y_value);
+
+        loss.forward();
+        loss.backward(1.0);
+        let value = loss.value().clone();
+        let gradients = (w.gradient(), v.gradient());
+        loss.zero_gradient();
+
+        let mut expected = build(&InputNode::new(x_value), &InputNode::new(y_value));
+        expected.forward();
+        expected.backward(1.0);
+
+        assert_close(&value, expected.value().deref(), 1e-5);
+        assert_close(&gradients.0, &w.gradient(), 1e-5);
+        assert_close(&gradients.1, &v.gradient(), 1e-5);
+        expected.zero_gradient();
+
+        check_gradients(&mut loss, &w, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn exp_base_e_is_exp() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let mut exp = x.exp().scalar_sum();
+        let mut exp_base = x.exp_base(::std::f64::consts::E as Float).scalar_sum();
+
+        exp.forward();
+        exp.backward(1.0);
+        let exp_gradient = x.dense_gradient().unwrap();
+        exp.zero_gradient();
+
+        exp_base.forward();
+        exp_base.backward(1.0);
+
+        assert_close(exp_base.value().deref(), exp.value().deref(), 1e-4);
+        assert_close(&x.dense_gradient().unwrap(), &exp_gradient, 1e-4);
+
+        let y = x.exp_base(2.0);
+        y.forward();
+        assert_close(y.value().deref(), &x.value().map(|x| x.exp2()), 1e-4);
+    }
+    #[test]
+    fn dot_square_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let y = ParameterNode::new(random_matrix(10, 5));
+        let mut z = x.vector_dot(&y).square();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn sigmoid_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let z = (x.clone() + x.clone()).sigmoid();
+        let mut z = z.clone() + z.clone();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn bounded_sigmoid_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5) * 10.0);
+        let mut z = x.bounded_sigmoid(-3.0, 5.0);
+
+        assert!(z.value().iter().all(|&x| -3.0 <= x && x <= 5.0));
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        // Gradients are those of the sigmoid, scaled by the range.
+        let mut sigmoid = x.sigmoid().scalar_sum();
+        sigmoid.forward();
+        sigmoid.backward(1.0);
+        let sigmoid_gradient = x.gradient();
+        sigmoid.zero_gradient();
+
+        let mut bounded = x.bounded_sigmoid(-3.0, 5.0).scalar_sum();
+        bounded.forward();
+        bounded.backward(1.0);
+        assert_close(&x.gradient(), &(sigmoid_gradient * 8.0), 1e-5);
+    }
+    #[test]
+    fn bump_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5) * 5.0);
+        let mut z = x.bump(-1.0, 2.0);
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        let mut composed = (x.clone() + 1.0).sigmoid() - (x.clone() - 2.0).sigmoid();
+        composed.forward();
+        z.forward();
+        assert_close(&z.value(), &composed.value(), 1e-6);
+        assert!(z.value().iter().all(|&x| 0.0 < x && x < 1.0));
+    }
+    #[test]
+    fn relu_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let z = (x.clone() + x.clone()).relu();
+        let mut z = z * 3.0;
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn softsign_finite_difference() {
+        let x = ParameterNode::new(arr2(&[[-2.0, -0.5, -0.01], [0.01, 0.5, 2.0]]));
+        let z = (x.clone() + x.clone()).softsign();
+        let mut z = z * 3.0;
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn selu_finite_difference() {
+        // Away from the kink at zero, where the slope jumps.
+        let x = random_matrix(4, 5).map(|&x| x.signum() * (0.1 + 3.0 * x.abs()));
+        let x = ParameterNode::new(x);
+        let mut z = x.selu() * 2.0;
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        // The constants of self-normalizing networks: the positive slope
+        // is `scale` and the negative saturation is `-scale * alpha`.
+        let x = InputNode::new(arr2(&[[1.0, -30.0]]));
+        let z = x.selu();
+        z.forward();
+        assert!((z.value()[(0, 0)] - 1.050_701).abs() < 1e-6);
+        assert!((z.value()[(0, 1)] + 1.758_099_3).abs() < 1e-6);
+    }
+    #[test]
+    fn hard_swish_finite_difference() {
+        let x = ParameterNode::new(arr2(&[
+            [-4.0, -3.1, -2.9, -1.0],
+            [0.5, 2.9, 3.1, 4.0],
+        ]));
+        let mut z = x.hard_swish() * 2.0;
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        z.forward();
+        z.backward(1.0);
+        let gradient = x.gradient();
+
+        assert_eq!(gradient[(0, 0)], 0.0);
+        assert_eq!(gradient[(1, 3)], 2.0);
+    }
+    #[test]
+    fn neg_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = -(x.clone() + x.clone());
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn softmax_finite_difference() {
+        let x = ParameterNode::new(random_matrix(1, 10));
+        let mut z = (x.clone() + x.clone()).softmax();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn softmax_jacobian_gradient() {
+        let x = ParameterNode::new(random_matrix(1, 50));
+        let y = InputNode::new(random_matrix(1, 50));
+        let w = InputNode::new(random_matrix(1, 50));
+        let s = x.softmax();
+        let mut z = (s.clone() * y.clone() + s.clone() * w.clone()).scalar_sum();
+
+        z.forward();
+        z.backward(1.0);
+
+        // The gradient through the materialized Jacobian.
+        let value = s.value().clone();
+        let jacobian = Arr::from_shape_fn((50, 50), |(row, col)| {
+            let diagonal = if row == col { value[(0, row)] } else { 0.0 };
+            diagonal - value[(0, row)] * value[(0, col)]
+        });
+        let expected = (&*y.value() + &*w.value()).dot(&jacobian);
+
+        assert_close(&x.gradient(), &expected, 1e-6);
+    }
+    #[test]
+    fn softmax_multiple_consumers_finite_difference() {
+        let x = ParameterNode::new(random_matrix(1, 10));
+        let y = InputNode::new(random_matrix(1, 10));
+        let s = x.softmax();
+        let mut z = s.clone() * y.clone() + s.clone().sigmoid();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        // Gradients through two branches must match those through
+        // a single branch computing the same function.
+        let w = InputNode::new(random_matrix(1, 10));
+        let mut z = s.clone() * y.clone() + s.clone() * w.clone();
+        z.forward();
+        z.backward(1.0);
+
+        let x_single = ParameterNode::new(x.value().clone());
+        let mut z_single = x_single.softmax() * (y + w);
+        z_single.forward();
+        z_single.backward(1.0);
+
+        assert_close(
+            &x.dense_gradient().unwrap(),
+            &x_single.dense_gradient().unwrap(),
+            1e-6,
+        );
+    }
+    #[test]
+    fn categorical_sample_frequencies() {
+        let probabilities: Arr = arr2(&[[0.1, 0.2, 0.7], [0.5, 0.25, 0.25]]);
+        let rows = 4000;
+        let logits = InputNode::new(Arr::from_shape_fn((rows, 3), |(row, col)| {
+            probabilities[(row % 2, col)].ln()
+        }));
+
+        let sample = logits.sample_categorical();
+        assert!(!sample.needs_gradient());
+        assert!(sample.parameters().is_empty());
+
+        sample.set_seed(42);
+        sample.forward();
+        let indices = sample.value_clone();
+        assert_close(
+            &sample.probabilities().select(Axis(0), &[0, 1]),
+            &probabilities,
+            1e-5,
+        );
+
+        let mut counts = Arr::zeros((2, 3));
+        for (row, &idx) in indices.iter().enumerate() {
+            counts[(row % 2, idx)] += 2.0 / rows as Float;
+        }
+        assert_close(&counts, &probabilities, 0.03);
+
+        // The same seed gives the same samples.
+        sample.zero_gradient();
+        sample.set_seed(42);
+        sample.forward();
+        assert_eq!(sample.value_clone(), indices);
+    }
+    #[test]
+    fn log_prob_finite_difference() {
+        let x = ParameterNode::new(random_matrix(4, 5));
+        let chosen = IndexInputNode::new(&[3, 0, 4, 3]);
+        let mut z = (x.clone() * 2.0).log_prob(&chosen);
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        let log_softmax = (x.clone() * 2.0).log_softmax_rows();
+        log_softmax.forward();
+        z.zero_gradient();
+        z.forward();
+        for (row, &idx) in [3, 0, 4, 3].iter().enumerate() {
+            assert_eq!(z.value()[(row, 0)], log_softmax.value()[(row, idx)]);
+        }
+
+        // Ascending the log-probability raises the chosen logits and
+        // lowers the others.
+        z.backward(1.0);
+        let gradient = x.dense_gradient().unwrap();
+        for (row, &idx) in [3, 0, 4, 3].iter().enumerate() {
+            for col in 0..5 {
+                assert_eq!(gradient[(row, col)] > 0.0, col == idx);
+            }
+        }
+    }
+    #[test]
+    fn log_softmax_finite_difference() {
+        let x = ParameterNode::new(random_matrix(1, 10));
+        let mut z = (x.clone() + x.clone()).log_softmax();
+        let v = (x.clone() + x.clone()).softmax().ln();
+
+        assert_close(v.value().deref(), z.value().deref(), TOLERANCE);
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn log_softmax_rows_finite_difference() {
+        let x = ParameterNode::new(random_matrix(4, 10));
+        let weights = InputNode::new(random_matrix(4, 10));
+        let mut z = (x.clone() + x.clone()).log_softmax_rows() * weights;
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn logit_adjusted_softmax_finite_difference() {
+        let x = ParameterNode::new(random_matrix(4, 5));
+        let weights = InputNode::new(random_matrix(4, 5));
+        let log_prior = InputNode::new(arr2(&[[-0.1, -2.0, -0.5, -3.0, -1.0]]));
+        let mut z = (x.clone() * 2.0).logit_adjusted_softmax(&log_prior) * weights;
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        // Rarer classes get less probability for the same logits.
+        let uniform = InputNode::new(Arr::zeros((4, 5)));
+        let probabilities = uniform.logit_adjusted_softmax(&log_prior);
+        assert!(probabilities.value().row(0)[1] < probabilities.value().row(0)[0]);
+        let sums = probabilities.value().sum_axis(Axis(1)).insert_axis(Axis(1));
+        assert_close(&sums, &Arr::ones((4, 1)), 1e-5);
+    }
+    #[test]
+    #[should_panic(expected = "The log-prior must be a row with one entry per class.")]
+    fn logit_adjusted_softmax_prior_shape() {
+        let x = InputNode::new(random_matrix(4, 3));
+        let log_prior = InputNode::new(Arr::zeros((1, 4)));
+        let z = x.t().logit_adjusted_softmax(&log_prior);
+
+        // A fifth row of `x` is a fifth class, which would get no prior.
+        x.set_value(&random_matrix(5, 3));
+        z.forward();
+    }
+    #[test]
+    fn logit_adjusted_softmax_zero_prior() {
+        let x = ParameterNode::new(random_matrix(3, 4));
+        let weights = InputNode::new(random_matrix(3, 4));
+        let log_prior = InputNode::new(Arr::zeros((1, 4)));
+
+        let mut adjusted = (x.logit_adjusted_softmax(&log_prior) * weights.clone()).scalar_sum();
+        adjusted.forward();
+        adjusted.backward(1.0);
+        let adjusted_gradient = x.gradient();
+        adjusted.zero_gradient();
+
+        let mut plain = (x.log_softmax_rows().exp() * weights).scalar_sum();
+        plain.forward();
+        plain.backward(1.0);
+
+        assert_close(
+            &x.logit_adjusted_softmax(&log_prior).value(),
+            &x.log_softmax_rows().exp().value(),
+            1e-5,
+        );
+        assert_close(&adjusted_gradient, &x.gradient(), 1e-5);
+    }
+    #[test]
+    fn log_softmax_rows_matches_single_rows() {
+        let logits = random_matrix(4, 10).map(|x| x * 20.0);
+        let weights = random_matrix(4, 10);
+
+        let x = ParameterNode::new(logits.clone());
+        let mut z = (x.log_softmax_rows() * InputNode::new(weights.clone())).scalar_sum();
+        z.forward();
+        z.backward(1.0);
+
+        let value = x.log_softmax_rows();
+        value.forward();
+        let gradient = x.dense_gradient().unwrap();
+
+        for row in 0..logits.rows() {
+            let x_row = ParameterNode::new(logits.select(Axis(0), &[row]));
+            let weights_row = InputNode::new(weights.select(Axis(0), &[row]));
+            let mut z_row = (x_row.log_softmax() * weights_row).scalar_sum();
+            z_row.forward();
+            z_row.backward(1.0);
+
+            let value_row = x_row.log_softmax();
+            value_row.forward();
+
+            assert_close(
+                &value.value().select(Axis(0), &[row]),
+                value_row.value().deref(),
+                1e-5,
+            );
+            assert_close(
+                &gradient.select(Axis(0), &[row]),
+                &x_row.dense_gradient().unwrap(),
+                1e-5,
+            );
+        }
+    }
+    #[test]
+    fn ordered_sum_is_reproducible() {
+        for &reduction in &[Reduction::Sequential, Reduction::Pairwise] {
+            let values = random_matrix(37, 29).map(|x| x * 1e4);
+            let sum = || {
+                let x = InputNode::new(values.clone());
+                let y = x.scalar_sum_ordered(reduction);
+                y.forward();
+                y.to_scalar()
+            };
+
+            assert_eq!(sum().to_bits(), sum().to_bits());
+        }
+
+        // Pairwise summation accumulates less rounding error.
+        let x = InputNode::new(Arr::zeros((1000, 100)).map(|_| 0.1 as Float));
+        let exact = f64::from(0.1 as Float) * 1e5;
+        let error = |reduction| {
+            let sum = x.scalar_sum_ordered(reduction);
+            sum.forward();
+            (f64::from(sum.to_scalar()) - exact).abs()
+        };
+
+        assert!(error(Reduction::Pairwise) < error(Reduction::Sequential));
+    }
+
+    #[test]
+    fn tied_parameters_updated_once() {
+        let x = ParameterNode::new(arr2(&[[1.0, -2.0], [0.5, 3.0]]));
+        let y = ParameterNode::new(arr2(&[[1.0, 1.0], [1.0, 1.0]]));
+        let mut loss = (x.clone() * x.clone() + y.clone() + x.clone()).scalar_sum();
+
+        assert_eq!(loss.parameters().len(), 2);
+        assert!(Rc::ptr_eq(&loss.parameters()[0].node, &x.node));
+
+        // Listing the parameter twice must not double the update.
+        let mut parameters = loss.parameters();
+        parameters.push(x.clone());
+        let optimizer = SGD::new(parameters).learning_rate(0.1);
+
+        loss.forward();
+        loss.backward(1.0);
+        optimizer.step();
+        loss.zero_gradient();
+
+        // d/dx (x^2 + x) = 2x + 1, so x <- x - 0.1 * (2x + 1).
+        assert_close(
+            x.value().deref(),
+            &arr2(&[[0.7, -1.7], [0.3, 2.3]]),
+            1e-6,
+        );
+        assert_close(y.value().deref(), &arr2(&[[0.9, 0.9], [0.9, 0.9]]), 1e-6);
+    }
+
+    #[test]
+    fn row_kl_matrix_finite_difference() {
+        let x = ParameterNode::new(arr2(&[[0.2, 0.3, 0.5], [0.6, 0.1, 0.3]]));
+        let weights = InputNode::new(random_matrix(2, 2));
+        let mut z = x.row_kl_matrix() * weights;
+
+        let kl = |p: &[Float], q: &[Float]| {
+            p.iter()
+                .zip(q)
+                .map(|(&p, &q)| p * (p / q).ln())
+                .sum::<Float>()
+        };
+        let p = [0.2, 0.3, 0.5];
+        let q = [0.6, 0.1, 0.3];
+        let expected = arr2(&[[0.0, kl(&p, &q)], [kl(&q, &p), 0.0]]);
+        assert_close(&x.row_kl_matrix().value(), &expected, TOLERANCE);
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+
+    #[test]
+    fn row_kl_matrix_zero_probability() {
+        let x = ParameterNode::new(arr2(&[[0.0, 0.5, 0.5], [0.5, 0.5, 0.0]]));
+        let mut z = x.row_kl_matrix().scalar_sum();
+
+        z.forward();
+        z.backward(1.0);
+
+        assert!(z.value().iter().all(|x| x.is_finite()));
+        assert!(x.gradient().iter().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn iir_finite_difference() {
+        let x = ParameterNode::new(random_matrix(6, 3));
+        let weights = InputNode::new(random_matrix(6, 3));
+        let mut z = (x.clone() + x.clone()).iir(0.7) * weights;
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        // A unit impulse decays geometrically.
+        let impulse = InputNode::new(arr2(&[[1.0], [0.0], [0.0]]));
+        assert_close(
+            impulse.iir(0.5).value().deref(),
+            &arr2(&[[0.5], [0.25], [0.125]]),
+            TOLERANCE,
+        );
+
+        // Only the last output depends on the last input, while the
+        // gradient of the first input sums over the whole sequence.
+        let x = ParameterNode::new(Arr::zeros((3, 1)));
+        let mut y = x.iir(0.5).scalar_sum();
+        y.forward();
+        y.backward(1.0);
+        assert_close(&x.gradient(), &arr2(&[[0.875], [0.75], [0.5]]), TOLERANCE);
+    }
+
+    #[test]
+    fn log_ratio_finite_difference() {
+        let x = ParameterNode::new(random_matrix(3, 5));
+        let positives = [0, 2, 4];
+        let negatives = vec![vec![1, 2, 3], vec![0, 1, 3, 4], vec![4]];
+        let mut z = (x.clone() + x.clone()).log_ratio(&positives, &negatives);
+
+        // The same logit as positive and only negative gives zero.
+        assert_close(
+            &z.value().slice_axis(Axis(0), Slice::from(2..)).to_owned(),
+            &Arr::zeros((1, 1)),
+            TOLERANCE,
+        );
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        // Maximizing the ratio raises the positives and lowers the
+        // negatives; columns used as neither are unaffected.
+        z.forward();
+        z.backward(1.0);
+        let gradient = x.gradient();
+        z.zero_gradient();
+
+        let signs = gradient.map(|&x| x.signum() as i32 * (x != 0.0) as i32);
+        assert_eq!(
+            signs,
+            arr2(&[[1, -1, -1, -1, 0], [-1, -1, 1, -1, -1], [0, 0, 0, 0, 0]])
+        );
+    }
+    #[test]
+    fn sparse_categorical_cross_entropy_finite_difference() {
+        let x = ParameterNode::new(random_matrix(1, 10));
+        let z = x.clone() + x.clone();
+        let idx = IndexInputNode::new(&vec![0][..]);
+        let mut loss = nn::losses::sparse_categorical_crossentropy(&z, &idx);
+
+        check_gradients(&mut loss, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn sparse_categorical_cross_entropy_minibatch() {
+        let logits = random_matrix(3, 5);
+        let classes = [4_usize, 0, 2];
+
+        let x = ParameterNode::new(logits.clone());
+        let idx = IndexInputNode::new(&classes[..]);
+        let mut loss = nn::losses::sparse_categorical_crossentropy(&(x.clone() * 2.0), &idx);
+
+        check_gradients(&mut loss, &x, EPSILON, TOLERANCE).unwrap();
+
+        loss.forward();
+        let summed: Float = classes
+            .iter()
+            .enumerate()
+            .map(|(row, &class)| {
+                let x_row = InputNode::new(logits.select(Axis(0), &[row]));
+                let idx_row = IndexInputNode::new(&[class]);
+                let loss_row =
+                    nn::losses::sparse_categorical_crossentropy(&(x_row * 2.0), &idx_row);
+                loss_row.forward();
+                loss_row.to_scalar()
+            })
+            .sum();
+
+        assert!((loss.to_scalar() - summed).abs() < 1e-4);
+    }
+    #[test]
+    fn focal_loss_finite_difference() {
+        let x = ParameterNode::new(random_matrix(1, 10));
+        let z = x.clone() + x.clone();
+        let idx = IndexInputNode::new(&vec![3][..]);
+        let mut loss = z.focal_loss(&idx, 2.0, 0.25) * 3.0;
+
+        check_gradients(&mut loss, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn focal_loss_without_focusing_is_cross_entropy() {
+        let x = ParameterNode::new(random_matrix(1, 10));
+        let idx = IndexInputNode::new(&vec![4][..]);
+
+        let mut focal = x.focal_loss(&idx, 0.0, 1.0);
+        focal.forward();
+        focal.backward(1.0);
+        let focal_value = focal.to_scalar();
+        let focal_gradient = x.dense_gradient().unwrap();
+        focal.zero_gradient();
+
+        let mut cross_entropy = nn::losses::sparse_categorical_crossentropy(&x, &idx);
+        cross_entropy.forward();
+        cross_entropy.backward(1.0);
+
+        assert!((focal_value - cross_entropy.to_scalar()).abs() < 1e-5);
+        assert_close(&focal_gradient, &x.dense_gradient().unwrap(), 1e-5);
+    }
+    #[test]
+    fn multiclass_hinge_finite_difference() {
+        let x = ParameterNode::new(random_matrix(3, 5));
+        let z = x.clone() * 4.0;
+        let idx = IndexInputNode::new(&[0, 3, 1]);
+        let mut loss = nn::losses::multiclass_hinge(&z, &idx) * 2.0;
+
+        check_gradients(&mut loss, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn multiclass_hinge_gradient() {
+        let x = ParameterNode::new(arr2(&[[2.0, 1.5, -1.0], [0.0, 3.0, 2.5]]));
+        let idx = IndexInputNode::new(&[0, 1]);
+        let mut loss = nn::losses::multiclass_hinge(&x, &idx);
+
+        loss.forward();
+        // Row 0: max(0, 1.5 - 2 + 1) + max(0, -1 - 2 + 1) = 0.5.
+        // Row 1: max(0, 0 - 3 + 1) + max(0, 2.5 - 3 + 1) = 0.5.
+        assert_eq!(loss.to_scalar(), 0.5);
+
+        loss.backward(1.0);
+        assert_eq!(
+            x.dense_gradient().unwrap(),
+            arr2(&[[-0.5, 0.5, 0.0], [0.0, -0.5, 0.5]])
+        );
+    }
+    #[test]
+    fn logistic_rank_finite_difference() {
+        let winners = ParameterNode::new(random_matrix(6, 1));
+        let losers = ParameterNode::new(random_matrix(6, 1));
+        let mut loss = nn::losses::logistic_rank(&(winners.clone() * 3.0), &losers) * 2.0;
+
+        check_gradients(&mut loss, &winners, EPSILON, TOLERANCE).unwrap();
+        check_gradients(&mut loss, &losers, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn logistic_rank_gradient() {
+        let winners = ParameterNode::new(arr2(&[[0.0], [30.0]]));
+        let losers = ParameterNode::new(arr2(&[[0.0], [-30.0]]));
+        let mut loss = nn::losses::logistic_rank(&winners, &losers);
+
+        loss.forward();
+        // A tied pair costs ln(2), a confidently-correct one nothing.
+        assert!((loss.to_scalar() - Float::ln(2.0) / 2.0).abs() < 1e-6);
+
+        loss.backward(1.0);
+        let gradient = winners.dense_gradient().unwrap();
+        assert!((gradient[(0, 0)] + 0.25).abs() < 1e-6);
+        assert!(gradient[(1, 0)].abs() < 1e-12);
+        assert_eq!(losers.dense_gradient().unwrap(), -gradient);
+    }
+    #[test]
+    fn entropy_finite_difference() {
+        let x = ParameterNode::new(arr2(&[[0.2, 0.3, 0.5], [0.7, 0.25, 0.05]]));
+        let mut z = x.entropy();
+
+        assert_eq!(z.value().shape(), &[2, 1]);
+
+        let expected = -(0.2 * Float::ln(0.2) + 0.3 * Float::ln(0.3) + 0.5 * Float::ln(0.5));
+        assert!((z.value()[(0, 0)] - expected).abs() < 1e-5);
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn cosine_matrix_finite_difference() {
+        let x = ParameterNode::new(random_matrix(3, 4));
+        let y = ParameterNode::new(random_matrix(2, 4));
+        let mut z = x.cosine_matrix(&y);
+
+        assert_eq!(z.value().dim(), (3, 2));
+        for ((i, j), &similarity) in z.value().indexed_iter() {
+            let (lhs, rhs) = (x.value().row(i).to_owned(), y.value().row(j).to_owned());
+            let expected = lhs.dot(&rhs) / (lhs.dot(&lhs) * rhs.dot(&rhs)).sqrt();
+            assert!((similarity - expected).abs() < 1e-5);
+        }
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+        check_gradients(&mut z, &y, EPSILON, TOLERANCE).unwrap();
+
+        // Zero rows are similar to nothing, and have finite gradients.
+        let x = ParameterNode::new(arr2(&[[0.0, 0.0], [1.0, 1.0]]));
+        let mut z = x.cosine_matrix(&x).scalar_sum();
+        z.forward();
+        z.backward(1.0);
+
+        assert_close(
+            x.cosine_matrix(&x).value().deref(),
+            &arr2(&[[0.0, 0.0], [0.0, 1.0]]),
+            1e-5,
+        );
+        assert!(x.gradient().iter().all(|x| x.is_finite()));
+        assert!(x.try_cosine_matrix(&ParameterNode::new(random_matrix(2, 3))).is_err());
+    }
+    #[test]
+    fn soft_max_pool_finite_difference() {
+        let x = ParameterNode::new(random_matrix(3, 5));
+        let mut z = x.soft_max_pool(2.0);
+
+        assert_eq!(z.value().shape(), &[3, 1]);
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn soft_max_pool_limits() {
+        let x = InputNode::new(arr2(&[[1.0, -2.0, 3.0, 0.5], [0.0, 4.0, -1.0, 1.0]]));
+        let (max, mean) = (arr2(&[[3.0], [4.0]]), arr2(&[[0.625], [1.0]]));
+
+        let distance = |beta: Float| {
+            let pooled = x.soft_max_pool(beta);
+            let distance = (pooled.value().deref() - &max).map(|x| x.abs()).scalar_sum();
+            distance
+        };
+        assert!(distance(1.0) < distance(0.5));
+        assert!(distance(10.0) < distance(1.0));
+        assert!(distance(100.0) < 0.05);
+
+        assert_close(x.soft_max_pool(1e-3).value().deref(), &mean, 0.05);
+    }
+    #[test]
+    fn log_barrier_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5).map(|x| x.abs() + 0.5));
+        let mut z = x.log_barrier();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn log_barrier_crossing_zero() {
+        let x = ParameterNode::new(arr2(&[[1.0, 0.5], [2.0, 0.1]]));
+        let shift = InputNode::new(Arr::zeros((2, 2)));
+        let mut z = (x.clone() - shift.clone()).log_barrier();
+
+        z.forward();
+        z.backward(1.0);
+        let expected = -(Float::ln(0.5) + Float::ln(2.0) + Float::ln(0.1));
+        assert!((z.to_scalar() - expected).abs() < 1e-5);
+
+        // Push the last element across zero.
+        shift.set_value(&arr2(&[[0.0, 0.0], [0.0, 0.2]]));
+        z.zero_gradient();
+        z.forward();
+        z.backward(1.0);
+
+        let value = z.to_scalar();
+        assert!(value.is_finite());
+        assert!(value > 1e5);
+
+        let gradient = x.dense_gradient().unwrap();
+        assert!(gradient.iter().all(|x| x.is_finite()));
+        assert_close(&gradient, &arr2(&[[-1.0, -2.0], [-0.5, 0.0]]), 1e-5);
+    }
+    #[test]
+    fn conv1d_finite_difference() {
+        for &(stride, padding) in &[(1, Padding::Valid), (2, Padding::Same)] {
+            let x = ParameterNode::new(random_matrix(7, 2));
+            let weights = ParameterNode::new(random_matrix(3 * 2, 4));
+            let mut z = x.conv1d(&weights, 3, stride, padding);
+
+            check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+            check_gradients(&mut z, &weights, EPSILON, TOLERANCE).unwrap();
+        }
+    }
+    #[test]
+    fn conv1d_value() {
+        let x = InputNode::new(arr2(&[[1.0], [2.0], [3.0], [4.0], [5.0]]));
+        let weights = ParameterNode::new(arr2(&[[1.0], [0.0], [-1.0]]));
+
+        let valid = x.conv1d(&weights, 3, 1, Padding::Valid);
+        assert_eq!(valid.value().deref(), &arr2(&[[-2.0], [-2.0], [-2.0]]));
+
+        let same = x.conv1d(&weights, 3, 2, Padding::Same);
+        assert_eq!(same.value().deref(), &arr2(&[[-2.0], [-2.0], [4.0]]));
+    }
+    #[test]
+    fn conv1d_same_padding_empty_input() {
+        let x = ParameterNode::new(Arr::zeros((0, 2)));
+        let weights = ParameterNode::new(random_matrix(3 * 2, 4));
+
+        let mut z = x.conv1d(&weights, 3, 2, Padding::Same).scalar_sum();
+        z.forward();
+        z.backward(1.0);
+        assert_eq!(z.to_scalar(), 0.0);
+        assert!(weights.gradient().iter().all(|&x| x == 0.0));
+
+        assert_eq!(x.max_pool1d(3, 2, Padding::Same).value().dim(), (0, 2));
+        assert_eq!(x.avg_pool1d(3, 2, Padding::Same).value().dim(), (0, 2));
+    }
+    #[test]
+    fn max_pool1d_finite_difference() {
+        for &(stride, padding) in &[(2, Padding::Valid), (2, Padding::Same), (3, Padding::Same)] {
+            let x = ParameterNode::new(random_matrix(7, 3));
+            let mut z = x.max_pool1d(3, stride, padding);
+
+            check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+        }
+    }
+    #[test]
+    fn max_pool1d_routes_gradient_to_maxima() {
+        let x = ParameterNode::new(arr2(&[
+            [1.0, 5.0],
+            [3.0, 4.0],
+            [2.0, 0.0],
+            [0.0, 6.0],
+            [4.0, 1.0],
+        ]));
+        let mut z = x.max_pool1d(2, 2, Padding::Same);
+
+        z.forward();
+        assert_eq!(z.value().deref(), &arr2(&[[3.0, 5.0], [2.0, 6.0], [4.0, 1.0]]));
+
+        z.backward(1.0);
+        assert_eq!(
+            x.dense_gradient().unwrap(),
+            arr2(&[
+                [0.0, 1.0],
+                [1.0, 0.0],
+                [1.0, 0.0],
+                [0.0, 1.0],
+                [1.0, 1.0],
+            ])
+        );
+    }
+    #[test]
+    fn avg_pool1d_finite_difference() {
+        for &(stride, padding) in &[(2, Padding::Valid), (2, Padding::Same), (3, Padding::Same)] {
+            let x = ParameterNode::new(random_matrix(7, 3));
+            let mut z = x.avg_pool1d(3, stride, padding);
+
+            check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+        }
+    }
+    #[test]
+    fn avg_pool1d_partial_windows() {
+        let x = ParameterNode::new(arr2(&[[1.0], [3.0], [2.0], [6.0], [4.0]]));
+        let mut z = x.avg_pool1d(2, 2, Padding::Same);
+
+        z.forward();
+        assert_eq!(z.value().deref(), &arr2(&[[2.0], [4.0], [4.0]]));
+
+        z.backward(1.0);
+        assert_eq!(
+            x.dense_gradient().unwrap(),
+            arr2(&[[0.5], [0.5], [0.5], [0.5], [1.0]])
+        );
+    }
+    #[test]
+    fn hypot_finite_difference() {
+        let x = ParameterNode::new(random_matrix(5, 3));
+        let y = ParameterNode::new(random_matrix(5, 3));
+        let mut z = x.hypot(&y).sigmoid();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+        check_gradients(&mut z, &y, EPSILON, TOLERANCE).unwrap();
+
+        let x = ParameterNode::new(arr2(&[[3e30, 0.0]]));
+        let y = ParameterNode::new(arr2(&[[4e30, 0.0]]));
+        let z = x.hypot(&y);
+        assert!((z.value()[(0, 0)] / 5e30 - 1.0).abs() < 1e-6);
+    }
+    #[test]
+    fn hypot_origin() {
+        let x = ParameterNode::new(Arr::zeros((2, 2)));
+        let y = ParameterNode::new(Arr::zeros((2, 2)));
+        let mut z = x.hypot(&y);
+
+        z.forward();
+        z.backward(1.0);
+
+        assert!(z.value().iter().all(|&v| v == 0.0));
+        assert!(x.dense_gradient().unwrap().iter().all(|v| v.is_finite()));
+        assert!(y.dense_gradient().unwrap().iter().all(|v| v.is_finite()));
+    }
+    #[test]
+    fn covariance_finite_difference() {
+        let x = ParameterNode::new(random_matrix(6, 3));
+        let z = x.covariance();
+
+        assert_eq!(z.value().shape(), &[3, 3]);
+
+        let x_value = x.value().clone();
+        let centered = &x_value - &x_value.mean_axis(ndarray::Axis(0));
+        let expected = centered.t().dot(&centered) / 6.0;
+        assert_close(z.value().deref(), &expected, 1e-5);
+
+        // Penalize the off-diagonal entries, as in decorrelation losses.
+        let mask = InputNode::new(arr2(&[[0.0, 1.0, 2.0], [1.0, 0.0, -1.0], [0.5, 1.0, 0.0]]));
+        let mut z = (z * mask).square();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn variance_hinge_finite_difference() {
+        let mut x_value = random_matrix(8, 4);
+        x_value.column_mut(0).map_inplace(|x| *x *= 100.0);
+        let x = ParameterNode::new(x_value.clone());
+        let mut z = x.variance_hinge(1.0, 1e-4);
+
+        let std: Vec<Float> = x_value
+            .gencolumns()
+            .into_iter()
+            .map(|column| {
+                let mean = column.scalar_sum() / 8.0;
+                let variance = column.iter().map(|x| (x - mean).powi(2)).sum::<Float>() / 8.0;
+                (variance + 1e-4).sqrt()
+            })
+            .collect();
+        assert!(std[0] > 1.0 && std[1..].iter().all(|&std| std < 1.0));
+        let expected = std.iter().map(|std| (1.0 - std).max(0.0)).sum::<Float>() / 4.0;
+        assert!((z.to_scalar() - expected).abs() < 1e-5);
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        // The high-variance feature is above the hinge.
+        z.forward();
+        z.backward(1.0);
+        assert!(x.gradient().column(0).iter().all(|&x| x == 0.0));
+        assert!(x.gradient().column(1).iter().any(|&x| x != 0.0));
+    }
+    #[test]
+    fn variance_hinge_constant_column() {
+        let mut x_value = random_matrix(8, 3);
+        x_value.column_mut(1).fill(0.5);
+        let x = ParameterNode::new(x_value);
+        let mut z = x.variance_hinge(1.0, 1e-4);
+
+        // The constant column contributes the most to the loss, but
+        // its centered values, and so its gradient, are zero.
+        z.forward();
+        z.backward(1.0);
+        assert!(z.to_scalar() > (1.0 - 0.01) / 3.0);
+        assert!(x.gradient().iter().all(|x| x.is_finite()));
+        assert!(x.gradient().column(1).iter().all(|&x| x == 0.0));
+        assert!(x.gradient().column(0).iter().any(|&x| x != 0.0));
+    }
+    #[test]
+    #[should_panic(expected = "The variance hinge epsilon must be positive, got 0.")]
+    fn variance_hinge_zero_epsilon() {
+        ParameterNode::new(random_matrix(8, 3)).variance_hinge(1.0, 0.0);
+    }
+    #[test]
+    fn standardize_finite_difference() {
+        let x = ParameterNode::new(random_matrix(4, 3));
+        let z = x.standardize();
+
+        let value = z.value().clone();
+        for (column, input_column) in value.gencolumns().into_iter().zip(x.value().gencolumns()) {
+            let input_mean = input_column.scalar_sum() / 4.0;
+            let input_variance = input_column
+                .iter()
+                .map(|x| (x - input_mean).powi(2))
+                .sum::<Float>() / 4.0;
+
+            // Unit variance, up to the epsilon guard.
+            let expected_variance = input_variance / (input_variance + 1e-5);
+
+            assert!((column.scalar_sum() / 4.0).abs() < 1e-5);
+            assert!((column.dot(&column) / 4.0 - expected_variance).abs() < 1e-4);
+        }
+
+        let weights = InputNode::new(random_matrix(4, 3));
+        let mut z = (z * weights).square();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn standardize_constant_column() {
+        let x = ParameterNode::new(arr2(&[[1.0, 2.0], [1.0, 4.0], [1.0, 6.0]]));
+        let mut z = x.standardize().scalar_sum();
+
+        z.forward();
+        z.backward(1.0);
+
+        let gradient = x.dense_gradient().unwrap();
+        assert!(z.to_scalar().is_finite());
+        assert!(gradient.iter().all(|x| x.is_finite()));
+    }
+    #[test]
+    fn running_norm_statistics_converge() {
+        let mut rng = rand::thread_rng();
+        let noise = Uniform::new(-1.0, 1.0);
+        let stream = Arr::from_shape_fn((5000, 3), |(_, col)| {
+            let x: Float = noise.sample(&mut rng);
+            (col as Float - 1.0) * 4.0 + (col + 1) as Float * x
+        });
+
+        let x = ParameterNode::new(Arr::zeros((1, 3)));
+        let norm = x.running_norm();
+        let mut y = norm.scalar_sum();
+
+        for row in stream.genrows() {
+            x.set_value(&row.to_owned().into_shape((1, 3)).unwrap());
+            y.forward();
+            // Cached passes do not count the row again.
+            y.forward();
+            y.zero_gradient();
+        }
+        assert_eq!(norm.count(), 5000);
+
+        let mean = stream.mean_axis(Axis(0)).insert_axis(Axis(0));
+        let variance = (&stream - &mean).map(|x| x.powi(2)).mean_axis(Axis(0));
+        let variance = variance.insert_axis(Axis(0));
+        assert_close(&norm.running_mean(), &mean, 1e-3);
+        assert_close(&norm.running_variance(), &variance, 1e-3);
+
+        // The statistics are constants in the backward pass.
+        y.forward();
+        y.backward(1.0);
+        let epsilon = stability().variance_epsilon;
+        let inverse_std = norm.running_variance().map(|&v| 1.0 / (v + epsilon).sqrt());
+        assert_close(&x.gradient(), &inverse_std, 1e-3);
+
+        norm.reset_statistics();
+        assert_eq!(norm.count(), 0);
+    }
+    #[test]
+    fn parameter_gradients() {
+        let x = ParameterNode::new(random_matrix(2, 3));
+        let w = ParameterNode::new(random_matrix(2, 3));
+        let mut z = (x.clone() * w.clone()).scalar_sum();
+
+        z.forward();
+        z.backward(1.0);
+
+        assert_eq!(&w.gradient(), x.value().deref());
+        assert!(w.sparse_gradient().is_empty());
+        z.zero_gradient();
+
+        let embedding = ParameterNode::new(random_matrix(5, 3));
+        let index = IndexInputNode::new(&[3, 1, 3]);
+        let mut z = (embedding.index(&index) * InputNode::new(arr2(&[
+            [1.0, 1.0, 1.0],
+            [2.0, 2.0, 2.0],
+            [3.0, 3.0, 3.0],
+        ]))).scalar_sum();
+
+        z.forward();
+        z.backward(1.0);
+
+        let sparse_gradient = embedding.sparse_gradient();
+        assert_eq!(sparse_gradient.len(), 1);
+        assert_eq!(sparse_gradient[0].0, vec![3, 1, 3]);
+
+        let mut expected = Arr::zeros((5, 3));
+        expected.row_mut(1).fill(2.0);
+        expected.row_mut(3).fill(4.0);
+        assert_eq!(embedding.gradient(), expected);
+    }
+    #[test]
+    fn coalesce_repeated_sparse_rows() {
+        let embedding = ParameterNode::new(Arr::zeros((5, 3)));
+        let index = IndexInputNode::new(&[2, 2, 2, 2]);
+        let single = IndexInputNode::new(&[2]);
+        let mut z = embedding.index(&index).scalar_sum() + embedding.index(&single).scalar_sum();
+
+        z.forward();
+        z.backward(1.0);
+        assert_eq!(embedding.sparse_gradient().len(), 2);
+
+        embedding.node.gradient.borrow_mut().sparse_gradient.coalesce();
+        let sparse_gradient = embedding.sparse_gradient();
+        assert_eq!(sparse_gradient.len(), 1);
+        assert_eq!(sparse_gradient[0].0, vec![2]);
+        assert_eq!(sparse_gradient[0].1, Arr::from_elem((1, 3), 5.0));
+
+        // Adagrad coalesces before applying a single accumulator update,
+        // of the summed gradient.
+        z.zero_gradient();
+        z.forward();
+        z.backward(1.0);
+
+        let optimizer = Adagrad::new(vec![embedding.clone()]);
+        optimizer.step();
+        let squared_gradients = embedding.node.value.squared_gradients();
+        assert_eq!(squared_gradients.row(2), Arr::from_elem((1, 3), 25.0).row(0));
+        assert_eq!(squared_gradients.row(1), Arr::zeros((1, 3)).row(0));
+    }
+    #[test]
+    fn clamp_keeps_sparse_gradients_sparse() {
+        let embedding = ParameterNode::new(random_matrix(10, 3));
+        let index = IndexInputNode::new(&[1, 4]);
+        let mut z = (embedding.index(&index) * 10.0).scalar_sum();
+
+        let optimizer = SGD::new(vec![embedding.clone()]).clamp(-1.0, 1.0);
+
+        for _ in 0..2 {
+            z.forward();
+            z.backward(1.0);
+            optimizer.step();
+            assert!(embedding.dense_gradient().is_none());
+
+            let sparse_gradient = embedding.sparse_gradient();
+            assert!(sparse_gradient[0].1.iter().all(|&x| x == 1.0));
+
+            z.zero_gradient();
+            assert!(embedding.dense_gradient().is_none());
+        }
+    }
+    #[test]
+    fn clip_gradients() {
+        let x = ParameterNode::new(Arr::zeros((3, 2)));
+        let embedding = ParameterNode::new(Arr::zeros((4, 2)));
+        let index = IndexInputNode::new(&[1, 3, 1]);
+        let x_weights = InputNode::new(arr2(&[[3.0, 0.0], [0.0, 0.0], [0.0, -4.0]]));
+        let embedding_weights =
+            InputNode::new(arr2(&[[1.0, 0.0], [0.0, 2.0], [1.0, -2.0]]));
+
+        let mut z = (x.clone() * x_weights).scalar_sum()
+            + (embedding.index(&index) * embedding_weights).scalar_sum();
+
+        z.forward();
+        z.backward(1.0);
+
+        // Sparse rows for the same index are added before taking the norm.
+        let embedding_gradient = arr2(&[[0.0, 0.0], [2.0, -2.0], [0.0, 0.0], [0.0, 2.0]]);
+        assert_eq!(embedding.gradient(), embedding_gradient);
+
+        let norm = optim::clip_gradient_norm(&[x.clone(), embedding.clone()], 5.0);
+        assert_close(&arr2(&[[norm]]), &arr2(&[[(25.0 + 12.0 as Float).sqrt()]]), 1e-5);
+        assert_close(
+            &x.gradient(),
+            &(arr2(&[[3.0, 0.0], [0.0, 0.0], [0.0, -4.0]]) * (5.0 / norm)),
+            1e-5,
+        );
+        assert_close(&embedding.gradient(), &(embedding_gradient * (5.0 / norm)), 1e-5);
+
+        // Norms within the bound are left alone.
+        assert_close(&arr2(&[[x.clip_gradient_norm(10.0)]]), &arr2(&[[5.0 * 5.0 / norm]]), 1e-5);
+        assert_close(
+            &x.gradient(),
+            &(arr2(&[[3.0, 0.0], [0.0, 0.0], [0.0, -4.0]]) * (5.0 / norm)),
+            1e-5,
+        );
+
+        z.zero_gradient();
+        z.forward();
+        z.backward(1.0);
+
+        optim::clip_gradient_values(&[x.clone(), embedding.clone()], -1.5, 1.0);
+        assert_eq!(x.gradient(), arr2(&[[1.0, 0.0], [0.0, 0.0], [0.0, -1.5]]));
+        assert_eq!(
+            embedding.gradient(),
+            arr2(&[[0.0, 0.0], [2.0, -1.5], [0.0, 0.0], [0.0, 1.0]])
+        );
+        assert!(embedding.dense_gradient().is_none());
+    }
+    #[test]
+    fn add_bias_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let z = x.add_bias();
+        let bias = z.bias();
+        bias.set_value(&random_matrix(1, 5));
+
+        assert_eq!(z.parameters().len(), 2);
+
+        let mut z = z.sigmoid();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        check_gradients(&mut z, &bias, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn rowwise_stack_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let y = ParameterNode::new(random_matrix(10, 5));
+        //let v = x.clone() + y.clone();
+
+        let z = x.stack(&y, ndarray::Axis(0));
+        let mut z = z.clone().sigmoid() * z.clone().relu();
+
+        assert_eq!(z.value().rows(), 20);
+        assert_eq!(z.value().cols(), 5);
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        check_gradients(&mut z, &y, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn columnwise_stack_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let y = ParameterNode::new(random_matrix(10, 5));
+        //let v = x.clone() + y.clone();
+
+        let mut z = x.stack(&y, ndarray::Axis(1)).sigmoid();
+
+        assert_eq!(z.value().rows(), 10);
+        assert_eq!(z.value().cols(), 10);
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        check_gradients(&mut z, &y, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn sparse_index_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let idx_0 = IndexInputNode::new(&[random_index(10)]);
+        let idx_1 = IndexInputNode::new(&[random_index(10)]);
+
+        let mut z = (x.index(&idx_0).tanh() * x.index(&idx_1)).square();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+    }
+    #[test]
+    fn sparse_update_on_first_step() {
+        let embedding = ParameterNode::new(random_matrix(5, 3));
+        let index = IndexInputNode::new(&[2]);
+        let mut loss = embedding.index(&index).scalar_sum();
+
+        let before = embedding.value().clone();
+
+        let optimizer = SGD::new(loss.parameters()).learning_rate(0.1);
+        loss.forward();
+        loss.backward(1.0);
+        optimizer.step();
+
+        let after = embedding.value();
+        assert!(after.row(2).iter().zip(before.row(2).iter()).all(|(a, b)| a != b));
+        assert_eq!(after.row(0), before.row(0));
+    }
+    #[test]
+    fn index_used_twice_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let idx = IndexInputNode::new(&[3, 1, 3]);
+        let embedding = x.index(&idx);
+
+        let mut z = (embedding.tanh() * embedding.clone()).square();
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        let mut z = (embedding.clone() + embedding.clone()).scalar_sum();
+        z.forward();
+        z.backward(1.0);
+        let gradient = x.gradient();
+        assert_eq!(gradient.row(3), Arr::from_elem((1, 5), 4.0).row(0));
+        assert_eq!(gradient.row(1), Arr::from_elem((1, 5), 2.0).row(0));
+        assert_eq!(gradient.row(0), Arr::zeros((1, 5)).row(0));
+
+        // Gradient buffers are reused by batches of other sizes.
+        z.zero_gradient();
+        idx.set_value(&[7_usize, 2, 2, 9, 7][..]);
+        z.forward();
+        z.backward(1.0);
+        assert_eq!(x.gradient().scalar_sum(), 50.0);
+        assert_eq!(x.gradient().row(2), Arr::from_elem((1, 5), 4.0).row(0));
+    }
+    #[test]
+    #[cfg(feature = "nan-guard")]
+    #[should_panic(expected = "Non-finite value computed by the Div node in the forward pass.")]
+    fn nan_guard_forward() {
+        let x = ParameterNode::new(Arr::ones((2, 2)));
+        let y = InputNode::new(Arr::zeros((2, 2)));
+        let z = (x / y).exp().scalar_sum();
+
+        z.forward();
+    }
+    #[test]
+    #[cfg(feature = "nan-guard")]
+    #[should_panic(expected = "Non-finite value computed by the Log node `log_y`")]
+    fn nan_guard_linearized_forward() {
+        let x = ParameterNode::new(Arr::ones((2, 2)));
+        let y = InputNode::new(Arr::zeros((2, 2)));
+        let mut z = (x * y.ln().named("log_y")).scalar_sum();
+
+        assert!(z.linearize());
+        z.forward();
+    }
+    #[test]
+    #[cfg(feature = "nan-guard")]
+    #[should_panic(expected = "Non-finite gradient computed by the Div node in the backward pass.")]
+    fn nan_guard_backward() {
+        let x = ParameterNode::new(arr2(&[[1e-10]]));
+        let y = ParameterNode::new(arr2(&[[1e-30]]));
+        let mut z = (x / y).scalar_sum();
+
+        // The value is finite, but its gradient in `y` is not.
+        z.forward();
+        z.backward(1.0);
+    }
+    #[test]
+    fn validate_consistent_graph() {
+        let x = ParameterNode::new(random_matrix(3, 4));
+        let y = x.tanh();
+        let cell = (y.clone() * y.clone()).boxed();
+        let mut z = (cell.clone() + cell.tanh()).scalar_sum();
+
+        assert_eq!(z.validate(), Ok(()));
+        z.forward();
+        assert_eq!(z.validate(), Ok(()));
+        z.backward(1.0);
+        assert_eq!(z.validate(), Ok(()));
+        z.zero_gradient();
+        assert_eq!(z.validate(), Ok(()));
+    }
+    #[test]
+    fn validate_counter_mismatch() {
+        let x = ParameterNode::new(random_matrix(3, 4));
+        let z = x.sigmoid().scalar_sum();
+
+        z.forward();
+        z.forward();
+        assert_eq!(
+            z.validate(),
+            Err(GraphError::CounterMismatch {
+                op: "Sum",
+                expected: 1,
+                forward_count: 2,
+            })
+        );
+
+        // Clearing a node shared with another graph leaves this one
+        // evaluated on top of it.
+        let shared = x.tanh();
+        let a = shared.square().scalar_sum();
+        let b = shared.sigmoid().scalar_sum();
+        a.forward();
+        b.forward();
+        a.zero_gradient();
+        assert_eq!(
+            b.validate(),
+            Err(GraphError::CounterMismatch {
+                op: "Tanh",
+                expected: 1,
+                forward_count: 0,
+            })
+        );
+    }
+    #[test]
+    fn validate_stale_counter() {
+        let x = ParameterNode::new(random_matrix(3, 4));
+        let y = x.sigmoid();
+        let z = y.scalar_sum();
+
+        // Evaluating part of the graph on its own.
+        y.forward();
+        assert_eq!(
+            z.validate(),
+            Err(GraphError::StaleCounter {
+                op: "Sigmoid",
+                forward_count: 1,
+            })
+        );
+    }
+    #[test]
+    fn validate_incomplete_backward() {
+        let x = ParameterNode::new(random_matrix(3, 4));
+        let mut y = x.sigmoid();
+        let z = (y.clone() + y.clone()).scalar_sum();
+
+        z.forward();
+        y.backward(1.0);
+        assert_eq!(
+            z.validate(),
+            Err(GraphError::IncompleteBackward {
+                op: "Sigmoid",
+                forward_count: 2,
+                backward_count: 1,
+            })
+        );
+    }
+    #[test]
+    fn validate_gradient_shape() {
+        let x = InputNode::new(random_matrix(4, 3));
+        let w = ParameterNode::new(random_matrix(3, 2));
+        let h = x.dot(&w);
+        let mut z = h.sigmoid().scalar_sum();
+
+        z.forward();
+        z.backward(1.0);
+
+        // Re-evaluating part of the graph with a new batch size.
+        x.set_value(&random_matrix(6, 3));
+        h.zero_gradient();
+        h.forward();
+        assert_eq!(
+            z.validate(),
+            Err(GraphError::GradientShape {
+                op: "Sigmoid",
+                operand: 0,
+                gradient: (4, 2),
+                value: (6, 2),
+            })
+        );
+    }
+    #[test]
+    fn validate_parameters() {
+        let x = ParameterNode::new(random_matrix(3, 4));
+        let z = x.sigmoid().scalar_sum();
+
+        let unlisted = Variable::new(Rc::clone(&z.node), Vec::new());
+        assert_eq!(
+            unlisted.validate(),
+            Err(GraphError::MissingParameter { shape: (3, 4) })
+        );
+
+        // Parameters behind a stopped gradient need not be listed.
+        let stopped = x.stop_gradient().scalar_sum();
+        assert!(stopped.parameters().is_empty());
+        assert_eq!(stopped.validate(), Ok(()));
+
+        // Gradients left over from another graph sharing the parameter.
+        let mut other = x.square().scalar_sum();
+        other.forward();
+        other.backward(1.0);
+        assert_eq!(
+            z.validate(),
+            Err(GraphError::StaleGradient { shape: (3, 4) })
+        );
+    }
+    #[test]
+    #[cfg(feature = "instrumentation")]
+    fn update_histogram_counts_sparse_updates() {
+        let parameters = Arc::new(HogwildParameter::new(random_matrix(5, 3)));
+        let embedding = ParameterNode::shared(parameters.clone());
+        let index = IndexInputNode::new(&[1, 3, 3]);
+
+        let mut loss = embedding.index(&index).square().scalar_sum();
+        let optimizer = SGD::new(loss.parameters());
+
+        let num_steps = 7;
+
+        for _ in 0..num_steps {
+            loss.forward();
+            loss.backward(1.0);
+            optimizer.step();
+            loss.zero_gradient();
+        }
+
+        assert_eq!(
+            parameters.update_histogram(),
+            vec![0, num_steps, 0, num_steps, 0]
+        );
+    }
+
+    #[test]
+    fn boxed_layers() {
+        let x = InputNode::new(random_matrix(3, 4));
+        let y = InputNode::new(random_matrix(3, 2));
+        let weights = vec![
+            ParameterNode::new(random_matrix(4, 8)),
+            ParameterNode::new(random_matrix(8, 8)),
+            ParameterNode::new(random_matrix(8, 2)),
+        ];
+
+        // Only the hidden layers have an activation.
+        let mut layers: Vec<Variable<BoxedNode>> = vec![x.boxed()];
+        for (idx, weight) in weights.iter().enumerate() {
+            let layer = layers[idx].dot(weight);
+            layers.push(if idx + 1 < weights.len() {
+                layer.tanh().boxed()
+            } else {
+                layer.boxed()
+            });
+        }
+
+        let mut loss = (layers[weights.len()].clone() - y).square().scalar_sum();
+        assert_eq!(loss.parameters().len(), weights.len());
+
+        for weight in &weights {
+            check_gradients(&mut loss, weight, EPSILON, TOLERANCE).unwrap();
+        }
+
+        let initial_values: Vec<_> = weights.iter().map(|x| x.value().clone()).collect();
+        let optimizer = SGD::new(loss.parameters()).learning_rate(0.01);
+
+        loss.forward();
+        let initial_loss = loss.to_scalar();
+        loss.zero_gradient();
+
+        for _ in 0..50 {
+            loss.forward();
+            loss.backward(1.0);
+            optimizer.step();
+            loss.zero_gradient();
+        }
+
+        loss.forward();
+        assert!(loss.to_scalar() < initial_loss);
+
+        for (weight, initial_value) in weights.iter().zip(initial_values) {
+            assert!(weight.value().deref() != &initial_value);
+        }
+    }
+    #[test]
+    fn univariate_regression() {
+        let slope = ParameterNode::new(random_matrix(1, 1));
+        let intercept = ParameterNode::new(random_matrix(1, 1));
+
+        let num_epochs = 200;
+
+        let x = InputNode::new(random_matrix(1, 1));
+        let y = InputNode::new(random_matrix(1, 1));
+
+        let y_hat = slope.clone() * x.clone() + intercept.clone();
+        let diff = y.clone() - y_hat.clone();
+        let mut loss = diff.square();
+
+        let optimizer = Adagrad::new(loss.parameters()).learning_rate(0.5);
+
+        for _ in 0..num_epochs {
+            let _x = arr2(&[[rand::thread_rng().gen()]]);
+            let _y = 0.5 * &_x + 0.2;
+
+            x.set_value(&_x);
+            y.set_value(&_y);
+
+            loss.forward();
+            loss.backward(1.0);
+
+            optimizer.step();
+            loss.zero_gradient();
+        }
+
+        println!(
+            "Predicted: {} Loss: {} Slope {} Intercept {}",
+            y_hat.value(),
+            loss.value(),
+            slope.value(),
+            intercept.value()
+        );
+
+        assert!(loss.to_scalar() < 1.0e-2);
+    }
+
+    #[test]
+    fn multivariate_regression() {
+        let slope = ParameterNode::new(random_matrix(1, 3));
+        let intercept = ParameterNode::new(random_matrix(1, 1));
+
+        let num_epochs = 200;
+
+        let coefficients = arr2(&[[1.0], [2.0], [3.0]]);
+
+        let x = InputNode::new(random_matrix(1, 3));
+        let y = InputNode::new(random_matrix(1, 1));
+
+        let y_hat = x.vector_dot(&slope) + intercept.clone();
+        let diff = y.clone() - y_hat.clone();
+        let mut loss = diff.square();
+
+        let optimizer = SGD::new(loss.parameters()).learning_rate(0.1);
+
+        for _ in 0..num_epochs {
+            let _x = arr2(&[[
+                rand::thread_rng().gen(),
+                rand::thread_rng().gen(),
+                rand::thread_rng().gen(),
+            ]]);
+            let _y = &_x.dot(&coefficients) + 5.0;
+
+            x.set_value(&_x);
+            y.set_value(&_y);
+
+            loss.forward();
+            loss.backward(1.0);
+
+            optimizer.step();
+            loss.zero_gradient();
+        }
+
+        println!(
+            "Predicted: {} Loss: {} Slope {} Intercept {}",
+            y_hat.value(),
+            loss.value(),
+            slope.value(),
+            intercept.value()
+        );
+
+        assert!(loss.to_scalar() < 1.0e-1);
+    }
+
+    #[test]
+    fn embedding_factorization() {
+        let (rows, cols) = (10, 4);
+
+        let true_u = random_matrix(rows, 10);
+        let true_v = random_matrix(cols, 10);
+        let x = true_u.dot(&true_v.t());
+
+        let y = random_matrix(1, 1);
+        let u_input = vec![0];
+        let v_input = vec![0];
+
+        let output = InputNode::new(y);
+
+        let u_embedding = ParameterNode::new(random_matrix(rows, 10));
+        let v_embedding = ParameterNode::new(random_matrix(cols, 10));
+
+        let u_index = IndexInputNode::new(&u_input);
+        let v_index = IndexInputNode::new(&v_input);
+
+        let u_vec = u_embedding.index(&u_index);
+        let v_vec = v_embedding.index(&v_index);
+
+        let y_hat = u_vec.vector_dot(&v_vec);
+        let mut loss = (output.clone() - y_hat.clone()).square();
+
+        let num_epochs = 200;
+        let optimizer = Adagrad::new(loss.parameters()).learning_rate(0.1);
+
+        let mut loss_val = 0.0;
+
+        for _ in 0..num_epochs {
+            loss_val = 0.0;
+
+            for row_idx in 0..rows {
+                for col_idx in 0..cols {
+                    u_index.set_value(row_idx);
+                    v_index.set_value(col_idx);
+
+                    output.set_value(x[(row_idx, col_idx)]);
 
-macro_rules! impl_arithmetic_op {
-    ($trait:ident, $fn:ident, $node:ident) => {
-        impl<LHS, RHS> $trait<Variable<RHS>> for Variable<LHS>
-        where
-            RHS: Node<Value = Arr, InputGradient = Arr>,
-            LHS: Node<Value = Arr, InputGradient = Arr>,
-        {
-            type Output = Variable<$node<LHS, RHS>>;
-            fn $fn(self, other: Variable<RHS>) -> Self::Output {
-                Variable::new(
-                    Rc::new($node::new(self.node, other.node)),
-                    merge_parameters(&self.parameters, &other.parameters),
-                )
-            }
-        }
+                    loss.forward();
+                    loss.backward(1.0);
 
-        /// The constant will be broadcast to have the same shape
-        /// as the LHS.
-        impl<LHS> $trait<f32> for Variable<LHS>
-        where
-            LHS: Node<Value = Arr, InputGradient = Arr>,
-        {
-            type Output = Variable<$node<LHS, InputNode>>;
-            fn $fn(self, other: f32) -> Self::Output {
-                let constant = InputNode::new(self.value().deref() * 0.0 + other);
+                    loss_val += loss.to_scalar();
 
-                Variable::new(
-                    Rc::new($node::new(self.node, constant.node)),
-                    merge_parameters(&self.parameters, &constant.parameters),
-                )
+                    optimizer.step();
+                    loss.zero_gradient();
+                }
             }
+
+            println!("Loss {}", loss_val)
         }
 
-        /// The constant will be broadcast to have the same shape
-        /// as the RHS.
-        impl<RHS> $trait<Variable<RHS>> for f32
-        where
-            RHS: Node<Value = Arr, InputGradient = Arr>,
-        {
-            type Output = Variable<$node<InputNode, RHS>>;
-            fn $fn(self, other: Variable<RHS>) -> Self::Output {
-                let constant = InputNode::new(other.value().deref() * 0.0 + self);
+        assert!(loss_val < 1e-2);
+    }
 
-                Variable::new(
-                    Rc::new($node::new(constant.node, other.node)),
-                    merge_parameters(&constant.parameters, &other.parameters),
-                )
-            }
-        }
-    };
-}
+    #[test]
+    fn model_spec_threads() {
+        let spec = nn::ModelSpec::new(
+            vec![Arc::new(HogwildParameter::new(random_matrix(3, 2)))],
+            |parameters| {
+                let x = InputNode::new(Arr::zeros((1, 3)));
+                let loss = x.dot(&parameters[0]).scalar_sum();
+                (x, parameters[0].clone(), loss)
+            },
+        );
 
-impl_arithmetic_op!(Add, add, AddNode);
-impl_arithmetic_op!(Sub, sub, SubNode);
-impl_arithmetic_op!(Mul, mul, MulNode);
-impl_arithmetic_op!(Div, div, DivNode);
+        let inputs = vec![random_matrix(1, 3), random_matrix(1, 3)];
+        let handles: Vec<_> = inputs
+            .iter()
+            .map(|input| {
+                let (spec, input) = (spec.clone(), input.clone());
+                std::thread::spawn(move || {
+                    let (x, weight, mut loss) = spec.build();
+                    x.set_value(&input);
+                    loss.forward();
+                    loss.backward(1.0);
 
-impl<T> Neg for Variable<T>
-where
-    T: Node<Value = Arr, InputGradient = Arr>,
-{
-    type Output = Variable<NegNode<T>>;
-    fn neg(self) -> Self::Output {
-        Variable::new(Rc::new(NegNode::new(self.node)), self.parameters.clone())
-    }
-}
+                    let value = loss.value().clone();
+                    (value, weight.gradient())
+                })
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|x| x.join().unwrap()).collect();
 
-/// Compute finite difference gradient estimates of the output variable
-/// with respect to the input. Use to verify correctness of gradient
-/// computations.
-pub fn finite_difference<T>(
-    input: &mut Variable<ParameterNode>,
-    output: &mut Variable<T>,
-) -> (Arr, Arr)
-where
-    T: Node<Value = Arr, InputGradient = Arr>,
-{
-    let delta_x = 1e-4;
+        let weight = spec.parameters()[0].value().clone();
+        for (input, &(ref value, ref gradient)) in inputs.iter().zip(&results) {
+            // Each thread's graph only saw its own input.
+            assert_close(value, &arr2(&[[input.dot(&weight).scalar_sum()]]), 1e-5);
+            assert_close(gradient, &input.t().dot(&Arr::ones((1, 2))), 1e-5);
+        }
 
-    let initial_input = { input.value().clone() };
-    let mut central_difference = &initial_input * 0.0;
+        // Updates through one graph are visible to graphs built elsewhere.
+        let (_, weight, _) = spec.build();
+        weight.set_value(&Arr::ones((3, 2)));
+        let (x, _, loss) = spec.build();
+        x.set_value(&Arr::ones((1, 3)));
+        loss.forward();
+        assert_close(loss.value().deref(), &arr2(&[[6.0]]), 1e-5);
+    }
+    #[test]
+    fn hogwild_embedding_factorization() {
+        let (rows, cols) = (10, 4);
 
-    for (idx, diff) in central_difference.indexed_iter_mut() {
-        let positive_difference = {
-            output.zero_gradient();
-            let mut changed_input = initial_input.clone();
-            changed_input[idx] += 0.5 * delta_x;
-            input.set_value(&changed_input);
-            output.forward();
-            output.backward(1.0);
-            output.value().clone()
-        };
+        let true_u = random_matrix(rows, 10);
+        let true_v = random_matrix(cols, 10);
+        let x = true_u.dot(&true_v.t());
 
-        let negative_difference = {
-            output.zero_gradient();
-            let mut changed_input = initial_input.clone();
-            changed_input[idx] -= 0.5 * delta_x;
-            input.set_value(&changed_input);
-            output.forward();
-            output.backward(1.0);
-            output.value().clone()
-        };
+        let u_input = vec![0];
+        let v_input = vec![0];
 
-        let central_difference = positive_difference - negative_difference;
+        let u_parameters = Arc::new(HogwildParameter::new(random_matrix(rows, 10)));
+        let v_parameters = Arc::new(HogwildParameter::new(random_matrix(cols, 10)));
 
-        *diff = central_difference.scalar_sum() / delta_x;
-    }
+        let losses: Vec<Float> = (0..rayon::current_num_threads())
+            .into_par_iter()
+            .map(|_| {
+                let u_embedding = ParameterNode::shared(u_parameters.clone());
+                let v_embedding = ParameterNode::shared(v_parameters.clone());
 
-    let gradient = {
-        output.zero_gradient();
-        input.set_value(&initial_input);
-        output.forward();
-        output.backward(1.0);
+                let u_index = IndexInputNode::new(&u_input);
+                let v_index = IndexInputNode::new(&v_input);
+                let output = InputNode::new(random_matrix(1, 1));
 
-        let mut gradient = input.dense_gradient().unwrap_or(initial_input * 0.0);
+                let u_vec = u_embedding.index(&u_index);
+                let v_vec = v_embedding.index(&v_index);
 
-        let sparse_gradient = input.sparse_gradient();
+                let y_hat = u_vec.vector_dot(&v_vec);
+                let mut loss = (output.clone() - y_hat.clone()).square();
 
-        for (indices, grad) in sparse_gradient.as_slice() {
-            for &row_idx in indices.iter() {
-                for (dest, orig) in gradient.row_mut(row_idx).iter_mut().zip(grad.iter()) {
-                    *dest += orig;
-                }
-            }
-        }
+                let num_epochs = 100;
 
-        gradient
-    };
+                let optimizer = SGD::new(loss.parameters());
 
-    output.zero_gradient();
+                let mut loss_val = 0.0;
 
-    (central_difference, gradient)
-}
+                for _ in 0..num_epochs {
+                    loss_val = 0.0;
 
-/// Assert two arrays are within `tol` of each other.
-pub fn assert_close(x: &Arr, y: &Arr, tol: f32) {
-    assert!(
-        x.all_close(y, tol),
-        "{:#?} not within {} of {:#?}",
-        x,
-        tol,
-        y
-    );
-}
+                    for row_idx in 0..rows {
+                        for col_idx in 0..cols {
+                            u_index.set_value(row_idx);
+                            v_index.set_value(col_idx);
 
-#[cfg(test)]
-mod tests {
+                            output.set_value(x[(row_idx, col_idx)]);
 
-    use ndarray::arr2;
+                            loss.forward();
+                            loss.backward(1.0);
 
-    use optim::{Adagrad, Optimizer, SGD};
-    use rand::distributions::{Distribution, Uniform};
-    use rand::Rng;
-    use rayon::prelude::*;
-    use std::sync::Arc;
+                            loss_val += loss.to_scalar();
 
-    use super::*;
+                            optimizer.step();
+                            loss.zero_gradient();
+                        }
+                    }
+                }
 
-    const TOLERANCE: f32 = 0.05;
+                println!("Loss val {}", loss_val);
 
-    fn random_matrix(rows: usize, cols: usize) -> Arr {
-        nn::xavier_normal(rows, cols)
-    }
+                loss_val
+            })
+            .collect();
 
-    fn random_index(rows: usize) -> usize {
-        Uniform::new(0, rows).sample(&mut rand::thread_rng())
+        let sum_loss: Float = losses.iter().sum();
+
+        assert!(sum_loss / (losses.len() as Float) < 1e-3);
     }
 
     #[test]
-    fn test_constant_sub() {
-        let mut x = ParameterNode::new(Arr::zeros((10, 10)) + 1.0);
-        let mut y = (1.0 - x.clone()) * 2.0;
+    fn set_value_from_buffers() {
+        let x = InputNode::new(Arr::zeros((2, 3)));
+        let data = random_matrix(10, 3);
+        let data_ptr = x.value().as_ptr();
 
-        assert_eq!(y.value().scalar_sum(), 0.0);
-        y.zero_gradient();
-        y.forward();
-        y.backward(1.0);
-        assert_eq!(y.value().scalar_sum(), 0.0);
+        for start in 0..8 {
+            let batch = data.slice_axis(Axis(0), Slice::from(start..start + 2));
 
-        let (difference, gradient) = finite_difference(&mut x, &mut y);
-        assert_close(&difference, &gradient, TOLERANCE);
-    }
+            x.set_value(batch);
+            assert_eq!(x.value().view(), batch);
 
-    #[test]
-    fn parameter_deduplication() {
-        let x = ParameterNode::new(random_matrix(1, 1));
-        let y = ParameterNode::new(random_matrix(1, 1));
+            x.set_value(batch.to_owned().as_slice().unwrap());
+            assert_eq!(x.value().view(), batch);
 
-        let z = x + y;
-        let z = z.clone() + z.clone();
+            // The input is filled in place.
+            assert_eq!(x.value().as_ptr(), data_ptr);
+        }
 
-        assert_eq!(z.parameters().len(), 2);
-    }
+        let index = IndexInputNode::new(&[0, 0, 0, 0]);
+        let index_ptr = index.value().as_ptr();
 
-    #[test]
-    fn add_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(1, 1));
-        let mut y = ParameterNode::new(random_matrix(1, 1));
-        let mut z = x.clone() + y.clone() + x.clone() + x.clone();
+        index.set_value(&[3u32, 1][..]);
+        assert_eq!(&index.value()[..], &[3, 1]);
 
-        let (difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
-        let (difference, gradient) = finite_difference(&mut y, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
+        index.set_value(2..6);
+        assert_eq!(&index.value()[..], &[2, 3, 4, 5]);
+        assert_eq!(index.value().as_ptr(), index_ptr);
     }
     #[test]
-    fn sub_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(1, 1));
-        let mut y = ParameterNode::new(random_matrix(1, 1));
-        let z = x.clone() - (y.clone() - x.clone());
-        let mut z = z.clone() * 2.0 + z.clone().sigmoid();
+    fn split_finite_difference() {
+        let x = ParameterNode::new(random_matrix(5, 6));
+        let pieces = (x.clone() * x.clone()).split(&[2, 4]);
 
-        let (difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
-        let (difference, gradient) = finite_difference(&mut y, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
-    }
-    #[test]
-    fn mul_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 10));
-        let mut y = ParameterNode::new(random_matrix(10, 10));
-        let z = x.clone() * y.clone();
-        let mut z = z.clone() + z.clone();
+        for piece in &pieces {
+            assert_eq!(piece.value().dim(), (5, 2));
+        }
 
-        let (difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
-        let (difference, gradient) = finite_difference(&mut y, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
+        // Use the pieces unevenly so that each column block
+        // receives a distinct gradient.
+        let weights = InputNode::new(random_matrix(5, 2));
+        let mut z = (pieces[0].clone() * weights.clone()).sigmoid()
+            + pieces[1].clone() * pieces[1].clone()
+            + pieces[2].clone().tanh() * weights
+            + pieces[0].clone();
+
+        check_gradients(&mut z, &x, EPSILON, TOLERANCE).unwrap();
+
+        // Re-stacking the pieces recovers the operand and its gradient.
+        let x = ParameterNode::new(random_matrix(5, 6));
+        let pieces = x.split(&[2, 4]);
+        let mut y = pieces[0]
+            .stack(&pieces[1], Axis(1))
+            .stack(&pieces[2], Axis(1))
+            .scalar_sum();
+        y.forward();
+        y.backward(1.0);
+        assert_eq!(
+            pieces[0]
+                .stack(&pieces[1], Axis(1))
+                .stack(&pieces[2], Axis(1))
+                .value()
+                .deref(),
+            x.value().deref()
+        );
+        assert_close(&x.gradient(), &Arr::ones((5, 6)), TOLERANCE);
     }
     #[test]
-    fn div_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(1, 1));
-        let y = ParameterNode::new(random_matrix(1, 1));
-        let mut z = (x.clone() + x.clone()) / y.clone();
+    fn stability_epsilon() {
+        let divide = |config: StabilityConfig| {
+            set_stability(config);
+            let x = ParameterNode::new(arr2(&[[1.0, 1.0]]));
+            let y = InputNode::new(arr2(&[[1e-8, 2.0]]));
+            let mut z = (x.clone() / y).scalar_sum();
+            set_stability(StabilityConfig::default());
+
+            z.forward();
+            z.backward(1.0);
+            (z.to_scalar(), x.gradient())
+        };
 
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&finite_difference, &gradient, TOLERANCE);
+        let (tiny, tiny_gradient) = divide(StabilityConfig::default().div_epsilon(1e-10));
+        let (large, large_gradient) = divide(StabilityConfig::default().div_epsilon(1e-2));
+
+        assert!((tiny - 1e8).abs() / 1e8 < 1e-3);
+        assert!((large - 100.5).abs() < 1e-3);
+        assert!((tiny_gradient[(0, 0)] - 1e8).abs() / 1e8 < 1e-3);
+        assert!((large_gradient[(0, 0)] - 100.0).abs() < 1e-3);
+        assert_eq!(tiny_gradient[(0, 1)], large_gradient[(0, 1)]);
+        assert_eq!(stability(), StabilityConfig::default());
+
+        // Division by zero keeps the sign of the zero.
+        set_stability(StabilityConfig::default().div_epsilon(0.5));
+        let z = InputNode::new(arr2(&[[1.0, 1.0]])) / InputNode::new(arr2(&[[0.0, -0.0]]));
+        set_stability(StabilityConfig::default());
+        assert_eq!(z.value().deref(), &arr2(&[[2.0, -2.0]]));
+
+        // Division is unguarded by default.
+        let tiny = (2.0 as Float).powi(-30);
+        let z = InputNode::new(arr2(&[[1.0, 1.0]])) / InputNode::new(arr2(&[[0.0, tiny]]));
+        assert_eq!(z.value().deref(), &arr2(&[[Float::INFINITY, 1.0 / tiny]]));
     }
     #[test]
-    fn vector_dot_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let mut y = ParameterNode::new(random_matrix(10, 5));
-        let z = x.vector_dot(&y);
-        let mut z = z.clone() + z.clone();
-
-        let (difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
-
-        let (difference, gradient) = finite_difference(&mut y, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
+    fn clone_architecture_div_epsilon() {
+        set_stability(StabilityConfig::default().div_epsilon(0.5));
+        let x = ParameterNode::new(arr2(&[[1.0, 1.0]]));
+        let y = InputNode::new(arr2(&[[0.0, 2.0]]));
+        let z = (x / y).scalar_sum();
+        set_stability(StabilityConfig::default());
+
+        let mut copy = z.clone_architecture(CloneMode::CopyValues).unwrap();
+        copy.forward();
+        assert_eq!(copy.to_scalar(), 2.5);
     }
     #[test]
-    fn dot_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let mut y = ParameterNode::new(random_matrix(5, 10));
-        let mut z = (x.clone() + x.clone()).dot(&y);
+    fn linearized_passes() {
+        let embeddings = ParameterNode::new(random_matrix(10, 4));
+        let u_index = IndexInputNode::new(&[1, 3]);
+        let v_index = IndexInputNode::new(&[2, 2]);
+        let x = ParameterNode::new(random_matrix(2, 3));
+        let w = ParameterNode::new(random_matrix(3, 1));
+
+        // Diamonds through the shared score, hidden and sigmoid nodes.
+        let score = embeddings
+            .index(&u_index)
+            .vector_dot(&embeddings.index(&v_index));
+        let hidden = x.dot(&w);
+        let sigmoid = (hidden.clone() + score.clone()).sigmoid();
+        let loss = (sigmoid.clone() * sigmoid.clone() - hidden * sigmoid + score).scalar_sum();
+
+        let mut recursive = loss.clone();
+        let mut linear = loss;
+        assert!(linear.linearize());
+
+        for &(u, v) in &[(1usize, 3usize), (2, 2), (0, 9), (4, 4)] {
+            u_index.set_value(&[u, v][..]);
+            v_index.set_value(&[v, u][..]);
+
+            recursive.forward();
+            recursive.backward(1.0);
+            let value = recursive.value().clone();
+            let gradients = (embeddings.gradient(), x.gradient(), w.gradient());
+            recursive.zero_gradient();
+
+            linear.forward();
+            linear.backward(1.0);
+            // Gradients may be summed in a different order.
+            assert_eq!(linear.value().deref(), &value);
+            assert_close(&embeddings.gradient(), &gradients.0, 1e-5);
+            assert_close(&x.gradient(), &gradients.1, 1e-5);
+            assert_close(&w.gradient(), &gradients.2, 1e-5);
+            linear.zero_gradient();
+        }
 
-        let (difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
+        check_gradients(&mut linear, &x, EPSILON, TOLERANCE).unwrap();
 
-        let (difference, gradient) = finite_difference(&mut y, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
+        // Nodes without linearized passes keep the graph recursive.
+        let mut unsupported = x.tanh().scalar_sum();
+        assert!(!unsupported.linearize());
     }
     #[test]
-    fn dot_accumulation_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let mut y = ParameterNode::new(random_matrix(5, 10));
-        let z = x.clone().dot(&y);
-        let mut v = z.clone() * z.clone();
-
-        let (difference, gradient) = finite_difference(&mut x, &mut v);
-        assert_close(&difference, &gradient, TOLERANCE);
+    fn shape_errors() {
+        fn check<T>(
+            result: Result<T, ShapeError>,
+            op: &str,
+            lhs: (usize, usize),
+            rhs: (usize, usize),
+        ) {
+            match result {
+                Err(error) => {
+                    assert_eq!(error.op, op);
+                    assert_eq!(error.lhs, lhs);
+                    assert_eq!(error.rhs, rhs);
+                }
+                Ok(_) => panic!("{} should have failed", op),
+            }
+        }
 
-        let (difference, gradient) = finite_difference(&mut y, &mut v);
-        assert_close(&difference, &gradient, TOLERANCE);
+        let x = ParameterNode::new(random_matrix(2, 3));
+        let y = ParameterNode::new(random_matrix(3, 2));
+
+        check(x.try_add(&y), "Add", (2, 3), (3, 2));
+        check(x.try_sub(&y), "Sub", (2, 3), (3, 2));
+        check(x.try_mul(&y), "Mul", (2, 3), (3, 2));
+        check(x.try_div(&y), "Div", (2, 3), (3, 2));
+        check(x.try_hypot(&y), "Hypot", (2, 3), (3, 2));
+        check(x.try_vector_dot(&y), "VectorDot", (2, 3), (3, 2));
+        check(x.try_dot(&x), "Dot", (2, 3), (2, 3));
+        check(x.try_stack(&y, Axis(0)), "Concatenate", (2, 3), (3, 2));
+        check(x.try_stack(&y, Axis(1)), "Concatenate", (2, 3), (3, 2));
+
+        assert!(x.try_dot(&y).is_ok());
+        assert!(x.try_add(&x).is_ok());
+        assert!(x.try_stack(&x, Axis(0)).is_ok());
     }
     #[test]
-    fn square_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let mut z = x.square();
-
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&finite_difference, &gradient, TOLERANCE);
+    #[should_panic(expected = "Incompatible operand shapes for Dot: (2, 3) and (2, 3).")]
+    fn dot_shape_mismatch() {
+        let x = ParameterNode::new(random_matrix(2, 3));
+        x.dot(&x);
     }
     #[test]
-    fn ln_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(2, 2));
-        let mut z = (x.clone() + x.clone()).exp().ln();
-
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&finite_difference, &gradient, TOLERANCE);
+    #[should_panic(expected = "Expected 6 values for an input of shape (2, 3), got 4.")]
+    fn set_value_wrong_length() {
+        let x = InputNode::new(Arr::zeros((2, 3)));
+        x.set_value(&[1.0, 2.0, 3.0, 4.0][..]);
     }
     #[test]
-    fn tanh_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(2, 2));
-        let mut z = (x.clone() + x.clone()).tanh();
-
-        let (difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
+    #[should_panic(expected = "Expected a view of shape (2, 3), got (3, 2).")]
+    fn set_value_wrong_shape() {
+        let x = InputNode::new(Arr::zeros((2, 3)));
+        x.set_value(Arr::zeros((3, 2)).view());
     }
     #[test]
-    fn sum_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let mut z = (x.clone() + x.clone()).scalar_sum();
-
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&finite_difference, &gradient, TOLERANCE * 2.0);
+    fn to_scalar_values() {
+        let x = InputNode::new(arr2(&[[1.0, 2.0], [3.0, 4.0]]));
+        let sum = x.scalar_sum();
+        sum.forward();
+
+        assert_eq!(sum.to_scalar(), 10.0);
+        assert_eq!(sum.try_to_scalar(), Some(10.0));
+        assert_eq!(x.try_to_scalar(), None);
     }
     #[test]
-    fn squared_sum_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let mut z = x.square().scalar_sum();
-
-        let (difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
+    #[should_panic(expected = "Expected a scalar of shape (1, 1), got (2, 1).")]
+    fn to_scalar_wrong_shape() {
+        let x = InputNode::new(Arr::zeros((2, 1)));
+        x.to_scalar();
     }
     #[test]
-    fn transpose_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let mut z = (x.clone() + x.clone()).t();
+    fn forward_hook_capture() {
+        let x = InputNode::new(random_matrix(2, 3));
+        let w = ParameterNode::new(random_matrix(3, 4));
+
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        let (hidden, activation) = x
+            .dot(&w)
+            .tanh()
+            .register_forward_hook(move |_| counter.set(counter.get() + 1))
+            .capture();
+
+        // Both consumers of the hidden layer share one evaluation.
+        let mut loss = (hidden.clone() + hidden.clone()).scalar_sum();
+
+        for batch_size in &[2, 5] {
+            x.set_value(&random_matrix(*batch_size, 3));
+            loss.forward();
+            assert_eq!(activation.borrow().deref(), hidden.value().deref());
 
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&finite_difference, &gradient, TOLERANCE);
-    }
-    #[test]
-    fn exp_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let mut z = (x.clone() + x.clone()).exp();
+            loss.backward(1.0);
+            assert!(w.gradient().iter().any(|&x| x != 0.0));
+            loss.zero_gradient();
+        }
 
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&finite_difference, &gradient, TOLERANCE);
+        assert_eq!(calls.get(), 2);
     }
     #[test]
-    fn dot_square_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let y = ParameterNode::new(random_matrix(10, 5));
-        let mut z = x.vector_dot(&y).square();
+    fn backward_for_subset() {
+        let embedding = ParameterNode::new(random_matrix(5, 3));
+        let w = ParameterNode::new(random_matrix(3, 2));
+        let v = ParameterNode::new(random_matrix(2, 2));
+        let index = IndexInputNode::new(&[0, 3]);
 
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&finite_difference, &gradient, TOLERANCE);
-    }
-    #[test]
-    fn sigmoid_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let z = (x.clone() + x.clone()).sigmoid();
-        let mut z = z.clone() + z.clone();
+        let mut loss = embedding.index(&index).dot(&w).dot(&v).square().scalar_sum();
 
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&finite_difference, &gradient, TOLERANCE);
-    }
-    #[test]
-    fn relu_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let z = (x.clone() + x.clone()).relu();
-        let mut z = z * 3.0;
+        loss.forward();
+        loss.backward_for(1.0, &[w.clone()]);
+
+        assert!(w.gradient().iter().any(|&x| x != 0.0));
+        assert!(v.gradient().iter().all(|&x| x == 0.0));
+        assert!(embedding.gradient().iter().all(|&x| x == 0.0));
 
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&finite_difference, &gradient, TOLERANCE);
+        // The full backward pass still reaches every parameter afterwards.
+        loss.zero_gradient();
+        loss.forward();
+        loss.backward(1.0);
+
+        for parameter in &[embedding, w, v] {
+            assert!(parameter.gradient().iter().any(|&x| x != 0.0));
+        }
     }
     #[test]
-    fn neg_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let mut z = -(x.clone() + x.clone());
-
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&finite_difference, &gradient, TOLERANCE);
+    fn backward_for_panic() {
+        let w = ParameterNode::new(random_matrix(3, 2));
+        let v = ParameterNode::new(random_matrix(2, 2));
+        let mut loss = w.dot(&v).scalar_sum();
+
+        // Backpropagating without a forward pass panics...
+        let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            loss.backward_for(1.0, &[w.clone()])
+        }));
+        assert!(result.is_err());
+
+        // ...and gradients into the other parameters are turned back on.
+        assert!(v.node.gradient_enabled());
     }
     #[test]
-    fn softmax_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(1, 10));
-        let mut z = (x.clone() + x.clone()).softmax();
+    fn backward_with_seed() {
+        let x = ParameterNode::new(random_matrix(6, 4));
+        let w = ParameterNode::new(random_matrix(4, 1));
+        let weights = InputNode::new(random_matrix(6, 1));
+
+        // Seeding the per-example losses with weights...
+        let mut losses = x.dot(&w).square();
+        losses.forward();
+        losses.backward_with(&weights.value());
+        let seeded: Vec<_> = losses.parameters().iter().map(|p| p.gradient()).collect();
+        losses.zero_gradient();
+
+        // ...matches weighting them inside the graph.
+        let mut weighted = (losses.clone() * weights.clone()).scalar_sum();
+        weighted.forward();
+        weighted.backward(1.0);
+
+        for (seeded, parameter) in seeded.iter().zip(weighted.parameters()) {
+            assert_close(seeded, &parameter.gradient(), 1e-5);
+        }
+        weighted.zero_gradient();
+
+        // A scalar weight is broadcast to every element of the output.
+        losses.forward();
+        losses.backward(2.0);
+        let broadcast: Vec<_> = losses.parameters().iter().map(|p| p.gradient()).collect();
+        losses.zero_gradient();
+
+        let mut summed = losses.scalar_sum();
+        summed.forward();
+        summed.backward(2.0);
 
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&finite_difference, &gradient, TOLERANCE);
+        for (broadcast, parameter) in broadcast.iter().zip(summed.parameters()) {
+            assert_close(broadcast, &parameter.gradient(), 1e-5);
+        }
     }
     #[test]
-    fn log_softmax_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(1, 10));
-        let mut z = (x.clone() + x.clone()).log_softmax();
-        let v = (x.clone() + x.clone()).softmax().ln();
+    #[should_panic(expected = "The seed gradient must have the shape of the output.")]
+    fn backward_with_wrong_shape() {
+        let x = ParameterNode::new(random_matrix(6, 4));
+        let mut y = x.sigmoid();
 
-        assert_close(v.value().deref(), z.value().deref(), TOLERANCE);
-
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&finite_difference, &gradient, TOLERANCE);
+        y.forward();
+        y.backward_with(&Arr::zeros((4, 6)));
     }
     #[test]
-    fn sparse_categorical_cross_entropy_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(1, 10));
-        let z = x.clone() + x.clone();
-        let idx = IndexInputNode::new(&vec![0][..]);
-        let mut loss = nn::losses::sparse_categorical_crossentropy(&z, &idx);
+    fn normalize_gradient_norm() {
+        let x = ParameterNode::new(random_matrix(4, 3));
+        let hidden = x.normalize_gradient(2.0);
+
+        for &scale in &[1e-3, 1.0, 1e3] {
+            let weights = InputNode::new(random_matrix(4, 3) * scale);
+            let mut loss = (hidden.clone() * weights).scalar_sum();
 
-        let (finite_difference, gradient) = finite_difference(&mut x, &mut loss);
-        assert_close(&finite_difference, &gradient, TOLERANCE);
+            loss.forward();
+            assert_eq!(*hidden.value(), *x.value());
+
+            loss.backward(1.0);
+            let norm = x.gradient().iter().map(|x| x * x).sum::<Float>().sqrt();
+            assert!((norm - 2.0).abs() < 1e-4);
+            loss.zero_gradient();
+        }
     }
     #[test]
-    fn rowwise_stack_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let mut y = ParameterNode::new(random_matrix(10, 5));
-        //let v = x.clone() + y.clone();
+    fn compile_inference_matches_forward() {
+        let x = InputNode::new(random_matrix(4, 6));
+        let index = IndexInputNode::new(&[3, 0, 1, 3]);
+        let embedding = ParameterNode::new(random_matrix(5, 4));
+        let w1 = ParameterNode::new(random_matrix(6, 8));
+        let w2 = ParameterNode::new(random_matrix(8, 3));
+
+        let hidden = x.dot(&w1).add_bias().relu();
+        let logits = (hidden.dot(&w2) * 0.5 - 1.0).tanh();
+        let mut output = logits.log_softmax();
+        let mut pooled = (embedding.index(&index).t().stop_gradient().sigmoid()
+            / (embedding.exp() + 2.0).ln().t().dot(&embedding.square()))
+            .stack(&-embedding.softmax().t(), Axis(1))
+            .scalar_sum();
+
+        let mut graph = output.compile_inference(&[("x", &x)]).unwrap();
+        let mut pooled_graph = pooled.compile_inference(&[]).unwrap();
+
+        for &rows in &[4, 1, 7] {
+            let batch = random_matrix(rows, 6);
+            x.set_value(&batch);
+            output.forward();
+            pooled.forward();
 
-        let z = x.stack(&y, ndarray::Axis(0));
-        let mut z = z.clone().sigmoid() * z.clone().relu();
+            assert_eq!(graph.run(&[("x", &batch)]), &*output.value());
+            assert_eq!(pooled_graph.run(&[]), &*pooled.value());
 
-        assert_eq!(z.value().rows(), 20);
-        assert_eq!(z.value().cols(), 5);
+            output.zero_gradient();
+            pooled.zero_gradient();
+        }
 
-        let (difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
+        // The compiled graph holds copies of the parameters.
+        let before = graph.run(&[]).clone();
+        w1.set_value(&Arr::zeros((6, 8)));
+        assert_eq!(graph.run(&[]), &before);
 
-        let (difference, gradient) = finite_difference(&mut y, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&graph);
+
+        let error = output.softsign().compile_inference(&[]);
+        assert_eq!(
+            error.unwrap_err(),
+            "Inference compilation is not supported for Softsign nodes."
+        );
+        let y = InputNode::new(random_matrix(1, 6));
+        let error = output.compile_inference(&[("y", &y)]);
+        assert_eq!(error.unwrap_err(), "Input \"y\" is not part of the graph.");
     }
     #[test]
-    fn columnwise_stack_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let mut y = ParameterNode::new(random_matrix(10, 5));
-        //let v = x.clone() + y.clone();
-
-        let mut z = x.stack(&y, ndarray::Axis(1)).sigmoid();
+    fn clone_architecture_copies() {
+        let x = InputNode::new(random_matrix(4, 3));
+        let index = IndexInputNode::new(&[0, 2, 1, 0]);
+        let embedding = ParameterNode::new(random_matrix(3, 5));
+        let weights = ParameterNode::new(random_matrix(3, 5));
+
+        let hidden = (x.dot(&weights) + embedding.index(&index)).tanh();
+        let output = (hidden.clone() * hidden).sigmoid() * 2.0;
+
+        let mut original = output.scalar_sum();
+        let mut copy = original.clone_architecture(CloneMode::CopyValues).unwrap();
+        assert_eq!(copy.parameters().len(), 2);
+
+        original.forward();
+        copy.forward();
+        assert_eq!(*copy.value(), *original.value());
+        original.zero_gradient();
+        copy.zero_gradient();
+
+        let before = weights.value().clone();
+        let optimizer = SGD::new(copy.parameters()).learning_rate(0.1);
+
+        for _ in 0..3 {
+            copy.forward();
+            copy.backward(1.0);
+            optimizer.step();
+            copy.zero_gradient();
+        }
 
-        assert_eq!(z.value().rows(), 10);
-        assert_eq!(z.value().cols(), 10);
+        assert_eq!(*weights.value(), before);
+        assert!(copy.to_scalar() < original.to_scalar());
 
-        let (difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
+        // Shared inputs feed both graphs.
+        let (original_before, copy_before) = (original.to_scalar(), copy.to_scalar());
+        x.set_value(&random_matrix(4, 3));
+        copy.forward();
+        original.forward();
+        assert!(original.to_scalar() != original_before);
+        assert!(copy.to_scalar() != copy_before);
 
-        let (difference, gradient) = finite_difference(&mut y, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
+        let fresh = original.clone_architecture(CloneMode::FreshXavier).unwrap();
+        assert!(fresh
+            .parameters()
+            .iter()
+            .all(|parameter| *parameter.value() != before));
     }
     #[test]
-    fn sparse_index_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let idx_0 = IndexInputNode::new(&[random_index(10)]);
-        let idx_1 = IndexInputNode::new(&[random_index(10)]);
+    fn clone_architecture_inputs() {
+        let x = InputNode::new(random_matrix(2, 3));
+        let weights = ParameterNode::new(random_matrix(3, 1));
+        let output = x.dot(&weights);
+
+        let mut builder = CloneBuilder::new(CloneMode::CopyValues).share_inputs(false);
+        let mut copy = builder.clone_variable(&output).unwrap();
+        let copied_x = builder.input(&x).unwrap();
+
+        copied_x.set_value(&(random_matrix(2, 3) + 1.0));
+        copy.forward();
+        assert!(*copy.value() != x.value().dot(&*weights.value()));
+        assert_eq!(*copy.value(), copied_x.value().dot(&*weights.value()));
+
+        let error = output.softsign().clone_architecture(CloneMode::CopyValues);
+        assert_eq!(error.unwrap_err(), "Cloning is not supported for Softsign nodes.");
+    }
+    #[test]
+    fn dedup_duplicate_subgraphs() {
+        let x = InputNode::new(random_matrix(4, 3));
+        let weights = ParameterNode::new(random_matrix(3, 2));
+        let hidden = x.dot(&weights);
+        let mut output = (hidden.sigmoid() * hidden.sigmoid()).scalar_sum();
+
+        let duplicates = output.find_duplicate_subgraphs();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].op, "Sigmoid");
+        assert_eq!(duplicates[0].copies, 2);
+        assert_eq!(duplicates[0].shape, (4, 2));
+        assert!((hidden.tanh() + hidden.sigmoid()).find_duplicate_subgraphs().is_empty());
 
-        let mut z = (x.index(&idx_0).tanh() * x.index(&idx_1)).square();
+        output.forward();
+        output.backward(1.0);
+        let value = output.value().clone();
+        let gradient = weights.gradient();
+        assert_eq!(hidden.graph_node().pass_counts(), Some((2, 2)));
+        output.zero_gradient();
 
-        let (difference, gradient) = finite_difference(&mut x, &mut z);
-        assert_close(&difference, &gradient, TOLERANCE);
+        let mut deduped = output.dedup().unwrap();
+        assert!(deduped.find_duplicate_subgraphs().is_empty());
+        assert_eq!(deduped.parameters().len(), 1);
+
+        deduped.forward();
+        deduped.backward(1.0);
+        assert_eq!(*deduped.value(), value);
+        assert_close(&weights.gradient(), &gradient, TOLERANCE);
+
+        // Sum -> Mul -> Sigmoid -> Dot.
+        let description = deduped.graph_node().description();
+        let description = description.operands[0].description();
+        let sigmoid = description.operands[0];
+        assert_eq!(description.operands[1].node_address(), sigmoid.node_address());
+        let dot = sigmoid.description().operands[0];
+        assert_eq!(dot.pass_counts(), Some((1, 1)));
     }
     #[test]
-    fn univariate_regression() {
-        let slope = ParameterNode::new(random_matrix(1, 1));
-        let intercept = ParameterNode::new(random_matrix(1, 1));
+    fn shared_encoder_two_heads() {
+        let x = InputNode::new(random_matrix(4, 3));
+        let encoder = ParameterNode::new(random_matrix(3, 5));
+        let head_a = ParameterNode::new(random_matrix(5, 1));
+        let head_b = ParameterNode::new(random_matrix(5, 2));
+
+        let hidden = x.dot(&encoder).tanh();
+        let mut loss_a = hidden.dot(&head_a).square().scalar_sum();
+        let mut loss_b = hidden.dot(&head_b).sigmoid().scalar_sum();
+
+        // The gradients of each loss on its own.
+        loss_a.forward();
+        loss_a.backward(1.0);
+        let gradient_a = encoder.gradient();
+        loss_a.zero_gradient();
+
+        loss_b.forward();
+        loss_b.backward(0.5);
+        let gradient_b = encoder.gradient();
+        loss_b.zero_gradient();
+
+        for &a_first in &[true, false] {
+            loss_a.forward();
+            loss_b.forward();
+
+            if a_first {
+                loss_a.backward(1.0);
+                loss_b.backward(0.5);
+            } else {
+                loss_b.backward(0.5);
+                loss_a.backward(1.0);
+            }
 
-        let num_epochs = 200;
+            assert_close(&encoder.gradient(), &(&gradient_a + &gradient_b), 1e-5);
+            assert_eq!(loss_a.validate_with(&[loss_b.graph_node()]), Ok(()));
 
-        let x = InputNode::new(random_matrix(1, 1));
-        let y = InputNode::new(random_matrix(1, 1));
+            // Zeroing either loss first resets the shared nodes.
+            if a_first {
+                loss_a.zero_gradient();
+                loss_b.zero_gradient();
+            } else {
+                loss_b.zero_gradient();
+                loss_a.zero_gradient();
+            }
 
-        let y_hat = slope.clone() * x.clone() + intercept.clone();
-        let diff = y.clone() - y_hat.clone();
-        let mut loss = diff.square();
+            assert!(encoder.gradient().iter().all(|&x| x == 0.0));
+            assert_eq!(hidden.validate(), Ok(()));
+        }
+    }
+    #[test]
+    #[should_panic(expected = "Node forwarded again after its backward pass")]
+    fn shared_encoder_interleaved_passes() {
+        let encoder = ParameterNode::new(random_matrix(3, 5));
+        let hidden = encoder.tanh();
+        let mut loss_a = hidden.square().scalar_sum();
+        let mut loss_b = hidden.sigmoid().scalar_sum();
+
+        loss_a.forward();
+        loss_a.backward(1.0);
+        loss_b.forward();
+        loss_b.backward(1.0);
+    }
+    #[test]
+    fn gradient_hook_zeroing() {
+        let w = ParameterNode::new(random_matrix(3, 2));
+        let v = ParameterNode::new(random_matrix(2, 2));
+        let x = InputNode::new(random_matrix(4, 3));
 
-        let optimizer = Adagrad::new(loss.parameters()).learning_rate(0.5);
+        // The hook stops the gradient reaching `w`, but not `v`.
+        let hidden = x.dot(&w).register_gradient_hook(|gradient| gradient.fill(0.0));
+        let mut loss = hidden.dot(&v).square().scalar_sum();
 
-        for _ in 0..num_epochs {
-            let _x = arr2(&[[rand::thread_rng().gen()]]);
-            let _y = 0.5 * &_x + 0.2;
+        let (w_value, v_value) = (w.value().clone(), v.value().clone());
+        let optimizer = SGD::new(loss.parameters()).learning_rate(0.1);
 
-            x.set_value(&_x);
-            y.set_value(&_y);
+        loss.forward();
+        loss.backward(1.0);
+        optimizer.step();
+        loss.zero_gradient();
+
+        assert_eq!(w.value().deref(), &w_value);
+        assert!(v.value().deref() != &v_value);
+    }
+    #[test]
+    fn gradient_hook_order() {
+        let x = ParameterNode::new(random_matrix(2, 3));
+        let observed = Rc::new(RefCell::new(Vec::new()));
+
+        let (first, second) = (Rc::clone(&observed), Rc::clone(&observed));
+        let hooked = x.register_gradient_hook(move |gradient| {
+            first.borrow_mut().push(gradient.map(|x| x * x).scalar_sum().sqrt());
+            gradient.map_inplace(|x| *x *= 2.0);
+        });
+        hooked.add_gradient_hook(move |gradient| {
+            second.borrow_mut().push(gradient.map(|x| x * x).scalar_sum().sqrt());
+        });
+
+        // The hooked node has two consumers, so the hooks see the gradient
+        // summed over both: every element is 3.
+        let mut loss = (hooked.clone() + hooked.clone() * 2.0).scalar_sum();
+        loss.forward();
+        loss.backward(1.0);
+
+        let norm = (6.0 as Float * 9.0).sqrt();
+        assert_eq!(observed.borrow().len(), 2);
+        assert!((observed.borrow()[0] - norm).abs() < 1e-5);
+        assert!((observed.borrow()[1] - 2.0 * norm).abs() < 1e-5);
+        assert_close(&x.gradient(), &Arr::from_elem((2, 3), 6.0), 1e-6);
+    }
+    #[test]
+    fn jvp_bilinear() {
+        let x = ParameterNode::new(random_matrix(2, 3));
+        let a = ParameterNode::new(random_matrix(3, 4));
+        let mut y = x.dot(&a);
 
+        let x_direction = random_matrix(2, 3);
+        let a_direction = random_matrix(3, 4);
+
+        y.forward();
+        y.backward(1.0);
+        let x_gradient = x.gradient();
+
+        // The map is linear in each input separately.
+        let product = jvp(&[(&x, &x_direction)], &mut y, 1e-2);
+        assert_close(&product, &x_direction.dot(a.value().deref()), 1e-3);
+
+        // And central differences are exact for the bilinear map in both.
+        let product = jvp(&[(&x, &x_direction), (&a, &a_direction)], &mut y, 1e-2);
+        let expected =
+            x_direction.dot(a.value().deref()) + x.value().deref().dot(&a_direction);
+        assert_close(&product, &expected, 1e-3);
+
+        // The inputs and the output are restored, and the gradients
+        // left alone.
+        assert_close(
+            y.value().deref(),
+            &x.value().deref().dot(a.value().deref()),
+            1e-5,
+        );
+        assert_eq!(x.gradient(), x_gradient);
+    }
+    #[test]
+    fn tangent_hessian_vector_product() {
+        let x = InputNode::new(random_matrix(4, 3));
+        let w = ParameterNode::new(random_matrix(3, 5));
+        let v = ParameterNode::new(random_matrix(5, 2));
+
+        let hidden = x.dot(&w);
+        let activation = hidden.tanh() * hidden.sigmoid() + hidden.clone();
+        let mut loss = (activation.dot(&v) - x.dot(&w).sigmoid().dot(&v))
+            .square()
+            .scalar_sum();
+
+        let w_direction = random_matrix(3, 5);
+        let v_direction = random_matrix(5, 2);
+        let directions = [(&w, &w_direction), (&v, &v_direction)];
+
+        // Finite differences need larger steps at single precision.
+        let epsilon = if cfg!(wyrm_f64) { 1e-5 } else { 1e-3 };
+
+        let mut tangent = loss.tangent(&directions).unwrap();
+        tangent.forward();
+        tangent.backward(1.0);
+
+        let directional_derivative = tangent.value().clone();
+        let hessian_vector_products = (w.gradient(), v.gradient());
+        tangent.zero_gradient();
+
+        // The tangent is the directional derivative...
+        let expected = jvp(&directions, &mut loss, epsilon);
+        let scale = expected.scalar_sum().abs().max(1.0);
+        assert_close(&directional_derivative, &expected, TOLERANCE * scale);
+        loss.zero_gradient();
+
+        // ...and differentiating it gives Hessian-vector products.
+
+        let initial = (w.value().clone(), v.value().clone());
+        let mut gradients_at = |step: Float| {
+            w.set_value(&(&initial.0 + &(&w_direction * step)));
+            v.set_value(&(&initial.1 + &(&v_direction * step)));
             loss.forward();
             loss.backward(1.0);
-
-            optimizer.step();
+            let gradients = (w.gradient(), v.gradient());
             loss.zero_gradient();
-        }
+            gradients
+        };
 
-        println!(
-            "Predicted: {} Loss: {} Slope {} Intercept {}",
-            y_hat.value(),
-            loss.value(),
-            slope.value(),
-            intercept.value()
-        );
+        let (w_positive, v_positive) = gradients_at(epsilon);
+        let (w_negative, v_negative) = gradients_at(-epsilon);
 
-        assert!(loss.value().scalar_sum() < 1.0e-2);
+        let assert_close_relative = |x: &Arr, y: &Arr| {
+            let scale = y.fold(1.0, |scale: Float, y| scale.max(y.abs()));
+            assert_close(x, y, TOLERANCE * scale);
+        };
+        assert_close_relative(
+            &hessian_vector_products.0,
+            &((w_positive - w_negative) / (2.0 * epsilon)),
+        );
+        assert_close_relative(
+            &hessian_vector_products.1,
+            &((v_positive - v_negative) / (2.0 * epsilon)),
+        );
     }
 
     #[test]
-    fn multivariate_regression() {
-        let slope = ParameterNode::new(random_matrix(1, 3));
-        let intercept = ParameterNode::new(random_matrix(1, 1));
+    fn tangent_relu() {
+        // Keep away from the kink, where finite differences fail.
+        let x = ParameterNode::new(
+            random_matrix(4, 3).map(|&x| if x > 0.0 { x + 0.1 } else { x - 0.1 }),
+        );
+        let weights = InputNode::new(random_matrix(4, 3));
+        let mut y = (x.relu() * weights).square().scalar_sum();
 
-        let num_epochs = 200;
+        let direction = random_matrix(4, 3);
+        let epsilon = if cfg!(wyrm_f64) { 1e-5 } else { 1e-3 };
 
-        let coefficients = arr2(&[[1.0], [2.0], [3.0]]);
+        let mut tangent = y.tangent(&[(&x, &direction)]).unwrap();
+        tangent.forward();
+        tangent.backward(1.0);
+        let value = tangent.value().clone();
+        tangent.zero_gradient();
 
-        let x = InputNode::new(random_matrix(1, 3));
-        let y = InputNode::new(random_matrix(1, 1));
+        let expected = jvp(&[(&x, &direction)], &mut y, epsilon);
+        let scale = expected.scalar_sum().abs().max(1.0);
+        assert_close(&value, &expected, TOLERANCE * scale);
+    }
 
-        let y_hat = x.vector_dot(&slope) + intercept.clone();
-        let diff = y.clone() - y_hat.clone();
-        let mut loss = diff.square();
+    #[test]
+    fn inference_mode_skips_gradients() {
+        let hidden = Arc::new(HogwildParameter::new(random_matrix(3, 4)));
+        let output = Arc::new(HogwildParameter::new(random_matrix(4, 2)));
+
+        let build = || {
+            let x = InputNode::new(arr2(&[[0.5, -1.0, 2.0]]));
+            x.dot(&ParameterNode::shared(Arc::clone(&hidden)))
+                .relu()
+                .dot(&ParameterNode::shared(Arc::clone(&output)))
+                .sigmoid()
+        };
 
-        let optimizer = SGD::new(loss.parameters()).learning_rate(0.1);
+        let training = build();
+        training.forward();
+        assert!(training.summary().gradient_bytes > 0);
 
-        for _ in 0..num_epochs {
-            let _x = arr2(&[[
-                rand::thread_rng().gen(),
-                rand::thread_rng().gen(),
-                rand::thread_rng().gen(),
-            ]]);
-            let _y = &_x.dot(&coefficients) + 5.0;
+        let inference = {
+            let _guard = inference_mode();
+            {
+                let _nested = inference_mode();
+            }
+            assert!(is_inference());
+            build()
+        };
+        assert!(!is_inference());
+
+        // The mode is captured when the graph is built.
+        inference.forward();
+        assert!(!inference.needs_gradient());
+        assert_eq!(inference.summary().gradient_bytes, 0);
+        assert_eq!(inference.value().deref(), training.value().deref());
+
+        let mut loss = build().scalar_sum();
+        let _guard = inference_mode();
+        assert!(loss.needs_gradient());
+        loss.forward();
+        loss.backward(1.0);
+    }
 
-            x.set_value(&_x);
-            y.set_value(&_y);
+    #[test]
+    fn no_gradient_buffers_without_gradients() {
+        let x = InputNode::new(random_matrix(4, 3));
+        let y = InputNode::new(random_matrix(3, 2));
+        let w = ParameterNode::new(random_matrix(2, 2));
+
+        // Preprocessing over inputs only needs no gradient buffers.
+        let features = (x.dot(&y).tanh() * 2.0).exp().square();
+        let mut preprocessing = features.scalar_sum();
+        preprocessing.forward();
+        assert!(!preprocessing.needs_gradient());
+        assert_eq!(preprocessing.summary().gradient_bytes, 0);
+
+        // Nor does it when part of a graph that does, and backpropagating
+        // skips it while still updating the parameters.
+        let mut loss = features.dot(&w).scalar_sum();
+        loss.forward();
+        loss.backward(1.0);
+
+        let head = InputNode::new(features.value().clone()).dot(&w).scalar_sum();
+        assert_eq!(loss.summary().gradient_bytes, head.summary().gradient_bytes);
+        let gradient = w.gradient();
+        assert_eq!(gradient, features.value().t().dot(&Arr::ones((4, 2))));
+        loss.zero_gradient();
+
+        // Skipping the backward pass keeps the pass counters of the
+        // skipped nodes consistent for the next iteration.
+        loss.forward();
+        loss.backward(1.0);
+        assert_eq!(w.gradient(), gradient);
+    }
+    #[test]
+    #[should_panic(expected = "Cannot backpropagate through a graph built in inference mode.")]
+    fn inference_mode_backward() {
+        let mut loss = {
+            let _guard = inference_mode();
+            let x = ParameterNode::new(random_matrix(2, 3));
+            x.square().scalar_sum()
+        };
+
+        loss.forward();
+        loss.backward(1.0);
+    }
+
+    #[test]
+    fn graph_arena_reuses_buffers() {
+        let x = ParameterNode::new(random_matrix(4, 6));
+        let weights = ParameterNode::new(random_matrix(6, 6));
 
+        let run = || {
+            let mut loss = (x.dot(&weights) + x.clone()).tanh().square().scalar_sum();
             loss.forward();
             loss.backward(1.0);
-
-            optimizer.step();
+            let gradient = weights.gradient().clone();
             loss.zero_gradient();
-        }
-
-        println!(
-            "Predicted: {} Loss: {} Slope {} Intercept {}",
-            y_hat.value(),
-            loss.value(),
-            slope.value(),
-            intercept.value()
-        );
-
-        assert!(loss.value().scalar_sum() < 1.0e-1);
-    }
+            let value = loss.value().clone();
+            (value, gradient)
+        };
 
-    #[test]
-    fn embedding_factorization() {
-        let (rows, cols) = (10, 4);
+        let expected = run();
+        let arena = GraphArena::new();
 
-        let true_u = random_matrix(rows, 10);
-        let true_v = random_matrix(cols, 10);
-        let x = true_u.dot(&true_v.t());
+        {
+            let _guard = arena.enter();
 
-        let y = random_matrix(1, 1);
-        let u_input = vec![0];
-        let v_input = vec![0];
+            for _ in 0..10 {
+                assert_eq!(run(), expected);
+            }
+        }
 
-        let output = InputNode::new(y);
+        // After the first graph, every buffer comes from the pool.
+        let stats = arena.stats();
+        assert_eq!(stats.reused, 9 * stats.allocated);
+        assert_eq!(stats.returned, stats.allocated + stats.reused);
+        assert_eq!(arena.pooled(), stats.allocated);
 
-        let u_embedding = ParameterNode::new(random_matrix(rows, 10));
-        let v_embedding = ParameterNode::new(random_matrix(cols, 10));
+        // Outside the arena, nodes allocate as usual.
+        assert_eq!(run(), expected);
+        assert_eq!(arena.stats(), stats);
+    }
+    #[test]
+    fn graph_arena_does_not_alias_live_nodes() {
+        let arena = GraphArena::new();
+        let _guard = arena.enter();
 
-        let u_index = IndexInputNode::new(&u_input);
-        let v_index = IndexInputNode::new(&v_input);
+        let x = ParameterNode::new(random_matrix(3, 3));
+        let tanh = x.tanh();
+        let first = tanh.exp();
+        let mut buffers = vec![first.value().as_ptr(), tanh.value().as_ptr()];
 
-        let u_vec = u_embedding.index(&u_index);
-        let v_vec = v_embedding.index(&v_index);
+        {
+            let dropped = x.sigmoid().exp();
+            dropped.forward();
+        }
+        assert!(arena.pooled() > 0);
+
+        // Buffers of dropped nodes are handed out again, but never those
+        // of live ones.
+        let neg = -x.clone();
+        let second = neg.exp();
+        buffers.push(second.value().as_ptr());
+        buffers.push(neg.value().as_ptr());
+
+        let mut unique = buffers.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), buffers.len());
+        assert_eq!(arena.pooled(), 0);
+
+        x.set_value(&random_matrix(3, 3));
+        first.forward();
+        second.forward();
+
+        let (expected_first, expected_second) = (x.tanh().exp(), (-x.clone()).exp());
+        expected_first.forward();
+        expected_second.forward();
+        assert_eq!(first.value().deref(), expected_first.value().deref());
+        assert_eq!(second.value().deref(), expected_second.value().deref());
+    }
 
-        let y_hat = u_vec.vector_dot(&v_vec);
-        let mut loss = (output.clone() - y_hat.clone()).square();
+    #[test]
+    fn tangent_unsupported_operation() {
+        let x = ParameterNode::new(random_matrix(2, 3));
+        let y = x.softmax().scalar_sum();
 
-        let num_epochs = 200;
-        let optimizer = Adagrad::new(loss.parameters()).learning_rate(0.1);
+        assert_eq!(
+            y.tangent(&[(&x, &random_matrix(2, 3))]).unwrap_err(),
+            "Forward-mode differentiation is not supported for Softmax nodes."
+        );
 
-        let mut loss_val = 0.0;
+        // Parameters without a direction have zero tangents.
+        let y = x.tanh().scalar_sum();
+        let tangent = y.tangent(&[]).unwrap();
+        tangent.forward();
+        assert_eq!(tangent.value().deref(), &Arr::zeros((1, 1)));
+    }
+    #[test]
+    fn stop_gradient() {
+        let x = ParameterNode::new(random_matrix(1, 3));
+        let w = ParameterNode::new(random_matrix(1, 3));
+        let target = InputNode::new(random_matrix(1, 3));
 
-        for _ in 0..num_epochs {
-            loss_val = 0.0;
+        let mut loss = (x.stop_gradient() * w.clone() - target).square().scalar_sum();
+        assert_eq!(loss.parameters().len(), 1);
 
-            for row_idx in 0..rows {
-                for col_idx in 0..cols {
-                    u_index.set_value(row_idx);
-                    v_index.set_value(col_idx);
+        let initial_x = x.value().clone();
+        let initial_w = w.value().clone();
 
-                    output.set_value(x[(row_idx, col_idx)]);
+        let optimizer = SGD::new(loss.parameters()).learning_rate(0.1);
+        for _ in 0..5 {
+            loss.forward();
+            loss.backward(1.0);
+            optimizer.step();
+            loss.zero_gradient();
+        }
 
-                    loss.forward();
-                    loss.backward(1.0);
+        assert_eq!(x.value().deref(), &initial_x);
+        assert!(w.value().deref() != &initial_w);
 
-                    loss_val += loss.value().scalar_sum();
+        // Gradients still flow through other uses of a shared node.
+        let y = x.tanh();
+        let mut z = (y.clone() + y.stop_gradient()).scalar_sum();
+        for _ in 0..2 {
+            z.forward();
+            z.backward(1.0);
 
-                    optimizer.step();
-                    loss.zero_gradient();
-                }
-            }
+            let expected = initial_x.map(|x| 1.0 - x.tanh().powi(2));
+            assert_close(&x.dense_gradient().unwrap(), &expected, 1e-5);
 
-            println!("Loss {}", loss_val)
+            z.zero_gradient();
         }
-
-        assert!(loss_val < 1e-2);
     }
-
     #[test]
-    fn hogwild_embedding_factorization() {
+    fn half_precision_embedding_factorization() {
         let (rows, cols) = (10, 4);
 
         let true_u = random_matrix(rows, 10);
         let true_v = random_matrix(cols, 10);
         let x = true_u.dot(&true_v.t());
 
-        let u_input = vec![0];
-        let v_input = vec![0];
-
-        let u_parameters = Arc::new(HogwildParameter::new(random_matrix(rows, 10)));
-        let v_parameters = Arc::new(HogwildParameter::new(random_matrix(cols, 10)));
-
-        let losses: Vec<f32> = (0..rayon::current_num_threads())
-            .into_par_iter()
-            .map(|_| {
-                let u_embedding = ParameterNode::shared(u_parameters.clone());
-                let v_embedding = ParameterNode::shared(v_parameters.clone());
-
-                let u_index = IndexInputNode::new(&u_input);
-                let v_index = IndexInputNode::new(&v_input);
-                let output = InputNode::new(random_matrix(1, 1));
-
-                let u_vec = u_embedding.index(&u_index);
-                let v_vec = v_embedding.index(&v_index);
-
-                let y_hat = u_vec.vector_dot(&v_vec);
-                let mut loss = (output.clone() - y_hat.clone()).square();
-
-                let num_epochs = 100;
+        let initial_u = random_matrix(rows, 10);
+        let initial_v = random_matrix(cols, 10);
 
-                let optimizer = SGD::new(loss.parameters());
+        let train = |u_parameters: HogwildParameter, v_parameters: HogwildParameter| {
+            let u_embedding = ParameterNode::shared(Arc::new(u_parameters));
+            let v_embedding = ParameterNode::shared(Arc::new(v_parameters));
 
-                let mut loss_val = 0.0;
+            let u_index = IndexInputNode::new(&[0]);
+            let v_index = IndexInputNode::new(&[0]);
+            let output = InputNode::new(random_matrix(1, 1));
 
-                for _ in 0..num_epochs {
-                    loss_val = 0.0;
+            let y_hat = u_embedding
+                .index(&u_index)
+                .vector_dot(&v_embedding.index(&v_index));
+            let mut loss = (output.clone() - y_hat.clone()).square();
 
-                    for row_idx in 0..rows {
-                        for col_idx in 0..cols {
-                            u_index.set_value(row_idx);
-                            v_index.set_value(col_idx);
+            let optimizer = Adagrad::new(loss.parameters()).learning_rate(0.1);
+            let mut loss_val = 0.0;
 
-                            output.set_value(x[(row_idx, col_idx)]);
+            for _ in 0..50 {
+                loss_val = 0.0;
 
-                            loss.forward();
-                            loss.backward(1.0);
+                for row_idx in 0..rows {
+                    for col_idx in 0..cols {
+                        u_index.set_value(row_idx);
+                        v_index.set_value(col_idx);
+                        output.set_value(x[(row_idx, col_idx)]);
 
-                            loss_val += loss.value().scalar_sum();
+                        loss.forward();
+                        loss.backward(1.0);
+                        loss_val += loss.to_scalar();
 
-                            optimizer.step();
-                            loss.zero_gradient();
-                        }
+                        optimizer.step();
+                        loss.zero_gradient();
                     }
                 }
+            }
 
-                println!("Loss val {}", loss_val);
+            (loss_val, loss.summary())
+        };
 
-                loss_val
-            })
-            .collect();
+        let (full_loss, full_summary) = train(
+            HogwildParameter::new(initial_u.clone()),
+            HogwildParameter::new(initial_v.clone()),
+        );
+        let (half_loss, half_summary) = train(
+            HogwildParameter::half(initial_u),
+            HogwildParameter::half(initial_v),
+        );
 
-        let sum_loss: f32 = losses.iter().sum();
+        assert!(full_loss < 1e-2);
+        assert!((full_loss - half_loss).abs() < 1e-3);
 
-        assert!(sum_loss / (losses.len() as f32) < 1e-3);
+        let shapes: Vec<_> = half_summary.parameters.iter().map(|x| x.shape).collect();
+        assert_eq!(shapes, vec![(rows, 10), (cols, 10)]);
+
+        for (full, half) in full_summary
+            .parameters
+            .iter()
+            .zip(half_summary.parameters.iter())
+        {
+            assert_eq!(half.bytes, full.size() * 2);
+            assert_eq!(full.bytes, full.size() * std::mem::size_of::<Float>());
+        }
     }
 }
@@ -0,0 +1,218 @@
+//! Common layers, owning their parameters and building their
+//! graphs on demand.
+use std::sync::Arc;
+
+use std::rc::Rc;
+
+use nodes::{
+    AttentionNode, HogwildParameter, IndexInputNode, IndexNode, InputNode, Node, ParameterNode,
+};
+
+use nn::xavier_normal;
+
+use {merge_parameters, Arr, Variable};
+
+/// An embedding table, whose rows are looked up by index.
+///
+/// Lookups go through `IndexNode`s, so the gradient of the table is
+/// sparse and optimizers only update the rows that were looked up.
+///
+/// ```rust
+/// # use wyrm::*;
+/// # use wyrm::nn::layers::Embedding;
+/// # use wyrm::optim::*;
+/// let embedding = Embedding::new(1000, 16);
+/// let index = IndexInputNode::new(&[3, 14, 15]);
+/// let mut loss = embedding.lookup_index(&index).square().scalar_sum();
+///
+/// let optimizer = SGD::new(loss.parameters()).learning_rate(0.1);
+///
+/// for batch in &[[3_usize, 14, 15], [9, 2, 6]] {
+///     index.set_value(&batch[..]);
+///     loss.forward();
+///     loss.backward(1.0);
+///     optimizer.step();
+///     loss.zero_gradient();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Embedding {
+    parameter: Variable<ParameterNode>,
+}
+
+impl Embedding {
+    /// Create an embedding of `num_embeddings` rows of dimension `dim`,
+    /// initialized with `xavier_normal`.
+    pub fn new(num_embeddings: usize, dim: usize) -> Self {
+        Embedding::shared(Arc::new(HogwildParameter::new(xavier_normal(
+            num_embeddings,
+            dim,
+        ))))
+    }
+
+    /// Create an embedding over a table shared with other embeddings,
+    /// as when training in several threads.
+    pub fn shared(table: Arc<HogwildParameter>) -> Self {
+        Embedding {
+            parameter: ParameterNode::shared(table),
+        }
+    }
+
+    /// Look up the rows at `indices`, as an `(indices.len(), dim)`
+    /// variable. Use `lookup_index` to change the indices of
+    /// an existing graph.
+    pub fn lookup(&self, indices: &[usize]) -> Variable<IndexNode<ParameterNode>> {
+        self.lookup_index(&IndexInputNode::new(indices))
+    }
+
+    /// Look up the rows at the indices held by `index`: setting its value
+    /// changes the rows looked up by the next forward pass.
+    pub fn lookup_index(
+        &self,
+        index: &Variable<IndexInputNode>,
+    ) -> Variable<IndexNode<ParameterNode>> {
+        self.parameter.index(index)
+    }
+
+    /// The embedding table. Lookups include it among their parameters,
+    /// so passing the loss's parameters to the optimizer suffices.
+    pub fn parameter(&self) -> &Variable<ParameterNode> {
+        &self.parameter
+    }
+}
+
+/// Scaled dot-product attention of `(n, d)` queries over `(m, d)` keys,
+/// averaging the rows of the `(m, d_v)` values: computes
+/// `softmax(Q K^T / sqrt(d)) V`, with the softmax over each row.
+///
+/// Where the optional `(n, m)` mask is zero, queries do not attend to
+/// keys, as in causal self-attention; setting the mask's value changes
+/// which keys the next forward pass attends to.
+///
+/// ```rust
+/// # use wyrm::*;
+/// # use wyrm::nn::layers::attention;
+/// let x = ParameterNode::new(nn::xavier_normal(3, 4));
+/// let causal = Arr::from_shape_fn((3, 3), |(row, col)| if col <= row { 1.0 } else { 0.0 });
+/// let causal = InputNode::new(causal);
+///
+/// let mut y = attention(&x, &x, &x, Some(&causal));
+/// y.forward();
+///
+/// // The first query only attends to the first key.
+/// assert_eq!(y.value().row(0), x.value().row(0));
+/// ```
+pub fn attention<Q, K, V>(
+    queries: &Variable<Q>,
+    keys: &Variable<K>,
+    values: &Variable<V>,
+    mask: Option<&Variable<InputNode>>,
+) -> Variable<AttentionNode<Q, K, V>>
+where
+    Q: Node<Value = Arr, InputGradient = Arr>,
+    K: Node<Value = Arr, InputGradient = Arr>,
+    V: Node<Value = Arr, InputGradient = Arr>,
+{
+    let node = AttentionNode::new(
+        Rc::clone(&queries.node),
+        Rc::clone(&keys.node),
+        Rc::clone(&values.node),
+        mask.map(|mask| Rc::clone(&mask.node)),
+    );
+    let parameters = merge_parameters(&queries.parameters, &keys.parameters);
+
+    Variable::new(
+        Rc::new(node),
+        merge_parameters(&parameters, &values.parameters),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Deref;
+
+    use ndarray::arr2;
+
+    use super::*;
+    use optim::{Optimizer, SGD};
+    use testing::check_gradients;
+    use {DataInput, Float};
+
+    const EPSILON: Float = 1e-3;
+    const TOLERANCE: Float = 0.05;
+
+    #[test]
+    fn embedding_updates_touched_rows() {
+        let embedding = Embedding::new(10, 4);
+        let initial = embedding.parameter().value().clone();
+
+        let first = embedding.lookup(&[1, 3]);
+        let second = embedding.lookup(&[3, 7]);
+        let mut loss = (first + second).square().scalar_sum();
+        assert_eq!(loss.parameters().len(), 1);
+
+        let optimizer = SGD::new(vec![embedding.parameter().clone()]).learning_rate(0.1);
+
+        let mut losses = Vec::new();
+
+        for _ in 0..5 {
+            loss.forward();
+            losses.push(loss.to_scalar());
+            loss.backward(1.0);
+            optimizer.step();
+            loss.zero_gradient();
+        }
+
+        let value = embedding.parameter().value();
+
+        for row in 0..10 {
+            let changed = value.row(row) != initial.row(row);
+            assert_eq!(changed, [1, 3, 7].contains(&row), "Row {}", row);
+        }
+
+        assert!(losses[4] < losses[0]);
+    }
+    #[test]
+    fn attention_finite_difference() {
+        let queries = ParameterNode::new(xavier_normal(2, 3));
+        let keys = ParameterNode::new(xavier_normal(4, 3));
+        let values = ParameterNode::new(xavier_normal(4, 2));
+        let mask = InputNode::new(Arr::from_shape_fn((2, 4), |(row, col)| {
+            if col <= row + 2 {
+                1.0
+            } else {
+                0.0
+            }
+        }));
+
+        for mask in &[None, Some(&mask)] {
+            let mut z = attention(&queries, &keys, &values, *mask).square();
+
+            check_gradients(&mut z, &queries, EPSILON, TOLERANCE).unwrap();
+            check_gradients(&mut z, &keys, EPSILON, TOLERANCE).unwrap();
+            check_gradients(&mut z, &values, EPSILON, TOLERANCE).unwrap();
+        }
+    }
+    #[test]
+    fn attention_masking() {
+        let queries = ParameterNode::new(xavier_normal(2, 3));
+        let keys = ParameterNode::new(xavier_normal(3, 3));
+        let values = ParameterNode::new(xavier_normal(3, 2));
+        let mask = InputNode::new(Arr::ones((2, 3)));
+
+        let unmasked = attention(&queries, &keys, &values, None);
+        let masked = attention(&queries, &keys, &values, Some(&mask));
+        unmasked.forward();
+        masked.forward();
+        assert_eq!(masked.value().deref(), unmasked.value().deref());
+        masked.zero_gradient();
+
+        // Attending to a single key copies its value; attending to
+        // none gives zeros.
+        mask.set_value(&arr2(&[[0.0, 1.0, 0.0], [0.0, 0.0, 0.0]]));
+        masked.forward();
+        assert_ne!(masked.value().deref(), unmasked.value().deref());
+        assert_eq!(masked.value().row(0), values.value().row(1));
+        assert!(masked.value().row(1).iter().all(|&x| x == 0.0));
+    }
+}
@@ -3,15 +3,22 @@ use std::cell::{Ref, RefCell};
 use std::ops::Deref;
 use std::rc::Rc;
 
-use nodes::{BackwardAction, Bor, ForwardAction, IndexInputNode, LogSoftmaxNode, PassCounter};
+use nodes::{
+    fit_shape, gradient_buffer, Attribute, BackwardAction, Bor, Description, ForwardAction,
+    IndexInputNode, LogSoftmaxNode, LogSoftmaxRowsNode, PassCounter,
+};
 use numerics;
-use {Arr, Node, Variable};
+use {merge_parameters, Arr, Float, Node, Variable};
 
 /// Sparse categorical cross entropy loss.
 ///
 /// Note that this performs a log-softmax operation
 /// internally, so there is no need to perform a softmax
 /// manually.
+///
+/// For a batch of `(n, classes)` logits, `y` holds the class of each
+/// row and the loss is summed over the batch. A single row of logits
+/// may instead be given several classes.
 pub fn sparse_categorical_crossentropy<T>(
     x: &Variable<T>,
     y: &Variable<IndexInputNode>,
@@ -27,7 +34,7 @@ where
 #[derive(Debug)]
 pub struct SparseCategoricalCrossentropyNode<LHS> {
     operand: Rc<LHS>,
-    log_softmax: LogSoftmaxNode<LHS>,
+    log_softmax: LogSoftmaxRowsNode<LHS>,
     y: Rc<IndexInputNode>,
     loss_value: RefCell<Arr>,
     gradient: RefCell<Arr>,
@@ -40,34 +47,201 @@ where
     LHS: Node<Value = Arr, InputGradient = Arr>,
 {
     pub fn new(operand: Rc<LHS>, y: Rc<IndexInputNode>) -> Self {
+        let log_softmax = LogSoftmaxRowsNode::new(Rc::clone(&operand));
+        let scalar_loss = crossentropy(&log_softmax.value(), &y.value());
+
+        let mut loss_value = Arr::zeros((1, 1));
+        loss_value.fill(scalar_loss);
+
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(operand.value().dim(), needs_gradient);
+
+        SparseCategoricalCrossentropyNode {
+            operand: operand,
+            log_softmax: log_softmax,
+            y: y,
+            loss_value: RefCell::new(loss_value),
+            gradient: RefCell::new(gradient),
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+
+    pub fn predictions(&self) -> Bor<Arr> {
+        self.log_softmax.value()
+    }
+}
+
+impl<LHS> Node for SparseCategoricalCrossentropyNode<LHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.log_softmax.forward();
+        self.y.forward();
+
+        let loss_value = crossentropy(&self.log_softmax.value(), &self.y.value());
+        self.loss_value.borrow_mut().fill(loss_value);
+    }
+    /// The backpropagation mechanics for this node are a little strange,
+    /// because it uses the log-softmax node for the forward pass but not
+    /// for the backward pass.
+    fn backward(&self, _: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        // TODO: actually use the input gradient
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let mut gradient = self.gradient.borrow_mut();
+            let value = self.log_softmax.value();
+            fit_shape(&mut gradient, value.dim());
+
+            {
+                let gradient_slice = gradient.as_slice_mut().unwrap();
+                let value_slice = value.as_slice().unwrap();
+
+                for (grad, &val) in izip!(gradient_slice.iter_mut(), value_slice.iter()) {
+                    *grad = beta * *grad + numerics::exp(val);
+                }
+            }
+
+            for (row, idx) in targets(value.rows(), &self.y.value()) {
+                gradient[(row, idx)] -= 1.0;
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.loss_value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.log_softmax.zero_counter();
+            self.y.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("SparseCategoricalCrossentropy")
+            .operand(&*self.operand)
+            .operand(&*self.y)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.gradient.borrow()]
+    }
+}
+
+/// The `(row, class)` positions of the targets in `y`: one class per
+/// row, or any number of classes for a single row.
+fn targets<'a>(rows: usize, y: &'a [usize]) -> impl Iterator<Item = (usize, usize)> + 'a {
+    assert!(
+        rows == 1 || rows == y.len(),
+        "Expected one class per row: got {} classes for {} rows.",
+        y.len(),
+        rows
+    );
+
+    y.iter()
+        .enumerate()
+        .map(move |(row, &idx)| (if rows == 1 { 0 } else { row }, idx))
+}
+
+/// The cross-entropy of the targets in `y`, summed over the batch.
+fn crossentropy(log_softmax: &Arr, y: &[usize]) -> Float {
+    targets(log_softmax.rows(), y)
+        .map(|position| -log_softmax[position])
+        .sum()
+}
+
+/// Softmax focal loss, `-alpha * (1 - p_t)^gamma * log(p_t)`.
+///
+/// Down-weights well-classified examples relative to the
+/// standard cross-entropy, to which it reduces when `gamma` is
+/// 0 and `alpha` is 1. As with `sparse_categorical_crossentropy`,
+/// the log-softmax is performed internally.
+pub fn focal_loss<T>(
+    x: &Variable<T>,
+    y: &Variable<IndexInputNode>,
+    gamma: Float,
+    alpha: Float,
+) -> Variable<FocalLossNode<T>>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    let node = FocalLossNode::new(Rc::clone(&x.node), Rc::clone(&y.node), gamma, alpha);
+
+    Variable::new(Rc::new(node), x.parameters.clone())
+}
+
+#[derive(Debug)]
+pub struct FocalLossNode<LHS> {
+    operand: Rc<LHS>,
+    log_softmax: LogSoftmaxNode<LHS>,
+    y: Rc<IndexInputNode>,
+    gamma: Float,
+    alpha: Float,
+    loss_value: RefCell<Arr>,
+    gradient: RefCell<Arr>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS> FocalLossNode<LHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    pub fn new(operand: Rc<LHS>, y: Rc<IndexInputNode>, gamma: Float, alpha: Float) -> Self {
         assert!(
             operand.value().rows() == 1,
             "Minibatches not supported: rows must be 1."
         );
+        assert!(gamma >= 0.0, "Focusing parameter gamma must be non-negative.");
 
         let log_softmax = LogSoftmaxNode::new(Rc::clone(&operand));
         let scalar_loss = {
             let log_softmax_value = log_softmax.value();
 
-            let mut scalar_loss = 0.0;
-
-            for &idx in y.value().iter() {
-                scalar_loss += -log_softmax_value[(0, idx)];
-            }
-
-            scalar_loss
+            y.value()
+                .iter()
+                .map(|&idx| focal_loss_value(log_softmax_value[(0, idx)], gamma, alpha))
+                .sum()
         };
 
         let mut loss_value = Arr::zeros((1, 1));
         loss_value.fill(scalar_loss);
 
-        let gradient = operand.value().deref() * 0.0;
         let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(operand.value().dim(), needs_gradient);
 
-        SparseCategoricalCrossentropyNode {
+        FocalLossNode {
             operand: operand,
             log_softmax: log_softmax,
             y: y,
+            gamma: gamma,
+            alpha: alpha,
             loss_value: RefCell::new(loss_value),
             gradient: RefCell::new(gradient),
             needs_gradient: needs_gradient,
@@ -80,7 +254,29 @@ where
     }
 }
 
-impl<LHS> Node for SparseCategoricalCrossentropyNode<LHS>
+fn focal_loss_value(log_p: Float, gamma: Float, alpha: Float) -> Float {
+    let p = numerics::exp(log_p);
+
+    -alpha * (1.0 - p).powf(gamma) * log_p
+}
+
+/// Derivative of the focal loss with respect to `log(p_t)`.
+fn focal_loss_derivative(log_p: Float, gamma: Float, alpha: Float) -> Float {
+    let p = numerics::exp(log_p);
+    let complement = 1.0 - p;
+
+    // The modulating factor's contribution vanishes at gamma = 0;
+    // skip it to avoid 0 * inf when p_t is exactly 1.
+    let modulating = if gamma == 0.0 {
+        0.0
+    } else {
+        gamma * p * log_p * complement.max(Float::EPSILON).powf(gamma - 1.0)
+    };
+
+    alpha * (modulating - complement.powf(gamma))
+}
+
+impl<LHS> Node for FocalLossNode<LHS>
 where
     LHS: Node<Value = Arr, InputGradient = Arr>,
 {
@@ -95,44 +291,53 @@ where
         self.log_softmax.forward();
         self.y.forward();
 
-        let softmax_value = self.log_softmax.value();
-        debug_assert!(
-            softmax_value.rows() == 1,
-            "Minibatches not supported: rows must be 1."
-        );
-        let softmax_slice = softmax_value.deref().as_slice().unwrap();
-
-        let mut loss_value = 0.0;
+        let log_softmax_value = self.log_softmax.value();
+        let log_softmax_slice = log_softmax_value.deref().as_slice().unwrap();
 
-        for &idx in self.y.value().iter() {
-            loss_value += -softmax_slice[idx];
-        }
+        let loss_value = self.y
+            .value()
+            .iter()
+            .map(|&idx| focal_loss_value(log_softmax_slice[idx], self.gamma, self.alpha))
+            .sum();
 
         self.loss_value.borrow_mut().fill(loss_value);
     }
-    /// The backpropagation mechanics for this node are a little strange,
-    /// because it uses the log-softmax node for the forward pass but not
-    /// for the backward pass.
-    fn backward(&self, _: &Ref<Self::InputGradient>) {
-        // TODO: actually use the input gradient
+    /// As with the cross-entropy node, the log-softmax node is used
+    /// for the forward pass only: the gradient with respect to the
+    /// logits is computed directly.
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
         let beta = match self.counter.backward() {
             BackwardAction::Set => 0.0,
             BackwardAction::Increment => 1.0,
         };
 
         {
+            let incoming = gradient[(0, 0)];
+
             let mut gradient = self.gradient.borrow_mut();
             let gradient_slice = gradient.as_slice_mut().unwrap();
 
             let value = self.log_softmax.value();
             let value_slice = value.as_slice().unwrap();
 
-            for (grad, &val) in izip!(gradient_slice.iter_mut(), value_slice.iter()) {
-                *grad = beta * *grad + numerics::exp(val);
+            for grad in gradient_slice.iter_mut() {
+                *grad *= beta;
             }
 
+            // d log(p_t) / dx_j = 1[j = t] - p_j
             for &idx in self.y.value().iter() {
-                gradient_slice[idx] -= 1.0;
+                let scale =
+                    incoming * focal_loss_derivative(value_slice[idx], self.gamma, self.alpha);
+
+                for (grad, &val) in izip!(gradient_slice.iter_mut(), value_slice.iter()) {
+                    *grad -= scale * numerics::exp(val);
+                }
+
+                gradient_slice[idx] += scale;
             }
         }
 
@@ -154,4 +359,333 @@ where
             self.counter.clear();
         }
     }
+    fn describe(&self) -> Description {
+        Description::new("FocalLoss")
+            .operand(&*self.operand)
+            .operand(&*self.y)
+            .attribute("gamma", Attribute::Float(self.gamma))
+            .attribute("alpha", Attribute::Float(self.alpha))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.gradient.borrow()]
+    }
+}
+
+/// Multiclass hinge (Crammer-Singer SVM) loss.
+///
+/// For logits `x` of shape `(rows, classes)` and one target class per
+/// row in `y`, computes the mean over rows of
+/// `sum_{j != y} max(0, x_j - x_y + 1)`.
+pub fn multiclass_hinge<T>(
+    x: &Variable<T>,
+    y: &Variable<IndexInputNode>,
+) -> Variable<MulticlassHingeNode<T>>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    let node = MulticlassHingeNode::new(Rc::clone(&x.node), Rc::clone(&y.node));
+
+    Variable::new(Rc::new(node), x.parameters.clone())
+}
+
+#[derive(Debug)]
+pub struct MulticlassHingeNode<LHS> {
+    operand: Rc<LHS>,
+    y: Rc<IndexInputNode>,
+    loss_value: RefCell<Arr>,
+    gradient: RefCell<Arr>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS> MulticlassHingeNode<LHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    pub fn new(operand: Rc<LHS>, y: Rc<IndexInputNode>) -> Self {
+        assert_eq!(
+            operand.value().rows(),
+            y.value().len(),
+            "There must be one target per row."
+        );
+
+        let mut loss_value = Arr::zeros((1, 1));
+        loss_value.fill(hinge_loss(&operand.value(), &y.value()));
+
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(operand.value().dim(), needs_gradient);
+
+        MulticlassHingeNode {
+            operand: operand,
+            y: y,
+            loss_value: RefCell::new(loss_value),
+            gradient: RefCell::new(gradient),
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+fn hinge_loss(x: &Arr, y: &[usize]) -> Float {
+    let mut loss = 0.0;
+
+    for (row, &target) in x.genrows().into_iter().zip(y.iter()) {
+        let target_score = row[target];
+
+        for (class, &score) in row.iter().enumerate() {
+            if class != target {
+                loss += (score - target_score + 1.0).max(0.0);
+            }
+        }
+    }
+
+    loss / x.rows() as Float
+}
+
+impl<LHS> Node for MulticlassHingeNode<LHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        self.y.forward();
+
+        self.loss_value
+            .borrow_mut()
+            .fill(hinge_loss(&self.operand.value(), &self.y.value()));
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let value = self.operand.value();
+            let mut operand_gradient = self.gradient.borrow_mut();
+            fit_shape(&mut operand_gradient, value.dim());
+            let scale = gradient[(0, 0)] / value.rows() as Float;
+
+            for (row, mut grad_row, &target) in izip!(
+                value.genrows(),
+                operand_gradient.genrows_mut(),
+                self.y.value().iter()
+            ) {
+                let target_score = row[target];
+                let mut violations = 0.0;
+
+                for (class, (&score, grad)) in row.iter().zip(grad_row.iter_mut()).enumerate() {
+                    *grad *= beta;
+
+                    // Each violated margin pushes its class down
+                    // and the target class up.
+                    if class != target && score - target_score + 1.0 > 0.0 {
+                        *grad += scale;
+                        violations += 1.0;
+                    }
+                }
+
+                grad_row[target] -= scale * violations;
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.loss_value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.y.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("MulticlassHinge")
+            .operand(&*self.operand)
+            .operand(&*self.y)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.gradient.borrow()]
+    }
+}
+
+/// Bradley-Terry (BPR) pairwise ranking loss.
+///
+/// For `winners` and `losers` scores of the same shape, each element
+/// a pair whose winner should score higher, computes the mean over
+/// pairs of `-ln(sigmoid(winner - loser))`.
+pub fn logistic_rank<W, L>(
+    winners: &Variable<W>,
+    losers: &Variable<L>,
+) -> Variable<LogisticRankNode<W, L>>
+where
+    W: Node<Value = Arr, InputGradient = Arr>,
+    L: Node<Value = Arr, InputGradient = Arr>,
+{
+    let node = LogisticRankNode::new(Rc::clone(&winners.node), Rc::clone(&losers.node));
+
+    Variable::new(
+        Rc::new(node),
+        merge_parameters(&winners.parameters, &losers.parameters),
+    )
+}
+
+#[derive(Debug)]
+pub struct LogisticRankNode<LHS, RHS> {
+    winners: Rc<LHS>,
+    losers: Rc<RHS>,
+    loss_value: RefCell<Arr>,
+    winner_gradient: RefCell<Arr>,
+    loser_gradient: RefCell<Arr>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS, RHS> LogisticRankNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    pub fn new(winners: Rc<LHS>, losers: Rc<RHS>) -> Self {
+        assert_eq!(
+            winners.value().dim(),
+            losers.value().dim(),
+            "Winner and loser scores must have the same shape."
+        );
+
+        let mut loss_value = Arr::zeros((1, 1));
+        loss_value.fill(rank_loss(&winners.value(), &losers.value()));
+
+        let needs_gradient = winners.needs_gradient() || losers.needs_gradient();
+        let winner_gradient = gradient_buffer(winners.value().dim(), needs_gradient);
+        let loser_gradient = gradient_buffer(losers.value().dim(), needs_gradient);
+
+        LogisticRankNode {
+            winners: winners,
+            losers: losers,
+            loss_value: RefCell::new(loss_value),
+            winner_gradient: RefCell::new(winner_gradient),
+            loser_gradient: RefCell::new(loser_gradient),
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+fn rank_loss(winners: &Arr, losers: &Arr) -> Float {
+    let loss: Float = winners
+        .iter()
+        .zip(losers.iter())
+        .map(|(&winner, &loser)| -numerics::log_sigmoid(winner - loser))
+        .sum();
+
+    loss / winners.len() as Float
+}
+
+impl<LHS, RHS> Node for LogisticRankNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.winners.forward();
+        self.losers.forward();
+
+        self.loss_value
+            .borrow_mut()
+            .fill(rank_loss(&self.winners.value(), &self.losers.value()));
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let winners = self.winners.value();
+            let losers = self.losers.value();
+            let mut winner_gradient = self.winner_gradient.borrow_mut();
+            let mut loser_gradient = self.loser_gradient.borrow_mut();
+            fit_shape(&mut winner_gradient, winners.dim());
+            fit_shape(&mut loser_gradient, losers.dim());
+            let scale = gradient[(0, 0)] / winners.len() as Float;
+
+            for (winner_grad, loser_grad, &winner, &loser) in izip!(
+                winner_gradient.iter_mut(),
+                loser_gradient.iter_mut(),
+                winners.iter(),
+                losers.iter()
+            ) {
+                // 1 - sigmoid(diff), written to stay finite for large `diff`.
+                let miss = 1.0 / (1.0 + numerics::exp(winner - loser));
+
+                *winner_grad = beta * *winner_grad - scale * miss;
+                *loser_grad = beta * *loser_grad + scale * miss;
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.winners.backward(&self.winner_gradient.borrow());
+            self.losers.backward(&self.loser_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.loss_value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.winners.zero_gradient();
+            self.losers.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("LogisticRank")
+            .operand(&*self.winners)
+            .operand(&*self.losers)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.winner_gradient.borrow(), self.loser_gradient.borrow()]
+    }
 }
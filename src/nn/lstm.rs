@@ -53,7 +53,7 @@ use nodes::{HogwildParameter, Node, ParameterNode};
 
 use nn::uniform;
 
-use {Arr, DataInput, Variable};
+use {Arr, DataInput, Float, Variable};
 
 /// Holds shared parameters for an LSTM cell.
 ///
@@ -105,7 +105,7 @@ impl Clone for Parameters {
 impl Parameters {
     /// Create a new LSTM parameters object.
     pub fn new<R: rand::Rng>(input_dim: usize, hidden_dim: usize, rng: &mut R) -> Self {
-        let max = 1.0 / (hidden_dim as f32).sqrt();
+        let max = 1.0 / (hidden_dim as Float).sqrt();
         let min = -max;
 
         Self {
@@ -305,24 +305,15 @@ mod tests {
     use std::ops::Deref;
 
     use super::*;
-    use finite_difference;
     use nn::losses::sparse_categorical_crossentropy;
     use nn::xavier_normal;
     use nodes::InputNode;
     use optim::{Adam, Optimizer};
+    use testing::check_gradients;
     use DataInput;
 
-    const TOLERANCE: f32 = 0.2;
-
-    fn assert_close(x: &Arr, y: &Arr, tol: f32) {
-        assert!(
-            x.all_close(y, tol),
-            "{:#?} not within {} of {:#?}",
-            x,
-            tol,
-            y
-        );
-    }
+    const EPSILON: Float = 0.5e-4;
+    const TOLERANCE: Float = 0.2;
 
     fn pi_digits(num: usize) -> Vec<usize> {
         let pi_str = include_str!("pi.txt");
@@ -339,7 +330,7 @@ mod tests {
         let num_steps = 10;
         let dim = 10;
 
-        let mut xs: Vec<_> = (0..num_steps)
+        let xs: Vec<_> = (0..num_steps)
             .map(|_| ParameterNode::new(xavier_normal(1, dim)))
             .collect();
 
@@ -349,14 +340,12 @@ mod tests {
         let mut hidden_states = lstm.forward(&xs);
         let mut hidden = hidden_states.last_mut().unwrap();
 
-        for x in &mut xs {
-            let (difference, gradient) = finite_difference(x, &mut hidden);
-            assert_close(&difference, &gradient, TOLERANCE);
+        for x in &xs {
+            check_gradients(&mut hidden, x, EPSILON, TOLERANCE).unwrap();
         }
 
-        for x in hidden.parameters().iter_mut() {
-            let (difference, gradient) = finite_difference(x, &mut hidden);
-            assert_close(&difference, &gradient, TOLERANCE);
+        for x in hidden.parameters().iter() {
+            check_gradients(&mut hidden, x, EPSILON, TOLERANCE).unwrap();
         }
     }
 
@@ -460,7 +449,7 @@ mod tests {
                 loss.forward();
                 loss.backward(1.0);
 
-                loss_val += loss.value().scalar_sum();
+                loss_val += loss.to_scalar();
 
                 optimizer.step();
                 loss.zero_gradient();
@@ -475,10 +464,10 @@ mod tests {
             println!(
                 "Loss {}, accuracy {}",
                 loss_val,
-                correct as f32 / total as f32
+                correct as Float / total as Float
             );
         }
 
-        assert!((correct as f32 / total as f32) > 0.75);
+        assert!((correct as Float / total as Float) > 0.75);
     }
 }
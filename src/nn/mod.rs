@@ -1,21 +1,115 @@
 //! Neural network components.
 
+pub mod layers;
 pub mod losses;
 pub mod lstm;
 
+use std::fmt;
+use std::sync::Arc;
+
 use rand;
 use rand::distributions::{Distribution, Normal, Uniform};
 
-use Arr;
+use nodes::{HogwildParameter, ParameterNode};
+use {Arr, Float, Variable};
 
 /// Return a Xavier-normal initialised random array.
 pub fn xavier_normal(rows: usize, cols: usize) -> Arr {
     let normal = Normal::new(0.0, 1.0 / (rows as f64).sqrt());
-    Arr::zeros((rows, cols)).map(|_| normal.sample(&mut rand::thread_rng()) as f32)
+    Arr::zeros((rows, cols)).map(|_| normal.sample(&mut rand::thread_rng()) as Float)
 }
 
 /// Return a random matrix with values drawn uniformly from `(min, max)`.
-pub fn uniform<R: rand::Rng>(rows: usize, cols: usize, min: f32, max: f32, rng: &mut R) -> Arr {
+pub fn uniform<R: rand::Rng>(rows: usize, cols: usize, min: Float, max: Float, rng: &mut R) -> Arr {
     let dist = Uniform::new(min, max);
-    Arr::zeros((rows, cols)).map(|_| dist.sample(rng) as f32)
+    Arr::zeros((rows, cols)).map(|_| dist.sample(rng) as Float)
+}
+
+/// A `Send`-able description of a model: its shared parameters and a
+/// function building the model's graph on top of them.
+///
+/// Graphs cannot be sent across threads, so Hogwild training builds a
+/// fresh graph in each thread. Cloning the spec into each thread and
+/// calling `build` there gives every thread its own nodes, with
+/// parameter values shared through the `HogwildParameter`s.
+///
+/// ```rust
+/// # extern crate wyrm;
+/// # extern crate rayon;
+/// # use std::sync::Arc;
+/// # use rayon::prelude::*;
+/// # use wyrm::*;
+/// # use wyrm::nn::{xavier_normal, ModelSpec};
+/// # fn main() {
+/// let spec = ModelSpec::new(
+///     vec![Arc::new(HogwildParameter::new(xavier_normal(3, 1)))],
+///     |parameters| {
+///         let x = InputNode::new(Arr::zeros((1, 3)));
+///         let y_hat = x.dot(&parameters[0]);
+///         (x, y_hat)
+///     },
+/// );
+///
+/// (0..rayon::current_num_threads())
+///     .into_par_iter()
+///     .for_each(|_| {
+///         let (x, y_hat) = spec.build();
+///         x.set_value(&Arr::ones((1, 3)));
+///         y_hat.forward();
+///     });
+/// # }
+/// ```
+pub struct ModelSpec<M> {
+    parameters: Vec<Arc<HogwildParameter>>,
+    build: Arc<BuildFn<M>>,
+}
+
+type BuildFn<M> = Fn(&[Variable<ParameterNode>]) -> M + Send + Sync;
+
+impl<M> ModelSpec<M> {
+    /// Create a spec from the shared parameters and a function building
+    /// the model from parameter nodes wrapping them, given in the same
+    /// order.
+    pub fn new<F>(parameters: Vec<Arc<HogwildParameter>>, build: F) -> Self
+    where
+        F: Fn(&[Variable<ParameterNode>]) -> M + Send + Sync + 'static,
+    {
+        ModelSpec {
+            parameters: parameters,
+            build: Arc::new(build),
+        }
+    }
+
+    /// The shared parameters of the model.
+    pub fn parameters(&self) -> &[Arc<HogwildParameter>] {
+        &self.parameters
+    }
+
+    /// Build a fresh graph of the model in the calling thread.
+    pub fn build(&self) -> M {
+        let parameters: Vec<_> = self
+            .parameters
+            .iter()
+            .map(|parameter| ParameterNode::shared(Arc::clone(parameter)))
+            .collect();
+
+        (self.build)(&parameters)
+    }
+}
+
+impl<M> Clone for ModelSpec<M> {
+    fn clone(&self) -> Self {
+        ModelSpec {
+            parameters: self.parameters.clone(),
+            build: Arc::clone(&self.build),
+        }
+    }
+}
+
+impl<M> fmt::Debug for ModelSpec<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ModelSpec")
+            .field("parameters", &self.parameters)
+            .finish()
+    }
 }
@@ -1,19 +1,35 @@
 use std;
+use std::borrow::Cow;
 use std::cell::{Cell, Ref, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::error;
 use std::fmt;
 use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{Arc, Once};
+#[cfg(feature = "instrumentation")]
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use serde::de;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use ndarray;
 use ndarray::Axis;
 
+use rand;
+use rand::prng::XorShiftRng;
+use rand::{Rng, SeedableRng};
+
 use smallvec::SmallVec;
 
+use arena;
+use inference::{InferenceBuilder, Op};
+use io::MappedValue;
 use numerics;
 use numerics::{ArraySlice, ArraySliceMut, ArraySliceOps};
 
-use super::{clamp, Arr, Variable};
+use super::{clamp, merge_parameters, Arr, Float, Variable};
 
 #[derive(Debug, PartialEq)]
 pub enum ForwardAction {
@@ -27,10 +43,26 @@ pub enum BackwardAction {
     Increment,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct PassCounter {
     forward_count: Cell<usize>,
     backward_count: Cell<usize>,
+    /// Whether the node was built in inference mode, and so is never
+    /// backpropagated through.
+    inference: bool,
+    /// The name given to the node with `Variable::named`.
+    name: Cell<Option<&'static str>>,
+}
+
+impl Default for PassCounter {
+    fn default() -> Self {
+        PassCounter {
+            forward_count: Cell::new(0),
+            backward_count: Cell::new(0),
+            inference: is_inference(),
+            name: Cell::new(None),
+        }
+    }
 }
 
 impl PassCounter {
@@ -38,9 +70,18 @@ impl PassCounter {
         self.forward_count.set(0);
         self.backward_count.set(0);
     }
+    /// Whether the node has not been evaluated since the counter was
+    /// last cleared.
+    ///
+    /// Forward-only passes, as in evaluation, are fine, but in debug
+    /// builds this panics if a backward pass was started and not
+    /// completed.
     #[inline(always)]
     pub fn is_zero(&self) -> bool {
-        debug_assert!(self.recurse_backward(), "Not fully backpropagated.");
+        debug_assert!(
+            self.inference || self.backward_count.get() == 0 || self.recurse_backward(),
+            "Not fully backpropagated."
+        );
 
         self.forward_count.get() == 0
     }
@@ -48,22 +89,60 @@ impl PassCounter {
         let backward_count = self.backward_count.get();
         let forward_count = self.forward_count.get();
 
-        assert!(backward_count <= forward_count);
+        assert!(
+            backward_count <= forward_count,
+            "Node received more backward than forward passes: \
+             forward each root before backpropagating it."
+        );
 
         backward_count == forward_count
     }
+    pub(crate) fn forward_count(&self) -> usize {
+        self.forward_count.get()
+    }
+    pub(crate) fn backward_count(&self) -> usize {
+        self.backward_count.get()
+    }
+    pub(crate) fn name(&self) -> Option<&'static str> {
+        self.name.get()
+    }
+    pub(crate) fn set_name(&self, name: &'static str) {
+        self.name.set(Some(name));
+    }
     #[inline(always)]
     pub fn forward(&self) -> ForwardAction {
+        self.forward_many(1)
+    }
+    /// Record `uses` forward passes at once, as a linearized pass does
+    /// for a node used by that many operations.
+    #[inline(always)]
+    pub fn forward_many(&self, uses: usize) -> ForwardAction {
         let count = self.forward_count.get();
-        self.forward_count.set(count + 1);
+        self.forward_count.set(count + uses);
 
         match count {
             0 => ForwardAction::Evaluate,
-            _ => ForwardAction::Cached,
+            _ => {
+                // A node that has already backpropagated would pass
+                // the gradients of its earlier uses on a second time.
+                // Nodes built in inference mode never do.
+                assert!(
+                    self.inference || self.backward_count.get() < count,
+                    "Node forwarded again after its backward pass: forward every root \
+                     sharing the node before backpropagating any of them, and zero \
+                     gradients between iterations."
+                );
+                ForwardAction::Cached
+            }
         }
     }
     #[inline(always)]
     pub fn backward(&self) -> BackwardAction {
+        assert!(
+            !self.inference,
+            "Cannot backpropagate through a node built in inference mode."
+        );
+
         let backward_count = self.backward_count.get();
 
         let action = match backward_count {
@@ -77,6 +156,124 @@ impl PassCounter {
     }
 }
 
+thread_local! {
+    /// Whether graphs on this thread are built for inference only.
+    static INFERENCE: Cell<bool> = Cell::new(false);
+}
+
+/// Turn on inference mode for graphs built on this thread until the
+/// returned guard is dropped, when the previous mode is restored.
+///
+/// Nodes capture the mode when they are built. Parameter nodes built in
+/// inference mode report that they need no gradient, so nodes built on
+/// top of them skip allocating gradient buffers, and the pass counters
+/// of nodes built in inference mode skip the checks guarding backward
+/// passes. Graphs built in inference mode cannot be backpropagated
+/// through, even once the guard is dropped: rebuild the graph instead.
+pub fn inference_mode() -> InferenceGuard {
+    let previous = INFERENCE.with(|flag| flag.replace(true));
+
+    InferenceGuard { previous: previous }
+}
+
+/// Keeps inference mode on for the current thread; see `inference_mode`.
+#[derive(Debug)]
+pub struct InferenceGuard {
+    previous: bool,
+}
+
+impl Drop for InferenceGuard {
+    fn drop(&mut self) {
+        let previous = self.previous;
+        INFERENCE.with(|flag| flag.set(previous));
+    }
+}
+
+/// Whether inference mode is on for this thread. See `inference_mode`.
+pub fn is_inference() -> bool {
+    INFERENCE.with(|flag| flag.get())
+}
+
+/// Epsilons guarding numerically delicate operations.
+///
+/// Nodes read the configuration of the current thread when they are
+/// built, so changing it with `set_stability` only affects nodes built
+/// afterwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StabilityConfig {
+    /// Lower bound on magnitudes that are divided by or whose logarithm
+    /// is taken, as in `hypot` gradients and entropies.
+    pub epsilon: Float,
+    /// Added to variances before normalizing by them.
+    pub variance_epsilon: Float,
+    /// Lower bound on the magnitude of the denominators of `Div` nodes.
+    /// The default of zero leaves division unguarded.
+    pub div_epsilon: Float,
+}
+
+impl Default for StabilityConfig {
+    fn default() -> Self {
+        StabilityConfig {
+            epsilon: 1e-7,
+            variance_epsilon: 1e-5,
+            div_epsilon: 0.0,
+        }
+    }
+}
+
+impl StabilityConfig {
+    /// Set the lower bound on denominators and logarithm arguments.
+    pub fn epsilon(mut self, epsilon: Float) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+    /// Set the lower bound on the denominators of `Div` nodes.
+    pub fn div_epsilon(mut self, div_epsilon: Float) -> Self {
+        self.div_epsilon = div_epsilon;
+        self
+    }
+    /// Set the epsilon added to variances.
+    pub fn variance_epsilon(mut self, variance_epsilon: Float) -> Self {
+        self.variance_epsilon = variance_epsilon;
+        self
+    }
+}
+
+thread_local! {
+    /// Stability configuration for graphs built on this thread.
+    static STABILITY: Cell<StabilityConfig> = Cell::new(StabilityConfig::default());
+}
+
+/// Set the stability configuration for nodes built on this thread.
+pub fn set_stability(config: StabilityConfig) {
+    STABILITY.with(|stability| stability.set(config));
+}
+
+/// The stability configuration of this thread. See `set_stability`.
+pub fn stability() -> StabilityConfig {
+    STABILITY.with(|stability| stability.get())
+}
+
+/// A zeroed gradient buffer of the given shape. Nodes that need no
+/// gradient get an empty buffer instead, and skip their backward pass.
+pub(crate) fn gradient_buffer(shape: (usize, usize), needs_gradient: bool) -> Arr {
+    if needs_gradient {
+        arena::zeros(shape)
+    } else {
+        Arr::zeros((0, 0))
+    }
+}
+
+/// Resizes `buffer` to `shape`, zeroing it, if its shape differs. Lets
+/// node buffers follow changes in the number of rows fed to the graph.
+#[inline(always)]
+pub(crate) fn fit_shape(buffer: &mut Arr, shape: (usize, usize)) {
+    if buffer.dim() != shape {
+        arena::recycle(buffer);
+        *buffer = arena::zeros(shape);
+    }
+}
+
 /// Generalisation over borrowed `RefCell` values
 /// and simple references.
 #[derive(Debug)]
@@ -120,626 +317,9392 @@ pub trait Node: fmt::Debug + 'static {
     /// If the node needs to be used in the backward step.
     fn needs_gradient(&self) -> bool;
     fn zero_gradient(&self);
-}
-
-impl Node for Rc<Node<Value = Arr, InputGradient = Arr>> {
-    type Value = Arr;
-    type InputGradient = Arr;
-    fn forward(&self) {
-        self.deref().forward()
+    /// Describe the operation the node performs and its operands,
+    /// for inspecting the structure of the graph. Nodes that do
+    /// not override this are reported as `"Unknown"` leaves.
+    fn describe(&self) -> Description {
+        Description::new("Unknown")
+    }
+    /// The parameter holding the node's value, if it is a parameter node.
+    fn shared_parameter(&self) -> Option<&Arc<HogwildParameter>> {
+        None
+    }
+    /// Build the forward-mode tangent of the node: a node computing the
+    /// directional derivative of its value, given the tangents of its
+    /// operands from `builder`. Nodes that do not override this do not
+    /// support forward-mode differentiation.
+    fn tangent(&self, _builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        Err(format!(
+            "Forward-mode differentiation is not supported for {} nodes.",
+            self.describe().op
+        ))
+    }
+    /// Build a copy of the node with the same operation, over the copies
+    /// of its operands from `builder`, or return `None` to share the node
+    /// itself between the original graph and the copy. Nodes that do not
+    /// override this cannot be cloned with `Variable::clone_architecture`.
+    fn clone_architecture(&self, _builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        Err(format!(
+            "Cloning is not supported for {} nodes.",
+            self.describe().op
+        ))
+    }
+    /// Compile the node into a step of an inference-only graph, given
+    /// the buffers of its operands from `builder`, returning the buffer
+    /// holding its value. Nodes that do not override this cannot be
+    /// compiled with `Variable::compile_inference`.
+    fn compile(&self, _builder: &mut InferenceBuilder) -> Result<usize, String> {
+        Err(format!(
+            "Inference compilation is not supported for {} nodes.",
+            self.describe().op
+        ))
+    }
+    /// Whether the node supports linearized passes, which evaluate the
+    /// nodes of a graph one by one in topological order instead of
+    /// recursing into operands. See `Variable::linearize`.
+    fn linearizable(&self) -> bool {
+        false
     }
-    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        self.deref().backward(gradient)
+    /// Compute the node's value from the current values of its operands,
+    /// without evaluating them first. `uses` is the number of operations
+    /// using the node, each of which counts as a forward pass.
+    fn evaluate(&self, _uses: usize) {
+        panic!(
+            "Linearized passes are not supported for {} nodes.",
+            self.describe().op
+        )
     }
-    fn value(&self) -> Bor<Self::Value> {
-        self.deref().value()
+    /// Accumulate `gradient` into the gradients of the node's operands,
+    /// without backpropagating it into them.
+    fn accumulate(&self, _gradient: &Ref<Self::InputGradient>) {
+        panic!(
+            "Linearized passes are not supported for {} nodes.",
+            self.describe().op
+        )
     }
-    fn needs_gradient(&self) -> bool {
-        self.deref().needs_gradient()
+    /// Once all of the node's gradient has been accumulated, pass the
+    /// gradients of its operands to their `accumulate`.
+    fn propagate(&self) {
+        panic!(
+            "Linearized passes are not supported for {} nodes.",
+            self.describe().op
+        )
     }
-    fn zero_gradient(&self) {
-        self.deref().zero_gradient()
+    /// Resize the node to `rows` rows if it holds a constant broadcast
+    /// to the shape of another operand, so that it follows changes in
+    /// the batch size. Other nodes ignore this.
+    fn fit_rows(&self, _rows: usize) {}
+    /// The address identifying the node. Wrappers, such as boxed nodes,
+    /// report the address of the node they wrap.
+    fn address(&self) -> *const u8 {
+        self as *const Self as *const u8
+    }
+    /// The counter tracking the node's forward and backward passes,
+    /// if it has one. Used by `Variable::validate`.
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        None
+    }
+    /// The gradient buffers the node keeps for its operands, in the
+    /// order of `describe`. Buffers that need not have the shape of
+    /// their operand are left out. Used by `Variable::validate`.
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        Vec::new()
+    }
+    /// The estimated FLOPs of a forward pass through the node, used by
+    /// `Variable::profile_flops`, or `None` for element-wise operations,
+    /// which count one FLOP per element of the largest of their value
+    /// and operands. Matrix products count a multiply and an add per term.
+    fn flops(&self) -> Option<usize> {
+        None
     }
 }
 
-#[derive(Debug)]
-pub struct AddNode<LHS, RHS> {
-    value: RefCell<Arr>,
-    gradient: RefCell<Arr>,
-    lhs: Rc<LHS>,
-    rhs: Rc<RHS>,
-    needs_gradient: bool,
-    counter: PassCounter,
+/// Attribute of an operation, such as the axis of a concatenation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Attribute {
+    Int(i64),
+    Float(Float),
 }
 
-impl<LHS, RHS> AddNode<LHS, RHS>
-where
-    LHS: Node<Value = Arr>,
-    RHS: Node<Value = Arr>,
-{
-    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
-        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
-        let value = lhs.value().deref() + rhs.value().deref();
-        let gradient = rhs.value().deref() * 0.0;
+/// Description of a node, as returned by `Node::describe`.
+#[derive(Debug)]
+pub struct Description<'a> {
+    /// Name of the operation, e.g. `"Add"` or `"Parameter"`.
+    pub op: &'static str,
+    /// The node's operands, in order.
+    pub operands: Vec<&'a GraphNode>,
+    /// Operation-specific attributes.
+    pub attributes: Vec<(&'static str, Attribute)>,
+}
 
-        AddNode {
-            value: RefCell::new(value),
-            gradient: RefCell::new(gradient),
-            lhs: lhs,
-            rhs: rhs,
-            needs_gradient: needs_gradient,
-            counter: PassCounter::default(),
+impl<'a> Description<'a> {
+    /// Create a description of an operation without operands.
+    pub fn new(op: &'static str) -> Self {
+        Description {
+            op: op,
+            operands: Vec::new(),
+            attributes: Vec::new(),
         }
     }
+    /// Add an operand.
+    pub fn operand(mut self, operand: &'a GraphNode) -> Self {
+        self.operands.push(operand);
+        self
+    }
+    /// Add an attribute.
+    pub fn attribute(mut self, name: &'static str, value: Attribute) -> Self {
+        self.attributes.push((name, value));
+        self
+    }
+    /// Return the value of the attribute `name`, if present.
+    pub fn get_attribute(&self, name: &str) -> Option<Attribute> {
+        self.attributes
+            .iter()
+            .find(|&&(attribute_name, _)| attribute_name == name)
+            .map(|&(_, value)| value)
+    }
 }
 
-impl<LHS, RHS> Node for AddNode<LHS, RHS>
-where
-    LHS: Node<Value = Arr, InputGradient = Arr>,
-    RHS: Node<Value = Arr, InputGradient = Arr>,
-{
-    type Value = Arr;
-    type InputGradient = Arr;
-    fn forward(&self) {
-        if self.counter.forward() == ForwardAction::Cached {
-            return;
-        }
-
-        self.lhs.forward();
-        self.rhs.forward();
+/// Values that can be held by nodes.
+pub trait NodeValue {
+    /// The `(rows, cols)` shape of the value. Index vectors are
+    /// reported as columns.
+    fn value_shape(&self) -> (usize, usize);
+    /// A copy of the value, if it is an array.
+    fn to_array(&self) -> Option<Arr> {
+        None
+    }
+    /// A copy of the value, if it is a vector of indices.
+    fn to_indices(&self) -> Option<Vec<usize>> {
+        None
+    }
+    /// Whether the value holds no infinities or NaNs.
+    fn is_finite(&self) -> bool {
+        true
+    }
+}
 
-        let lhs_value = self.lhs.value();
-        let rhs_value = self.rhs.value();
+impl NodeValue for Arr {
+    fn value_shape(&self) -> (usize, usize) {
+        (self.rows(), self.cols())
+    }
+    fn to_array(&self) -> Option<Arr> {
+        Some(self.clone())
+    }
+    fn is_finite(&self) -> bool {
+        self.iter().all(|x| x.is_finite())
+    }
+}
 
-        debug_assert_eq!(
-            lhs_value.shape(),
-            self.value().shape(),
-            "LHS operand changed shape."
-        );
-        debug_assert_eq!(
-            rhs_value.shape(),
-            self.value().shape(),
-            "RHS operand changed shape."
-        );
+impl NodeValue for SmallVec<[usize; 4]> {
+    fn value_shape(&self) -> (usize, usize) {
+        (self.len(), 1)
+    }
+    fn to_indices(&self) -> Option<Vec<usize>> {
+        Some(self.to_vec())
+    }
+}
 
-        let mut self_value = self.value.borrow_mut();
+/// Object-safe view of a node, used to walk the graph regardless
+/// of the concrete node types.
+pub trait GraphNode: fmt::Debug {
+    /// Describe the node's operation and operands.
+    fn description(&self) -> Description;
+    /// The `(rows, cols)` shape of the node's current value.
+    fn value_shape(&self) -> (usize, usize);
+    /// A copy of the node's value, if it is an array.
+    fn value_array(&self) -> Option<Arr>;
+    /// A copy of the node's value, if it is a vector of indices.
+    fn value_indices(&self) -> Option<Vec<usize>>;
+    /// Whether the node's value holds no infinities or NaNs.
+    fn value_is_finite(&self) -> bool;
+    /// The name given to the node with `Variable::named`, if any.
+    fn node_name(&self) -> Option<&'static str>;
+    /// Whether gradients are propagated through the node.
+    fn requires_gradient(&self) -> bool;
+    /// The parameter holding the node's value, if it is a parameter node.
+    fn parameter(&self) -> Option<&Arc<HogwildParameter>>;
+    /// See `Node::linearizable`.
+    fn is_linearizable(&self) -> bool;
+    /// See `Node::evaluate`.
+    fn evaluate_node(&self, uses: usize);
+    /// See `Node::propagate`.
+    fn propagate_node(&self);
+    /// See `Node::zero_gradient`.
+    fn zero_gradient_node(&self);
+    /// See `Node::address`.
+    fn node_address(&self) -> *const u8;
+    /// The `(forward, backward)` pass counts of the node, if it
+    /// keeps them.
+    fn pass_counts(&self) -> Option<(usize, usize)>;
+    /// See `Node::gradients`.
+    fn gradients_node(&self) -> Vec<Ref<Arr>>;
+    /// See `Node::flops`.
+    fn flops_node(&self) -> Option<usize>;
+}
 
-        for (v, &lhs, &rhs) in izip!(
-            self_value.fast_slice_mut(),
-            lhs_value.fast_slice(),
-            rhs_value.fast_slice()
-        ) {
-            *v = lhs + rhs;
+impl<T> GraphNode for T
+where
+    T: Node,
+    T::Value: NodeValue,
+{
+    fn description(&self) -> Description {
+        self.describe()
+    }
+    fn value_shape(&self) -> (usize, usize) {
+        match self.shared_parameter() {
+            Some(parameter) => parameter.shape(),
+            None => self.value().value_shape(),
         }
     }
-    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => {
-                let mut operand_gradient = self.gradient.borrow_mut();
-                operand_gradient.slice_assign(gradient.deref());
-            }
-            BackwardAction::Increment => {
-                let mut operand_gradient = self.gradient.borrow_mut();
-                operand_gradient.slice_add_assign(gradient.deref());
-            }
+    fn value_array(&self) -> Option<Arr> {
+        match self.shared_parameter() {
+            Some(parameter) => Some(parameter.full_value().into_owned()),
+            None => self.value().to_array(),
         }
-
-        if self.counter.recurse_backward() {
-            let gradient = self.gradient.borrow();
-            self.lhs.backward(&gradient);
-            self.rhs.backward(&gradient);
+    }
+    fn value_indices(&self) -> Option<Vec<usize>> {
+        self.value().to_indices()
+    }
+    fn value_is_finite(&self) -> bool {
+        match self.shared_parameter() {
+            Some(parameter) => parameter.full_value().is_finite(),
+            None => self.value().is_finite(),
         }
     }
-    fn value(&self) -> Bor<Self::Value> {
-        Bor::RefGuard(self.value.borrow())
+    fn node_name(&self) -> Option<&'static str> {
+        self.pass_counter().and_then(|counter| counter.name())
     }
-    fn needs_gradient(&self) -> bool {
-        self.needs_gradient
+    fn requires_gradient(&self) -> bool {
+        Node::needs_gradient(self)
     }
-    fn zero_gradient(&self) {
-        if !self.counter.is_zero() {
-            self.lhs.zero_gradient();
-            self.rhs.zero_gradient();
-            self.counter.clear();
+    fn parameter(&self) -> Option<&Arc<HogwildParameter>> {
+        self.shared_parameter()
+    }
+    fn is_linearizable(&self) -> bool {
+        self.linearizable()
+    }
+    fn evaluate_node(&self, uses: usize) {
+        self.evaluate(uses)
+    }
+    fn propagate_node(&self) {
+        self.propagate()
+    }
+    fn zero_gradient_node(&self) {
+        Node::zero_gradient(self)
+    }
+    fn node_address(&self) -> *const u8 {
+        self.address()
+    }
+    fn pass_counts(&self) -> Option<(usize, usize)> {
+        self.pass_counter()
+            .map(|counter| (counter.forward_count(), counter.backward_count()))
+    }
+    fn gradients_node(&self) -> Vec<Ref<Arr>> {
+        self.gradients()
+    }
+    fn flops_node(&self) -> Option<usize> {
+        self.flops()
+    }
+}
+
+/// Address of a node, identifying it within a graph.
+pub(crate) fn node_id(node: &GraphNode) -> *const u8 {
+    node as *const GraphNode as *const u8
+}
+
+/// Return the nodes of the graph ending at `root`, each node
+/// appearing once and after all of its operands.
+pub(crate) fn topological_order(root: &GraphNode) -> Vec<&GraphNode> {
+    let mut order = Vec::new();
+    let mut visited = ::std::collections::HashSet::new();
+    // Explicit stack of (node, operands expanded) to avoid
+    // recursing through deep graphs.
+    let mut stack = vec![(root, false)];
+
+    while let Some((node, expanded)) = stack.pop() {
+        if expanded {
+            order.push(node);
+            continue;
+        }
+
+        if !visited.insert(node_id(node)) {
+            continue;
+        }
+
+        stack.push((node, true));
+
+        for &operand in node.description().operands.iter().rev() {
+            if !visited.contains(&node_id(operand)) {
+                stack.push((operand, false));
+            }
         }
     }
+
+    order
 }
 
-fn row_wise_stack(dest: &mut Arr, lhs: &Arr, rhs: &Arr) {
-    for (mut dest_row, source_row) in dest
-        .genrows_mut()
-        .into_iter()
-        .zip(lhs.genrows().into_iter().chain(rhs.genrows()))
-    {
-        numerics::slice_assign(
-            dest_row.as_slice_mut().unwrap(),
-            source_row.as_slice().unwrap(),
-        );
+/// The node's operation, name and attributes, for error messages.
+#[cfg(feature = "nan-guard")]
+fn node_label(node: &GraphNode) -> String {
+    let description = node.description();
+    let mut label = format!("{} node", description.op);
+
+    if let Some(name) = node.node_name() {
+        label.push_str(&format!(" `{}`", name));
+    }
+
+    if !description.attributes.is_empty() {
+        let attributes: Vec<_> = description
+            .attributes
+            .iter()
+            .map(|&(key, attribute)| match attribute {
+                Attribute::Int(x) => format!("{} = {}", key, x),
+                Attribute::Float(x) => format!("{} = {}", key, x),
+            })
+            .collect();
+        label.push_str(&format!(" ({})", attributes.join(", ")));
     }
+
+    label
 }
 
-fn column_wise_stack(dest: &mut Arr, lhs: &Arr, rhs: &Arr) {
-    for (mut dest_row, lhs_row, rhs_row) in izip!(
-        dest.genrows_mut().into_iter(),
-        lhs.genrows().into_iter(),
-        rhs.genrows().into_iter()
-    ) {
-        let dest_row = dest_row.as_slice_mut().unwrap();
-        let lhs_row = lhs_row.as_slice().unwrap();
-        let rhs_row = rhs_row.as_slice().unwrap();
+/// Panics naming `node` if its value holds infinities or NaNs. Checked
+/// after each node is evaluated, this names the node where non-finite
+/// values first appear.
+#[cfg(feature = "nan-guard")]
+pub(crate) fn check_finite_value(node: &GraphNode) {
+    if !node.value_is_finite() {
+        panic!(
+            "Non-finite value computed by the {} in the forward pass.",
+            node_label(node)
+        );
+    }
+}
 
-        let (left, right) = dest_row.split_at_mut(lhs_row.len());
-        numerics::slice_assign(left, lhs_row);
-        numerics::slice_assign(right, rhs_row);
+/// Panics naming `node` if the gradients it passed to its operands hold
+/// infinities or NaNs. Checked after each node is backpropagated through,
+/// this names the node where non-finite gradients first appear.
+#[cfg(feature = "nan-guard")]
+pub(crate) fn check_finite_gradients(node: &GraphNode) {
+    if !node
+        .gradients_node()
+        .iter()
+        .all(|gradient| gradient.iter().all(|x| x.is_finite()))
+    {
+        panic!(
+            "Non-finite gradient computed by the {} in the backward pass.",
+            node_label(node)
+        );
     }
 }
 
-fn column_wise_stack_gradient(gradient: &Arr, lhs: &mut Arr, rhs: &mut Arr, op: &BackwardAction) {
-    for (grad_row, mut lhs_row, mut rhs_row) in izip!(
-        gradient.genrows().into_iter(),
-        lhs.genrows_mut().into_iter(),
-        rhs.genrows_mut().into_iter()
-    ) {
-        let grad_row = grad_row.fast_slice();
-        let lhs_row = lhs_row.fast_slice_mut();
-        let rhs_row = rhs_row.fast_slice_mut();
+/// Check the state of the graph ending at `root`, whose parameters are
+/// `parameters`, together with the graphs ending at `others` that share
+/// its nodes. See `Variable::validate`.
+pub(crate) fn validate_graph(
+    root: &GraphNode,
+    others: &[&GraphNode],
+    parameters: &[Rc<ParameterNode>],
+) -> Result<(), GraphError> {
+    let is_evaluated =
+        |node: &GraphNode| node.pass_counts().map_or(true, |(forward, _)| forward > 0);
+
+    // Boxed nodes appear next to the nodes they wrap, so nodes are told
+    // apart by address rather than by `node_id`. Appending the nodes of
+    // each graph keeps the order topological.
+    let mut seen = HashSet::new();
+    let order: Vec<_> = Some(root)
+        .into_iter()
+        .chain(others.iter().cloned())
+        .flat_map(topological_order)
+        .filter(|node| seen.insert(node.node_address()))
+        .collect();
+
+    // The number of evaluated nodes using each node, or `None` if one of
+    // them keeps no counter and its uses cannot be told.
+    let mut uses: HashMap<*const u8, Option<usize>> = HashMap::new();
+    // Nodes that gradients from `root` flow into, whose parameters must
+    // be listed.
+    let mut trainable = HashSet::new();
+    let mut root_evaluated = false;
+
+    for &root in Some(root).iter().chain(others) {
+        let root_uses = uses.entry(root.node_address()).or_insert(Some(0));
+        *root_uses = root_uses.map(|x| x + is_evaluated(root) as usize);
+        root_evaluated |= is_evaluated(root);
+    }
+    trainable.insert(root.node_address());
+
+    for &node in order.iter().rev() {
+        let counts = node.pass_counts();
+        let is_trainable =
+            trainable.contains(&node.node_address()) && node.requires_gradient();
+
+        for &operand in &node.description().operands {
+            let operand_uses = uses.entry(operand.node_address()).or_insert(Some(0));
+            *operand_uses = match counts {
+                Some((forward, _)) => operand_uses.map(|x| x + (forward > 0) as usize),
+                None => None,
+            };
+
+            if is_trainable {
+                trainable.insert(operand.node_address());
+            }
+        }
+    }
 
-        let (left, right) = grad_row.split_at(lhs_row.len());
+    for &node in &order {
+        let description = node.description();
 
-        match op {
-            BackwardAction::Increment => {
-                for (x, y) in lhs_row.iter_mut().zip(left.iter()) {
-                    *x += y;
+        if let Some(parameter) = node.parameter() {
+            let listed = parameters
+                .iter()
+                .find(|listed| Arc::ptr_eq(&listed.value, parameter));
+
+            match listed {
+                Some(listed) => {
+                    let gradient = listed.gradient.borrow();
+
+                    if !root_evaluated
+                        && (gradient.has_dense || !gradient.sparse_entries().is_empty())
+                    {
+                        return Err(GraphError::StaleGradient {
+                            shape: parameter.shape(),
+                        });
+                    }
                 }
-                for (x, y) in rhs_row.iter_mut().zip(right.iter()) {
-                    *x += y;
+                None if trainable.contains(&node.node_address()) => {
+                    return Err(GraphError::MissingParameter {
+                        shape: parameter.shape(),
+                    })
                 }
+                None => {}
             }
-            BackwardAction::Set => {
-                lhs_row.copy_from_slice(left);
-                rhs_row.copy_from_slice(right);
+        }
+
+        let (forward_count, backward_count) = match node.pass_counts() {
+            Some(counts) => counts,
+            None => continue,
+        };
+
+        if let Some(expected) = uses[&node.node_address()] {
+            if expected == 0 && forward_count > 0 {
+                return Err(GraphError::StaleCounter {
+                    op: description.op,
+                    forward_count: forward_count,
+                });
+            } else if expected != forward_count {
+                return Err(GraphError::CounterMismatch {
+                    op: description.op,
+                    expected: expected,
+                    forward_count: forward_count,
+                });
+            }
+        }
+
+        if backward_count > 0 && backward_count < forward_count {
+            return Err(GraphError::IncompleteBackward {
+                op: description.op,
+                forward_count: forward_count,
+                backward_count: backward_count,
+            });
+        }
+
+        if backward_count == 0 || backward_count < forward_count {
+            continue;
+        }
+
+        for (idx, (&operand, gradient)) in description
+            .operands
+            .iter()
+            .zip(node.gradients_node())
+            .enumerate()
+        {
+            let gradient = gradient.dim();
+            let value = operand.value_shape();
+
+            if operand.requires_gradient() && gradient != value {
+                return Err(GraphError::GradientShape {
+                    op: description.op,
+                    operand: idx,
+                    gradient: gradient,
+                    value: value,
+                });
             }
         }
     }
+
+    Ok(())
 }
 
-fn row_wise_stack_gradient(gradient: &Arr, lhs: &mut Arr, rhs: &mut Arr, op: &BackwardAction) {
-    for (grad_row, mut dest_row) in gradient
-        .genrows()
-        .into_iter()
-        .zip(lhs.genrows_mut().into_iter().chain(rhs.genrows_mut()))
-    {
-        let grad_row = grad_row.as_slice().unwrap();
-        let dest_row = dest_row.as_slice_mut().unwrap();
+/// What a node computes: its operation, attributes and operands.
+type StructuralKey = (&'static str, String, Vec<*const u8>);
 
-        match op {
-            BackwardAction::Increment => for (x, y) in dest_row.iter_mut().zip(grad_row.iter()) {
-                *x += y;
-            },
-            BackwardAction::Set => for (x, &y) in dest_row.iter_mut().zip(grad_row.iter()) {
-                *x = y;
-            },
+/// The structural key of a node, with `canonical` mapping the addresses
+/// of its operands to those of the nodes computing the same values, or
+/// `None` for leaves and for nodes whose results are not determined by
+/// their description, such as hooks and closures.
+fn structural_key<F>(description: &Description, canonical: F) -> Option<StructuralKey>
+where
+    F: Fn(*const u8) -> *const u8,
+{
+    match description.op {
+        "Unknown" | "GradientHook" | "ForwardHook" | "CategoricalSample" | "RunningNorm" => {
+            return None
         }
+        _ if description.operands.is_empty() => return None,
+        _ => {}
     }
-}
 
-#[derive(Debug)]
-pub struct ConcatenateNode<LHS, RHS> {
-    axis: ndarray::Axis,
-    value: RefCell<Arr>,
-    lhs_gradient: RefCell<Arr>,
-    rhs_gradient: RefCell<Arr>,
-    lhs: Rc<LHS>,
-    rhs: Rc<RHS>,
-    needs_gradient: bool,
-    counter: PassCounter,
+    Some((
+        description.op,
+        format!("{:?}", description.attributes),
+        description
+            .operands
+            .iter()
+            .map(|operand| canonical(operand.node_address()))
+            .collect(),
+    ))
 }
 
-impl<LHS, RHS> ConcatenateNode<LHS, RHS>
-where
-    LHS: Node<Value = Arr>,
-    RHS: Node<Value = Arr>,
-{
-    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>, axis: ndarray::Axis) -> Self {
-        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
-
-        let value = ndarray::stack(
-            axis,
-            &[lhs.value().deref().view(), rhs.value().deref().view()],
-        ).expect("Unable to concatenate arrays.");
-
-        let lhs_gradient = lhs.value().deref() * 0.0;
-        let rhs_gradient = rhs.value().deref() * 0.0;
+/// Structurally identical subgraphs computing the same value from the
+/// same operands, as found by `Variable::find_duplicate_subgraphs`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DuplicateSubgraph {
+    /// Name of the operation at the root of the subgraphs.
+    pub op: &'static str,
+    /// The number of copies in the graph.
+    pub copies: usize,
+    /// The `(rows, cols)` shape of the value they compute.
+    pub shape: (usize, usize),
+}
 
-        ConcatenateNode {
-            axis: axis,
-            value: RefCell::new(value),
-            lhs_gradient: RefCell::new(lhs_gradient),
-            rhs_gradient: RefCell::new(rhs_gradient),
-            lhs: lhs,
-            rhs: rhs,
-            needs_gradient: needs_gradient,
-            counter: PassCounter::default(),
-        }
+impl fmt::Display for DuplicateSubgraph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} copies of a subgraph ending in {} with a value of shape {:?}",
+            self.copies, self.op, self.shape
+        )
     }
 }
 
-impl<LHS, RHS> Node for ConcatenateNode<LHS, RHS>
-where
-    LHS: Node<Value = Arr, InputGradient = Arr>,
-    RHS: Node<Value = Arr, InputGradient = Arr>,
-{
-    type Value = Arr;
-    type InputGradient = Arr;
-    fn forward(&self) {
-        if self.counter.forward() == ForwardAction::Cached {
-            return;
+/// Find duplicated subgraphs of the graph ending at `root`, reporting
+/// only the largest of nested duplicates. See
+/// `Variable::find_duplicate_subgraphs`.
+pub(crate) fn find_duplicates(root: &GraphNode) -> Vec<DuplicateSubgraph> {
+    let mut seen = HashSet::new();
+    let mut canonical: HashMap<*const u8, *const u8> = HashMap::new();
+    let mut consumers: HashMap<*const u8, Vec<*const u8>> = HashMap::new();
+    let mut classes: HashMap<StructuralKey, Vec<*const u8>> = HashMap::new();
+    let mut keys = Vec::new();
+    let mut shapes = HashMap::new();
+
+    for node in topological_order(root) {
+        // Boxed nodes appear next to the nodes they wrap.
+        let address = node.node_address();
+
+        if !seen.insert(address) {
+            continue;
         }
 
-        self.lhs.forward();
-        self.rhs.forward();
+        let description = node.description();
 
-        let lhs_value = self.lhs.value();
-        let rhs_value = self.rhs.value();
+        for operand in &description.operands {
+            consumers
+                .entry(operand.node_address())
+                .or_default()
+                .push(address);
+        }
 
-        let mut self_value = self.value.borrow_mut();
+        let key = structural_key(&description, |operand| {
+            *canonical.get(&operand).unwrap_or(&operand)
+        });
 
-        match self.axis {
-            // Vertically
-            ndarray::Axis(0) => {
-                row_wise_stack(self_value.deref_mut(), lhs_value.deref(), rhs_value.deref())
-            }
-            // Horizontally
-            ndarray::Axis(1) => {
-                column_wise_stack(self_value.deref_mut(), lhs_value.deref(), rhs_value.deref())
+        if let Some(key) = key {
+            if !classes.contains_key(&key) {
+                keys.push(key.clone());
+                shapes.insert(address, node.value_shape());
             }
-            // Not allowed
-            _ => panic!("Stacking tensors not allowed."),
+
+            let class = classes.entry(key).or_default();
+            class.push(address);
+            canonical.insert(address, class[0]);
         }
     }
-    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        {
-            let mut lhs_grad = self.lhs_gradient.borrow_mut();
-            let mut rhs_grad = self.rhs_gradient.borrow_mut();
 
-            match self.axis {
-                ndarray::Axis(0) => row_wise_stack_gradient(
-                    gradient,
-                    lhs_grad.deref_mut(),
-                    rhs_grad.deref_mut(),
-                    &self.counter.backward(),
-                ),
-                ndarray::Axis(1) => column_wise_stack_gradient(
-                    gradient,
-                    lhs_grad.deref_mut(),
-                    rhs_grad.deref_mut(),
-                    &self.counter.backward(),
-                ),
-                _ => panic!("Stacking tensors not allowed."),
-            }
+    let duplicated: HashSet<_> = classes
+        .values()
+        .filter(|class| class.len() > 1)
+        .flat_map(|class| class.iter().cloned())
+        .collect();
+
+    // Copies used only by copies of a larger subgraph are
+    // reported as part of it.
+    let nested = |class: &[*const u8]| {
+        class.iter().all(|member| match consumers.get(member) {
+            Some(consumers) => consumers.iter().all(|x| duplicated.contains(x)),
+            None => false,
+        })
+    };
+
+    keys.iter()
+        .filter(|&key| classes[key].len() > 1 && !nested(&classes[key]))
+        .map(|key| DuplicateSubgraph {
+            op: key.0,
+            copies: classes[key].len(),
+            shape: shapes[&classes[key][0]],
+        })
+        .collect()
+}
+
+/// A graph flattened into topological order, so that passes through it
+/// visit each node once without recursing. See `Variable::linearize`.
+pub(crate) struct Schedule {
+    /// Each node with operands, after its operands, with the number of
+    /// operations using it.
+    steps: Vec<(*const GraphNode, usize)>,
+}
+
+impl Schedule {
+    /// Linearize the graph ending at `root`, or return `None` if any of
+    /// its nodes does not support linearized passes.
+    ///
+    /// The schedule refers to the nodes without owning them: it must not
+    /// outlive `root`.
+    pub(crate) fn new(root: &GraphNode) -> Option<Self> {
+        let order = topological_order(root);
+
+        if !order.iter().all(|node| node.is_linearizable()) {
+            return None;
         }
 
-        if self.counter.recurse_backward() {
-            self.lhs.backward(&self.lhs_gradient.borrow());
-            self.rhs.backward(&self.rhs_gradient.borrow());
+        // The root is used once, by the variable running the passes.
+        let mut uses = HashMap::new();
+        uses.insert(node_id(root), 1);
+
+        for node in &order {
+            for &operand in &node.description().operands {
+                *uses.entry(node_id(operand)).or_insert(0) += 1;
+            }
         }
-    }
 
-    fn value(&self) -> Bor<Self::Value> {
-        Bor::RefGuard(self.value.borrow())
+        // Leaves have nothing to compute or propagate, and their gradients
+        // are zeroed by the nodes using them.
+        let steps = order
+            .into_iter()
+            .filter(|node| !node.description().operands.is_empty())
+            .map(|node| {
+                let uses = uses[&node_id(node)];
+                // The caller keeps the nodes alive for as long as the
+                // schedule exists.
+                let node: *const GraphNode = unsafe { ::std::mem::transmute(node) };
+
+                (node, uses)
+            })
+            .collect();
+
+        Some(Schedule { steps: steps })
     }
-    fn needs_gradient(&self) -> bool {
-        self.needs_gradient
+
+    pub(crate) fn forward(&self) {
+        for &(node, uses) in &self.steps {
+            let node = unsafe { &*node };
+            node.evaluate_node(uses);
+
+            #[cfg(feature = "nan-guard")]
+            check_finite_value(node);
+        }
     }
-    fn zero_gradient(&self) {
-        if !self.counter.is_zero() {
-            self.lhs.zero_gradient();
-            self.rhs.zero_gradient();
-            self.counter.clear();
+
+    /// Backpropagate gradients already accumulated into the root.
+    pub(crate) fn backward(&self) {
+        for &(node, _) in self.steps.iter().rev() {
+            let node = unsafe { &*node };
+            node.propagate_node();
+
+            #[cfg(feature = "nan-guard")]
+            check_finite_gradients(node);
         }
     }
-}
 
-/// Input node for the graph.
-#[derive(Debug)]
-pub struct InputNode {
-    pub value: RefCell<Arr>,
+    pub(crate) fn zero_gradient(&self) {
+        // Operands are cleared first, so that each node's zero_gradient
+        // finds them already cleared and does not recurse further.
+        for &(node, _) in &self.steps {
+            unsafe { &*node }.zero_gradient_node();
+        }
+    }
 }
 
-impl InputNode {
-    /// Create a new input node with a given value. This fixes the shape
-    /// of the node in the graph.
-    pub fn new(value: Arr) -> Variable<Self> {
-        Variable::new(
-            Rc::new(InputNode {
-                value: RefCell::new(value),
-            }),
-            Vec::new(),
-        )
+impl fmt::Debug for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Schedule({} steps)", self.steps.len())
     }
 }
 
-impl Node for InputNode {
+impl Node for Rc<Node<Value = Arr, InputGradient = Arr>> {
     type Value = Arr;
     type InputGradient = Arr;
-    fn forward(&self) {}
-    fn backward(&self, _: &Ref<Self::InputGradient>) {}
+    fn forward(&self) {
+        self.deref().forward()
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        self.deref().backward(gradient)
+    }
     fn value(&self) -> Bor<Self::Value> {
-        Bor::RefGuard(self.value.borrow())
+        self.deref().value()
     }
     fn needs_gradient(&self) -> bool {
-        false
+        self.deref().needs_gradient()
+    }
+    fn zero_gradient(&self) {
+        self.deref().zero_gradient()
+    }
+    fn describe(&self) -> Description {
+        self.deref().describe()
+    }
+    fn shared_parameter(&self) -> Option<&Arc<HogwildParameter>> {
+        self.deref().shared_parameter()
+    }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        self.deref().tangent(builder)
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        self.deref().clone_architecture(builder)
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        self.deref().compile(builder)
+    }
+    fn fit_rows(&self, rows: usize) {
+        self.deref().fit_rows(rows)
+    }
+    fn address(&self) -> *const u8 {
+        self.deref().address()
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        self.deref().pass_counter()
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        self.deref().gradients()
+    }
+    fn flops(&self) -> Option<usize> {
+        self.deref().flops()
     }
-    fn zero_gradient(&self) {}
 }
 
-#[derive(Debug, Clone)]
-pub(crate) struct SparseGradientStore {
-    len: usize,
-    data: Vec<(Vec<usize>, Arr)>,
+/// A type-erased node, as produced by `Variable::boxed`.
+type BoxedNode = Rc<Node<Value = Arr, InputGradient = Arr>>;
+
+/// The forward-mode tangent of a node, or `None` if the tangent is zero.
+pub type Tangent = Option<Variable<BoxedNode>>;
+
+/// Builds forward-mode tangents of the nodes of a graph, given
+/// the directions in which its parameters move.
+pub struct TangentBuilder {
+    directions: Vec<(*const ParameterNode, Arr)>,
+    tangents: HashMap<*const u8, Tangent>,
 }
 
-impl SparseGradientStore {
-    pub fn new() -> Self {
-        SparseGradientStore {
-            len: 0,
-            data: Vec::new(),
+impl TangentBuilder {
+    pub(crate) fn new(directions: &[(&Variable<ParameterNode>, &Arr)]) -> Self {
+        TangentBuilder {
+            directions: directions
+                .iter()
+                .map(|&(parameter, direction)| {
+                    assert_eq!(
+                        parameter.value().dim(),
+                        direction.dim(),
+                        "Direction must have the shape of the parameter."
+                    );
+                    (&*parameter.node as *const ParameterNode, direction.clone())
+                })
+                .collect(),
+            tangents: HashMap::new(),
         }
     }
 
-    pub fn push(&mut self, gradient: (&[usize], &Arr)) {
-        let (index, value) = gradient;
+    /// The tangent of `node`. Tangents of nodes used several times
+    /// in the graph are only built once.
+    pub(crate) fn tangent<N>(&mut self, node: &Rc<N>) -> Result<Tangent, String>
+    where
+        N: Node<Value = Arr, InputGradient = Arr>,
+    {
+        let id = &**node as *const N as *const u8;
 
-        if self.len < self.data.len() {
-            let (index_vec, grad) = &mut self.data[self.len];
-            index_vec.clear();
-            index_vec.extend_from_slice(&index[..]);
-            grad.slice_assign(value);
-            self.len += 1;
-        } else {
-            self.data.push((Vec::from(&index[..]), value.clone()));
+        if let Some(tangent) = self.tangents.get(&id) {
+            return Ok(tangent.clone());
         }
-    }
 
-    pub fn as_slice(&self) -> &[(Vec<usize>, Arr)] {
-        &self.data[..self.len]
+        let tangent = node.tangent(self)?;
+        self.tangents.insert(id, tangent.clone());
+
+        Ok(tangent)
     }
 
-    pub fn as_slice_mut(&mut self) -> &mut [(Vec<usize>, Arr)] {
-        &mut self.data[..self.len]
+    fn direction(&self, parameter: &ParameterNode) -> Option<&Arr> {
+        self.directions
+            .iter()
+            .find(|&&(ptr, _)| ptr == parameter as *const ParameterNode)
+            .map(|&(_, ref direction)| direction)
     }
+}
 
-    pub fn clear(&mut self) {
-        self.len = 0;
+/// Wrap a node as a variable, for use in building tangents.
+fn tangent_operand<N>(node: &Rc<N>) -> Variable<BoxedNode>
+where
+    N: Node<Value = Arr, InputGradient = Arr>,
+{
+    Variable::new(Rc::new(Rc::clone(node) as BoxedNode), Vec::new())
+}
+
+fn add_tangents(lhs: Tangent, rhs: Tangent) -> Tangent {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => Some((lhs + rhs).boxed()),
+        (lhs, None) => lhs,
+        (None, rhs) => rhs,
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct GradientAccumulator {
-    pub dense_shape: (usize, usize),
-    pub dense_gradient: Option<Arr>,
-    pub sparse_gradient: SparseGradientStore,
-    pub has_dense: bool,
+/// The copy of a node built by `Node::clone_architecture`, or `None` if
+/// the node is shared between the original graph and the copy.
+pub type Cloned = Option<Variable<BoxedNode>>;
+
+/// How `Variable::clone_architecture` initializes the parameters
+/// of the copy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloneMode {
+    /// Draw new values with `nn::xavier_normal`.
+    FreshXavier,
+    /// Copy the current values of the original parameters.
+    CopyValues,
+    /// Use the original parameter nodes, so that the copy trains
+    /// the same parameters.
+    ShareParameters,
 }
 
-impl GradientAccumulator {
-    fn new(dense_shape: (usize, usize)) -> Self {
-        GradientAccumulator {
-            dense_shape: dense_shape,
-            dense_gradient: None,
-            sparse_gradient: SparseGradientStore::new(),
-            has_dense: false,
+/// Builds structural copies of graphs, with new parameters and pass
+/// state. Nodes used several times, including parameters, are copied
+/// once, so the copy shares nodes wherever the original does.
+///
+/// ```rust
+/// # use wyrm::*;
+/// let x = InputNode::new(Arr::zeros((1, 3)));
+/// let w = ParameterNode::new(nn::xavier_normal(3, 2));
+/// let y = x.dot(&w).sigmoid();
+///
+/// let mut builder = CloneBuilder::new(CloneMode::CopyValues).share_inputs(false);
+/// let copy = builder.clone_variable(&y).unwrap();
+/// let copied_x = builder.input(&x).unwrap();
+///
+/// copied_x.set_value(&Arr::ones((1, 3)));
+/// assert_eq!(copy.parameters().len(), 1);
+/// assert!(x.value().iter().all(|&v| v == 0.0));
+/// ```
+pub struct CloneBuilder {
+    mode: CloneMode,
+    share_inputs: bool,
+    nodes: HashMap<*const u8, Variable<BoxedNode>>,
+    parameters: HashMap<*const u8, Variable<ParameterNode>>,
+    inputs: HashMap<*const u8, Variable<InputNode>>,
+    index_inputs: HashMap<*const u8, Variable<IndexInputNode>>,
+    deduplicate: bool,
+    structures: HashMap<StructuralKey, Variable<BoxedNode>>,
+}
+
+impl CloneBuilder {
+    /// Create a builder initializing parameters according to `mode`.
+    /// Input nodes are shared with the original graph.
+    pub fn new(mode: CloneMode) -> Self {
+        CloneBuilder {
+            mode: mode,
+            share_inputs: true,
+            nodes: HashMap::new(),
+            parameters: HashMap::new(),
+            inputs: HashMap::new(),
+            index_inputs: HashMap::new(),
+            deduplicate: false,
+            structures: HashMap::new(),
         }
     }
-    pub fn dense_gradient(&mut self) -> &mut Arr {
-        let shape = self.dense_shape;
 
-        self.dense_gradient.get_or_insert_with(|| Arr::zeros(shape))
+    /// Whether to share input and index input nodes with the original
+    /// graph, so that setting an input feeds both, or to give the copy
+    /// its own inputs, available through `input` and `index_input`.
+    /// Constant inputs, as created by arithmetic with scalars, are
+    /// always copied.
+    pub fn share_inputs(mut self, share_inputs: bool) -> Self {
+        self.share_inputs = share_inputs;
+        self
     }
-    fn zero_gradient(&mut self) {
-        if self.has_dense {
-            self.dense_gradient().fill(0.0);
-        }
 
-        self.sparse_gradient.clear();
-        self.has_dense = false;
+    /// Whether to merge structurally identical subgraphs of the copy,
+    /// computing each of them once: see `Variable::dedup`.
+    pub fn deduplicate(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
     }
 
-    pub fn clamp(&mut self, min: f32, max: f32) {
-        self.dense_gradient()
-            .as_slice_mut()
-            .unwrap()
-            .iter_mut()
-            .for_each(|x| *x = clamp(*x, min, max));
-        self.sparse_gradient
-            .as_slice_mut()
-            .iter_mut()
-            .for_each(|(_, ref mut grad)| {
-                grad.as_slice_mut()
-                    .unwrap()
-                    .iter_mut()
-                    .for_each(|x| *x = clamp(*x, min, max))
-            });
-    }
-}
+    /// Copy the graph terminating at `variable`.
+    pub fn clone_variable<T>(
+        &mut self,
+        variable: &Variable<T>,
+    ) -> Result<Variable<BoxedNode>, String>
+    where
+        T: Node<Value = Arr, InputGradient = Arr>,
+    {
+        if self.mode == CloneMode::ShareParameters {
+            for parameter in &variable.parameters {
+                self.parameters
+                    .entry(parameter.address())
+                    .or_insert_with(|| {
+                        Variable::new(Rc::clone(parameter), vec![Rc::clone(parameter)])
+                    });
+            }
+        }
 
-pub trait GradientSink<T> {
-    fn accumulate_gradient(&mut self, gradient: T);
-}
+        self.clone_node(&variable.node)
+    }
 
-impl<'a, 'b> GradientSink<&'a Ref<'b, Arr>> for GradientAccumulator {
-    fn accumulate_gradient(&mut self, gradient: &Ref<Arr>) {
-        self.dense_gradient().slice_add_assign(gradient.deref());
-        self.has_dense = true;
+    /// The copy of `input`, or `input` itself if inputs are shared.
+    /// Returns `None` if inputs are copied but no copied graph uses `input`.
+    pub fn input(&self, input: &Variable<InputNode>) -> Option<Variable<InputNode>> {
+        if self.share_inputs {
+            Some(input.clone())
+        } else {
+            self.inputs.get(&input.node.address()).cloned()
+        }
     }
-}
+
+    /// The copy of `index`, or `index` itself if inputs are shared.
+    /// Returns `None` if inputs are copied but no copied graph uses `index`.
+    pub fn index_input(
+        &self,
+        index: &Variable<IndexInputNode>,
+    ) -> Option<Variable<IndexInputNode>> {
+        if self.share_inputs {
+            Some(index.clone())
+        } else {
+            self.index_inputs
+                .get(&(&*index.node as *const IndexInputNode as *const u8))
+                .cloned()
+        }
+    }
+
+    fn clone_node<N>(&mut self, node: &Rc<N>) -> Result<Variable<BoxedNode>, String>
+    where
+        N: Node<Value = Arr, InputGradient = Arr>,
+    {
+        let id = node.address();
+
+        if let Some(clone) = self.nodes.get(&id) {
+            return Ok(clone.clone());
+        }
+
+        let mut clone = node.clone_architecture(self)?
+            .unwrap_or_else(|| tangent_operand(node));
+
+        if self.deduplicate {
+            // Operands are deduplicated first, so identical subgraphs
+            // have identical operands.
+            if let Some(key) = structural_key(&clone.node.describe(), |operand| operand) {
+                clone = self.structures.entry(key).or_insert(clone).clone();
+            }
+        }
+
+        self.nodes.insert(id, clone.clone());
+
+        Ok(clone)
+    }
+
+    fn parameter(&mut self, parameter: &ParameterNode) -> Variable<ParameterNode> {
+        let id = parameter.address();
+        let mode = self.mode;
+
+        self.parameters
+            .entry(id)
+            .or_insert_with(|| {
+                let value = match mode {
+                    CloneMode::FreshXavier => {
+                        let (rows, cols) = parameter.value.shape();
+                        super::nn::xavier_normal(rows, cols)
+                    }
+                    CloneMode::CopyValues => parameter.value.full_value().into_owned(),
+                    // Parameters missing from the variable's parameters
+                    // share their values instead.
+                    CloneMode::ShareParameters => {
+                        return ParameterNode::shared(Arc::clone(&parameter.value))
+                    }
+                };
+                let value = if parameter.value.is_half() {
+                    HogwildParameter::half(value)
+                } else {
+                    HogwildParameter::new(value)
+                };
+
+                ParameterNode::shared(Arc::new(value))
+            })
+            .clone()
+    }
+
+    fn copy_input(&mut self, input: &InputNode) -> Cloned {
+        if self.share_inputs && input.fill.is_none() {
+            return None;
+        }
+
+        let clone = self.inputs
+            .entry(input.address())
+            .or_insert_with(|| {
+                Variable::new(
+                    Rc::new(InputNode {
+                        value: RefCell::new(input.value.borrow().clone()),
+                        fill: input.fill,
+                    }),
+                    Vec::new(),
+                )
+            });
+
+        Some(clone.boxed())
+    }
+
+    fn copy_index(&mut self, index: &Rc<IndexInputNode>) -> Variable<IndexInputNode> {
+        let share_inputs = self.share_inputs;
+
+        self.index_inputs
+            .entry(&**index as *const IndexInputNode as *const u8)
+            .or_insert_with(|| {
+                if share_inputs {
+                    Variable::new(Rc::clone(index), Vec::new())
+                } else {
+                    IndexInputNode::new(&index.value.borrow()[..])
+                }
+            })
+            .clone()
+    }
+}
+
+/// Error returned when the operands of a node have incompatible shapes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeError {
+    /// Name of the operation, as given by `Node::describe`.
+    pub op: &'static str,
+    /// The `(rows, cols)` shape of the left-hand operand.
+    pub lhs: (usize, usize),
+    /// The `(rows, cols)` shape of the right-hand operand.
+    pub rhs: (usize, usize),
+}
+
+impl ShapeError {
+    fn new(op: &'static str, lhs: &Arr, rhs: &Arr) -> Self {
+        ShapeError {
+            op: op,
+            lhs: lhs.dim(),
+            rhs: rhs.dim(),
+        }
+    }
+}
+
+impl fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Incompatible operand shapes for {}: {:?} and {:?}.",
+            self.op, self.lhs, self.rhs
+        )
+    }
+}
+
+impl error::Error for ShapeError {
+    fn description(&self) -> &str {
+        "incompatible operand shapes"
+    }
+}
+
+/// Inconsistent state of a graph, as found by `Variable::validate`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphError {
+    /// A node was forwarded a different number of times than it is used
+    /// by evaluated nodes, so its backward pass will not recurse into its
+    /// operands when it should.
+    CounterMismatch {
+        op: &'static str,
+        expected: usize,
+        forward_count: usize,
+    },
+    /// A node was evaluated though none of the nodes using it were: it
+    /// was forwarded on its own, or `zero_gradient` did not reach it.
+    StaleCounter {
+        op: &'static str,
+        forward_count: usize,
+    },
+    /// A node received fewer backward passes than forward passes.
+    IncompleteBackward {
+        op: &'static str,
+        forward_count: usize,
+        backward_count: usize,
+    },
+    /// The gradient a node passed back to its `operand`-th operand does
+    /// not have the shape of the operand's value.
+    GradientShape {
+        op: &'static str,
+        operand: usize,
+        gradient: (usize, usize),
+        value: (usize, usize),
+    },
+    /// A parameter of the graph is missing from `Variable::parameters`,
+    /// so optimizers will not update it.
+    MissingParameter { shape: (usize, usize) },
+    /// A parameter holds gradients though the graph has not been
+    /// evaluated, as at the start of an iteration.
+    StaleGradient { shape: (usize, usize) },
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GraphError::CounterMismatch {
+                op,
+                expected,
+                forward_count,
+            } => write!(
+                f,
+                "{} node was forwarded {} times, but has {} evaluated uses.",
+                op, forward_count, expected
+            ),
+            GraphError::StaleCounter { op, forward_count } => write!(
+                f,
+                "{} node was forwarded {} times, but none of its uses were evaluated.",
+                op, forward_count
+            ),
+            GraphError::IncompleteBackward {
+                op,
+                forward_count,
+                backward_count,
+            } => write!(
+                f,
+                "{} node received {} of {} backward passes.",
+                op, backward_count, forward_count
+            ),
+            GraphError::GradientShape {
+                op,
+                operand,
+                gradient,
+                value,
+            } => write!(
+                f,
+                "{} node has a gradient of shape {:?} for operand {} of shape {:?}.",
+                op, gradient, operand, value
+            ),
+            GraphError::MissingParameter { shape } => write!(
+                f,
+                "Parameter of shape {:?} is missing from the parameters of the graph.",
+                shape
+            ),
+            GraphError::StaleGradient { shape } => write!(
+                f,
+                "Parameter of shape {:?} holds gradients before the graph was evaluated.",
+                shape
+            ),
+        }
+    }
+}
+
+impl error::Error for GraphError {
+    fn description(&self) -> &str {
+        "inconsistent graph state"
+    }
+}
+
+/// Brings a broadcast constant among the operands of an element-wise
+/// binary node to the number of rows of the other operand.
+fn fit_operand_rows<LHS, RHS>(lhs: &LHS, rhs: &RHS)
+where
+    LHS: Node<Value = Arr>,
+    RHS: Node<Value = Arr>,
+{
+    let rhs_rows = rhs.value().rows();
+    lhs.fit_rows(rhs_rows);
+    let lhs_rows = lhs.value().rows();
+    rhs.fit_rows(lhs_rows);
+}
+
+fn check_same_shape(op: &'static str, lhs: &Arr, rhs: &Arr) -> Result<(), ShapeError> {
+    if lhs.dim() == rhs.dim() {
+        Ok(())
+    } else {
+        Err(ShapeError::new(op, lhs, rhs))
+    }
+}
+
+#[derive(Debug)]
+pub struct AddNode<LHS, RHS> {
+    value: RefCell<Arr>,
+    gradient: RefCell<Arr>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS, RHS> AddNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr>,
+    RHS: Node<Value = Arr>,
+{
+    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
+        Self::try_new(lhs, rhs).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `new`, but returns an error if the operands' shapes are
+    /// incompatible.
+    pub fn try_new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Result<Self, ShapeError> {
+        check_same_shape("Add", &lhs.value(), &rhs.value())?;
+
+        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
+        let mut value = arena::zeros(lhs.value().dim());
+        numerics::map_assign_binary(&mut value, &lhs.value(), &rhs.value(), |x, y| x + y);
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        Ok(AddNode {
+            value: RefCell::new(value),
+            gradient: RefCell::new(gradient),
+            lhs: lhs,
+            rhs: rhs,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        })
+    }
+
+    fn compute(&self) {
+        fit_operand_rows(&*self.lhs, &*self.rhs);
+
+        let lhs_value = self.lhs.value();
+        let rhs_value = self.rhs.value();
+        debug_assert_eq!(lhs_value.dim(), rhs_value.dim(), "Operand shapes diverged.");
+
+        let mut self_value = self.value.borrow_mut();
+        fit_shape(&mut self_value, lhs_value.dim());
+
+        numerics::map_assign_binary(&mut self_value, &lhs_value, &rhs_value, |x, y| x + y);
+    }
+}
+
+impl<LHS, RHS> Drop for AddNode<LHS, RHS> {
+    fn drop(&mut self) {
+        arena::recycle(self.value.get_mut());
+        arena::recycle(self.gradient.get_mut());
+    }
+}
+
+impl<LHS, RHS> Node for AddNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.lhs.forward();
+        self.rhs.forward();
+
+        self.compute();
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        self.accumulate(gradient);
+
+        if self.counter.recurse_backward() {
+            let gradient = self.gradient.borrow();
+            self.lhs.backward(&gradient);
+            self.rhs.backward(&gradient);
+        }
+    }
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, uses: usize) {
+        if self.counter.forward_many(uses) == ForwardAction::Cached {
+            return;
+        }
+
+        self.compute();
+    }
+    fn accumulate(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut operand_gradient = self.gradient.borrow_mut();
+                fit_shape(&mut operand_gradient, gradient.dim());
+                operand_gradient.slice_assign(gradient.deref());
+            }
+            BackwardAction::Increment => {
+                let mut operand_gradient = self.gradient.borrow_mut();
+                operand_gradient.slice_add_assign(gradient.deref());
+            }
+        }
+    }
+    fn propagate(&self) {
+        if self.counter.recurse_backward() {
+            let gradient = self.gradient.borrow();
+            self.lhs.accumulate(&gradient);
+            self.rhs.accumulate(&gradient);
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.lhs.zero_gradient();
+            self.rhs.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        Ok(add_tangents(
+            builder.tangent(&self.lhs)?,
+            builder.tangent(&self.rhs)?,
+        ))
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let lhs = builder.clone_node(&self.lhs)?;
+        let rhs = builder.clone_node(&self.rhs)?;
+        Ok(Some((lhs + rhs).boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let lhs = builder.compile(&self.lhs)?;
+        let rhs = builder.compile(&self.rhs)?;
+        Ok(builder.step(Op::Add, vec![lhs, rhs], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Add")
+            .operand(&*self.lhs)
+            .operand(&*self.rhs)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.gradient.borrow(), self.gradient.borrow()]
+    }
+}
+
+/// Add the `(1, cols)` row `bias` to every row of `operand`.
+pub(crate) fn add_bias(dest: &mut Arr, operand: &Arr, bias: &Arr) {
+    fit_shape(dest, operand.dim());
+    let bias = bias.fast_slice();
+
+    for (mut dest_row, operand_row) in dest.genrows_mut().into_iter().zip(operand.genrows()) {
+        for (v, &x, &b) in izip!(
+            dest_row.as_slice_mut().unwrap(),
+            operand_row.as_slice().unwrap(),
+            bias
+        ) {
+            *v = x + b;
+        }
+    }
+}
+
+/// Adds a learnable `(1, cols)` bias vector to every row of its operand.
+#[derive(Debug)]
+pub struct BiasNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    bias_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    bias: Rc<ParameterNode>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> BiasNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, bias: Rc<ParameterNode>) -> Self {
+        let value = operand.value().deref() + bias.value().deref();
+        let needs_gradient = operand.needs_gradient() || bias.needs_gradient();
+        let operand_gradient = gradient_buffer(value.dim(), needs_gradient);
+        let bias_gradient = gradient_buffer(bias.value().dim(), needs_gradient);
+
+        BiasNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(operand_gradient),
+            bias_gradient: RefCell::new(bias_gradient),
+            operand: operand,
+            bias: bias,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+
+    pub(crate) fn bias(&self) -> &Rc<ParameterNode> {
+        &self.bias
+    }
+}
+
+impl<OP> Node for BiasNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        add_bias(
+            &mut self.value.borrow_mut(),
+            &self.operand.value(),
+            &self.bias.value(),
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        {
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            let mut bias_gradient = self.bias_gradient.borrow_mut();
+
+            match self.counter.backward() {
+                BackwardAction::Set => {
+                    fit_shape(&mut operand_gradient, gradient.dim());
+                    operand_gradient.slice_assign(gradient.deref());
+                    bias_gradient.fill(0.0);
+                }
+                BackwardAction::Increment => {
+                    operand_gradient.slice_add_assign(gradient.deref());
+                }
+            }
+
+            let bias_gradient = bias_gradient.fast_slice_mut();
+
+            for gradient_row in gradient.genrows() {
+                for (b, &g) in izip!(bias_gradient.iter_mut(), gradient_row.as_slice().unwrap()) {
+                    *b += g;
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+            self.bias.backward(&self.bias_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.bias.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        let bias = builder.compile(&self.bias)?;
+        Ok(builder.step(Op::Bias, vec![operand, bias], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Bias")
+            .operand(&*self.operand)
+            .operand(&*self.bias)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow(), self.bias_gradient.borrow()]
+    }
+}
+
+/// Computes the convex combination `s * lhs + (1 - s) * rhs`, where the
+/// mixing coefficient `s = sigmoid(alpha)` is learned through the scalar
+/// parameter `alpha`.
+#[derive(Debug)]
+pub struct ConvexCombineNode<LHS, RHS> {
+    value: RefCell<Arr>,
+    mixing: Cell<Float>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
+    alpha_gradient: RefCell<Arr>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    alpha: Rc<ParameterNode>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+fn convex_combine(dest: &mut Arr, lhs: &Arr, rhs: &Arr, mixing: Float) {
+    numerics::map_assign_binary(dest, lhs, rhs, |x, y| mixing * x + (1.0 - mixing) * y);
+}
+
+impl<LHS, RHS> ConvexCombineNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr>,
+    RHS: Node<Value = Arr>,
+{
+    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>, alpha: Rc<ParameterNode>) -> Self {
+        Self::try_new(lhs, rhs, alpha).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `new`, but returns an error if the operands' shapes are
+    /// incompatible.
+    pub fn try_new(
+        lhs: Rc<LHS>,
+        rhs: Rc<RHS>,
+        alpha: Rc<ParameterNode>,
+    ) -> Result<Self, ShapeError> {
+        check_same_shape("ConvexCombine", &lhs.value(), &rhs.value())?;
+        assert_eq!(
+            alpha.value().dim(),
+            (1, 1),
+            "The mixing parameter must be a scalar."
+        );
+
+        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient() || alpha.needs_gradient();
+
+        let mixing = numerics::sigmoid(alpha.value()[(0, 0)]);
+        let mut value = lhs.value().deref() * 0.0;
+        convex_combine(&mut value, &lhs.value(), &rhs.value(), mixing);
+
+        let lhs_gradient = gradient_buffer(value.dim(), needs_gradient);
+        let rhs_gradient = gradient_buffer(value.dim(), needs_gradient);
+        let alpha_gradient = gradient_buffer((1, 1), needs_gradient);
+
+        Ok(ConvexCombineNode {
+            value: RefCell::new(value),
+            mixing: Cell::new(mixing),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            rhs_gradient: RefCell::new(rhs_gradient),
+            alpha_gradient: RefCell::new(alpha_gradient),
+            lhs: lhs,
+            rhs: rhs,
+            alpha: alpha,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        })
+    }
+}
+
+impl<LHS, RHS> Node for ConvexCombineNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.lhs.forward();
+        self.rhs.forward();
+
+        let mixing = numerics::sigmoid(self.alpha.value()[(0, 0)]);
+        self.mixing.set(mixing);
+
+        fit_shape(&mut self.value.borrow_mut(), self.lhs.value().dim());
+        convex_combine(
+            &mut self.value.borrow_mut(),
+            &self.lhs.value(),
+            &self.rhs.value(),
+            mixing,
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let mixing = self.mixing.get();
+            let lhs_value = self.lhs.value();
+            let rhs_value = self.rhs.value();
+
+            let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+            let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+            fit_shape(&mut lhs_gradient, lhs_value.dim());
+            fit_shape(&mut rhs_gradient, rhs_value.dim());
+
+            let mut mixing_gradient = 0.0;
+
+            for (lhs_grad, rhs_grad, &grad, &lhs, &rhs) in izip!(
+                lhs_gradient.fast_slice_mut(),
+                rhs_gradient.fast_slice_mut(),
+                gradient.fast_slice(),
+                lhs_value.fast_slice(),
+                rhs_value.fast_slice()
+            ) {
+                *lhs_grad = beta * *lhs_grad + mixing * grad;
+                *rhs_grad = beta * *rhs_grad + (1.0 - mixing) * grad;
+                mixing_gradient += grad * (lhs - rhs);
+            }
+
+            // Through the sigmoid.
+            let mut alpha_gradient = self.alpha_gradient.borrow_mut();
+            alpha_gradient[(0, 0)] =
+                beta * alpha_gradient[(0, 0)] + mixing_gradient * mixing * (1.0 - mixing);
+        }
+
+        if self.counter.recurse_backward() {
+            self.lhs.backward(&self.lhs_gradient.borrow());
+            self.rhs.backward(&self.rhs_gradient.borrow());
+            self.alpha.backward(&self.alpha_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.lhs.zero_gradient();
+            self.rhs.zero_gradient();
+            self.alpha.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("ConvexCombine")
+            .operand(&*self.lhs)
+            .operand(&*self.rhs)
+            .operand(&*self.alpha)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.lhs_gradient.borrow(), self.rhs_gradient.borrow(), self.alpha_gradient.borrow()]
+    }
+}
+
+pub(crate) fn row_wise_stack(dest: &mut Arr, lhs: &Arr, rhs: &Arr) {
+    for (mut dest_row, source_row) in dest
+        .genrows_mut()
+        .into_iter()
+        .zip(lhs.genrows().into_iter().chain(rhs.genrows()))
+    {
+        numerics::slice_assign(
+            dest_row.as_slice_mut().unwrap(),
+            source_row.as_slice().unwrap(),
+        );
+    }
+}
+
+pub(crate) fn column_wise_stack(dest: &mut Arr, lhs: &Arr, rhs: &Arr) {
+    for (mut dest_row, lhs_row, rhs_row) in izip!(
+        dest.genrows_mut().into_iter(),
+        lhs.genrows().into_iter(),
+        rhs.genrows().into_iter()
+    ) {
+        let dest_row = dest_row.as_slice_mut().unwrap();
+        let lhs_row = lhs_row.as_slice().unwrap();
+        let rhs_row = rhs_row.as_slice().unwrap();
+
+        let (left, right) = dest_row.split_at_mut(lhs_row.len());
+        numerics::slice_assign(left, lhs_row);
+        numerics::slice_assign(right, rhs_row);
+    }
+}
+
+fn column_wise_stack_gradient(gradient: &Arr, lhs: &mut Arr, rhs: &mut Arr, op: &BackwardAction) {
+    for (grad_row, mut lhs_row, mut rhs_row) in izip!(
+        gradient.genrows().into_iter(),
+        lhs.genrows_mut().into_iter(),
+        rhs.genrows_mut().into_iter()
+    ) {
+        let grad_row = grad_row.fast_slice();
+        let lhs_row = lhs_row.fast_slice_mut();
+        let rhs_row = rhs_row.fast_slice_mut();
+
+        let (left, right) = grad_row.split_at(lhs_row.len());
+
+        match op {
+            BackwardAction::Increment => {
+                for (x, y) in lhs_row.iter_mut().zip(left.iter()) {
+                    *x += y;
+                }
+                for (x, y) in rhs_row.iter_mut().zip(right.iter()) {
+                    *x += y;
+                }
+            }
+            BackwardAction::Set => {
+                lhs_row.copy_from_slice(left);
+                rhs_row.copy_from_slice(right);
+            }
+        }
+    }
+}
+
+fn row_wise_stack_gradient(gradient: &Arr, lhs: &mut Arr, rhs: &mut Arr, op: &BackwardAction) {
+    for (grad_row, mut dest_row) in gradient
+        .genrows()
+        .into_iter()
+        .zip(lhs.genrows_mut().into_iter().chain(rhs.genrows_mut()))
+    {
+        let grad_row = grad_row.as_slice().unwrap();
+        let dest_row = dest_row.as_slice_mut().unwrap();
+
+        match op {
+            BackwardAction::Increment => for (x, y) in dest_row.iter_mut().zip(grad_row.iter()) {
+                *x += y;
+            },
+            BackwardAction::Set => for (x, &y) in dest_row.iter_mut().zip(grad_row.iter()) {
+                *x = y;
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConcatenateNode<LHS, RHS> {
+    axis: ndarray::Axis,
+    value: RefCell<Arr>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS, RHS> ConcatenateNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr>,
+    RHS: Node<Value = Arr>,
+{
+    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>, axis: ndarray::Axis) -> Self {
+        Self::try_new(lhs, rhs, axis).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `new`, but returns an error if the operands' shapes are
+    /// incompatible.
+    pub fn try_new(
+        lhs: Rc<LHS>,
+        rhs: Rc<RHS>,
+        axis: ndarray::Axis,
+    ) -> Result<Self, ShapeError> {
+        {
+            let (lhs_value, rhs_value) = (lhs.value(), rhs.value());
+            let other_axis = 1 - axis.index();
+
+            if lhs_value.len_of(Axis(other_axis)) != rhs_value.len_of(Axis(other_axis)) {
+                return Err(ShapeError::new("Concatenate", &lhs_value, &rhs_value));
+            }
+        }
+
+        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
+
+        let value = ndarray::stack(
+            axis,
+            &[lhs.value().deref().view(), rhs.value().deref().view()],
+        ).expect("Shapes checked above.");
+
+        let lhs_gradient = gradient_buffer(lhs.value().dim(), needs_gradient);
+        let rhs_gradient = gradient_buffer(rhs.value().dim(), needs_gradient);
+
+        Ok(ConcatenateNode {
+            axis: axis,
+            value: RefCell::new(value),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            rhs_gradient: RefCell::new(rhs_gradient),
+            lhs: lhs,
+            rhs: rhs,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        })
+    }
+}
+
+impl<LHS, RHS> Node for ConcatenateNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.lhs.forward();
+        self.rhs.forward();
+
+        let lhs_value = self.lhs.value();
+        let rhs_value = self.rhs.value();
+
+        let mut self_value = self.value.borrow_mut();
+        let (lhs_rows, lhs_cols) = lhs_value.dim();
+        let (rhs_rows, rhs_cols) = rhs_value.dim();
+
+        match self.axis {
+            ndarray::Axis(0) => fit_shape(&mut self_value, (lhs_rows + rhs_rows, lhs_cols)),
+            _ => fit_shape(&mut self_value, (lhs_rows, lhs_cols + rhs_cols)),
+        }
+
+        match self.axis {
+            // Vertically
+            ndarray::Axis(0) => {
+                row_wise_stack(self_value.deref_mut(), lhs_value.deref(), rhs_value.deref())
+            }
+            // Horizontally
+            ndarray::Axis(1) => {
+                column_wise_stack(self_value.deref_mut(), lhs_value.deref(), rhs_value.deref())
+            }
+            // Not allowed
+            _ => panic!("Stacking tensors not allowed."),
+        }
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        {
+            let mut lhs_grad = self.lhs_gradient.borrow_mut();
+            let mut rhs_grad = self.rhs_gradient.borrow_mut();
+            fit_shape(&mut lhs_grad, self.lhs.value().dim());
+            fit_shape(&mut rhs_grad, self.rhs.value().dim());
+
+            match self.axis {
+                ndarray::Axis(0) => row_wise_stack_gradient(
+                    gradient,
+                    lhs_grad.deref_mut(),
+                    rhs_grad.deref_mut(),
+                    &self.counter.backward(),
+                ),
+                ndarray::Axis(1) => column_wise_stack_gradient(
+                    gradient,
+                    lhs_grad.deref_mut(),
+                    rhs_grad.deref_mut(),
+                    &self.counter.backward(),
+                ),
+                _ => panic!("Stacking tensors not allowed."),
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.lhs.backward(&self.lhs_gradient.borrow());
+            self.rhs.backward(&self.rhs_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.lhs.zero_gradient();
+            self.rhs.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let lhs = builder.clone_node(&self.lhs)?;
+        let rhs = builder.clone_node(&self.rhs)?;
+        Ok(Some(lhs.stack(&rhs, self.axis).boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let lhs = builder.compile(&self.lhs)?;
+        let rhs = builder.compile(&self.rhs)?;
+        Ok(builder.step(Op::Concatenate(self.axis), vec![lhs, rhs], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Concatenate")
+            .operand(&*self.lhs)
+            .operand(&*self.rhs)
+            .attribute("axis", Attribute::Int(self.axis.index() as i64))
+    }
+    fn flops(&self) -> Option<usize> {
+        Some(0)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.lhs_gradient.borrow(), self.rhs_gradient.borrow()]
+    }
+}
+
+/// Coordinator shared by the pieces of a column-wise split.
+///
+/// Each piece is a `SplitViewNode`; their gradients are accumulated
+/// here so that the operand is backpropagated through once.
+#[derive(Debug)]
+pub struct SplitNode<OP> {
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> SplitNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    /// Split the columns of `operand` at `split_points`, returning
+    /// one view node per piece.
+    pub fn new(operand: Rc<OP>, split_points: &[usize]) -> Vec<SplitViewNode<OP>> {
+        let (rows, cols) = operand.value().dim();
+        let needs_gradient = operand.needs_gradient();
+
+        let mut bounds = Vec::with_capacity(split_points.len() + 2);
+        bounds.push(0);
+        bounds.extend_from_slice(split_points);
+        bounds.push(cols);
+
+        assert!(
+            bounds.windows(2).all(|pair| pair[0] <= pair[1]),
+            "Split points must be sorted and at most {}, got {:?}.",
+            cols,
+            split_points
+        );
+
+        let split = Rc::new(SplitNode {
+            operand_gradient: RefCell::new(gradient_buffer((rows, cols), needs_gradient)),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        });
+
+        bounds
+            .windows(2)
+            .map(|pair| SplitViewNode::new(Rc::clone(&split), pair[0], pair[1]))
+            .collect()
+    }
+
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+    }
+
+    fn backward(&self, start: usize, gradient: &Arr) {
+        {
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            if self.counter.backward() == BackwardAction::Set {
+                fit_shape(&mut operand_gradient, self.operand.value().dim());
+                operand_gradient.fill(0.0);
+            }
+
+            let mut columns = operand_gradient.slice_axis_mut(
+                Axis(1),
+                ndarray::Slice::from(start..start + gradient.cols()),
+            );
+            columns += gradient;
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+}
+
+/// One piece of a column-wise split: the columns `start..end` of
+/// the operand of its `SplitNode`.
+#[derive(Debug)]
+pub struct SplitViewNode<OP> {
+    start: usize,
+    end: usize,
+    value: RefCell<Arr>,
+    gradient: RefCell<Arr>,
+    split: Rc<SplitNode<OP>>,
+    counter: PassCounter,
+}
+
+impl<OP> SplitViewNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    fn new(split: Rc<SplitNode<OP>>, start: usize, end: usize) -> Self {
+        let value = split
+            .operand
+            .value()
+            .slice_axis(Axis(1), ndarray::Slice::from(start..end))
+            .to_owned();
+        let gradient = gradient_buffer(value.dim(), split.needs_gradient);
+
+        SplitViewNode {
+            start: start,
+            end: end,
+            value: RefCell::new(value),
+            gradient: RefCell::new(gradient),
+            split: split,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for SplitViewNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.split.forward();
+
+        let operand_value = self.split.operand.value();
+        let columns = operand_value.slice_axis(Axis(1), ndarray::Slice::from(self.start..self.end));
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, columns.dim());
+        value.assign(&columns);
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.split.needs_gradient {
+            return;
+        }
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut own_gradient = self.gradient.borrow_mut();
+                fit_shape(&mut own_gradient, gradient.dim());
+                own_gradient.slice_assign(gradient.deref());
+            }
+            BackwardAction::Increment => {
+                self.gradient.borrow_mut().slice_add_assign(gradient.deref());
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.split.backward(self.start, &self.gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.split.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.split.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("SplitView")
+            .operand(&*self.split.operand)
+            .attribute("start", Attribute::Int(self.start as i64))
+            .attribute("end", Attribute::Int(self.end as i64))
+    }
+    fn flops(&self) -> Option<usize> {
+        Some(0)
+    }
+}
+
+/// Input node for the graph.
+#[derive(Debug)]
+pub struct InputNode {
+    pub value: RefCell<Arr>,
+    fill: Option<Float>,
+}
+
+impl InputNode {
+    /// Create a new input node with a given value. This fixes the number
+    /// of columns of the node; the number of rows may change through
+    /// `set_value`.
+    pub fn new(value: Arr) -> Variable<Self> {
+        Variable::new(
+            Rc::new(InputNode {
+                value: RefCell::new(value),
+                fill: None,
+            }),
+            Vec::new(),
+        )
+    }
+
+    /// An input node of the given shape filled with `fill`, which resizes
+    /// itself to match the rows of the operand it is broadcast against.
+    pub(crate) fn constant(shape: (usize, usize), fill: Float) -> Variable<Self> {
+        Variable::new(
+            Rc::new(InputNode {
+                value: RefCell::new(Arr::from_elem(shape, fill)),
+                fill: Some(fill),
+            }),
+            Vec::new(),
+        )
+    }
+}
+
+impl Node for InputNode {
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {}
+    fn backward(&self, _: &Ref<Self::InputGradient>) {}
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, _: usize) {}
+    fn accumulate(&self, _: &Ref<Self::InputGradient>) {}
+    fn propagate(&self) {}
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        false
+    }
+    fn zero_gradient(&self) {}
+    fn tangent(&self, _: &mut TangentBuilder) -> Result<Tangent, String> {
+        Ok(None)
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        Ok(builder.copy_input(self))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        Ok(builder.constant(self.value.borrow().clone(), self.fill))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Input")
+    }
+    fn flops(&self) -> Option<usize> {
+        Some(0)
+    }
+    fn fit_rows(&self, rows: usize) {
+        if let Some(fill) = self.fill {
+            let mut value = self.value.borrow_mut();
+            let cols = value.cols();
+
+            if value.rows() != rows {
+                *value = Arr::from_elem((rows, cols), fill);
+            }
+        }
+    }
+}
+
+/// Passes the value of its operand through unchanged, but blocks
+/// gradients from flowing back into it.
+///
+/// To keep the pass counters of a shared operand consistent, the
+/// operand still receives a backward pass, with an all-zero gradient.
+#[derive(Debug)]
+pub struct StopGradientNode<OP> {
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    counter: PassCounter,
+}
+
+impl<OP> StopGradientNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let gradient = gradient_buffer(operand.value().dim(), operand.needs_gradient());
+
+        StopGradientNode {
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for StopGradientNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+    }
+    fn backward(&self, _: &Ref<Self::InputGradient>) {
+        if !self.operand.needs_gradient() {
+            return;
+        }
+
+        if self.counter.backward() == BackwardAction::Set {
+            fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        self.operand.value()
+    }
+    fn needs_gradient(&self) -> bool {
+        false
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn tangent(&self, _: &mut TangentBuilder) -> Result<Tangent, String> {
+        Ok(None)
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.stop_gradient().boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        builder.compile(&self.operand)
+    }
+    fn describe(&self) -> Description {
+        Description::new("StopGradient").operand(&*self.operand)
+    }
+    fn flops(&self) -> Option<usize> {
+        Some(0)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// Passes its operand through unchanged, calling hooks on the gradient
+/// flowing back through it. See `Variable::register_gradient_hook`.
+pub struct HookNode<OP> {
+    gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    hooks: RefCell<Vec<Box<Fn(&mut Arr)>>>,
+    counter: PassCounter,
+}
+
+impl<OP> HookNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let gradient = gradient_buffer(operand.value().dim(), operand.needs_gradient());
+
+        HookNode {
+            gradient: RefCell::new(gradient),
+            operand: operand,
+            hooks: RefCell::new(Vec::new()),
+            counter: PassCounter::default(),
+        }
+    }
+
+    /// Add a hook, to be called after the hooks added before it.
+    pub fn add_hook<F>(&self, hook: F)
+    where
+        F: Fn(&mut Arr) + 'static,
+    {
+        self.hooks.borrow_mut().push(Box::new(hook));
+    }
+}
+
+impl<OP: fmt::Debug> fmt::Debug for HookNode<OP> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HookNode")
+            .field("hooks", &self.hooks.borrow().len())
+            .field("operand", &self.operand)
+            .finish()
+    }
+}
+
+impl<OP> Node for HookNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.operand.needs_gradient() {
+            return;
+        }
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut dest = self.gradient.borrow_mut();
+                fit_shape(&mut dest, gradient.dim());
+                dest.assign(gradient);
+            }
+            BackwardAction::Increment => {
+                self.gradient.borrow_mut().slice_add_assign(gradient.deref());
+            }
+        }
+
+        // Hooks see the gradient summed over all consumers of the node.
+        if self.counter.recurse_backward() {
+            for hook in self.hooks.borrow().iter() {
+                hook(&mut self.gradient.borrow_mut());
+            }
+
+            self.operand.backward(&self.gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        self.operand.value()
+    }
+    fn needs_gradient(&self) -> bool {
+        self.operand.needs_gradient()
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("GradientHook").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.gradient.borrow()]
+    }
+}
+
+/// Passes its operand through unchanged, calling hooks on its value
+/// whenever it is computed. See `Variable::register_forward_hook`.
+pub struct ForwardHookNode<OP> {
+    gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    hook: Box<Fn(&Arr)>,
+    counter: PassCounter,
+}
+
+impl<OP> ForwardHookNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new<F>(operand: Rc<OP>, hook: F) -> Self
+    where
+        F: Fn(&Arr) + 'static,
+    {
+        let gradient = gradient_buffer(operand.value().dim(), operand.needs_gradient());
+
+        ForwardHookNode {
+            gradient: RefCell::new(gradient),
+            operand: operand,
+            hook: Box::new(hook),
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP: fmt::Debug> fmt::Debug for ForwardHookNode<OP> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ForwardHookNode")
+            .field("operand", &self.operand)
+            .finish()
+    }
+}
+
+impl<OP> Node for ForwardHookNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        (self.hook)(self.operand.value().deref());
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.operand.needs_gradient() {
+            return;
+        }
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut dest = self.gradient.borrow_mut();
+                fit_shape(&mut dest, gradient.dim());
+                dest.assign(gradient);
+            }
+            BackwardAction::Increment => {
+                self.gradient.borrow_mut().slice_add_assign(gradient.deref());
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        self.operand.value()
+    }
+    fn needs_gradient(&self) -> bool {
+        self.operand.needs_gradient()
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("ForwardHook").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.gradient.borrow()]
+    }
+}
+
+/// Passes its operand through unchanged, rescaling the gradient flowing
+/// back through it to a fixed L2 norm, as when balancing the gradients
+/// of several loss heads at the point where they merge.
+#[derive(Debug)]
+pub struct GradNormNode<OP> {
+    gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    target_norm: Float,
+    counter: PassCounter,
+}
+
+impl<OP> GradNormNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, target_norm: Float) -> Self {
+        assert!(target_norm >= 0.0, "The target norm must be non-negative.");
+
+        let gradient = gradient_buffer(operand.value().dim(), operand.needs_gradient());
+
+        GradNormNode {
+            gradient: RefCell::new(gradient),
+            operand: operand,
+            target_norm: target_norm,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for GradNormNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.operand.needs_gradient() {
+            return;
+        }
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut dest = self.gradient.borrow_mut();
+                fit_shape(&mut dest, gradient.dim());
+                dest.assign(gradient);
+            }
+            BackwardAction::Increment => {
+                self.gradient.borrow_mut().slice_add_assign(gradient.deref());
+            }
+        }
+
+        // The gradient summed over all consumers is rescaled; a zero
+        // gradient has no direction and is passed on as it is.
+        if self.counter.recurse_backward() {
+            {
+                let mut gradient = self.gradient.borrow_mut();
+                let norm = gradient.iter().map(|x| x * x).sum::<Float>().sqrt();
+
+                if norm > 0.0 {
+                    let scale = self.target_norm / norm;
+                    gradient.map_inplace(|x| *x *= scale);
+                }
+            }
+
+            self.operand.backward(&self.gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        self.operand.value()
+    }
+    fn needs_gradient(&self) -> bool {
+        self.operand.needs_gradient()
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("GradNorm")
+            .operand(&*self.operand)
+            .attribute("target_norm", Attribute::Float(self.target_norm))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.gradient.borrow()]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SparseGradientStore {
+    len: usize,
+    data: Vec<(Vec<usize>, Arr)>,
+    /// Scratch space of `coalesce`, reused across steps: the slot
+    /// of every row index, and the entry rows are merged into.
+    slots: HashMap<usize, usize>,
+    merged: (Vec<usize>, Arr),
+}
+
+impl SparseGradientStore {
+    pub fn new() -> Self {
+        SparseGradientStore {
+            len: 0,
+            data: Vec::new(),
+            slots: HashMap::new(),
+            merged: (Vec::new(), Arr::zeros((0, 0))),
+        }
+    }
+
+    pub fn push(&mut self, gradient: (&[usize], &Arr)) {
+        let (index, value) = gradient;
+
+        if self.len < self.data.len() {
+            let (index_vec, grad) = &mut self.data[self.len];
+            index_vec.clear();
+            index_vec.extend_from_slice(&index[..]);
+            // Entries are reused across passes, whose batches
+            // may select a different number of rows.
+            fit_shape(grad, value.dim());
+            grad.slice_assign(value);
+            self.len += 1;
+        } else {
+            self.data.push((Vec::from(&index[..]), value.clone()));
+            self.len += 1;
+        }
+    }
+
+    pub fn as_slice(&self) -> &[(Vec<usize>, Arr)] {
+        &self.data[..self.len]
+    }
+
+    pub fn as_slice_mut(&mut self) -> &mut [(Vec<usize>, Arr)] {
+        &mut self.data[..self.len]
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Merge the entries into one holding every row index once, with the
+    /// gradients of repeated rows summed, so that optimizers apply a
+    /// single update per row.
+    pub fn coalesce(&mut self) {
+        let slots = &mut self.slots;
+        let (ref mut indices, ref mut merged) = self.merged;
+        slots.clear();
+        indices.clear();
+
+        for (index_vec, _) in &self.data[..self.len] {
+            for &idx in index_vec {
+                let slot = slots.len();
+                slots.entry(idx).or_insert_with(|| {
+                    indices.push(idx);
+                    slot
+                });
+            }
+        }
+
+        let is_coalesced = match &self.data[..self.len] {
+            [] => true,
+            [(index_vec, _)] => index_vec.len() == indices.len(),
+            _ => false,
+        };
+
+        if is_coalesced {
+            return;
+        }
+
+        fit_shape(merged, (indices.len(), self.data[0].1.cols()));
+        merged.fill(0.0);
+
+        for (index_vec, grad) in &self.data[..self.len] {
+            for (idx, grad_row) in index_vec.iter().zip(grad.genrows()) {
+                let mut dest = merged.row_mut(slots[idx]);
+                dest += &grad_row;
+            }
+        }
+
+        // The buffers of the replaced entry are merged into next time.
+        std::mem::swap(&mut self.data[0], &mut self.merged);
+        self.len = 1;
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct GradientAccumulator {
+    pub dense_shape: (usize, usize),
+    pub dense_gradient: Option<Arr>,
+    pub sparse_gradient: SparseGradientStore,
+    pub has_dense: bool,
+    /// Whether gradients are accumulated; see `Variable::backward_for`.
+    pub enabled: bool,
+}
+
+impl GradientAccumulator {
+    pub fn new(dense_shape: (usize, usize)) -> Self {
+        GradientAccumulator {
+            dense_shape: dense_shape,
+            dense_gradient: None,
+            sparse_gradient: SparseGradientStore::new(),
+            has_dense: false,
+            enabled: true,
+        }
+    }
+    pub fn dense_gradient(&mut self) -> &mut Arr {
+        let shape = self.dense_shape;
+
+        self.dense_gradient.get_or_insert_with(|| Arr::zeros(shape))
+    }
+    /// The dense gradient, with the rows of the sparse gradients
+    /// added in.
+    pub fn folded_gradient(&self) -> Arr {
+        let mut gradient = match self.dense_gradient {
+            Some(ref gradient) => gradient.clone(),
+            None => Arr::zeros(self.dense_shape),
+        };
+
+        for &(ref indices, ref grad) in self.sparse_gradient.as_slice() {
+            for (&row_idx, grad_row) in indices.iter().zip(grad.genrows()) {
+                let mut dest = gradient.row_mut(row_idx);
+                dest += &grad_row;
+            }
+        }
+
+        gradient
+    }
+
+    /// The sparse gradients, as pairs of row indices and the
+    /// gradients of those rows.
+    pub fn sparse_entries(&self) -> &[(Vec<usize>, Arr)] {
+        self.sparse_gradient.as_slice()
+    }
+
+    /// The dense gradient, if one was accumulated since the gradient
+    /// was last zeroed. Unlike `dense_gradient`, this never allocates,
+    /// so sparse-only parameters such as embedding tables stay sparse.
+    fn accumulated_dense_gradient(&mut self) -> Option<&mut Arr> {
+        match self.dense_gradient {
+            Some(ref mut gradient) if self.has_dense => Some(gradient),
+            _ => None,
+        }
+    }
+
+    fn zero_gradient(&mut self) {
+        if let Some(gradient) = self.accumulated_dense_gradient() {
+            gradient.fill(0.0);
+        }
+
+        self.sparse_gradient.clear();
+        self.has_dense = false;
+    }
+
+    pub fn clamp(&mut self, min: Float, max: Float) {
+        if let Some(gradient) = self.accumulated_dense_gradient() {
+            gradient
+                .as_slice_mut()
+                .unwrap()
+                .iter_mut()
+                .for_each(|x| *x = clamp(*x, min, max));
+        }
+        self.sparse_gradient
+            .as_slice_mut()
+            .iter_mut()
+            .for_each(|(_, ref mut grad)| {
+                grad.as_slice_mut()
+                    .unwrap()
+                    .iter_mut()
+                    .for_each(|x| *x = clamp(*x, min, max))
+            });
+    }
+
+    /// The squared L2 norm of the gradient, with the rows of the sparse
+    /// gradients added in. Only rows touched by the sparse gradients
+    /// are materialized.
+    pub fn squared_norm(&self) -> Float {
+        let mut sparse_rows: HashMap<usize, Vec<Float>> = HashMap::new();
+
+        for &(ref indices, ref grad) in self.sparse_gradient.as_slice() {
+            for (&row_idx, grad_row) in indices.iter().zip(grad.genrows()) {
+                let row = sparse_rows.entry(row_idx).or_insert_with(|| vec![0.0; grad_row.len()]);
+
+                for (dest, &grad) in row.iter_mut().zip(grad_row.iter()) {
+                    *dest += grad;
+                }
+            }
+        }
+
+        let dense_gradient = match self.dense_gradient {
+            Some(ref gradient) if self.has_dense => Some(gradient),
+            _ => None,
+        };
+
+        let mut squared_norm = 0.0;
+
+        if let Some(dense_gradient) = dense_gradient {
+            for (row_idx, row) in dense_gradient.genrows().into_iter().enumerate() {
+                if !sparse_rows.contains_key(&row_idx) {
+                    squared_norm += row.dot(&row);
+                }
+            }
+        }
+
+        for (row_idx, row) in &sparse_rows {
+            squared_norm += match dense_gradient {
+                Some(dense_gradient) => row.iter()
+                    .zip(dense_gradient.row(*row_idx).iter())
+                    .map(|(sparse, dense)| (sparse + dense).powi(2))
+                    .sum::<Float>(),
+                None => row.iter().map(|x| x.powi(2)).sum::<Float>(),
+            };
+        }
+
+        squared_norm
+    }
+
+    /// Multiply the gradient by `factor`.
+    pub fn scale(&mut self, factor: Float) {
+        if let Some(gradient) = self.accumulated_dense_gradient() {
+            gradient.map_inplace(|x| *x *= factor);
+        }
+        self.sparse_gradient
+            .as_slice_mut()
+            .iter_mut()
+            .for_each(|(_, ref mut grad)| grad.map_inplace(|x| *x *= factor));
+    }
+}
+
+pub trait GradientSink<T> {
+    fn accumulate_gradient(&mut self, gradient: T);
+}
+
+impl<'a, 'b> GradientSink<&'a Ref<'b, Arr>> for GradientAccumulator {
+    fn accumulate_gradient(&mut self, gradient: &Ref<Arr>) {
+        if !self.enabled {
+            return;
+        }
+
+        self.dense_gradient().slice_add_assign(gradient.deref());
+        self.has_dense = true;
+    }
+}
 
 impl<'a> GradientSink<(&'a [usize], &'a Arr)> for GradientAccumulator {
     fn accumulate_gradient(&mut self, gradient: (&'a [usize], &'a Arr)) {
-        self.sparse_gradient.push(gradient);
+        if self.enabled {
+            self.sparse_gradient.push(gradient);
+        }
+    }
+}
+
+/// Per-row counts of applied optimizer updates, used to diagnose
+/// contention between Hogwild threads.
+#[cfg(feature = "instrumentation")]
+#[derive(Debug, Default)]
+pub(crate) struct UpdateCounts(Vec<AtomicU32>);
+
+#[cfg(feature = "instrumentation")]
+impl UpdateCounts {
+    fn new(rows: usize) -> Self {
+        UpdateCounts((0..rows).map(|_| AtomicU32::new(0)).collect())
+    }
+
+    #[inline(always)]
+    fn increment(&self, row: usize) {
+        if let Some(count) = self.0.get(row) {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn increment_all(&self) {
+        for count in &self.0 {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn histogram(&self) -> Vec<u32> {
+        self.0.iter().map(|x| x.load(Ordering::Relaxed)).collect()
+    }
+}
+
+#[cfg(feature = "instrumentation")]
+impl Clone for UpdateCounts {
+    fn clone(&self) -> Self {
+        UpdateCounts(
+            self.histogram()
+                .into_iter()
+                .map(AtomicU32::new)
+                .collect(),
+        )
+    }
+}
+
+#[cfg(feature = "instrumentation")]
+impl Serialize for UpdateCounts {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.histogram().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "instrumentation")]
+impl<'de> Deserialize<'de> for UpdateCounts {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let counts = Vec::<u32>::deserialize(deserializer)?;
+        Ok(UpdateCounts(counts.into_iter().map(AtomicU32::new).collect()))
+    }
+}
+
+unsafe impl Sync for HogwildParameter {}
+
+/// The serialized fields of a `HogwildParameter`, validated before
+/// the parameter is constructed.
+#[derive(Deserialize)]
+#[serde(rename = "HogwildParameter")]
+struct HogwildParameterFields {
+    value: Arr,
+    squared_gradients: Arr,
+    #[serde(default)]
+    moments: Arr,
+    num_updates: i32,
+    #[cfg(feature = "instrumentation")]
+    #[serde(default)]
+    update_counts: UpdateCounts,
+    #[serde(default)]
+    half: bool,
+}
+
+impl HogwildParameterFields {
+    fn validate(self) -> Result<HogwildParameter, String> {
+        let shape = self.value.dim();
+
+        for &(name, buffer) in &[
+            ("squared_gradients", &self.squared_gradients),
+            ("moments", &self.moments),
+        ] {
+            // Empty buffers are allocated when first used.
+            if buffer.dim() != shape && !buffer.is_empty() {
+                return Err(format!(
+                    "{} has shape {:?}, but value has shape {:?}",
+                    name,
+                    buffer.dim(),
+                    shape
+                ));
+            }
+        }
+
+        for &(name, buffer) in &[
+            ("value", &self.value),
+            ("squared_gradients", &self.squared_gradients),
+            ("moments", &self.moments),
+        ] {
+            if let Some(idx) = buffer.iter().position(|x| !x.is_finite()) {
+                return Err(format!(
+                    "{} has a non-finite entry at {:?}",
+                    name,
+                    (idx / shape.1, idx % shape.1)
+                ));
+            }
+        }
+
+        #[cfg(feature = "instrumentation")]
+        let update_counts = match self.update_counts.0.len() {
+            0 => UpdateCounts::new(shape.0),
+            rows if rows == shape.0 => self.update_counts,
+            rows => {
+                return Err(format!(
+                    "update_counts has {} rows, but value has {}",
+                    rows, shape.0
+                ))
+            }
+        };
+
+        let (value, storage) = if self.half {
+            let half_value = HalfValue::new(&self.value);
+            (Arr::zeros((0, shape.1)), Storage::Half(half_value))
+        } else {
+            (self.value, Storage::Dense)
+        };
+
+        Ok(HogwildParameter {
+            value: RefCell::new(value),
+            squared_gradients: RefCell::new(self.squared_gradients),
+            moments: RefCell::new(self.moments),
+            num_updates: Cell::new(self.num_updates),
+            #[cfg(feature = "instrumentation")]
+            update_counts: update_counts,
+            storage: storage,
+            allocate_buffers: Once::new(),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for HogwildParameter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HogwildParameterFields::deserialize(deserializer)?
+            .validate()
+            .map_err(|msg| de::Error::custom(format!("invalid HogwildParameter: {}", msg)))
+    }
+}
+
+/// Struct used to hold parameters that need to be shared among
+/// multiple `ParameterNode`s for asynchronous, parallel optimization.
+///
+/// Deserialization checks that the optimizer buffers match the shape
+/// of the value and that all entries are finite.
+///
+/// The optimizer buffers (`squared_gradients` and `moments`) may be
+/// left empty, as they are by `from_mmap` or when missing from older
+/// serialized parameters; they are then allocated, zeroed, the first
+/// time an optimizer uses them.
+#[derive(Debug)]
+pub struct HogwildParameter {
+    pub value: RefCell<Arr>,
+    pub squared_gradients: RefCell<Arr>,
+    pub moments: RefCell<Arr>,
+    num_updates: Cell<i32>,
+    #[cfg(feature = "instrumentation")]
+    update_counts: UpdateCounts,
+    storage: Storage,
+    allocate_buffers: Once,
+}
+
+impl Clone for HogwildParameter {
+    fn clone(&self) -> Self {
+        HogwildParameter {
+            value: self.value.clone(),
+            squared_gradients: self.squared_gradients.clone(),
+            moments: self.moments.clone(),
+            num_updates: self.num_updates.clone(),
+            #[cfg(feature = "instrumentation")]
+            update_counts: self.update_counts.clone(),
+            storage: self.storage.clone(),
+            allocate_buffers: Once::new(),
+        }
+    }
+}
+
+/// Half-precision parameters are serialized at full precision, keeping
+/// the format readable regardless of how the parameter was stored, and
+/// flagged so that they are deserialized in half precision. Memory-mapped
+/// parameters are deserialized as ordinary parameters.
+impl Serialize for HogwildParameter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let num_fields = if cfg!(feature = "instrumentation") {
+            6
+        } else {
+            5
+        };
+
+        let mut state = serializer.serialize_struct("HogwildParameter", num_fields)?;
+        state.serialize_field("value", self.full_value().deref())?;
+        state.serialize_field("squared_gradients", &self.squared_gradients)?;
+        state.serialize_field("moments", &self.moments)?;
+        state.serialize_field("num_updates", &self.num_updates)?;
+        #[cfg(feature = "instrumentation")]
+        state.serialize_field("update_counts", &self.update_counts)?;
+        state.serialize_field("half", &self.is_half())?;
+        state.end()
+    }
+}
+
+/// How the value of a `HogwildParameter` is stored.
+#[derive(Clone, Debug)]
+enum Storage {
+    /// In the `value` array.
+    Dense,
+    /// In half precision.
+    Half(HalfValue),
+    /// In a memory-mapped raw parameter file.
+    Mapped(MappedValue),
+}
+
+/// Row-major parameter values stored as IEEE 754 half-precision bits.
+#[derive(Clone, Debug)]
+struct HalfValue {
+    shape: (usize, usize),
+    data: RefCell<Vec<u16>>,
+}
+
+impl HalfValue {
+    fn new(value: &Arr) -> Self {
+        HalfValue {
+            shape: value.dim(),
+            data: RefCell::new(value.iter().map(|&x| numerics::float_to_f16(x)).collect()),
+        }
+    }
+
+    fn row(&self, row: usize) -> ::std::ops::Range<usize> {
+        row * self.shape.1..(row + 1) * self.shape.1
+    }
+
+    fn read_row(&self, row: usize, out: &mut [Float]) {
+        let data = unsafe { &*self.data.as_ptr() };
+
+        for (x, &half) in izip!(out.iter_mut(), data[self.row(row)].iter()) {
+            *x = numerics::f16_to_float(half);
+        }
+    }
+
+    unsafe fn write_row(&self, row: usize, values: &[Float]) {
+        let data = &mut *self.data.as_ptr();
+
+        for (half, &x) in izip!(data[self.row(row)].iter_mut(), values.iter()) {
+            *half = numerics::float_to_f16(x);
+        }
+    }
+
+    fn to_arr(&self) -> Arr {
+        let data = unsafe { &*self.data.as_ptr() };
+
+        Arr::from_shape_vec(
+            self.shape,
+            data.iter().map(|&x| numerics::f16_to_float(x)).collect(),
+        ).unwrap()
+    }
+}
+
+thread_local! {
+    /// Scratch row for updating half-precision parameters at full precision.
+    static HALF_PRECISION_ROW: RefCell<Vec<Float>> = RefCell::new(Vec::new());
+}
+
+#[cfg_attr(feature = "cargo-clippy", allow(mut_from_ref))]
+impl HogwildParameter {
+    /// Create a new parameter object.
+    pub fn new(value: Arr) -> Self {
+        let squared_gradients = &value * 0.0;
+        let moments = &value * 0.0;
+
+        HogwildParameter {
+            #[cfg(feature = "instrumentation")]
+            update_counts: UpdateCounts::new(value.rows()),
+            value: RefCell::new(value),
+            squared_gradients: RefCell::new(squared_gradients),
+            moments: RefCell::new(moments),
+            num_updates: Cell::new(0),
+            storage: Storage::Dense,
+            allocate_buffers: Once::new(),
+        }
+    }
+
+    /// Create a new parameter object whose optimizer buffers are
+    /// only allocated once an optimizer needs them.
+    pub(crate) fn with_lazy_buffers(value: Arr) -> Self {
+        HogwildParameter {
+            #[cfg(feature = "instrumentation")]
+            update_counts: UpdateCounts::new(value.rows()),
+            value: RefCell::new(value),
+            squared_gradients: RefCell::new(Arr::zeros((0, 0))),
+            moments: RefCell::new(Arr::zeros((0, 0))),
+            num_updates: Cell::new(0),
+            storage: Storage::Dense,
+            allocate_buffers: Once::new(),
+        }
+    }
+
+    /// Create a new parameter object that stores its value in IEEE 754
+    /// half precision, halving the memory needed for large embedding
+    /// tables.
+    ///
+    /// Rows are converted to `Float` when read by `Variable::index`, and
+    /// optimizers update them at full precision before rounding them back;
+    /// the optimizer buffers are kept at full precision. Half-precision
+    /// parameters can only be used through `Variable::index`, and
+    /// `value` panics: use `full_value` to read all values.
+    pub fn half(value: Arr) -> Self {
+        let half_value = HalfValue::new(&value);
+        let mut parameter = HogwildParameter::with_lazy_buffers(Arr::zeros((0, value.cols())));
+
+        #[cfg(feature = "instrumentation")]
+        {
+            parameter.update_counts = UpdateCounts::new(value.rows());
+        }
+        parameter.storage = Storage::Half(half_value);
+
+        parameter
+    }
+
+    /// Create a parameter object whose value is read from, and
+    /// written to, a memory map. Like half-precision parameters, it
+    /// can only be used through `Variable::index`.
+    pub(crate) fn mapped(value: MappedValue) -> Self {
+        let mut parameter = HogwildParameter::with_lazy_buffers(Arr::zeros((0, value.shape().1)));
+
+        #[cfg(feature = "instrumentation")]
+        {
+            parameter.update_counts = UpdateCounts::new(value.shape().0);
+        }
+        parameter.storage = Storage::Mapped(value);
+
+        parameter
+    }
+
+    /// Whether the value is stored in half precision.
+    pub fn is_half(&self) -> bool {
+        match self.storage {
+            Storage::Half(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the value is memory-mapped read-only, in which case
+    /// the parameter is not trained: it needs no gradient and is
+    /// skipped by optimizers.
+    pub fn is_frozen(&self) -> bool {
+        match self.storage {
+            Storage::Mapped(ref mapped_value) => mapped_value.is_frozen(),
+            _ => false,
+        }
+    }
+
+    /// Whether the value is held in the `value` array, rather than
+    /// in half precision or in a memory map.
+    pub(crate) fn is_dense(&self) -> bool {
+        match self.storage {
+            Storage::Dense => true,
+            _ => false,
+        }
+    }
+
+    /// The `(rows, cols)` shape of the parameter.
+    pub fn shape(&self) -> (usize, usize) {
+        match self.storage {
+            Storage::Dense => unsafe { (*self.value.as_ptr()).dim() },
+            Storage::Half(ref half_value) => half_value.shape,
+            Storage::Mapped(ref mapped_value) => mapped_value.shape(),
+        }
+    }
+
+    /// The memory used to store the value, in bytes.
+    pub fn value_bytes(&self) -> usize {
+        let (rows, cols) = self.shape();
+        let width = if self.is_half() {
+            ::std::mem::size_of::<u16>()
+        } else {
+            ::std::mem::size_of::<Float>()
+        };
+
+        rows * cols * width
+    }
+
+    /// The value of the parameter, converted to `Float` if it is
+    /// stored in half precision.
+    pub fn full_value(&self) -> Cow<Arr> {
+        match self.storage {
+            Storage::Dense => Cow::Borrowed(self.value()),
+            Storage::Half(ref half_value) => Cow::Owned(half_value.to_arr()),
+            Storage::Mapped(ref mapped_value) => Cow::Owned(mapped_value.to_arr()),
+        }
+    }
+
+    /// Copy the rows at `indices` into the rows of `out`.
+    pub(crate) fn read_rows(&self, indices: &[usize], out: &mut Arr) {
+        match self.storage {
+            Storage::Half(ref half_value) => {
+                for (&idx, mut row) in indices.iter().zip(out.genrows_mut()) {
+                    half_value.read_row(idx, row.as_slice_mut().unwrap());
+                }
+            }
+            Storage::Mapped(ref mapped_value) => {
+                for (&idx, mut row) in indices.iter().zip(out.genrows_mut()) {
+                    row.as_slice_mut()
+                        .unwrap()
+                        .copy_from_slice(mapped_value.row(idx));
+                }
+            }
+            Storage::Dense => {
+                let value = self.value();
+
+                for (&idx, mut row) in indices.iter().zip(out.genrows_mut()) {
+                    row.slice_assign(&value.subview(Axis(0), idx));
+                }
+            }
+        }
+    }
+
+    /// Apply `update` to a row of the value. Rows stored in half
+    /// precision are updated at full precision, then rounded back.
+    #[inline(always)]
+    pub(crate) unsafe fn update_row<F: FnOnce(&mut [Float])>(&self, row: usize, update: F) {
+        match self.storage {
+            Storage::Half(ref half_value) => HALF_PRECISION_ROW.with(|buffer| {
+                let mut buffer = buffer.borrow_mut();
+                buffer.resize(half_value.shape.1, 0.0);
+
+                half_value.read_row(row, &mut buffer);
+                update(&mut buffer);
+                half_value.write_row(row, &buffer);
+            }),
+            Storage::Mapped(ref mapped_value) => update(mapped_value.row_mut(row)),
+            Storage::Dense => update(
+                self.value_mut()
+                    .subview_mut(Axis(0), row)
+                    .into_slice()
+                    .unwrap(),
+            ),
+        }
+    }
+
+    /// Panic if the parameter is stored in half precision or in a
+    /// memory map, which do not support dense updates.
+    pub(crate) fn assert_dense_updates(&self) {
+        assert!(
+            self.is_dense(),
+            "Half-precision and memory-mapped parameters only support sparse \
+             updates through `Variable::index`."
+        );
+    }
+
+    /// Return the number of values held by the optimizer buffers,
+    /// which is zero until they are first used.
+    pub fn optimizer_buffer_len(&self) -> usize {
+        unsafe { (*self.squared_gradients.as_ptr()).len() + (*self.moments.as_ptr()).len() }
+    }
+
+    /// Allocate any optimizer buffers that are still empty. Only the
+    /// first call does any work; the others return straight away.
+    fn allocate_optimizer_buffers(&self) {
+        self.allocate_buffers.call_once(|| {
+            let shape = self.shape();
+
+            for buffer in &[&self.squared_gradients, &self.moments] {
+                let buffer = unsafe { &mut *buffer.as_ptr() };
+
+                if buffer.dim() != shape {
+                    *buffer = Arr::zeros(shape);
+                }
+            }
+        });
+    }
+
+    /// The value of the parameter.
+    ///
+    /// Panics if the value is stored in half precision or in a memory
+    /// map, as it is not held in an array: use `full_value` instead.
+    pub fn value(&self) -> &Arr {
+        assert!(
+            self.is_dense(),
+            "Half-precision and memory-mapped parameters have no dense value: \
+             use `HogwildParameter::full_value`."
+        );
+
+        unsafe { &*(self.value.as_ptr()) }
+    }
+
+    pub fn squared_gradients(&self) -> &Arr {
+        unsafe { &*(self.squared_gradients.as_ptr()) }
+    }
+
+    pub(crate) unsafe fn value_mut(&self) -> &mut Arr {
+        &mut *(self.value.as_ptr())
+    }
+
+    pub(crate) unsafe fn squared_gradient_mut(&self) -> &mut Arr {
+        self.allocate_optimizer_buffers();
+        &mut *(self.squared_gradients.as_ptr())
+    }
+
+    pub(crate) unsafe fn moments_mut(&self) -> &mut Arr {
+        self.allocate_optimizer_buffers();
+        &mut *(self.moments.as_ptr())
+    }
+
+    pub(crate) unsafe fn num_updates_mut(&self) -> &mut i32 {
+        &mut *(self.num_updates.as_ptr())
+    }
+
+    /// Return the number of optimizer updates applied to each row
+    /// of the parameter. Dense updates count towards every row.
+    ///
+    /// Use this to find hot embedding rows that suffer from
+    /// contention in Hogwild training.
+    #[cfg(feature = "instrumentation")]
+    pub fn update_histogram(&self) -> Vec<u32> {
+        self.update_counts.histogram()
+    }
+
+    /// Record an update to a single row. Compiles to nothing unless
+    /// the `instrumentation` feature is enabled.
+    #[inline(always)]
+    #[cfg_attr(not(feature = "instrumentation"), allow(unused_variables))]
+    pub(crate) fn record_row_update(&self, row: usize) {
+        #[cfg(feature = "instrumentation")]
+        self.update_counts.increment(row);
+    }
+
+    /// Record a dense update to all rows. Compiles to nothing unless
+    /// the `instrumentation` feature is enabled.
+    #[inline(always)]
+    pub(crate) fn record_dense_update(&self) {
+        #[cfg(feature = "instrumentation")]
+        self.update_counts.increment_all();
+    }
+}
+
+/// Turns off the accumulation of gradients into parameter nodes until
+/// dropped, when their previous settings are restored, even if the
+/// backward pass panics. See `Variable::backward_for`.
+pub(crate) struct DisabledGradients {
+    parameters: Vec<(Rc<ParameterNode>, bool)>,
+}
+
+impl DisabledGradients {
+    pub(crate) fn new(parameters: Vec<Rc<ParameterNode>>) -> Self {
+        let parameters = parameters
+            .into_iter()
+            .map(|parameter| {
+                let enabled = parameter.gradient_enabled();
+                parameter.set_gradient_enabled(false);
+                (parameter, enabled)
+            })
+            .collect();
+
+        DisabledGradients {
+            parameters: parameters,
+        }
+    }
+}
+
+impl Drop for DisabledGradients {
+    fn drop(&mut self) {
+        for &(ref parameter, enabled) in &self.parameters {
+            parameter.set_gradient_enabled(enabled);
+        }
+    }
+}
+
+/// Parameter node, holds the optimizable parameters of the model.
+#[derive(Debug)]
+pub struct ParameterNode {
+    pub(crate) value: Arc<HogwildParameter>,
+    pub(crate) gradient: RefCell<GradientAccumulator>,
+    /// Whether the node was built in inference mode.
+    pub(crate) inference: bool,
+}
+
+impl ParameterNode {
+    /// Create a parameter node that shares its parameter values
+    /// with other parameter nodes via the `HogwildParameter` object.
+    pub fn shared(value: Arc<HogwildParameter>) -> Variable<Self> {
+        // This method can be called in multiple threads, so borrowing
+        // (even immutably) will read to borrow failures.
+        let shape = value.shape();
+
+        let node = Rc::new(ParameterNode {
+            value: value,
+            gradient: RefCell::new(GradientAccumulator::new(shape)),
+            inference: is_inference(),
+        });
+        let params = vec![Rc::clone(&node)];
+
+        Variable::new(node, params)
+    }
+    /// Create a new parameter node. The parameters held by this node
+    /// cannot be shared and optimized in parallel.
+    pub fn new(value: Arr) -> Variable<Self> {
+        let shape = (value.rows(), value.cols());
+
+        let node = Rc::new(ParameterNode {
+            value: Arc::new(HogwildParameter::new(value)),
+            gradient: RefCell::new(GradientAccumulator::new(shape)),
+            inference: is_inference(),
+        });
+        let params = vec![Rc::clone(&node)];
+
+        Variable::new(node, params)
+    }
+    /// Turn the accumulation of gradients into this node on or off.
+    pub(crate) fn set_gradient_enabled(&self, enabled: bool) {
+        self.gradient.borrow_mut().enabled = enabled;
+    }
+    /// Whether gradients are accumulated into this node.
+    pub(crate) fn gradient_enabled(&self) -> bool {
+        self.gradient.borrow().enabled
+    }
+    // /// Zero the accumulated gradients of this node.
+    // pub fn zero_gradient(&self) {
+    //     //self.gradient.borrow_mut().zero_gradient();
+    // }
+}
+
+impl Node for ParameterNode {
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {}
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        self.accumulate(gradient);
+    }
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, _: usize) {}
+    fn accumulate(&self, gradient: &Ref<Self::InputGradient>) {
+        self.gradient.borrow_mut().accumulate_gradient(gradient);
+    }
+    fn propagate(&self) {}
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::Reference(self.value.value())
+    }
+    fn needs_gradient(&self) -> bool {
+        !self.inference && !self.value.is_frozen()
+    }
+    fn zero_gradient(&self) {
+        self.gradient.borrow_mut().zero_gradient();
+    }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        Ok(builder
+            .direction(self)
+            .map(|direction| InputNode::new(direction.clone()).boxed()))
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        Ok(Some(builder.parameter(self).boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        Ok(builder.constant(self.value.full_value().into_owned(), None))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Parameter")
+    }
+    fn flops(&self) -> Option<usize> {
+        Some(0)
+    }
+    fn shared_parameter(&self) -> Option<&Arc<HogwildParameter>> {
+        Some(&self.value)
+    }
+}
+
+#[derive(Debug)]
+pub struct SubNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    value: RefCell<Arr>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS, RHS> SubNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
+        Self::try_new(lhs, rhs).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `new`, but returns an error if the operands' shapes are
+    /// incompatible.
+    pub fn try_new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Result<Self, ShapeError> {
+        check_same_shape("Sub", &lhs.value(), &rhs.value())?;
+
+        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
+        let mut value = arena::zeros(lhs.value().dim());
+        numerics::sub(&lhs.value(), &rhs.value(), &mut value);
+
+        let rhs_gradient = gradient_buffer(rhs.value().dim(), needs_gradient);
+        let lhs_gradient = gradient_buffer(lhs.value().dim(), needs_gradient);
+
+        Ok(SubNode {
+            value: RefCell::new(value),
+            rhs_gradient: RefCell::new(rhs_gradient),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            lhs: lhs,
+            rhs: rhs,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        })
+    }
+
+    fn compute(&self) {
+        fit_operand_rows(&*self.lhs, &*self.rhs);
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.lhs.value().dim());
+
+        numerics::sub(
+            self.lhs.value().deref(),
+            self.rhs.value().deref(),
+            dest.deref_mut(),
+        );
+    }
+}
+
+impl<LHS, RHS> Drop for SubNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    fn drop(&mut self) {
+        arena::recycle(self.value.get_mut());
+        arena::recycle(self.lhs_gradient.get_mut());
+        arena::recycle(self.rhs_gradient.get_mut());
+    }
+}
+
+impl<LHS, RHS> Node for SubNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.lhs.forward();
+        self.rhs.forward();
+
+        self.compute();
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        self.accumulate(gradient);
+
+        if self.counter.recurse_backward() {
+            self.lhs.backward(&self.lhs_gradient.borrow());
+            self.rhs.backward(&self.rhs_gradient.borrow());
+        }
+    }
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, uses: usize) {
+        if self.counter.forward_many(uses) == ForwardAction::Cached {
+            return;
+        }
+
+        self.compute();
+    }
+    fn accumulate(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+                fit_shape(&mut rhs_gradient, gradient.dim());
+
+                numerics::simd_scaled_assign(
+                    rhs_gradient.as_slice_mut().unwrap(),
+                    gradient.as_slice().unwrap(),
+                    -1.0,
+                );
+
+                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+                fit_shape(&mut lhs_gradient, gradient.dim());
+
+                numerics::simd_scaled_assign(
+                    lhs_gradient.as_slice_mut().unwrap(),
+                    gradient.as_slice().unwrap(),
+                    1.0,
+                );
+            }
+            BackwardAction::Increment => {
+                let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+                rhs_gradient.slice_sub_assign(gradient.deref());
+
+                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+                lhs_gradient.slice_add_assign(gradient.deref());
+            }
+        }
+    }
+    fn propagate(&self) {
+        if self.counter.recurse_backward() {
+            self.lhs.accumulate(&self.lhs_gradient.borrow());
+            self.rhs.accumulate(&self.rhs_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.lhs.zero_gradient();
+            self.rhs.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        let rhs = builder.tangent(&self.rhs)?.map(|rhs| (-rhs).boxed());
+        Ok(add_tangents(builder.tangent(&self.lhs)?, rhs))
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let lhs = builder.clone_node(&self.lhs)?;
+        let rhs = builder.clone_node(&self.rhs)?;
+        Ok(Some((lhs - rhs).boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let lhs = builder.compile(&self.lhs)?;
+        let rhs = builder.compile(&self.rhs)?;
+        Ok(builder.step(Op::Sub, vec![lhs, rhs], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Sub")
+            .operand(&*self.lhs)
+            .operand(&*self.rhs)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.lhs_gradient.borrow(), self.rhs_gradient.borrow()]
+    }
+}
+
+#[derive(Debug)]
+pub struct MulNode<LHS, RHS> {
+    value: RefCell<Arr>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS, RHS> MulNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr>,
+    RHS: Node<Value = Arr>,
+{
+    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
+        Self::try_new(lhs, rhs).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `new`, but returns an error if the operands' shapes are
+    /// incompatible.
+    pub fn try_new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Result<Self, ShapeError> {
+        check_same_shape("Mul", &lhs.value(), &rhs.value())?;
+
+        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
+        let mut value = arena::zeros(lhs.value().dim());
+        numerics::mul(&lhs.value(), &rhs.value(), &mut value);
+
+        let lhs_gradient = gradient_buffer(value.dim(), needs_gradient);
+        let rhs_gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        Ok(MulNode {
+            value: RefCell::new(value),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            rhs_gradient: RefCell::new(rhs_gradient),
+            lhs: lhs,
+            rhs: rhs,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        })
+    }
+
+    fn compute(&self) {
+        fit_operand_rows(&*self.lhs, &*self.rhs);
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.lhs.value().dim());
+
+        numerics::mul(
+            self.lhs.value().deref(),
+            self.rhs.value().deref(),
+            dest.deref_mut(),
+        );
+    }
+}
+
+impl<LHS, RHS> Drop for MulNode<LHS, RHS> {
+    fn drop(&mut self) {
+        arena::recycle(self.value.get_mut());
+        arena::recycle(self.lhs_gradient.get_mut());
+        arena::recycle(self.rhs_gradient.get_mut());
+    }
+}
+
+impl<LHS, RHS> Node for MulNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.lhs.forward();
+        self.rhs.forward();
+
+        self.compute();
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        self.accumulate(gradient);
+
+        if self.counter.recurse_backward() {
+            self.lhs.backward(&self.lhs_gradient.borrow());
+            self.rhs.backward(&self.rhs_gradient.borrow());
+        }
+    }
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, uses: usize) {
+        if self.counter.forward_many(uses) == ForwardAction::Cached {
+            return;
+        }
+
+        self.compute();
+    }
+    fn accumulate(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+                fit_shape(&mut lhs_gradient, gradient.dim());
+
+                numerics::mul(
+                    self.rhs.value().deref(),
+                    gradient.deref(),
+                    lhs_gradient.deref_mut(),
+                );
+
+                let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+                fit_shape(&mut rhs_gradient, gradient.dim());
+
+                numerics::mul(
+                    self.lhs.value().deref(),
+                    gradient.deref(),
+                    rhs_gradient.deref_mut(),
+                );
+            }
+            BackwardAction::Increment => {
+                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+                let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+
+                numerics::increment_mul(
+                    self.rhs.value().deref(),
+                    gradient.deref(),
+                    lhs_gradient.deref_mut(),
+                );
+                numerics::increment_mul(
+                    self.lhs.value().deref(),
+                    gradient.deref(),
+                    rhs_gradient.deref_mut(),
+                );
+            }
+        }
+    }
+    fn propagate(&self) {
+        if self.counter.recurse_backward() {
+            self.lhs.accumulate(&self.lhs_gradient.borrow());
+            self.rhs.accumulate(&self.rhs_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.lhs.zero_gradient();
+            self.rhs.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        let lhs = builder
+            .tangent(&self.lhs)?
+            .map(|lhs| (lhs * tangent_operand(&self.rhs)).boxed());
+        let rhs = builder
+            .tangent(&self.rhs)?
+            .map(|rhs| (tangent_operand(&self.lhs) * rhs).boxed());
+
+        Ok(add_tangents(lhs, rhs))
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let lhs = builder.clone_node(&self.lhs)?;
+        let rhs = builder.clone_node(&self.rhs)?;
+        Ok(Some((lhs * rhs).boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let lhs = builder.compile(&self.lhs)?;
+        let rhs = builder.compile(&self.rhs)?;
+        Ok(builder.step(Op::Mul, vec![lhs, rhs], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Mul")
+            .operand(&*self.lhs)
+            .operand(&*self.rhs)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.lhs_gradient.borrow(), self.rhs_gradient.borrow()]
+    }
+}
+
+/// Computes `lhs * rhs + addend` element-wise in one pass, without
+/// allocating the intermediate product.
+#[derive(Debug)]
+pub struct FmaNode<LHS, RHS, ADD> {
+    value: RefCell<Arr>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
+    addend_gradient: RefCell<Arr>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    addend: Rc<ADD>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS, RHS, ADD> FmaNode<LHS, RHS, ADD>
+where
+    LHS: Node<Value = Arr>,
+    RHS: Node<Value = Arr>,
+    ADD: Node<Value = Arr>,
+{
+    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>, addend: Rc<ADD>) -> Self {
+        Self::try_new(lhs, rhs, addend).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `new`, but returns an error if the operands' shapes are
+    /// incompatible.
+    pub fn try_new(lhs: Rc<LHS>, rhs: Rc<RHS>, addend: Rc<ADD>) -> Result<Self, ShapeError> {
+        check_same_shape("Fma", &lhs.value(), &rhs.value())?;
+        check_same_shape("Fma", &lhs.value(), &addend.value())?;
+
+        let needs_gradient =
+            lhs.needs_gradient() || rhs.needs_gradient() || addend.needs_gradient();
+
+        let mut value = addend.value().clone();
+        fma(&mut value, &lhs.value(), &rhs.value(), &addend.value());
+
+        let lhs_gradient = gradient_buffer(value.dim(), needs_gradient);
+        let rhs_gradient = gradient_buffer(value.dim(), needs_gradient);
+        let addend_gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        Ok(FmaNode {
+            value: RefCell::new(value),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            rhs_gradient: RefCell::new(rhs_gradient),
+            addend_gradient: RefCell::new(addend_gradient),
+            lhs: lhs,
+            rhs: rhs,
+            addend: addend,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        })
+    }
+
+    fn compute(&self) {
+        fit_operand_rows(&*self.lhs, &*self.rhs);
+        fit_operand_rows(&*self.rhs, &*self.addend);
+        fit_operand_rows(&*self.lhs, &*self.addend);
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.lhs.value().dim());
+
+        fma(
+            &mut dest,
+            &self.lhs.value(),
+            &self.rhs.value(),
+            &self.addend.value(),
+        );
+    }
+}
+
+fn fma(dest: &mut Arr, lhs: &Arr, rhs: &Arr, addend: &Arr) {
+    for (dest, &lhs, &rhs, &addend) in izip!(
+        dest.fast_slice_mut(),
+        lhs.fast_slice(),
+        rhs.fast_slice(),
+        addend.fast_slice()
+    ) {
+        *dest = lhs * rhs + addend;
+    }
+}
+
+impl<LHS, RHS, ADD> Node for FmaNode<LHS, RHS, ADD>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+    ADD: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.lhs.forward();
+        self.rhs.forward();
+        self.addend.forward();
+
+        self.compute();
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        self.accumulate(gradient);
+
+        if self.counter.recurse_backward() {
+            self.lhs.backward(&self.lhs_gradient.borrow());
+            self.rhs.backward(&self.rhs_gradient.borrow());
+            self.addend.backward(&self.addend_gradient.borrow());
+        }
+    }
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, uses: usize) {
+        if self.counter.forward_many(uses) == ForwardAction::Cached {
+            return;
+        }
+
+        self.compute();
+    }
+    fn accumulate(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        let lhs_value = self.lhs.value();
+        let rhs_value = self.rhs.value();
+
+        let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+        let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+        let mut addend_gradient = self.addend_gradient.borrow_mut();
+        fit_shape(&mut lhs_gradient, gradient.dim());
+        fit_shape(&mut rhs_gradient, gradient.dim());
+        fit_shape(&mut addend_gradient, gradient.dim());
+
+        for (lhs_grad, rhs_grad, addend_grad, &grad, &lhs, &rhs) in izip!(
+            lhs_gradient.fast_slice_mut(),
+            rhs_gradient.fast_slice_mut(),
+            addend_gradient.fast_slice_mut(),
+            gradient.fast_slice(),
+            lhs_value.fast_slice(),
+            rhs_value.fast_slice()
+        ) {
+            *lhs_grad = beta * *lhs_grad + grad * rhs;
+            *rhs_grad = beta * *rhs_grad + grad * lhs;
+            *addend_grad = beta * *addend_grad + grad;
+        }
+    }
+    fn propagate(&self) {
+        if self.counter.recurse_backward() {
+            self.lhs.accumulate(&self.lhs_gradient.borrow());
+            self.rhs.accumulate(&self.rhs_gradient.borrow());
+            self.addend.accumulate(&self.addend_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.lhs.zero_gradient();
+            self.rhs.zero_gradient();
+            self.addend.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        let lhs = builder
+            .tangent(&self.lhs)?
+            .map(|lhs| (lhs * tangent_operand(&self.rhs)).boxed());
+        let rhs = builder
+            .tangent(&self.rhs)?
+            .map(|rhs| (tangent_operand(&self.lhs) * rhs).boxed());
+        let addend = builder.tangent(&self.addend)?;
+
+        Ok(add_tangents(add_tangents(lhs, rhs), addend))
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let lhs = builder.clone_node(&self.lhs)?;
+        let rhs = builder.clone_node(&self.rhs)?;
+        let addend = builder.clone_node(&self.addend)?;
+        Ok(Some(lhs.mul_add(&rhs, &addend).boxed()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Fma")
+            .operand(&*self.lhs)
+            .operand(&*self.rhs)
+            .operand(&*self.addend)
+    }
+    fn flops(&self) -> Option<usize> {
+        Some(2 * self.value.borrow().len())
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.lhs_gradient.borrow(), self.rhs_gradient.borrow(), self.addend_gradient.borrow()]
+    }
+}
+
+#[derive(Debug)]
+pub struct DivNode<LHS, RHS> {
+    value: RefCell<Arr>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    epsilon: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+/// Push `x` away from zero to a magnitude of at least `epsilon`,
+/// keeping its sign.
+fn guard_denominator(x: Float, epsilon: Float) -> Float {
+    if x.abs() < epsilon {
+        epsilon.copysign(x)
+    } else {
+        x
+    }
+}
+
+/// Write `lhs / rhs` into `dest`, pushing denominators away from zero
+/// to a magnitude of at least `epsilon`. Unguarded division, with an
+/// `epsilon` of zero, takes the vectorized path.
+pub(crate) fn guarded_div(dest: &mut Arr, lhs: &Arr, rhs: &Arr, epsilon: Float) {
+    if epsilon == 0.0 {
+        numerics::div(lhs, rhs, dest);
+    } else {
+        numerics::map_assign_binary(dest, lhs, rhs, |x, y| x / guard_denominator(y, epsilon));
+    }
+}
+
+impl<LHS, RHS> DivNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr>,
+    RHS: Node<Value = Arr>,
+{
+    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
+        Self::try_new(lhs, rhs).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `new`, but returns an error if the operands' shapes are
+    /// incompatible.
+    pub fn try_new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Result<Self, ShapeError> {
+        Self::try_with_epsilon(lhs, rhs, stability().div_epsilon)
+    }
+
+    /// Like `try_new`, but guarding the denominator with `epsilon` rather
+    /// than the current `StabilityConfig::div_epsilon`.
+    pub(crate) fn try_with_epsilon(
+        lhs: Rc<LHS>,
+        rhs: Rc<RHS>,
+        epsilon: Float,
+    ) -> Result<Self, ShapeError> {
+        check_same_shape("Div", &lhs.value(), &rhs.value())?;
+
+        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
+        let mut value = lhs.value().deref() * 0.0;
+        guarded_div(&mut value, &lhs.value(), &rhs.value(), epsilon);
+
+        let lhs_gradient = gradient_buffer(value.dim(), needs_gradient);
+        let rhs_gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        Ok(DivNode {
+            value: RefCell::new(value),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            rhs_gradient: RefCell::new(rhs_gradient),
+            lhs: lhs,
+            rhs: rhs,
+            epsilon: epsilon,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        })
+    }
+}
+
+impl<LHS, RHS> Node for DivNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.lhs.forward();
+        self.rhs.forward();
+
+        fit_operand_rows(&*self.lhs, &*self.rhs);
+
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, self.lhs.value().dim());
+
+        guarded_div(
+            value.deref_mut(),
+            &self.lhs.value(),
+            &self.rhs.value(),
+            self.epsilon,
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let epsilon = self.epsilon;
+            let lhs_value = self.lhs.value();
+            let rhs_value = self.rhs.value();
+
+            let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+            let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+            fit_shape(&mut lhs_gradient, lhs_value.dim());
+            fit_shape(&mut rhs_gradient, rhs_value.dim());
+
+            if epsilon == 0.0 {
+                if beta == 0.0 {
+                    numerics::div(gradient, &rhs_value, &mut lhs_gradient);
+                } else {
+                    numerics::increment_div(gradient, &rhs_value, &mut lhs_gradient);
+                }
+
+                for (rhs_grad, &grad, &lhs, &rhs) in izip!(
+                    rhs_gradient.fast_slice_mut(),
+                    gradient.fast_slice(),
+                    lhs_value.fast_slice(),
+                    rhs_value.fast_slice()
+                ) {
+                    *rhs_grad = beta * *rhs_grad - lhs / rhs.powi(2) * grad;
+                }
+            } else {
+                for (lhs_grad, rhs_grad, &grad, &lhs, &rhs) in izip!(
+                    lhs_gradient.fast_slice_mut(),
+                    rhs_gradient.fast_slice_mut(),
+                    gradient.fast_slice(),
+                    lhs_value.fast_slice(),
+                    rhs_value.fast_slice()
+                ) {
+                    let denominator = guard_denominator(rhs, epsilon);
+                    let scale = grad / denominator;
+
+                    *lhs_grad = beta * *lhs_grad + scale;
+                    *rhs_grad = beta * *rhs_grad - lhs * scale / denominator;
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.lhs.backward(&self.lhs_gradient.borrow());
+            self.rhs.backward(&self.rhs_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.lhs.zero_gradient();
+            self.rhs.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let lhs = builder.clone_node(&self.lhs)?;
+        let rhs = builder.clone_node(&self.rhs)?;
+        // The copy keeps the original's guard, whatever the current
+        // stability configuration.
+        let node =
+            DivNode::try_with_epsilon(Rc::clone(&lhs.node), Rc::clone(&rhs.node), self.epsilon)
+                .map_err(|error| error.to_string())?;
+        let parameters = merge_parameters(&lhs.parameters, &rhs.parameters);
+        Ok(Some(Variable::new(Rc::new(node), parameters).boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let lhs = builder.compile(&self.lhs)?;
+        let rhs = builder.compile(&self.rhs)?;
+        Ok(builder.step(Op::Div(self.epsilon), vec![lhs, rhs], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Div")
+            .operand(&*self.lhs)
+            .operand(&*self.rhs)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.lhs_gradient.borrow(), self.rhs_gradient.borrow()]
+    }
+}
+
+/// Computes `sqrt(lhs^2 + rhs^2)` element-wise without
+/// intermediate overflow.
+///
+/// The hypotenuse is bounded below by the stability epsilon when
+/// computing gradients, so that they are zero rather than NaN at
+/// the origin.
+#[derive(Debug)]
+pub struct HypotNode<LHS, RHS> {
+    value: RefCell<Arr>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    epsilon: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS, RHS> HypotNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr>,
+    RHS: Node<Value = Arr>,
+{
+    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
+        Self::try_new(lhs, rhs).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `new`, but returns an error if the operands' shapes are
+    /// incompatible.
+    pub fn try_new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Result<Self, ShapeError> {
+        check_same_shape("Hypot", &lhs.value(), &rhs.value())?;
+
+        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
+
+        let mut value = lhs.value().deref() * 0.0;
+        numerics::map_assign_binary(
+            &mut value,
+            lhs.value().deref(),
+            rhs.value().deref(),
+            Float::hypot,
+        );
+
+        let lhs_gradient = gradient_buffer(value.dim(), needs_gradient);
+        let rhs_gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        Ok(HypotNode {
+            value: RefCell::new(value),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            rhs_gradient: RefCell::new(rhs_gradient),
+            lhs: lhs,
+            rhs: rhs,
+            epsilon: stability().epsilon,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        })
+    }
+}
+
+impl<LHS, RHS> Node for HypotNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.lhs.forward();
+        self.rhs.forward();
+
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, self.lhs.value().dim());
+
+        numerics::map_assign_binary(
+            value.deref_mut(),
+            self.lhs.value().deref(),
+            self.rhs.value().deref(),
+            Float::hypot,
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let value = self.value.borrow();
+            let lhs_value = self.lhs.value();
+            let rhs_value = self.rhs.value();
+
+            let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+            let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+            fit_shape(&mut lhs_gradient, lhs_value.dim());
+            fit_shape(&mut rhs_gradient, rhs_value.dim());
+
+            for (lhs_grad, rhs_grad, &grad, &lhs, &rhs, &hypot) in izip!(
+                lhs_gradient.fast_slice_mut(),
+                rhs_gradient.fast_slice_mut(),
+                gradient.fast_slice(),
+                lhs_value.fast_slice(),
+                rhs_value.fast_slice(),
+                value.fast_slice()
+            ) {
+                let scale = grad / hypot.max(self.epsilon);
+
+                *lhs_grad = beta * *lhs_grad + lhs * scale;
+                *rhs_grad = beta * *rhs_grad + rhs * scale;
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.lhs.backward(&self.lhs_gradient.borrow());
+            self.rhs.backward(&self.rhs_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.lhs.zero_gradient();
+            self.rhs.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("Hypot")
+            .operand(&*self.lhs)
+            .operand(&*self.rhs)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.lhs_gradient.borrow(), self.rhs_gradient.borrow()]
+    }
+}
+
+/// Computes the `(n, m)` matrix of cosine similarities between the rows
+/// of an `(n, d)` and an `(m, d)` operand.
+///
+/// Row norms are clamped to at least the stability epsilon, so that
+/// zero rows have zero similarity to every row.
+#[derive(Debug)]
+pub struct CosineMatrixNode<LHS, RHS> {
+    value: RefCell<Arr>,
+    lhs_normalized: RefCell<Arr>,
+    rhs_normalized: RefCell<Arr>,
+    lhs_norms: RefCell<Vec<Float>>,
+    rhs_norms: RefCell<Vec<Float>>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    epsilon: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+/// Writes the rows of `operand` divided by their norms, clamped to at
+/// least `epsilon`, to `dest`, and the clamped norms to `norms`.
+fn normalize_rows(dest: &mut Arr, norms: &mut Vec<Float>, operand: &Arr, epsilon: Float) {
+    fit_shape(dest, operand.dim());
+    norms.clear();
+
+    for (mut dest, row) in dest.genrows_mut().into_iter().zip(operand.genrows()) {
+        let norm = row.dot(&row).sqrt().max(epsilon);
+
+        dest.zip_mut_with(&row, |dest, &x| *dest = x / norm);
+        norms.push(norm);
+    }
+}
+
+/// Backpropagates `gradient`, the gradient of rows normalized by
+/// `normalize_rows`, into the gradient of the unnormalized rows.
+fn normalize_rows_backward(
+    dest: &mut Arr,
+    beta: Float,
+    normalized: &Arr,
+    norms: &[Float],
+    gradient: &Arr,
+    epsilon: Float,
+) {
+    fit_shape(dest, normalized.dim());
+
+    for (mut dest, normalized, grad, &norm) in izip!(
+        dest.genrows_mut(),
+        normalized.genrows(),
+        gradient.genrows(),
+        norms
+    ) {
+        // Rows whose norm was clamped were divided by a constant.
+        let projection = if norm > epsilon {
+            normalized.dot(&grad)
+        } else {
+            0.0
+        };
+
+        for (dest, &x, &grad) in izip!(dest.iter_mut(), normalized.iter(), grad.iter()) {
+            *dest = beta * *dest + (grad - x * projection) / norm;
+        }
+    }
+}
+
+impl<LHS, RHS> CosineMatrixNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr>,
+    RHS: Node<Value = Arr>,
+{
+    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
+        Self::try_new(lhs, rhs).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `new`, but returns an error if the operands' shapes are
+    /// incompatible.
+    pub fn try_new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Result<Self, ShapeError> {
+        if lhs.value().cols() != rhs.value().cols() {
+            return Err(ShapeError::new("CosineMatrix", &lhs.value(), &rhs.value()));
+        }
+
+        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
+        let (lhs_shape, rhs_shape) = (lhs.value().dim(), rhs.value().dim());
+        let lhs_gradient = gradient_buffer(lhs_shape, needs_gradient);
+        let rhs_gradient = gradient_buffer(rhs_shape, needs_gradient);
+
+        let node = CosineMatrixNode {
+            value: RefCell::new(Arr::zeros((lhs_shape.0, rhs_shape.0))),
+            lhs_normalized: RefCell::new(Arr::zeros(lhs_shape)),
+            rhs_normalized: RefCell::new(Arr::zeros(rhs_shape)),
+            lhs_norms: RefCell::new(Vec::new()),
+            rhs_norms: RefCell::new(Vec::new()),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            rhs_gradient: RefCell::new(rhs_gradient),
+            lhs: lhs,
+            rhs: rhs,
+            epsilon: stability().epsilon,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        };
+        node.compute();
+
+        Ok(node)
+    }
+
+    fn compute(&self) {
+        let mut lhs_normalized = self.lhs_normalized.borrow_mut();
+        let mut rhs_normalized = self.rhs_normalized.borrow_mut();
+
+        normalize_rows(
+            &mut lhs_normalized,
+            &mut self.lhs_norms.borrow_mut(),
+            self.lhs.value().deref(),
+            self.epsilon,
+        );
+        normalize_rows(
+            &mut rhs_normalized,
+            &mut self.rhs_norms.borrow_mut(),
+            self.rhs.value().deref(),
+            self.epsilon,
+        );
+
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, (lhs_normalized.rows(), rhs_normalized.rows()));
+
+        numerics::mat_mul(1.0, &lhs_normalized, &rhs_normalized.t(), 0.0, &mut value);
+    }
+}
+
+impl<LHS, RHS> Node for CosineMatrixNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.lhs.forward();
+        self.rhs.forward();
+
+        self.compute();
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let lhs_normalized = self.lhs_normalized.borrow();
+            let rhs_normalized = self.rhs_normalized.borrow();
+
+            normalize_rows_backward(
+                &mut self.lhs_gradient.borrow_mut(),
+                beta,
+                &lhs_normalized,
+                &self.lhs_norms.borrow(),
+                &gradient.dot(rhs_normalized.deref()),
+                self.epsilon,
+            );
+            normalize_rows_backward(
+                &mut self.rhs_gradient.borrow_mut(),
+                beta,
+                &rhs_normalized,
+                &self.rhs_norms.borrow(),
+                &gradient.t().dot(lhs_normalized.deref()),
+                self.epsilon,
+            );
+        }
+
+        if self.counter.recurse_backward() {
+            self.lhs.backward(&self.lhs_gradient.borrow());
+            self.rhs.backward(&self.rhs_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.lhs.zero_gradient();
+            self.rhs.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("CosineMatrix")
+            .operand(&*self.lhs)
+            .operand(&*self.rhs)
+    }
+    fn flops(&self) -> Option<usize> {
+        let (rows, cols) = self.value.borrow().dim();
+        let d = self.lhs.value().cols();
+        Some(2 * rows * cols * d + 3 * (rows + cols) * d)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.lhs_gradient.borrow(), self.rhs_gradient.borrow()]
+    }
+}
+
+#[derive(Debug)]
+pub struct DotNode<LHS, RHS> {
+    value: RefCell<Arr>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS, RHS> DotNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr>,
+    RHS: Node<Value = Arr>,
+{
+    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
+        Self::try_new(lhs, rhs).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `new`, but returns an error if the operands' shapes are
+    /// incompatible.
+    pub fn try_new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Result<Self, ShapeError> {
+        if lhs.value().cols() != rhs.value().rows() {
+            return Err(ShapeError::new("Dot", &lhs.value(), &rhs.value()));
+        }
+
+        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
+        let mut value = arena::zeros((lhs.value().rows(), rhs.value().cols()));
+        numerics::mat_mul(1.0, &lhs.value(), &rhs.value(), 0.0, &mut value);
+
+        let lhs_gradient = gradient_buffer(lhs.value().dim(), needs_gradient);
+        let rhs_gradient = gradient_buffer(rhs.value().dim(), needs_gradient);
+
+        Ok(DotNode {
+            value: RefCell::new(value),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            rhs_gradient: RefCell::new(rhs_gradient),
+            lhs: lhs,
+            rhs: rhs,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        })
+    }
+
+    fn compute(&self) {
+        let lhs_value = self.lhs.value();
+        let rhs_value = self.rhs.value();
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, (lhs_value.rows(), rhs_value.cols()));
+
+        numerics::mat_mul(1.0, &lhs_value, &rhs_value, 0.0, value.deref_mut());
+    }
+}
+
+impl<LHS, RHS> Drop for DotNode<LHS, RHS> {
+    fn drop(&mut self) {
+        arena::recycle(self.value.get_mut());
+        arena::recycle(self.lhs_gradient.get_mut());
+        arena::recycle(self.rhs_gradient.get_mut());
+    }
+}
+
+impl<LHS, RHS> Node for DotNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.lhs.forward();
+        self.rhs.forward();
+
+        self.compute();
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        self.accumulate(gradient);
+
+        if self.counter.recurse_backward() {
+            self.lhs.backward(&self.lhs_gradient.borrow());
+            self.rhs.backward(&self.rhs_gradient.borrow());
+        }
+    }
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, uses: usize) {
+        if self.counter.forward_many(uses) == ForwardAction::Cached {
+            return;
+        }
+
+        self.compute();
+    }
+    fn accumulate(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let rhs_value = self.rhs.value();
+            let lhs_value = self.lhs.value();
+
+            let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+            let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+            fit_shape(&mut lhs_gradient, lhs_value.dim());
+            fit_shape(&mut rhs_gradient, rhs_value.dim());
+
+            numerics::mat_mul(1.0, gradient, &rhs_value.t(), beta, &mut lhs_gradient);
+            numerics::mat_mul(
+                1.0,
+                &lhs_value.t(),
+                gradient.deref(),
+                beta,
+                &mut rhs_gradient,
+            );
+        }
+    }
+    fn propagate(&self) {
+        if self.counter.recurse_backward() {
+            self.lhs.accumulate(&self.lhs_gradient.borrow());
+            self.rhs.accumulate(&self.rhs_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.lhs.zero_gradient();
+            self.rhs.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        let lhs = builder
+            .tangent(&self.lhs)?
+            .map(|lhs| lhs.dot(&tangent_operand(&self.rhs)).boxed());
+        let rhs = builder
+            .tangent(&self.rhs)?
+            .map(|rhs| tangent_operand(&self.lhs).dot(&rhs).boxed());
+
+        Ok(add_tangents(lhs, rhs))
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let lhs = builder.clone_node(&self.lhs)?;
+        let rhs = builder.clone_node(&self.rhs)?;
+        Ok(Some(lhs.dot(&rhs).boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let lhs = builder.compile(&self.lhs)?;
+        let rhs = builder.compile(&self.rhs)?;
+        Ok(builder.step(Op::Dot, vec![lhs, rhs], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Dot")
+            .operand(&*self.lhs)
+            .operand(&*self.rhs)
+    }
+    fn flops(&self) -> Option<usize> {
+        let (rows, cols) = self.value.borrow().dim();
+        Some(2 * rows * cols * self.lhs.value().cols())
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.lhs_gradient.borrow(), self.rhs_gradient.borrow()]
+    }
+}
+
+/// Padding applied to the row (time) axis by `Conv1dNode`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Padding {
+    /// No padding: the kernel only visits windows that lie
+    /// entirely within the input.
+    Valid,
+    /// Zero-pad so that the output has `ceil(rows / stride)` rows.
+    Same,
+}
+
+/// Return the number of output rows and the zero padding added
+/// before and after the input rows.
+fn conv1d_geometry(
+    rows: usize,
+    kernel_size: usize,
+    stride: usize,
+    padding: Padding,
+) -> (usize, usize, usize) {
+    match padding {
+        Padding::Valid => {
+            assert!(
+                rows >= kernel_size,
+                "Input has fewer rows than the kernel size."
+            );
+            ((rows - kernel_size) / stride + 1, 0, 0)
+        }
+        // An empty input has an empty output, with nothing to pad.
+        Padding::Same if rows == 0 => (0, 0, 0),
+        Padding::Same => {
+            let output_rows = (rows + stride - 1) / stride;
+            let total = ((output_rows - 1) * stride + kernel_size).saturating_sub(rows);
+            (output_rows, total / 2, total - total / 2)
+        }
+    }
+}
+
+/// One-dimensional convolution over the rows of its operand.
+///
+/// The operand is laid out as `(time, in_features)`. The weight
+/// is the `(kernel_size, in_features, out_features)` kernel flattened
+/// to `(kernel_size * in_features, out_features)`, with the row
+/// `k * in_features + i` holding the weights of input feature `i`
+/// at kernel offset `k`. The output is `(output_time, out_features)`.
+///
+/// Windows of the input are gathered into the rows of a column
+/// matrix so that the convolution becomes a single matrix product.
+#[derive(Debug)]
+pub struct Conv1dNode<OP> {
+    value: RefCell<Arr>,
+    columns: RefCell<Arr>,
+    columns_gradient: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    weight_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    weight: Rc<ParameterNode>,
+    kernel_size: usize,
+    stride: usize,
+    padding: Padding,
+    pad_before: Cell<usize>,
+    pad_after: Cell<usize>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> Conv1dNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(
+        operand: Rc<OP>,
+        weight: Rc<ParameterNode>,
+        kernel_size: usize,
+        stride: usize,
+        padding: Padding,
+    ) -> Self {
+        assert!(kernel_size > 0, "Kernel size must be positive.");
+        assert!(stride > 0, "Stride must be positive.");
+
+        let (rows, in_features) = {
+            let value = operand.value();
+            (value.rows(), value.cols())
+        };
+        let out_features = weight.value().cols();
+
+        assert_eq!(
+            weight.value().rows(),
+            kernel_size * in_features,
+            "Weight must have kernel_size * in_features rows."
+        );
+
+        let (output_rows, pad_before, pad_after) =
+            conv1d_geometry(rows, kernel_size, stride, padding);
+
+        let mut columns = Arr::zeros((output_rows, kernel_size * in_features));
+        im2col(
+            operand.value().deref(),
+            &mut columns,
+            kernel_size,
+            stride,
+            pad_before,
+        );
+        let value = columns.dot(weight.value().deref());
+        let needs_gradient = operand.needs_gradient() || weight.needs_gradient();
+
+        Conv1dNode {
+            value: RefCell::new(value),
+            columns_gradient: RefCell::new(gradient_buffer(columns.dim(), needs_gradient)),
+            columns: RefCell::new(columns),
+            operand_gradient: RefCell::new(gradient_buffer((rows, in_features), needs_gradient)),
+            weight_gradient: RefCell::new(gradient_buffer(
+                (kernel_size * in_features, out_features),
+                needs_gradient,
+            )),
+            operand: operand,
+            weight: weight,
+            kernel_size: kernel_size,
+            stride: stride,
+            padding: padding,
+            pad_before: Cell::new(pad_before),
+            pad_after: Cell::new(pad_after),
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+/// Copy the (zero-padded) input window of each output row into
+/// the corresponding row of `columns`.
+fn im2col(input: &Arr, columns: &mut Arr, kernel_size: usize, stride: usize, pad_before: usize) {
+    let in_features = input.cols();
+    let input_slice = input.as_slice().unwrap();
+
+    for (t, row) in columns
+        .as_slice_mut()
+        .unwrap()
+        .chunks_mut(kernel_size * in_features)
+        .enumerate()
+    {
+        for (k, window) in row.chunks_mut(in_features).enumerate() {
+            match (t * stride + k).checked_sub(pad_before) {
+                Some(source) if source < input.rows() => window.copy_from_slice(
+                    &input_slice[source * in_features..(source + 1) * in_features],
+                ),
+                _ => {
+                    for x in window.iter_mut() {
+                        *x = 0.0;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Accumulate the gradient of the column matrix into the input
+/// rows it was gathered from.
+fn col2im(
+    columns: &Arr,
+    input: &mut Arr,
+    kernel_size: usize,
+    stride: usize,
+    pad_before: usize,
+) {
+    let rows = input.rows();
+    let in_features = input.cols();
+    let input_slice = input.as_slice_mut().unwrap();
+
+    for (t, row) in columns
+        .as_slice()
+        .unwrap()
+        .chunks(kernel_size * in_features)
+        .enumerate()
+    {
+        for (k, window) in row.chunks(in_features).enumerate() {
+            if let Some(source) = (t * stride + k).checked_sub(pad_before) {
+                if source < rows {
+                    for (x, &grad) in izip!(
+                        input_slice[source * in_features..(source + 1) * in_features].iter_mut(),
+                        window.iter()
+                    ) {
+                        *x += grad;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<OP> Node for Conv1dNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        self.weight.forward();
+
+        let operand_value = self.operand.value();
+        let weight_value = self.weight.value();
+
+        let (output_rows, pad_before, pad_after) = conv1d_geometry(
+            operand_value.rows(),
+            self.kernel_size,
+            self.stride,
+            self.padding,
+        );
+        self.pad_before.set(pad_before);
+        self.pad_after.set(pad_after);
+
+        let mut columns = self.columns.borrow_mut();
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut columns, (output_rows, weight_value.rows()));
+        fit_shape(&mut value, (output_rows, weight_value.cols()));
+
+        im2col(
+            operand_value.deref(),
+            &mut columns,
+            self.kernel_size,
+            self.stride,
+            pad_before,
+        );
+
+        numerics::mat_mul(1.0, columns.deref(), weight_value.deref(), 0.0, value.deref_mut());
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let columns = self.columns.borrow();
+            let mut columns_gradient = self.columns_gradient.borrow_mut();
+            let mut weight_gradient = self.weight_gradient.borrow_mut();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            fit_shape(&mut columns_gradient, columns.dim());
+            fit_shape(&mut operand_gradient, self.operand.value().dim());
+
+            numerics::mat_mul(
+                1.0,
+                &columns.t(),
+                gradient.deref(),
+                beta,
+                &mut weight_gradient,
+            );
+            numerics::mat_mul(
+                1.0,
+                gradient.deref(),
+                &self.weight.value().t(),
+                0.0,
+                &mut columns_gradient,
+            );
+
+            if beta == 0.0 {
+                operand_gradient.fill(0.0);
+            }
+
+            col2im(
+                &columns_gradient,
+                &mut operand_gradient,
+                self.kernel_size,
+                self.stride,
+                self.pad_before.get(),
+            );
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+            self.weight.backward(&self.weight_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.weight.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("Conv1d")
+            .operand(&*self.operand)
+            .operand(&*self.weight)
+            .attribute("kernel_size", Attribute::Int(self.kernel_size as i64))
+            .attribute("stride", Attribute::Int(self.stride as i64))
+            .attribute("pad_before", Attribute::Int(self.pad_before.get() as i64))
+            .attribute("pad_after", Attribute::Int(self.pad_after.get() as i64))
+    }
+    fn flops(&self) -> Option<usize> {
+        let (window, out_features) = self.weight.value().dim();
+        Some(2 * self.value.borrow().rows() * window * out_features)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow(), self.weight_gradient.borrow()]
+    }
+}
+
+/// Max-pooling over windows of the rows of its operand, applied
+/// to each column independently.
+///
+/// The row ranges of the windows follow the same conventions as
+/// `Conv1dNode`: with `Padding::Same`, windows overhanging either end
+/// of the input are truncated to the rows that exist.
+#[derive(Debug)]
+pub struct MaxPool1dNode<OP> {
+    value: RefCell<Arr>,
+    argmax: RefCell<Vec<usize>>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    window_size: usize,
+    stride: usize,
+    padding: Padding,
+    pad_before: Cell<usize>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> MaxPool1dNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, window_size: usize, stride: usize, padding: Padding) -> Self {
+        assert!(window_size > 0, "Window size must be positive.");
+        assert!(stride > 0, "Stride must be positive.");
+
+        let (rows, cols) = {
+            let value = operand.value();
+            (value.rows(), value.cols())
+        };
+        let (output_rows, pad_before, _) = conv1d_geometry(rows, window_size, stride, padding);
+
+        let mut value = Arr::zeros((output_rows, cols));
+        let mut argmax = vec![0; output_rows * cols];
+        max_pool(
+            operand.value().deref(),
+            &mut value,
+            &mut argmax,
+            window_size,
+            stride,
+            pad_before,
+        );
+
+        let needs_gradient = operand.needs_gradient();
+
+        MaxPool1dNode {
+            value: RefCell::new(value),
+            argmax: RefCell::new(argmax),
+            operand_gradient: RefCell::new(gradient_buffer((rows, cols), needs_gradient)),
+            operand: operand,
+            window_size: window_size,
+            stride: stride,
+            padding: padding,
+            pad_before: Cell::new(pad_before),
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+/// Write the maximum of each window into `value`, and the input
+/// row it was taken from into `argmax`.
+fn max_pool(
+    input: &Arr,
+    value: &mut Arr,
+    argmax: &mut [usize],
+    window_size: usize,
+    stride: usize,
+    pad_before: usize,
+) {
+    let cols = input.cols();
+
+    for (t, (value_row, argmax_row)) in value
+        .as_slice_mut()
+        .unwrap()
+        .chunks_mut(cols)
+        .zip(argmax.chunks_mut(cols))
+        .enumerate()
+    {
+        let start = (t * stride).saturating_sub(pad_before);
+        let stop = ::std::cmp::min(
+            (t * stride + window_size).saturating_sub(pad_before),
+            input.rows(),
+        );
+
+        for (col, (max, max_row)) in value_row.iter_mut().zip(argmax_row.iter_mut()).enumerate() {
+            *max = input[(start, col)];
+            *max_row = start;
+
+            for row in start + 1..stop {
+                if input[(row, col)] > *max {
+                    *max = input[(row, col)];
+                    *max_row = row;
+                }
+            }
+        }
+    }
+}
+
+impl<OP> Node for MaxPool1dNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let operand_value = self.operand.value();
+        let (rows, cols) = operand_value.dim();
+        let (output_rows, pad_before, _) =
+            conv1d_geometry(rows, self.window_size, self.stride, self.padding);
+        self.pad_before.set(pad_before);
+
+        let mut value = self.value.borrow_mut();
+        let mut argmax = self.argmax.borrow_mut();
+        fit_shape(&mut value, (output_rows, cols));
+        argmax.resize(output_rows * cols, 0);
+
+        max_pool(
+            operand_value.deref(),
+            value.deref_mut(),
+            argmax.as_mut_slice(),
+            self.window_size,
+            self.stride,
+            pad_before,
+        );
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        {
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            if self.counter.backward() == BackwardAction::Set {
+                fit_shape(&mut operand_gradient, self.operand.value().dim());
+                operand_gradient.fill(0.0);
+            }
+
+            let cols = operand_gradient.cols();
+            let argmax = self.argmax.borrow();
+
+            for (idx, (&row, &grad)) in argmax.iter().zip(gradient.iter()).enumerate() {
+                operand_gradient[(row, idx % cols)] += grad;
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("MaxPool1d")
+            .operand(&*self.operand)
+            .attribute("window_size", Attribute::Int(self.window_size as i64))
+            .attribute("stride", Attribute::Int(self.stride as i64))
+            .attribute("pad_before", Attribute::Int(self.pad_before.get() as i64))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// Average-pooling over windows of the rows of its operand, applied
+/// to each column independently.
+///
+/// Windows follow the same conventions as `MaxPool1dNode`. Partial
+/// windows at either end of the input, which only arise with
+/// `Padding::Same`, are averaged over the rows they cover: padding
+/// does not count towards the average.
+#[derive(Debug)]
+pub struct AvgPool1dNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    window_size: usize,
+    stride: usize,
+    padding: Padding,
+    pad_before: Cell<usize>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> AvgPool1dNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, window_size: usize, stride: usize, padding: Padding) -> Self {
+        assert!(window_size > 0, "Window size must be positive.");
+        assert!(stride > 0, "Stride must be positive.");
+
+        let (rows, cols) = {
+            let value = operand.value();
+            (value.rows(), value.cols())
+        };
+        let (output_rows, pad_before, _) = conv1d_geometry(rows, window_size, stride, padding);
+
+        let needs_gradient = operand.needs_gradient();
+
+        let mut node = AvgPool1dNode {
+            value: RefCell::new(Arr::zeros((output_rows, cols))),
+            operand_gradient: RefCell::new(gradient_buffer((rows, cols), needs_gradient)),
+            needs_gradient: needs_gradient,
+            operand: operand,
+            window_size: window_size,
+            stride: stride,
+            padding: padding,
+            pad_before: Cell::new(pad_before),
+            counter: PassCounter::default(),
+        };
+        node.pool();
+
+        node
+    }
+
+    /// The range of input rows covered by the `t`-th window.
+    fn window(&self, t: usize, rows: usize) -> ::std::ops::Range<usize> {
+        let pad_before = self.pad_before.get();
+        let start = (t * self.stride).saturating_sub(pad_before);
+        let stop = ::std::cmp::min(
+            (t * self.stride + self.window_size).saturating_sub(pad_before),
+            rows,
+        );
+
+        start..stop
+    }
+
+    fn pool(&self) {
+        let input = self.operand.value();
+        let (output_rows, pad_before, _) =
+            conv1d_geometry(input.rows(), self.window_size, self.stride, self.padding);
+        self.pad_before.set(pad_before);
+
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, (output_rows, input.cols()));
+
+        for (t, mut output_row) in value.outer_iter_mut().enumerate() {
+            let window = self.window(t, input.rows());
+            let scale = 1.0 / window.len() as Float;
+
+            output_row.fill(0.0);
+            for row in window {
+                output_row.scaled_add(scale, &input.subview(Axis(0), row));
+            }
+        }
+    }
+}
+
+impl<OP> Node for AvgPool1dNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        self.pool();
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        {
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            if self.counter.backward() == BackwardAction::Set {
+                fit_shape(&mut operand_gradient, self.operand.value().dim());
+                operand_gradient.fill(0.0);
+            }
+
+            let rows = operand_gradient.rows();
+
+            for (t, gradient_row) in gradient.outer_iter().enumerate() {
+                let window = self.window(t, rows);
+                let scale = 1.0 / window.len() as Float;
+
+                for row in window {
+                    operand_gradient
+                        .subview_mut(Axis(0), row)
+                        .scaled_add(scale, &gradient_row);
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("AvgPool1d")
+            .operand(&*self.operand)
+            .attribute("window_size", Attribute::Int(self.window_size as i64))
+            .attribute("stride", Attribute::Int(self.stride as i64))
+            .attribute("pad_before", Attribute::Int(self.pad_before.get() as i64))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// Leaky integration (a first-order IIR filter) down the row axis:
+/// `y_t = alpha * y_{t-1} + (1 - alpha) * x_t`, with `y_{-1} = 0`.
+#[derive(Debug)]
+pub struct IirNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    alpha: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+fn leaky_integrate(dest: &mut Arr, operand: &Arr, alpha: Float) {
+    let mut previous = vec![0.0; operand.cols()];
+
+    for (mut dest_row, row) in dest.genrows_mut().into_iter().zip(operand.genrows()) {
+        for (dest, state, &x) in izip!(
+            dest_row.fast_slice_mut(),
+            previous.iter_mut(),
+            row.fast_slice()
+        ) {
+            *state = alpha * *state + (1.0 - alpha) * x;
+            *dest = *state;
+        }
+    }
+}
+
+impl<OP> IirNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, alpha: Float) -> Self {
+        assert!(
+            alpha >= 0.0 && alpha <= 1.0,
+            "The smoothing factor must be in [0, 1], got {}.",
+            alpha
+        );
+
+        let mut value = Arr::zeros(operand.value().dim());
+        leaky_integrate(&mut value, &operand.value(), alpha);
+
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        IirNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            alpha: alpha,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for IirNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let operand_value = self.operand.value();
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, operand_value.dim());
+        leaky_integrate(&mut value, &operand_value, self.alpha);
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            // Backpropagate through time: the gradient with respect to
+            // y_t collects the incoming gradient and that of y_{t+1}.
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            fit_shape(&mut operand_gradient, gradient.dim());
+            let mut carry = vec![0.0; gradient.cols()];
+
+            for t in (0..gradient.rows()).rev() {
+                for (grad, carry, &incoming) in izip!(
+                    operand_gradient.row_mut(t),
+                    carry.iter_mut(),
+                    gradient.row(t)
+                ) {
+                    *carry = incoming + self.alpha * *carry;
+                    *grad = beta * *grad + (1.0 - self.alpha) * *carry;
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow())
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("Iir")
+            .operand(&*self.operand)
+            .attribute("alpha", Attribute::Float(self.alpha))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+#[derive(Debug)]
+pub struct VectorDotNode<LHS, RHS> {
+    value: RefCell<Arr>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS, RHS> VectorDotNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
+        Self::try_new(lhs, rhs).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `new`, but returns an error if the operands' shapes are
+    /// incompatible.
+    pub fn try_new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Result<Self, ShapeError> {
+        check_same_shape("VectorDot", &lhs.value(), &rhs.value())?;
+
+        let (value, lhs_gradient, rhs_gradient, needs_gradient) = {
+            let lhs_value = lhs.value();
+            let rhs_value = rhs.value();
+
+            let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
+
+            let mut value = Arr::zeros((lhs_value.shape()[0], 1));
+
+            for (result, lhs, rhs) in izip!(
+                value.as_slice_mut().unwrap(),
+                lhs_value
+                    .genrows()
+                    .into_iter()
+                    .map(|x| x.into_slice().unwrap()),
+                rhs_value
+                    .genrows()
+                    .into_iter()
+                    .map(|x| x.into_slice().unwrap())
+            ) {
+                *result = numerics::simd_dot(lhs, rhs);
+            }
+
+            let lhs_gradient = gradient_buffer(lhs_value.dim(), needs_gradient);
+            let rhs_gradient = gradient_buffer(rhs_value.dim(), needs_gradient);
+
+            (value, lhs_gradient, rhs_gradient, needs_gradient)
+        };
+
+        Ok(VectorDotNode {
+            value: RefCell::new(value),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            rhs_gradient: RefCell::new(rhs_gradient),
+            lhs: lhs,
+            rhs: rhs,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        })
+    }
+
+    fn compute(&self) {
+        let lhs_value = self.lhs.value();
+        let rhs_value = self.rhs.value();
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, (lhs_value.rows(), 1));
+
+        for (result, lhs, rhs) in izip!(
+            value.as_slice_mut().unwrap(),
+            lhs_value
+                .genrows()
+                .into_iter()
+                .map(|x| x.into_slice().unwrap()),
+            rhs_value
+                .genrows()
+                .into_iter()
+                .map(|x| x.into_slice().unwrap())
+        ) {
+            *result = numerics::simd_dot(lhs, rhs);
+        }
+    }
+}
+
+impl<LHS, RHS> Node for VectorDotNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.lhs.forward();
+        self.rhs.forward();
+
+        self.compute();
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        self.accumulate(gradient);
+
+        if self.counter.recurse_backward() {
+            self.lhs.backward(&self.lhs_gradient.borrow());
+            self.rhs.backward(&self.rhs_gradient.borrow());
+        }
+    }
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, uses: usize) {
+        if self.counter.forward_many(uses) == ForwardAction::Cached {
+            return;
+        }
+
+        self.compute();
+    }
+    fn accumulate(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let lhs_value = self.lhs.value();
+        let rhs_value = self.rhs.value();
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut lhs_grad = self.lhs_gradient.borrow_mut();
+                let mut rhs_grad = self.rhs_gradient.borrow_mut();
+                fit_shape(&mut lhs_grad, lhs_value.dim());
+                fit_shape(&mut rhs_grad, rhs_value.dim());
+
+                for (backward_row, rhs_row, &gradient) in izip!(
+                    lhs_grad
+                        .genrows_mut()
+                        .into_iter()
+                        .map(|x| x.into_slice().unwrap()),
+                    rhs_value
+                        .genrows()
+                        .into_iter()
+                        .map(|x| x.into_slice().unwrap()),
+                    gradient.as_slice().unwrap()
+                ) {
+                    numerics::simd_scaled_assign(backward_row, rhs_row, gradient)
+                }
+                for (backward_row, lhs_row, &gradient) in izip!(
+                    rhs_grad
+                        .genrows_mut()
+                        .into_iter()
+                        .map(|x| x.into_slice().unwrap()),
+                    lhs_value
+                        .genrows()
+                        .into_iter()
+                        .map(|x| x.into_slice().unwrap()),
+                    gradient.as_slice().unwrap()
+                ) {
+                    numerics::simd_scaled_assign(backward_row, lhs_row, gradient)
+                }
+            }
+            BackwardAction::Increment => {
+                let mut lhs_grad = self.lhs_gradient.borrow_mut();
+                let mut rhs_grad = self.rhs_gradient.borrow_mut();
+
+                for (backward_row, rhs_row, &gradient) in izip!(
+                    lhs_grad
+                        .genrows_mut()
+                        .into_iter()
+                        .map(|x| x.into_slice().unwrap()),
+                    rhs_value
+                        .genrows()
+                        .into_iter()
+                        .map(|x| x.into_slice().unwrap()),
+                    gradient.as_slice().unwrap()
+                ) {
+                    numerics::simd_scaled_add(backward_row, rhs_row, gradient)
+                }
+                for (backward_row, lhs_row, &gradient) in izip!(
+                    rhs_grad
+                        .genrows_mut()
+                        .into_iter()
+                        .map(|x| x.into_slice().unwrap()),
+                    lhs_value
+                        .genrows()
+                        .into_iter()
+                        .map(|x| x.into_slice().unwrap()),
+                    gradient.as_slice().unwrap()
+                ) {
+                    numerics::simd_scaled_add(backward_row, lhs_row, gradient)
+                }
+            }
+        }
+    }
+    fn propagate(&self) {
+        if self.counter.recurse_backward() {
+            self.lhs.accumulate(&self.lhs_gradient.borrow());
+            self.rhs.accumulate(&self.rhs_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.lhs.zero_gradient();
+            self.rhs.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let lhs = builder.clone_node(&self.lhs)?;
+        let rhs = builder.clone_node(&self.rhs)?;
+        Ok(Some(lhs.vector_dot(&rhs).boxed()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("VectorDot")
+            .operand(&*self.lhs)
+            .operand(&*self.rhs)
+    }
+    fn flops(&self) -> Option<usize> {
+        let (n, d) = self.lhs.value().dim();
+        Some(2 * n * d)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.lhs_gradient.borrow(), self.rhs_gradient.borrow()]
+    }
+}
+
+/// Computes `sum_j w_j x_ij^2` for each row of an `(n, d)` operand into
+/// an `(n, 1)` column, where the `(1, d)` feature weights `w` are
+/// learnable.
+#[derive(Debug)]
+pub struct WeightedSqNormNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    weights_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    weights: Rc<ParameterNode>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+fn weighted_sq_norm(dest: &mut Arr, operand: &Arr, weights: &Arr) {
+    fit_shape(dest, (operand.rows(), 1));
+
+    let weights = weights.fast_slice();
+
+    for (dest, row) in dest.iter_mut().zip(operand.genrows()) {
+        *dest = izip!(row.as_slice().unwrap(), weights)
+            .map(|(&x, &w)| w * x.powi(2))
+            .sum();
+    }
+}
+
+impl<OP> WeightedSqNormNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, weights: Rc<ParameterNode>) -> Self {
+        assert_eq!(
+            weights.value().dim(),
+            (1, operand.value().cols()),
+            "The weights must be a row with one entry per column."
+        );
+
+        let needs_gradient = operand.needs_gradient() || weights.needs_gradient();
+
+        let mut value = Arr::zeros((operand.value().rows(), 1));
+        weighted_sq_norm(&mut value, &operand.value(), &weights.value());
+
+        let operand_gradient = gradient_buffer(operand.value().dim(), needs_gradient);
+        let weights_gradient = gradient_buffer(weights.value().dim(), needs_gradient);
+
+        WeightedSqNormNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(operand_gradient),
+            weights_gradient: RefCell::new(weights_gradient),
+            operand: operand,
+            weights: weights,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for WeightedSqNormNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        weighted_sq_norm(
+            &mut self.value.borrow_mut(),
+            &self.operand.value(),
+            &self.weights.value(),
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let operand_value = self.operand.value();
+            let weights_value = self.weights.value();
+            let weights = weights_value.fast_slice();
+
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            let mut weights_gradient = self.weights_gradient.borrow_mut();
+            fit_shape(&mut operand_gradient, operand_value.dim());
+
+            let weights_gradient = weights_gradient.fast_slice_mut();
+
+            if beta == 0.0 {
+                weights_gradient.iter_mut().for_each(|w_grad| *w_grad = 0.0);
+            }
+
+            for (mut grad_row, row, &grad) in izip!(
+                operand_gradient.genrows_mut(),
+                operand_value.genrows(),
+                gradient.iter()
+            ) {
+                for (x_grad, w_grad, &x, &w) in izip!(
+                    grad_row.as_slice_mut().unwrap(),
+                    weights_gradient.iter_mut(),
+                    row.as_slice().unwrap(),
+                    weights
+                ) {
+                    *x_grad = beta * *x_grad + 2.0 * grad * w * x;
+                    *w_grad += grad * x.powi(2);
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+            self.weights.backward(&self.weights_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.weights.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("WeightedSqNorm")
+            .operand(&*self.operand)
+            .operand(&*self.weights)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow(), self.weights_gradient.borrow()]
+    }
+}
+
+/// Computes the `(d, d)` covariance matrix of the columns of
+/// an `(n, d)` input.
+#[derive(Debug)]
+pub struct CovarianceNode<OP> {
+    value: RefCell<Arr>,
+    centered: RefCell<Arr>,
+    symmetric_gradient: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+fn center_columns(dest: &mut Arr, source: &Arr) {
+    dest.slice_assign(source);
+
+    let n = source.rows() as Float;
+
+    for mut column in dest.gencolumns_mut() {
+        let mean = column.scalar_sum() / n;
+        column.map_inplace(|x| *x -= mean);
+    }
+}
+
+impl<OP> CovarianceNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let (n, d) = operand.value().dim();
+
+        let mut centered = Arr::zeros((n, d));
+        center_columns(&mut centered, operand.value().deref());
+
+        let mut value = Arr::zeros((d, d));
+        numerics::mat_mul(1.0 / n as Float, &centered.t(), &centered, 0.0, &mut value);
+
+        let needs_gradient = operand.needs_gradient();
+        let operand_gradient = gradient_buffer(centered.dim(), needs_gradient);
+
+        CovarianceNode {
+            symmetric_gradient: RefCell::new(gradient_buffer(value.dim(), needs_gradient)),
+            value: RefCell::new(value),
+            centered: RefCell::new(centered),
+            operand_gradient: RefCell::new(operand_gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for CovarianceNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let operand_value = self.operand.value();
+        let mut centered = self.centered.borrow_mut();
+        fit_shape(&mut centered, operand_value.dim());
+        center_columns(&mut centered, operand_value.deref());
+
+        numerics::mat_mul(
+            1.0 / centered.rows() as Float,
+            &centered.t(),
+            centered.deref(),
+            0.0,
+            self.value.borrow_mut().deref_mut(),
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let centered = self.centered.borrow();
+            let mut symmetric_gradient = self.symmetric_gradient.borrow_mut();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            fit_shape(&mut operand_gradient, centered.dim());
+
+            symmetric_gradient.slice_assign(gradient.deref());
+            *symmetric_gradient += &gradient.t();
+
+            // Backpropagating through the centering subtracts the column
+            // means of the gradient. The gradient is the centered input
+            // multiplied on the right, so its column means are already zero.
+            numerics::mat_mul(
+                1.0 / centered.rows() as Float,
+                centered.deref(),
+                symmetric_gradient.deref(),
+                beta,
+                operand_gradient.deref_mut(),
+            );
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("Covariance").operand(&*self.operand)
+    }
+    fn flops(&self) -> Option<usize> {
+        let (n, d) = self.operand.value().dim();
+        Some(2 * n * d * d + n * d)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// Computes the mean over the columns of an `(n, d)` input of
+/// `max(0, gamma - sqrt(var + epsilon))`, where `var` is the variance
+/// of the column over the rows: the variance term of VICReg, which
+/// keeps features from collapsing to constants.
+#[derive(Debug)]
+pub struct VarianceHingeNode<OP> {
+    value: RefCell<Arr>,
+    centered: RefCell<Arr>,
+    std: RefCell<Vec<Float>>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    gamma: Float,
+    epsilon: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+/// Center the columns of `source` into `centered`, writing their
+/// standard deviations into `std` and returning the hinge loss.
+fn variance_hinge(
+    centered: &mut Arr,
+    std: &mut Vec<Float>,
+    source: &Arr,
+    gamma: Float,
+    epsilon: Float,
+) -> Float {
+    center_columns(centered, source);
+
+    let n = source.rows() as Float;
+
+    std.clear();
+    std.extend(centered.gencolumns().into_iter().map(|column| {
+        let variance = column.iter().map(|x| x.powi(2)).sum::<Float>() / n;
+        (variance + epsilon).sqrt()
+    }));
+
+    let loss: Float = std.iter().map(|&std| (gamma - std).max(0.0)).sum();
+
+    loss / std.len() as Float
+}
+
+impl<OP> VarianceHingeNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, gamma: Float, epsilon: Float) -> Self {
+        // Constant columns have a standard deviation of `sqrt(epsilon)`,
+        // which the backward pass divides by.
+        assert!(
+            epsilon > 0.0,
+            "The variance hinge epsilon must be positive, got {}.",
+            epsilon
+        );
+
+        let mut centered = Arr::zeros(operand.value().dim());
+        let mut std = Vec::new();
+        let loss = variance_hinge(
+            &mut centered,
+            &mut std,
+            operand.value().deref(),
+            gamma,
+            epsilon,
+        );
+
+        let needs_gradient = operand.needs_gradient();
+        let operand_gradient = gradient_buffer(centered.dim(), needs_gradient);
+
+        VarianceHingeNode {
+            value: RefCell::new(Arr::from_elem((1, 1), loss)),
+            centered: RefCell::new(centered),
+            std: RefCell::new(std),
+            operand_gradient: RefCell::new(operand_gradient),
+            operand: operand,
+            gamma: gamma,
+            epsilon: epsilon,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for VarianceHingeNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let operand_value = self.operand.value();
+        let mut centered = self.centered.borrow_mut();
+        fit_shape(&mut centered, operand_value.dim());
+
+        let loss = variance_hinge(
+            &mut centered,
+            &mut self.std.borrow_mut(),
+            operand_value.deref(),
+            self.gamma,
+            self.epsilon,
+        );
+        self.value.borrow_mut().fill(loss);
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let centered = self.centered.borrow();
+            let std = self.std.borrow();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            fit_shape(&mut operand_gradient, centered.dim());
+
+            let (n, d) = centered.dim();
+            let scale = gradient[(0, 0)] / (n * d) as Float;
+
+            // As in the covariance, the centered columns sum to zero,
+            // so centering adds nothing to the gradient. Columns above
+            // the hinge get none.
+            for (mut grad_column, column, &std) in izip!(
+                operand_gradient.gencolumns_mut(),
+                centered.gencolumns(),
+                std.iter()
+            ) {
+                let factor = if std < self.gamma { -scale / std } else { 0.0 };
+
+                for (grad, &x) in grad_column.iter_mut().zip(column.iter()) {
+                    *grad = beta * *grad + factor * x;
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("VarianceHinge")
+            .operand(&*self.operand)
+            .attribute("gamma", Attribute::Float(self.gamma))
+            .attribute("epsilon", Attribute::Float(self.epsilon))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// Standardizes each column of an `(n, d)` input to zero mean and unit
+/// variance, using statistics computed over the rows.
+///
+/// The stability variance epsilon is added to the column variances so
+/// that constant columns do not divide by zero.
+#[derive(Debug)]
+pub struct StandardizeNode<OP> {
+    value: RefCell<Arr>,
+    inverse_std: RefCell<Vec<Float>>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    variance_epsilon: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+fn standardize_columns(
+    dest: &mut Arr,
+    inverse_std: &mut [Float],
+    source: &Arr,
+    variance_epsilon: Float,
+) {
+    center_columns(dest, source);
+
+    let n = source.rows() as Float;
+
+    for (mut column, inverse_std) in dest.gencolumns_mut().into_iter().zip(inverse_std) {
+        let variance = column.iter().map(|x| x.powi(2)).sum::<Float>() / n;
+        *inverse_std = 1.0 / (variance + variance_epsilon).sqrt();
+
+        let scale = *inverse_std;
+        column.map_inplace(|x| *x *= scale);
+    }
+}
+
+impl<OP> StandardizeNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let (n, d) = operand.value().dim();
+
+        let variance_epsilon = stability().variance_epsilon;
+        let mut value = Arr::zeros((n, d));
+        let mut inverse_std = vec![0.0; d];
+        standardize_columns(
+            &mut value,
+            &mut inverse_std,
+            operand.value().deref(),
+            variance_epsilon,
+        );
+
+        let needs_gradient = operand.needs_gradient();
+        let operand_gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        StandardizeNode {
+            value: RefCell::new(value),
+            inverse_std: RefCell::new(inverse_std),
+            operand_gradient: RefCell::new(operand_gradient),
+            operand: operand,
+            variance_epsilon: variance_epsilon,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for StandardizeNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, self.operand.value().dim());
+
+        standardize_columns(
+            value.deref_mut(),
+            &mut self.inverse_std.borrow_mut(),
+            self.operand.value().deref(),
+            self.variance_epsilon,
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let value = self.value.borrow();
+            let inverse_std = self.inverse_std.borrow();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            fit_shape(&mut operand_gradient, value.dim());
+            let n = value.rows() as Float;
+
+            // Both the mean and the standard deviation depend on every
+            // row, giving `(g - mean(g) - y * mean(g * y)) / std` for
+            // each column.
+            for (mut dest, value, grad, &inverse_std) in izip!(
+                operand_gradient.gencolumns_mut(),
+                value.gencolumns(),
+                gradient.gencolumns(),
+                inverse_std.iter()
+            ) {
+                let grad_mean = grad.scalar_sum() / n;
+                let grad_value_mean = grad.dot(&value) / n;
+
+                for (dest, &y, &g) in izip!(dest.iter_mut(), value.iter(), grad.iter()) {
+                    *dest = beta * *dest
+                        + inverse_std * (g - grad_mean - y * grad_value_mean);
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("Standardize").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// Welford running mean and variance of the columns of a stream of rows.
+#[derive(Clone, Debug)]
+struct RunningStats {
+    count: usize,
+    mean: Vec<Float>,
+    squared_deviations: Vec<Float>,
+}
+
+impl RunningStats {
+    fn new(cols: usize) -> Self {
+        RunningStats {
+            count: 0,
+            mean: vec![0.0; cols],
+            squared_deviations: vec![0.0; cols],
+        }
+    }
+
+    fn update(&mut self, rows: &Arr) {
+        for row in rows.genrows() {
+            self.count += 1;
+            let n = self.count as Float;
+
+            for (mean, squared_deviations, &x) in
+                izip!(&mut self.mean, &mut self.squared_deviations, row.iter())
+            {
+                let delta = x - *mean;
+                *mean += delta / n;
+                *squared_deviations += delta * (x - *mean);
+            }
+        }
+    }
+
+    fn variance(&self) -> Vec<Float> {
+        let n = ::std::cmp::max(self.count, 1) as Float;
+        self.squared_deviations.iter().map(|x| x / n).collect()
+    }
+}
+
+fn normalize_running(
+    dest: &mut Arr,
+    inverse_std: &mut [Float],
+    stats: &RunningStats,
+    source: &Arr,
+    variance_epsilon: Float,
+) {
+    for (inverse_std, variance) in inverse_std.iter_mut().zip(stats.variance()) {
+        *inverse_std = 1.0 / (variance + variance_epsilon).sqrt();
+    }
+
+    for (mut dest, source) in dest.genrows_mut().into_iter().zip(source.genrows()) {
+        for (dest, &x, &mean, &inverse_std) in izip!(
+            dest.iter_mut(),
+            source.iter(),
+            stats.mean.iter(),
+            inverse_std.iter()
+        ) {
+            *dest = (x - mean) * inverse_std;
+        }
+    }
+}
+
+/// Normalizes each column of its input by the running mean and variance
+/// of all the rows it has seen, as in online normalization of a stream
+/// processed a row or a batch at a time.
+///
+/// The statistics are updated with the input's rows on every evaluated
+/// forward pass, before normalizing. As with other nodes, a pass is only
+/// evaluated once until the gradient is zeroed: reaching the node through
+/// several paths, or calling `forward` again without `zero_gradient`,
+/// does not count the input twice. The backward pass treats the
+/// statistics as constants.
+///
+/// The stability variance epsilon is added to the running variances.
+#[derive(Debug)]
+pub struct RunningNormNode<OP> {
+    value: RefCell<Arr>,
+    stats: RefCell<RunningStats>,
+    inverse_std: RefCell<Vec<Float>>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    variance_epsilon: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> RunningNormNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let (n, d) = operand.value().dim();
+
+        // The statistics only count the rows of evaluated forward passes;
+        // the initial value previews those of the first one.
+        let stats = RunningStats::new(d);
+        let mut preview = stats.clone();
+        preview.update(&operand.value());
+
+        let variance_epsilon = stability().variance_epsilon;
+        let mut value = Arr::zeros((n, d));
+        let mut inverse_std = vec![0.0; d];
+        normalize_running(
+            &mut value,
+            &mut inverse_std,
+            &preview,
+            operand.value().deref(),
+            variance_epsilon,
+        );
+
+        let needs_gradient = operand.needs_gradient();
+        let operand_gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        RunningNormNode {
+            value: RefCell::new(value),
+            stats: RefCell::new(stats),
+            inverse_std: RefCell::new(inverse_std),
+            operand_gradient: RefCell::new(operand_gradient),
+            operand: operand,
+            variance_epsilon: variance_epsilon,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+
+    /// The number of rows the statistics were computed over.
+    pub(crate) fn count(&self) -> usize {
+        self.stats.borrow().count
+    }
+
+    /// The running mean of each column, as a `(1, d)` array.
+    pub(crate) fn mean(&self) -> Arr {
+        let mean = &self.stats.borrow().mean;
+        Arr::from_shape_vec((1, mean.len()), mean.clone()).unwrap()
+    }
+
+    /// The running (population) variance of each column, as a
+    /// `(1, d)` array.
+    pub(crate) fn variance(&self) -> Arr {
+        let variance = self.stats.borrow().variance();
+        Arr::from_shape_vec((1, variance.len()), variance).unwrap()
+    }
+
+    /// Forget the rows seen so far.
+    pub(crate) fn reset(&self) {
+        let cols = self.inverse_std.borrow().len();
+        *self.stats.borrow_mut() = RunningStats::new(cols);
+    }
+}
+
+impl<OP> Node for RunningNormNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let operand_value = self.operand.value();
+        let mut stats = self.stats.borrow_mut();
+        stats.update(&operand_value);
+
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, operand_value.dim());
+
+        normalize_running(
+            value.deref_mut(),
+            &mut self.inverse_std.borrow_mut(),
+            &stats,
+            operand_value.deref(),
+            self.variance_epsilon,
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let inverse_std = self.inverse_std.borrow();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            fit_shape(&mut operand_gradient, gradient.dim());
+
+            for (mut dest, grad) in operand_gradient
+                .genrows_mut()
+                .into_iter()
+                .zip(gradient.genrows())
+            {
+                for (dest, &g, &inverse_std) in
+                    izip!(dest.iter_mut(), grad.iter(), inverse_std.iter())
+                {
+                    *dest = beta * *dest + g * inverse_std;
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("RunningNorm").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+#[derive(Debug)]
+pub struct SquareNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> SquareNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let mut value = arena::zeros(operand.value().dim());
+        numerics::map_assign(&mut value, &operand.value(), |x| x.powi(2));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        SquareNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Drop for SquareNode<OP> {
+    fn drop(&mut self) {
+        arena::recycle(self.value.get_mut());
+        arena::recycle(self.operand_gradient.get_mut());
+    }
+}
+
+impl<OP> Node for SquareNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+        self.operand.forward();
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+
+        dest.assign(self.operand.value().deref());
+        dest.map_inplace(|x| *x = x.powi(2));
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        match self.counter.backward() {
+            BackwardAction::Set => for (dest, operand_val, grad_val) in izip!(
+                self.operand_gradient.borrow_mut().iter_mut(),
+                self.operand.value().iter(),
+                gradient.iter()
+            ) {
+                *dest = operand_val * 2.0 * grad_val;
+            },
+            BackwardAction::Increment => for (dest, operand_val, grad_val) in izip!(
+                self.operand_gradient.borrow_mut().iter_mut(),
+                self.operand.value().iter(),
+                gradient.iter()
+            ) {
+                *dest += operand_val * 2.0 * grad_val;
+            },
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        Ok(builder
+            .tangent(&self.operand)?
+            .map(|x| (tangent_operand(&self.operand) * x * 2.0).boxed()))
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.square().boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        Ok(builder.step(Op::Square, vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Square").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+#[derive(Debug)]
+pub struct LogNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> LogNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let value = operand.value().map(|&x| numerics::ln(x));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        LogNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+
+    fn compute(&self) {
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+
+        dest.assign(self.operand.value().deref());
+        dest.map_inplace(|x| *x = numerics::ln(*x));
+    }
+}
+
+impl<OP> Node for LogNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        self.compute();
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        self.accumulate(gradient);
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, uses: usize) {
+        if self.counter.forward_many(uses) == ForwardAction::Cached {
+            return;
+        }
+
+        self.compute();
+    }
+    fn accumulate(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        match self.counter.backward() {
+            BackwardAction::Set => for (dest, operand_val, grad_val) in izip!(
+                self.operand_gradient.borrow_mut().iter_mut(),
+                self.operand.value().iter(),
+                gradient.iter()
+            ) {
+                *dest = grad_val / operand_val;
+            },
+            BackwardAction::Increment => for (dest, operand_val, grad_val) in izip!(
+                self.operand_gradient.borrow_mut().iter_mut(),
+                self.operand.value().iter(),
+                gradient.iter()
+            ) {
+                *dest += grad_val / operand_val;
+            },
+        }
+    }
+    fn propagate(&self) {
+        if self.counter.recurse_backward() {
+            self.operand.accumulate(&self.operand_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.ln().boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        Ok(builder.step(Op::Log, vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Log").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+#[derive(Debug)]
+pub struct TanhNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> TanhNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let mut value = arena::zeros(operand.value().dim());
+        numerics::map_assign(&mut value, &operand.value(), numerics::tanh);
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        TanhNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Drop for TanhNode<OP> {
+    fn drop(&mut self) {
+        arena::recycle(self.value.get_mut());
+        arena::recycle(self.operand_gradient.get_mut());
+    }
+}
+
+impl<OP> Node for TanhNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+        numerics::tanh_slice(dest.fast_slice_mut(), self.operand.value().fast_slice());
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        match self.counter.backward() {
+            BackwardAction::Set => numerics::map_assign_binary(
+                &mut self.operand_gradient.borrow_mut(),
+                self.value.borrow().deref(),
+                gradient,
+                |value, grad| grad * (1.0 - value.powi(2)),
+            ),
+            BackwardAction::Increment => numerics::map_inplace_assign_binary(
+                &mut self.operand_gradient.borrow_mut(),
+                self.value.borrow().deref(),
+                gradient,
+                |dest, value, grad| *dest += grad * (1.0 - value.powi(2)),
+            ),
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        Ok(builder.tangent(&self.operand)?.map(|x| {
+            let tanh = tangent_operand(&self.operand).tanh();
+            (x * (1.0 - tanh.square())).boxed()
+        }))
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.tanh().boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        Ok(builder.step(Op::Tanh, vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Tanh").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// Element-wise tanh-shrink `x - tanh(x)`, whose derivative is
+/// `tanh(x)^2`. The tanh of the last forward pass is kept for the
+/// backward pass.
+#[derive(Debug)]
+pub struct TanhShrinkNode<OP> {
+    value: RefCell<Arr>,
+    tanh: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+/// Coefficients of `x^3, x^5, ...` in the series of `x - tanh(x)`.
+const TANH_SHRINK_SERIES: [Float; 6] = [
+    1.0 / 3.0,
+    -2.0 / 15.0,
+    17.0 / 315.0,
+    -62.0 / 2835.0,
+    1382.0 / 155_925.0,
+    -21_844.0 / 6_081_075.0,
+];
+
+/// Below this magnitude, `x - tanh(x)` loses most of its digits to
+/// cancellation, and the series is accurate to single precision.
+const TANH_SHRINK_SERIES_MAX: Float = 0.5;
+
+fn tanh_shrink(value: &mut Arr, tanh: &mut Arr, operand: &Arr) {
+    numerics::tanh_slice(tanh.fast_slice_mut(), operand.fast_slice());
+
+    for (dest, &x, &tanh) in izip!(
+        value.fast_slice_mut(),
+        operand.fast_slice(),
+        tanh.fast_slice()
+    ) {
+        *dest = if x.abs() < TANH_SHRINK_SERIES_MAX {
+            let square = x * x;
+            let series = TANH_SHRINK_SERIES
+                .iter()
+                .rev()
+                .fold(0.0, |acc, &c| acc * square + c);
+            series * square * x
+        } else {
+            x - tanh
+        };
+    }
+}
+
+impl<OP> TanhShrinkNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let mut value = Arr::zeros(operand.value().dim());
+        let mut tanh = Arr::zeros(operand.value().dim());
+        tanh_shrink(&mut value, &mut tanh, &operand.value());
+
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        TanhShrinkNode {
+            value: RefCell::new(value),
+            tanh: RefCell::new(tanh),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for TanhShrinkNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let operand_value = self.operand.value();
+        let mut value = self.value.borrow_mut();
+        let mut tanh = self.tanh.borrow_mut();
+        fit_shape(&mut value, operand_value.dim());
+        fit_shape(&mut tanh, operand_value.dim());
+
+        tanh_shrink(&mut value, &mut tanh, &operand_value);
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let tanh = self.tanh.borrow();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            fit_shape(&mut operand_gradient, tanh.dim());
+
+            for (dest, &tanh, &grad) in izip!(
+                operand_gradient.fast_slice_mut(),
+                tanh.fast_slice(),
+                gradient.fast_slice()
+            ) {
+                *dest = beta * *dest + grad * tanh.powi(2);
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.tanh_shrink().boxed()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("TanhShrink").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+#[derive(Debug)]
+pub struct SigmoidNode<T> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<T>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<T> SigmoidNode<T>
+where
+    T: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<T>) -> Self {
+        let mut value = arena::zeros(operand.value().dim());
+        numerics::map_assign(&mut value, &operand.value(), numerics::sigmoid);
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        SigmoidNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+
+    fn compute(&self) {
+        {
+            let mut dest = self.value.borrow_mut();
+            fit_shape(&mut dest, self.operand.value().dim());
+
+            numerics::sigmoid_slice(dest.fast_slice_mut(), self.operand.value().fast_slice());
+        }
+    }
+}
+
+impl<T> Drop for SigmoidNode<T> {
+    fn drop(&mut self) {
+        arena::recycle(self.value.get_mut());
+        arena::recycle(self.operand_gradient.get_mut());
+    }
+}
+
+impl<T> Node for SigmoidNode<T>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        self.compute();
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        self.accumulate(gradient);
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow())
+        }
+    }
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, uses: usize) {
+        if self.counter.forward_many(uses) == ForwardAction::Cached {
+            return;
+        }
+
+        self.compute();
+    }
+    fn accumulate(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+                numerics::map_assign_binary(
+                    &mut operand_gradient,
+                    self.value.borrow().deref(),
+                    gradient,
+                    |sigmoid, grad| grad * sigmoid * (1.0 - sigmoid),
+                );
+            }
+            BackwardAction::Increment => {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+                numerics::map_inplace_assign_binary(
+                    &mut operand_gradient,
+                    self.value.borrow().deref(),
+                    gradient,
+                    |dest, sigmoid, grad| *dest += grad * sigmoid * (1.0 - sigmoid),
+                );
+            }
+        }
+    }
+    fn propagate(&self) {
+        if self.counter.recurse_backward() {
+            self.operand.accumulate(&self.operand_gradient.borrow())
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        Ok(builder.tangent(&self.operand)?.map(|x| {
+            let sigmoid = tangent_operand(&self.operand).sigmoid();
+            (x * (sigmoid.clone() - sigmoid.square())).boxed()
+        }))
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.sigmoid().boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        Ok(builder.step(Op::Sigmoid, vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Sigmoid").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// Computes `lower + (upper - lower) * sigmoid(x)` element-wise, squashing
+/// its operand into the open interval `(lower, upper)`.
+#[derive(Debug)]
+pub struct BoundedSigmoidNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    lower: Float,
+    upper: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> BoundedSigmoidNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, lower: Float, upper: Float) -> Self {
+        assert!(
+            lower < upper,
+            "Lower bound {} must be below upper bound {}.",
+            lower,
+            upper
+        );
+
+        let range = upper - lower;
+        let value = operand
+            .value()
+            .deref()
+            .map(|&x| lower + range * numerics::sigmoid(x));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        BoundedSigmoidNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            lower: lower,
+            upper: upper,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for BoundedSigmoidNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+        let (lower, range) = (self.lower, self.upper - self.lower);
+
+        numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), |x| {
+            lower + range * numerics::sigmoid(x)
+        });
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let range = self.upper - self.lower;
+            let operand_value = self.operand.value();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            for (dest, &x, &grad) in izip!(
+                operand_gradient.fast_slice_mut(),
+                operand_value.fast_slice(),
+                gradient.fast_slice()
+            ) {
+                let sigmoid = numerics::sigmoid(x);
+                *dest = beta * *dest + grad * range * sigmoid * (1.0 - sigmoid);
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("BoundedSigmoid")
+            .operand(&*self.operand)
+            .attribute("lower", Attribute::Float(self.lower))
+            .attribute("upper", Attribute::Float(self.upper))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// Computes `sigmoid(x - a) - sigmoid(x - b)` element-wise: a smooth bump
+/// close to one between `a` and `b`, and to zero outside of them.
+#[derive(Debug)]
+pub struct BumpNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    a: Float,
+    b: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> BumpNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, a: Float, b: Float) -> Self {
+        assert!(a < b, "Bump start {} must be below bump end {}.", a, b);
+
+        let value = operand.value().deref().map(|&x| bump(x, a, b));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        BumpNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            a: a,
+            b: b,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+fn bump(x: Float, a: Float, b: Float) -> Float {
+    numerics::sigmoid(x - a) - numerics::sigmoid(x - b)
+}
+
+impl<OP> Node for BumpNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+        let (a, b) = (self.a, self.b);
+
+        numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), |x| {
+            bump(x, a, b)
+        });
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let operand_value = self.operand.value();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            for (dest, &x, &grad) in izip!(
+                operand_gradient.fast_slice_mut(),
+                operand_value.fast_slice(),
+                gradient.fast_slice()
+            ) {
+                let rising = numerics::sigmoid(x - self.a);
+                let falling = numerics::sigmoid(x - self.b);
+                let derivative = rising * (1.0 - rising) - falling * (1.0 - falling);
+                *dest = beta * *dest + grad * derivative;
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("Bump")
+            .operand(&*self.operand)
+            .attribute("a", Attribute::Float(self.a))
+            .attribute("b", Attribute::Float(self.b))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+#[derive(Debug)]
+pub struct ReluNode<T> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<T>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<T> ReluNode<T>
+where
+    T: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<T>) -> Self {
+        let mut value = arena::zeros(operand.value().dim());
+        numerics::map_assign(&mut value, &operand.value(), |x| if x < 0.0 { 0.0 } else { x });
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        ReluNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<T> Drop for ReluNode<T> {
+    fn drop(&mut self) {
+        arena::recycle(self.value.get_mut());
+        arena::recycle(self.operand_gradient.get_mut());
+    }
+}
+
+impl<T> Node for ReluNode<T>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+
+        numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), |x| {
+            if x < 0.0 {
+                0.0
+            } else {
+                x
+            }
+        });
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+                numerics::map_assign_binary(
+                    &mut operand_gradient,
+                    self.value.borrow().deref(),
+                    gradient,
+                    |x, grad| if x <= 0.0 { 0.0 } else { grad },
+                );
+            }
+            BackwardAction::Increment => {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+                numerics::map_inplace_assign_binary(
+                    &mut operand_gradient,
+                    self.value.borrow().deref(),
+                    gradient,
+                    |dest, x, grad| *dest += if x <= 0.0 { 0.0 } else { grad },
+                );
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow())
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        Ok(builder.tangent(&self.operand)?.map(|x| {
+            Variable::new(
+                Rc::new(ReluTangentNode::new(Rc::clone(&self.operand), x.node)),
+                Vec::new(),
+            ).boxed()
+        }))
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.relu().boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        Ok(builder.step(Op::Relu, vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Relu").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// The forward-mode tangent of a `ReluNode`: passes the tangent of the
+/// ReLU's operand through wherever the operand is positive.
+#[derive(Debug)]
+pub struct ReluTangentNode<OP, T> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    tangent_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    tangent: Rc<T>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+fn relu_tangent(dest: &mut Arr, operand: &Arr, tangent: &Arr) {
+    for (dest, &x, &tangent) in izip!(
+        dest.fast_slice_mut(),
+        operand.fast_slice(),
+        tangent.fast_slice()
+    ) {
+        *dest = if x > 0.0 { tangent } else { 0.0 };
+    }
+}
+
+impl<OP, T> ReluTangentNode<OP, T>
+where
+    OP: Node<Value = Arr>,
+    T: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, tangent: Rc<T>) -> Self {
+        let mut value = tangent.value().deref() * 0.0;
+        relu_tangent(&mut value, operand.value().deref(), tangent.value().deref());
+
+        let needs_gradient = tangent.needs_gradient();
+        let operand_gradient = gradient_buffer(value.dim(), needs_gradient);
+        let tangent_gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        ReluTangentNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(operand_gradient),
+            tangent_gradient: RefCell::new(tangent_gradient),
+            operand: operand,
+            tangent: tangent,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP, T> Node for ReluTangentNode<OP, T>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        self.tangent.forward();
+
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, self.tangent.value().dim());
+
+        relu_tangent(
+            value.deref_mut(),
+            self.operand.value().deref(),
+            self.tangent.value().deref(),
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let operand_value = self.operand.value();
+            let mut tangent_gradient = self.tangent_gradient.borrow_mut();
+            fit_shape(&mut tangent_gradient, gradient.dim());
+
+            for (dest, &x, &grad) in izip!(
+                tangent_gradient.fast_slice_mut(),
+                operand_value.fast_slice(),
+                gradient.fast_slice()
+            ) {
+                *dest = beta * *dest + if x > 0.0 { grad } else { 0.0 };
+            }
+        }
+
+        // The mask is piecewise constant, so the operand's
+        // gradient is zero almost everywhere.
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+            self.tangent.backward(&self.tangent_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.tangent.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("ReluTangent")
+            .operand(&*self.operand)
+            .operand(&*self.tangent)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow(), self.tangent_gradient.borrow()]
+    }
+}
+
+#[derive(Debug)]
+pub struct SoftsignNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> SoftsignNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let value = operand.value().map(|&x| x / (1.0 + x.abs()));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        SoftsignNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for SoftsignNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+        numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), |x| {
+            x / (1.0 + x.abs())
+        });
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+                numerics::map_assign_binary(
+                    &mut operand_gradient,
+                    self.operand.value().deref(),
+                    gradient,
+                    |x, grad| grad / numerics::pow2(1.0 + x.abs()),
+                );
+            }
+            BackwardAction::Increment => {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+                numerics::map_inplace_assign_binary(
+                    &mut operand_gradient,
+                    self.operand.value().deref(),
+                    gradient,
+                    |dest, x, grad| *dest += grad / numerics::pow2(1.0 + x.abs()),
+                );
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow())
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("Softsign").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+fn hard_swish(x: Float) -> Float {
+    x * clamp(x + 3.0, 0.0, 6.0) / 6.0
+}
+
+fn hard_swish_derivative(x: Float) -> Float {
+    if x < -3.0 {
+        0.0
+    } else if x > 3.0 {
+        1.0
+    } else {
+        (2.0 * x + 3.0) / 6.0
+    }
+}
+
+#[derive(Debug)]
+pub struct HardSwishNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> HardSwishNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let value = operand.value().map(|&x| hard_swish(x));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        HardSwishNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for HardSwishNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+        numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), hard_swish);
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+                numerics::map_assign_binary(
+                    &mut operand_gradient,
+                    self.operand.value().deref(),
+                    gradient,
+                    |x, grad| grad * hard_swish_derivative(x),
+                );
+            }
+            BackwardAction::Increment => {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+                numerics::map_inplace_assign_binary(
+                    &mut operand_gradient,
+                    self.operand.value().deref(),
+                    gradient,
+                    |dest, x, grad| *dest += grad * hard_swish_derivative(x),
+                );
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow())
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("HardSwish").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// The `alpha` of the SELU of self-normalizing networks.
+const SELU_ALPHA: Float = 1.673_263_242_354_377_2_f64 as Float;
+/// The `scale` of the SELU of self-normalizing networks.
+const SELU_SCALE: Float = 1.050_700_987_355_480_5_f64 as Float;
+
+fn selu(x: Float) -> Float {
+    if x > 0.0 {
+        SELU_SCALE * x
+    } else {
+        SELU_SCALE * SELU_ALPHA * (numerics::exp(x) - 1.0)
+    }
+}
+
+fn selu_derivative(x: Float) -> Float {
+    if x > 0.0 {
+        SELU_SCALE
+    } else {
+        SELU_SCALE * SELU_ALPHA * numerics::exp(x)
+    }
+}
+
+/// Computes the scaled exponential linear unit, `scale * x` for positive
+/// inputs and `scale * alpha * (exp(x) - 1)` otherwise, element-wise.
+#[derive(Debug)]
+pub struct SeluNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> SeluNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let value = operand.value().map(|&x| selu(x));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        SeluNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for SeluNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+        numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), selu);
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+                numerics::map_assign_binary(
+                    &mut operand_gradient,
+                    self.operand.value().deref(),
+                    gradient,
+                    |x, grad| grad * selu_derivative(x),
+                );
+            }
+            BackwardAction::Increment => {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+                numerics::map_inplace_assign_binary(
+                    &mut operand_gradient,
+                    self.operand.value().deref(),
+                    gradient,
+                    |dest, x, grad| *dest += grad * selu_derivative(x),
+                );
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow())
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("Selu").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// `2 / sqrt(pi)`, the scale of the derivative of the error function.
+const ERF_SCALE: Float = ::std::f64::consts::FRAC_2_SQRT_PI as Float;
+
+fn erf_derivative(x: Float) -> Float {
+    ERF_SCALE * numerics::exp(-x * x)
+}
+
+/// Computes the error function element-wise.
+#[derive(Debug)]
+pub struct ErfNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> ErfNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let value = operand.value().map(|&x| numerics::erf(x));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        ErfNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for ErfNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+        numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), numerics::erf);
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+                numerics::map_assign_binary(
+                    &mut operand_gradient,
+                    self.operand.value().deref(),
+                    gradient,
+                    |x, grad| grad * erf_derivative(x),
+                );
+            }
+            BackwardAction::Increment => {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+                numerics::map_inplace_assign_binary(
+                    &mut operand_gradient,
+                    self.operand.value().deref(),
+                    gradient,
+                    |dest, x, grad| *dest += grad * erf_derivative(x),
+                );
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow())
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("Erf").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// `sqrt(2 / pi)`, used in the tanh approximation of the GELU.
+const GELU_SCALE: Float = 0.797_884_6;
+/// Coefficient of the cubic term in the tanh approximation of the GELU.
+const GELU_CUBIC: Float = 0.044_715;
+
+fn gelu(x: Float) -> Float {
+    0.5 * x * (1.0 + numerics::tanh(GELU_SCALE * (x + GELU_CUBIC * x.powi(3))))
+}
+
+fn gelu_derivative(x: Float) -> Float {
+    let tanh = numerics::tanh(GELU_SCALE * (x + GELU_CUBIC * x.powi(3)));
+
+    0.5 * (1.0 + tanh)
+        + 0.5 * x * (1.0 - tanh.powi(2)) * GELU_SCALE * (1.0 + 3.0 * GELU_CUBIC * x.powi(2))
+}
+
+/// Splits the columns of its `(n, 2d)` operand in half, yielding the
+/// `(n, d)` product `gelu(a) * b` of the GELU of the first half `a` and
+/// the second half `b`. The GELU uses the tanh approximation.
+#[derive(Debug)]
+pub struct GeGluNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+fn geglu(dest: &mut Arr, operand: &Arr) {
+    let half = dest.cols();
+
+    for (dest_row, operand_row) in dest.genrows_mut().into_iter().zip(operand.genrows()) {
+        let operand_row = operand_row.fast_slice();
+        let (gate, linear) = operand_row.split_at(half);
+
+        for (dest, &a, &b) in izip!(dest_row.into_slice().unwrap(), gate, linear) {
+            *dest = gelu(a) * b;
+        }
+    }
+}
+
+impl<OP> GeGluNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let (rows, cols) = operand.value().dim();
+        assert!(
+            cols % 2 == 0,
+            "GeGLU requires an even number of columns, got {}.",
+            cols
+        );
+
+        let mut value = Arr::zeros((rows, cols / 2));
+        geglu(&mut value, operand.value().deref());
+
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(operand.value().dim(), needs_gradient);
+
+        GeGluNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for GeGluNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let operand_value = self.operand.value();
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, (operand_value.rows(), operand_value.cols() / 2));
+
+        geglu(value.deref_mut(), operand_value.deref());
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let operand_value = self.operand.value();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            let half = gradient.cols();
+
+            for (dest_row, operand_row, grad_row) in izip!(
+                operand_gradient.genrows_mut(),
+                operand_value.genrows(),
+                gradient.genrows()
+            ) {
+                let (gate, linear) = operand_row.fast_slice().split_at(half);
+                let (gate_dest, linear_dest) = dest_row.into_slice().unwrap().split_at_mut(half);
+
+                for (gate_dest, linear_dest, &a, &b, &grad) in izip!(
+                    gate_dest,
+                    linear_dest,
+                    gate,
+                    linear,
+                    grad_row.fast_slice()
+                ) {
+                    *gate_dest = beta * *gate_dest + grad * b * gelu_derivative(a);
+                    *linear_dest = beta * *linear_dest + grad * gelu(a);
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow())
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("GeGlu").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+#[derive(Debug)]
+pub struct NegNode<T> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<T>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<T> NegNode<T>
+where
+    T: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<T>) -> Self {
+        let mut value = arena::zeros(operand.value().dim());
+        numerics::map_assign(&mut value, &operand.value(), |x| -x);
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        NegNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+
+    fn compute(&self) {
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+
+        dest.assign(self.operand.value().deref());
+        dest.map_inplace(|x| *x = -*x);
+    }
+}
+
+impl<T> Drop for NegNode<T> {
+    fn drop(&mut self) {
+        arena::recycle(self.value.get_mut());
+        arena::recycle(self.operand_gradient.get_mut());
+    }
+}
+
+impl<T> Node for NegNode<T>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        self.compute();
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        self.accumulate(gradient);
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, uses: usize) {
+        if self.counter.forward_many(uses) == ForwardAction::Cached {
+            return;
+        }
+
+        self.compute();
+    }
+    fn accumulate(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        match self.counter.backward() {
+            BackwardAction::Set => for (dest, grad_val) in izip!(
+                self.operand_gradient.borrow_mut().iter_mut(),
+                gradient.iter()
+            ) {
+                *dest = -grad_val;
+            },
+            BackwardAction::Increment => for (dest, grad_val) in izip!(
+                self.operand_gradient.borrow_mut().iter_mut(),
+                gradient.iter()
+            ) {
+                *dest += -grad_val;
+            },
+        }
+    }
+    fn propagate(&self) {
+        if self.counter.recurse_backward() {
+            self.operand.accumulate(&self.operand_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        Ok(builder.tangent(&self.operand)?.map(|x| (-x).boxed()))
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some((-operand).boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        Ok(builder.step(Op::Neg, vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Neg").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+#[derive(Debug)]
+pub struct ExpNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> ExpNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let mut value = arena::zeros(operand.value().dim());
+        numerics::map_assign(&mut value, &operand.value(), numerics::exp);
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        ExpNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Drop for ExpNode<OP> {
+    fn drop(&mut self) {
+        arena::recycle(self.value.get_mut());
+        arena::recycle(self.operand_gradient.get_mut());
+    }
+}
+
+impl<OP> Node for ExpNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+
+        numerics::exp_slice(dest.fast_slice_mut(), self.operand.value().fast_slice());
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        match self.counter.backward() {
+            BackwardAction::Set => for (dest, self_val, grad_val) in izip!(
+                self.operand_gradient.borrow_mut().iter_mut(),
+                self.value.borrow().iter(),
+                gradient.iter()
+            ) {
+                *dest = self_val * grad_val;
+            },
+            BackwardAction::Increment => for (dest, self_val, grad_val) in izip!(
+                self.operand_gradient.borrow_mut().iter_mut(),
+                self.value.borrow().iter(),
+                gradient.iter()
+            ) {
+                *dest += self_val * grad_val;
+            },
+        }
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.exp().boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        Ok(builder.step(Op::Exp, vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Exp").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
     }
 }
 
-unsafe impl Sync for HogwildParameter {}
+/// Computes `base^x` element-wise, as `exp(x * ln(base))`.
+#[derive(Debug)]
+pub struct ExpBaseNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    base: Float,
+    ln_base: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
 
-/// Struct used to hold parameters that need to be shared among
-/// multiple `ParameterNode`s for asynchronous, parallel optimization.
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct HogwildParameter {
-    pub value: RefCell<Arr>,
-    pub squared_gradients: RefCell<Arr>,
-    pub moments: RefCell<Arr>,
-    num_updates: Cell<i32>,
+impl<OP> ExpBaseNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, base: Float) -> Self {
+        assert!(base > 0.0, "Base must be positive.");
+
+        let ln_base = numerics::ln(base);
+        let value = operand
+            .value()
+            .deref()
+            .map(|&x| numerics::exp(x * ln_base));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        ExpBaseNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            base: base,
+            ln_base: ln_base,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
 }
 
-#[cfg_attr(feature = "cargo-clippy", allow(mut_from_ref))]
-impl HogwildParameter {
-    /// Create a new parameter object.
-    pub fn new(value: Arr) -> Self {
-        let squared_gradients = &value * 0.0;
-        let moments = &value * 0.0;
+impl<OP> Node for ExpBaseNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
 
-        HogwildParameter {
+        self.operand.forward();
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+        let ln_base = self.ln_base;
+
+        dest.assign(self.operand.value().deref());
+        dest.map_inplace(|x| *x = numerics::exp(*x * ln_base));
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        let ln_base = self.ln_base;
+
+        match self.counter.backward() {
+            BackwardAction::Set => for (dest, self_val, grad_val) in izip!(
+                self.operand_gradient.borrow_mut().iter_mut(),
+                self.value.borrow().iter(),
+                gradient.iter()
+            ) {
+                *dest = self_val * ln_base * grad_val;
+            },
+            BackwardAction::Increment => for (dest, self_val, grad_val) in izip!(
+                self.operand_gradient.borrow_mut().iter_mut(),
+                self.value.borrow().iter(),
+                gradient.iter()
+            ) {
+                *dest += self_val * ln_base * grad_val;
+            },
+        }
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("ExpBase")
+            .operand(&*self.operand)
+            .attribute("base", Attribute::Float(self.base))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// Element-wise pseudo-Huber function, a smooth approximation
+/// of the absolute value.
+#[derive(Debug)]
+pub struct PseudoHuberNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    delta: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+fn pseudo_huber(x: Float, delta: Float) -> Float {
+    let scaled = x / delta;
+    delta * delta * ((1.0 + scaled * scaled).sqrt() - 1.0)
+}
+
+fn pseudo_huber_derivative(x: Float, delta: Float) -> Float {
+    let scaled = x / delta;
+    x / (1.0 + scaled * scaled).sqrt()
+}
+
+impl<OP> PseudoHuberNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, delta: Float) -> Self {
+        assert!(delta > 0.0, "Delta must be positive.");
+
+        let value = operand.value().deref().map(|&x| pseudo_huber(x, delta));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        PseudoHuberNode {
             value: RefCell::new(value),
-            squared_gradients: RefCell::new(squared_gradients),
-            moments: RefCell::new(moments),
-            num_updates: Cell::new(0),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            delta: delta,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
         }
     }
+}
 
-    pub fn value(&self) -> &Arr {
-        unsafe { &*(self.value.as_ptr()) }
+impl<OP> Node for PseudoHuberNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+        let delta = self.delta;
+
+        dest.assign(self.operand.value().deref());
+        dest.map_inplace(|x| *x = pseudo_huber(*x, delta));
     }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
 
-    pub fn squared_gradients(&self) -> &Arr {
-        unsafe { &*(self.squared_gradients.as_ptr()) }
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        let delta = self.delta;
+
+        match self.counter.backward() {
+            BackwardAction::Set => for (dest, &x, grad_val) in izip!(
+                self.operand_gradient.borrow_mut().iter_mut(),
+                self.operand.value().iter(),
+                gradient.iter()
+            ) {
+                *dest = pseudo_huber_derivative(x, delta) * grad_val;
+            },
+            BackwardAction::Increment => for (dest, &x, grad_val) in izip!(
+                self.operand_gradient.borrow_mut().iter_mut(),
+                self.operand.value().iter(),
+                gradient.iter()
+            ) {
+                *dest += pseudo_huber_derivative(x, delta) * grad_val;
+            },
+        }
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
     }
 
-    pub(crate) unsafe fn value_mut(&self) -> &mut Arr {
-        &mut *(self.value.as_ptr())
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("PseudoHuber")
+            .operand(&*self.operand)
+            .attribute("delta", Attribute::Float(self.delta))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// Element-wise soft-threshold `sign(x) * max(|x| - lambda, 0)`, the
+/// proximal operator of the L1 norm.
+#[derive(Debug)]
+pub struct SoftThresholdNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    lambda: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+fn soft_threshold(x: Float, lambda: Float) -> Float {
+    if x > lambda {
+        x - lambda
+    } else if x < -lambda {
+        x + lambda
+    } else {
+        0.0
+    }
+}
+
+impl<OP> SoftThresholdNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, lambda: Float) -> Self {
+        assert!(lambda >= 0.0, "Lambda must be non-negative.");
+
+        let value = operand.value().deref().map(|&x| soft_threshold(x, lambda));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        SoftThresholdNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            lambda: lambda,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for SoftThresholdNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+        let lambda = self.lambda;
+
+        numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), |x| {
+            soft_threshold(x, lambda)
+        });
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let lambda = self.lambda;
+            let operand_value = self.operand.value();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            // The gradient is zero inside the dead zone, breakpoints included.
+            for (dest, &x, &grad) in izip!(
+                operand_gradient.fast_slice_mut(),
+                operand_value.fast_slice(),
+                gradient.fast_slice()
+            ) {
+                *dest = beta * *dest + if x.abs() > lambda { grad } else { 0.0 };
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
     }
-
-    pub(crate) unsafe fn squared_gradient_mut(&self) -> &mut Arr {
-        &mut *(self.squared_gradients.as_ptr())
+    fn describe(&self) -> Description {
+        Description::new("SoftThreshold")
+            .operand(&*self.operand)
+            .attribute("lambda", Attribute::Float(self.lambda))
     }
-
-    pub(crate) unsafe fn moments_mut(&self) -> &mut Arr {
-        &mut *(self.moments.as_ptr())
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
     }
-
-    pub(crate) unsafe fn num_updates_mut(&self) -> &mut i32 {
-        &mut *(self.num_updates.as_ptr())
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
     }
 }
 
-/// Parameter node, holds the optimizable parameters of the model.
+/// Element-wise `min(x, ceiling)` in the forward pass, passing the
+/// gradient through unchanged in the backward pass, as a straight-through
+/// estimator: values above the ceiling are clipped but still learn.
 #[derive(Debug)]
-pub struct ParameterNode {
-    pub(crate) value: Arc<HogwildParameter>,
-    pub(crate) gradient: RefCell<GradientAccumulator>,
+pub struct ScalarMinSteNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    ceiling: Float,
+    needs_gradient: bool,
+    counter: PassCounter,
 }
 
-impl ParameterNode {
-    /// Create a parameter node that shares its parameter values
-    /// with other parameter nodes via the `HogwildParameter` object.
-    pub fn shared(value: Arc<HogwildParameter>) -> Variable<Self> {
-        let shape = unsafe {
-            // This method can be called in multiple threads, so borrowing
-            // (even immutably) will read to borrow failures.
-            (
-                (*value.value.as_ptr()).rows(),
-                (*value.value.as_ptr()).cols(),
-            )
-        };
-
-        let node = Rc::new(ParameterNode {
-            value: value,
-            gradient: RefCell::new(GradientAccumulator::new(shape)),
-        });
-        let params = vec![Rc::clone(&node)];
-
-        Variable::new(node, params)
-    }
-    /// Create a new parameter node. The parameters held by this node
-    /// cannot be shared and optimized in parallel.
-    pub fn new(value: Arr) -> Variable<Self> {
-        let shape = (value.rows(), value.cols());
-
-        let node = Rc::new(ParameterNode {
-            value: Arc::new(HogwildParameter::new(value)),
-            gradient: RefCell::new(GradientAccumulator::new(shape)),
-        });
-        let params = vec![Rc::clone(&node)];
+impl<OP> ScalarMinSteNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, ceiling: Float) -> Self {
+        let value = operand.value().deref().map(|&x| x.min(ceiling));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
 
-        Variable::new(node, params)
+        ScalarMinSteNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            ceiling: ceiling,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
     }
-    // /// Zero the accumulated gradients of this node.
-    // pub fn zero_gradient(&self) {
-    //     //self.gradient.borrow_mut().zero_gradient();
-    // }
 }
 
-impl Node for ParameterNode {
+impl<OP> Node for ScalarMinSteNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
     type Value = Arr;
     type InputGradient = Arr;
-    fn forward(&self) {}
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
+        let ceiling = self.ceiling;
+
+        numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), |x| {
+            x.min(ceiling)
+        });
+    }
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        self.gradient.borrow_mut().accumulate_gradient(gradient);
+        if !self.needs_gradient {
+            return;
+        }
+
+        {
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            match self.counter.backward() {
+                BackwardAction::Set => {
+                    fit_shape(&mut operand_gradient, gradient.dim());
+                    operand_gradient.slice_assign(gradient.deref());
+                }
+                BackwardAction::Increment => {
+                    operand_gradient.slice_add_assign(gradient.deref());
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
     }
     fn value(&self) -> Bor<Self::Value> {
-        Bor::Reference(unsafe { &*(self.value.value.as_ptr() as *const Arr) })
+        Bor::RefGuard(self.value.borrow())
     }
     fn needs_gradient(&self) -> bool {
-        true
+        self.needs_gradient
     }
     fn zero_gradient(&self) {
-        self.gradient.borrow_mut().zero_gradient();
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        Description::new("ScalarMinSte")
+            .operand(&*self.operand)
+            .attribute("ceiling", Attribute::Float(self.ceiling))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
     }
 }
 
+/// How gradients flow through a `OneHotArgmaxNode`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArgmaxGradient {
+    /// The selection is a constant: the scores receive no gradient.
+    Blocked,
+    /// The score at the selected position of each row receives the
+    /// gradient of that position, as if the selection were the identity.
+    StraightThrough,
+}
+
+/// Replaces each row of scores with a one-hot row selecting its
+/// maximum, as in a hard attention readout. Ties go to the first
+/// maximum.
 #[derive(Debug)]
-pub struct SubNode<LHS, RHS>
-where
-    LHS: Node<Value = Arr, InputGradient = Arr>,
-    RHS: Node<Value = Arr, InputGradient = Arr>,
-{
+pub struct OneHotArgmaxNode<OP> {
     value: RefCell<Arr>,
-    lhs_gradient: RefCell<Arr>,
-    rhs_gradient: RefCell<Arr>,
-    lhs: Rc<LHS>,
-    rhs: Rc<RHS>,
+    argmax: RefCell<Vec<usize>>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    gradient_mode: ArgmaxGradient,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<LHS, RHS> SubNode<LHS, RHS>
+fn one_hot_argmax(dest: &mut Arr, argmax: &mut Vec<usize>, operand: &Arr) {
+    dest.fill(0.0);
+    argmax.clear();
+
+    for (mut dest_row, row) in dest.genrows_mut().into_iter().zip(operand.genrows()) {
+        let idx = row_argmax(row.iter());
+
+        dest_row[idx] = 1.0;
+        argmax.push(idx);
+    }
+}
+
+/// The index of the first maximum of `row`.
+fn row_argmax<'a, I>(row: I) -> usize
 where
-    LHS: Node<Value = Arr, InputGradient = Arr>,
-    RHS: Node<Value = Arr, InputGradient = Arr>,
+    I: Iterator<Item = &'a Float>,
 {
-    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
-        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
-        let value = lhs.value().deref() - rhs.value().deref();
+    row.enumerate()
+        .fold((0, Float::MIN), |(idx, max), (i, &x)| {
+            if x > max {
+                (i, x)
+            } else {
+                (idx, max)
+            }
+        })
+        .0
+}
+
+impl<OP> OneHotArgmaxNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, gradient_mode: ArgmaxGradient) -> Self {
+        let mut value = Arr::zeros(operand.value().dim());
+        let mut argmax = Vec::new();
+        one_hot_argmax(&mut value, &mut argmax, operand.value().deref());
 
-        let rhs_gradient = rhs.value().deref() * 0.0;
-        let lhs_gradient = lhs.value().deref() * 0.0;
+        let needs_gradient =
+            gradient_mode == ArgmaxGradient::StraightThrough && operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), operand.needs_gradient());
 
-        SubNode {
+        OneHotArgmaxNode {
             value: RefCell::new(value),
-            rhs_gradient: RefCell::new(rhs_gradient),
-            lhs_gradient: RefCell::new(lhs_gradient),
-            lhs: lhs,
-            rhs: rhs,
+            argmax: RefCell::new(argmax),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            gradient_mode: gradient_mode,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl<LHS, RHS> Node for SubNode<LHS, RHS>
+impl<OP> Node for OneHotArgmaxNode<OP>
 where
-    LHS: Node<Value = Arr, InputGradient = Arr>,
-    RHS: Node<Value = Arr, InputGradient = Arr>,
+    OP: Node<Value = Arr, InputGradient = Arr>,
 {
     type Value = Arr;
     type InputGradient = Arr;
@@ -748,49 +9711,44 @@ where
             return;
         }
 
-        self.lhs.forward();
-        self.rhs.forward();
+        self.operand.forward();
 
         let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
 
-        numerics::sub(
-            self.lhs.value().deref(),
-            self.rhs.value().deref(),
+        one_hot_argmax(
             dest.deref_mut(),
+            &mut self.argmax.borrow_mut(),
+            self.operand.value().deref(),
         );
     }
-
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => {
-                let mut rhs_gradient = self.rhs_gradient.borrow_mut();
-
-                numerics::simd_scaled_assign(
-                    rhs_gradient.as_slice_mut().unwrap(),
-                    gradient.as_slice().unwrap(),
-                    -1.0,
-                );
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
 
-                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
 
-                numerics::simd_scaled_assign(
-                    lhs_gradient.as_slice_mut().unwrap(),
-                    gradient.as_slice().unwrap(),
-                    1.0,
-                );
-            }
-            BackwardAction::Increment => {
-                let mut rhs_gradient = self.rhs_gradient.borrow_mut();
-                rhs_gradient.slice_sub_assign(gradient.deref());
+        // In blocked mode, the operand still receives an all-zero
+        // gradient to keep its pass counter consistent.
+        if self.gradient_mode == ArgmaxGradient::StraightThrough {
+            let argmax = self.argmax.borrow();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
 
-                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
-                lhs_gradient.slice_add_assign(gradient.deref());
+            for ((mut dest, grad), &idx) in operand_gradient
+                .genrows_mut()
+                .into_iter()
+                .zip(gradient.genrows())
+                .zip(argmax.iter())
+            {
+                dest.map_inplace(|x| *x *= beta);
+                dest[idx] += grad[idx];
             }
         }
 
         if self.counter.recurse_backward() {
-            self.lhs.backward(&self.lhs_gradient.borrow());
-            self.rhs.backward(&self.rhs_gradient.borrow());
+            self.operand.backward(&self.operand_gradient.borrow());
         }
     }
     fn value(&self) -> Bor<Self::Value> {
@@ -801,52 +9759,207 @@ where
     }
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
-            self.lhs.zero_gradient();
-            self.rhs.zero_gradient();
+            self.operand.zero_gradient();
+            self.counter.clear();
+        }
+    }
+    fn describe(&self) -> Description {
+        let straight_through = self.gradient_mode == ArgmaxGradient::StraightThrough;
+
+        Description::new("OneHotArgmax")
+            .operand(&*self.operand)
+            .attribute("straight_through", Attribute::Int(straight_through as i64))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// A generator seeded with `seed`, so that sampling is reproducible.
+fn seeded_rng(seed: u64) -> XorShiftRng {
+    let words = [seed, !seed.rotate_left(32)];
+    let mut bytes = [0; 16];
+
+    for (idx, byte) in bytes.iter_mut().enumerate() {
+        *byte = (words[idx / 8] >> (8 * (idx % 8))) as u8;
+    }
+
+    XorShiftRng::from_seed(bytes)
+}
+
+/// Draw one column index per row of `probabilities`, each row a
+/// categorical distribution.
+fn sample_categorical<R: Rng>(dest: &mut SmallVec<[usize; 4]>, probabilities: &Arr, rng: &mut R) {
+    dest.clear();
+
+    for row in probabilities.genrows() {
+        let threshold: Float = rng.gen();
+        let mut cumulative = 0.0;
+
+        // Rounding may leave the cumulative sum just short of 1.
+        let idx = row
+            .iter()
+            .position(|&probability| {
+                cumulative += probability;
+                cumulative > threshold
+            })
+            .unwrap_or(row.len() - 1);
+
+        dest.push(idx);
+    }
+}
+
+/// Samples one index per row of its operand, from the categorical
+/// distribution given by the softmax of the row, as when choosing the
+/// actions of a policy.
+///
+/// Like `IndexInputNode`, the value is a vector of indices, and no
+/// gradient flows back through the sampling.
+#[derive(Debug)]
+pub struct CategoricalSampleNode<OP> {
+    value: RefCell<SmallVec<[usize; 4]>>,
+    probabilities: RefCell<Arr>,
+    operand: Rc<OP>,
+    rng: RefCell<XorShiftRng>,
+    counter: PassCounter,
+}
+
+impl<OP> CategoricalSampleNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let mut rng = XorShiftRng::from_rng(rand::thread_rng()).unwrap();
+
+        let mut probabilities = Arr::zeros(operand.value().dim());
+        log_softmax_rows(&mut probabilities, &operand.value());
+        probabilities.map_inplace(|x| *x = numerics::exp(*x));
+
+        let mut value = SmallVec::new();
+        sample_categorical(&mut value, &probabilities, &mut rng);
+
+        CategoricalSampleNode {
+            value: RefCell::new(value),
+            probabilities: RefCell::new(probabilities),
+            operand: operand,
+            rng: RefCell::new(rng),
+            counter: PassCounter::default(),
+        }
+    }
+
+    /// The probabilities of each row's categories, as of the last
+    /// forward pass.
+    pub fn probabilities(&self) -> Bor<Arr> {
+        Bor::RefGuard(self.probabilities.borrow())
+    }
+
+    /// Reseed the generator, making the following samples reproducible.
+    pub fn set_seed(&self, seed: u64) {
+        *self.rng.borrow_mut() = seeded_rng(seed);
+    }
+}
+
+impl<OP> Node for CategoricalSampleNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = SmallVec<[usize; 4]>;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut probabilities = self.probabilities.borrow_mut();
+        log_softmax_rows(&mut probabilities, &self.operand.value());
+        probabilities.map_inplace(|x| *x = numerics::exp(*x));
+
+        sample_categorical(
+            &mut self.value.borrow_mut(),
+            &probabilities,
+            &mut *self.rng.borrow_mut(),
+        );
+    }
+    fn backward(&self, _: &Ref<Self::InputGradient>) {}
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        false
+    }
+    fn zero_gradient(&self) {
+        if !self.counter.is_zero() {
+            self.operand.zero_gradient();
             self.counter.clear();
         }
     }
+    fn describe(&self) -> Description {
+        Description::new("CategoricalSample").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
 }
 
+/// Subtracts the maximum of each row from that row, the stabilization
+/// step of softmax as a separate operation.
 #[derive(Debug)]
-pub struct MulNode<LHS, RHS> {
+pub struct SubtractRowMaxNode<OP> {
     value: RefCell<Arr>,
-    lhs_gradient: RefCell<Arr>,
-    rhs_gradient: RefCell<Arr>,
-    lhs: Rc<LHS>,
-    rhs: Rc<RHS>,
+    argmax: RefCell<Vec<usize>>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<LHS, RHS> MulNode<LHS, RHS>
+/// Subtracts the maximum of each row of `operand` from that row, writing
+/// the result to `dest` and the index of each maximum to `argmax`. Ties
+/// go to the first maximum.
+fn subtract_row_max(dest: &mut Arr, argmax: &mut Vec<usize>, operand: &Arr) {
+    dest.assign(operand);
+    argmax.clear();
+
+    for mut row in dest.genrows_mut() {
+        let idx = row_argmax(row.iter());
+        let max = row[idx];
+
+        row.map_inplace(|x| *x -= max);
+        argmax.push(idx);
+    }
+}
+
+impl<OP> SubtractRowMaxNode<OP>
 where
-    LHS: Node<Value = Arr>,
-    RHS: Node<Value = Arr>,
+    OP: Node<Value = Arr>,
 {
-    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
-        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
-        let value = lhs.value().deref() * rhs.value().deref();
+    pub fn new(operand: Rc<OP>) -> Self {
+        let mut value = Arr::zeros(operand.value().dim());
+        let mut argmax = Vec::new();
+        subtract_row_max(&mut value, &mut argmax, operand.value().deref());
 
-        let lhs_gradient = &value * 0.0;
-        let rhs_gradient = &value * 0.0;
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
 
-        MulNode {
+        SubtractRowMaxNode {
             value: RefCell::new(value),
-            lhs_gradient: RefCell::new(lhs_gradient),
-            rhs_gradient: RefCell::new(rhs_gradient),
-            lhs: lhs,
-            rhs: rhs,
+            argmax: RefCell::new(argmax),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl<LHS, RHS> Node for MulNode<LHS, RHS>
+impl<OP> Node for SubtractRowMaxNode<OP>
 where
-    LHS: Node<Value = Arr, InputGradient = Arr>,
-    RHS: Node<Value = Arr, InputGradient = Arr>,
+    OP: Node<Value = Arr, InputGradient = Arr>,
 {
     type Value = Arr;
     type InputGradient = Arr;
@@ -855,56 +9968,49 @@ where
             return;
         }
 
-        self.lhs.forward();
-        self.rhs.forward();
+        self.operand.forward();
 
         let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
 
-        numerics::mul(
-            self.lhs.value().deref(),
-            self.rhs.value().deref(),
+        subtract_row_max(
             dest.deref_mut(),
+            &mut self.argmax.borrow_mut(),
+            self.operand.value().deref(),
         );
     }
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => {
-                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+        if !self.needs_gradient {
+            return;
+        }
 
-                numerics::mul(
-                    self.rhs.value().deref(),
-                    gradient.deref(),
-                    lhs_gradient.deref_mut(),
-                );
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
 
-                let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
 
-                numerics::mul(
-                    self.lhs.value().deref(),
-                    gradient.deref(),
-                    rhs_gradient.deref_mut(),
-                );
-            }
-            BackwardAction::Increment => {
-                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
-                let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+        {
+            let argmax = self.argmax.borrow();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
 
-                numerics::increment_mul(
-                    self.rhs.value().deref(),
-                    gradient.deref(),
-                    lhs_gradient.deref_mut(),
-                );
-                numerics::increment_mul(
-                    self.lhs.value().deref(),
-                    gradient.deref(),
-                    rhs_gradient.deref_mut(),
-                );
+            for ((mut dest, grad), &idx) in operand_gradient
+                .genrows_mut()
+                .into_iter()
+                .zip(gradient.genrows())
+                .zip(argmax.iter())
+            {
+                dest.zip_mut_with(&grad, |dest, &grad| *dest = beta * *dest + grad);
+
+                // The maximum is subtracted from every entry of the row,
+                // so its position also receives minus the row's gradient.
+                dest[idx] -= grad.scalar_sum();
             }
         }
 
         if self.counter.recurse_backward() {
-            self.lhs.backward(&self.lhs_gradient.borrow());
-            self.rhs.backward(&self.rhs_gradient.borrow());
+            self.operand.backward(&self.operand_gradient.borrow());
         }
     }
     fn value(&self) -> Bor<Self::Value> {
@@ -915,52 +10021,73 @@ where
     }
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
-            self.lhs.zero_gradient();
-            self.rhs.zero_gradient();
+            self.operand.zero_gradient();
             self.counter.clear();
         }
     }
+    fn describe(&self) -> Description {
+        Description::new("SubtractRowMax").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
 }
 
-#[derive(Debug)]
-pub struct DivNode<LHS, RHS> {
+/// Applies a user-supplied function element-wise, backpropagating
+/// through its user-supplied derivative. See `Variable::map`.
+pub struct MapNode<OP> {
     value: RefCell<Arr>,
-    lhs_gradient: RefCell<Arr>,
-    rhs_gradient: RefCell<Arr>,
-    lhs: Rc<LHS>,
-    rhs: Rc<RHS>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    name: &'static str,
+    function: Box<Fn(Float) -> Float>,
+    derivative: Box<Fn(Float) -> Float>,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<LHS, RHS> DivNode<LHS, RHS>
+impl<OP> MapNode<OP>
 where
-    LHS: Node<Value = Arr>,
-    RHS: Node<Value = Arr>,
+    OP: Node<Value = Arr>,
 {
-    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
-        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
-        let value = lhs.value().deref() / rhs.value().deref();
-
-        let lhs_gradient = &value * 0.0;
-        let rhs_gradient = &value * 0.0;
+    pub fn new<F, D>(operand: Rc<OP>, name: &'static str, function: F, derivative: D) -> Self
+    where
+        F: Fn(Float) -> Float + 'static,
+        D: Fn(Float) -> Float + 'static,
+    {
+        let value = operand.value().deref().map(|&x| function(x));
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
 
-        DivNode {
+        MapNode {
             value: RefCell::new(value),
-            lhs_gradient: RefCell::new(lhs_gradient),
-            rhs_gradient: RefCell::new(rhs_gradient),
-            lhs: lhs,
-            rhs: rhs,
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            name: name,
+            function: Box::new(function),
+            derivative: Box::new(derivative),
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl<LHS, RHS> Node for DivNode<LHS, RHS>
+impl<OP: fmt::Debug> fmt::Debug for MapNode<OP> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MapNode")
+            .field("name", &self.name)
+            .field("value", &self.value)
+            .field("operand", &self.operand)
+            .finish()
+    }
+}
+
+impl<OP> Node for MapNode<OP>
 where
-    LHS: Node<Value = Arr, InputGradient = Arr>,
-    RHS: Node<Value = Arr, InputGradient = Arr>,
+    OP: Node<Value = Arr, InputGradient = Arr>,
 {
     type Value = Arr;
     type InputGradient = Arr;
@@ -969,172 +10096,138 @@ where
             return;
         }
 
-        self.lhs.forward();
-        self.rhs.forward();
+        self.operand.forward();
 
         let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, self.operand.value().dim());
 
-        numerics::div(
-            self.lhs.value().deref(),
-            self.rhs.value().deref(),
-            dest.deref_mut(),
-        );
+        // User functions need not be `Sync`, so they always run serially.
+        for (dest, &x) in dest
+            .fast_slice_mut()
+            .iter_mut()
+            .zip(self.operand.value().fast_slice())
+        {
+            *dest = (self.function)(x);
+        }
     }
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => {
-                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
-                let rhs_value = self.rhs.value();
-
-                numerics::div(
-                    gradient.deref(),
-                    rhs_value.deref(),
-                    lhs_gradient.deref_mut(),
-                );
-
-                let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+        if !self.needs_gradient {
+            return;
+        }
 
-                izip!(
-                    rhs_gradient.iter_mut(),
-                    self.lhs.value().iter(),
-                    rhs_value.iter(),
-                    gradient.iter()
-                ).for_each(|(dest, lhs_val, rhs_val, grad_val)| {
-                    *dest = -lhs_val / rhs_val.powi(2) * grad_val
-                });
-            }
-            BackwardAction::Increment => {
-                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
-                let rhs_value = self.rhs.value();
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
 
-                numerics::increment_div(
-                    gradient.deref(),
-                    rhs_value.deref(),
-                    lhs_gradient.deref_mut(),
-                );
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
 
-                let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+        {
+            let operand_value = self.operand.value();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
 
-                izip!(
-                    rhs_gradient.iter_mut(),
-                    self.lhs.value().iter(),
-                    rhs_value.iter(),
-                    gradient.iter()
-                ).for_each(|(dest, lhs_val, rhs_val, grad_val)| {
-                    *dest += -lhs_val / rhs_val.powi(2) * grad_val
-                });
+            for (dest, &x, &grad) in izip!(
+                operand_gradient.fast_slice_mut(),
+                operand_value.fast_slice(),
+                gradient.fast_slice()
+            ) {
+                *dest = beta * *dest + grad * (self.derivative)(x);
             }
         }
 
         if self.counter.recurse_backward() {
-            self.lhs.backward(&self.lhs_gradient.borrow());
-            self.rhs.backward(&self.rhs_gradient.borrow());
+            self.operand.backward(&self.operand_gradient.borrow());
         }
     }
-
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
-
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
-
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
-            self.lhs.zero_gradient();
-            self.rhs.zero_gradient();
+            self.operand.zero_gradient();
             self.counter.clear();
         }
     }
+    fn describe(&self) -> Description {
+        Description::new(self.name).operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
 }
 
 #[derive(Debug)]
-pub struct DotNode<LHS, RHS> {
+pub struct TransposeNode<OP> {
     value: RefCell<Arr>,
-    lhs_gradient: RefCell<Arr>,
-    rhs_gradient: RefCell<Arr>,
-    lhs: Rc<LHS>,
-    rhs: Rc<RHS>,
+    gradient: RefCell<Arr>,
+    operand: Rc<OP>,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<LHS, RHS> DotNode<LHS, RHS>
+impl<OP> TransposeNode<OP>
 where
-    LHS: Node<Value = Arr>,
-    RHS: Node<Value = Arr>,
+    OP: Node<Value = Arr>,
 {
-    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
-        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
-        let value = lhs.value().dot(rhs.value().deref());
-
-        let lhs_gradient = lhs.value().deref() * 0.0;
-        let rhs_gradient = rhs.value().deref() * 0.0;
+    pub fn new(operand: Rc<OP>) -> Self {
+        let needs_gradient = operand.needs_gradient();
+        let mut value = Arr::zeros((operand.value().cols(), operand.value().rows()));
+        value.assign(&operand.value().t());
+        let value = RefCell::new(value);
+        let gradient = RefCell::new(gradient_buffer(operand.value().dim(), needs_gradient));
 
-        DotNode {
-            value: RefCell::new(value),
-            lhs_gradient: RefCell::new(lhs_gradient),
-            rhs_gradient: RefCell::new(rhs_gradient),
-            lhs: lhs,
-            rhs: rhs,
+        TransposeNode {
+            value: value,
+            gradient: gradient,
+            operand: operand,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl<LHS, RHS> Node for DotNode<LHS, RHS>
+impl<OP> Node for TransposeNode<OP>
 where
-    LHS: Node<Value = Arr, InputGradient = Arr>,
-    RHS: Node<Value = Arr, InputGradient = Arr>,
+    OP: Node<Value = Arr, InputGradient = Arr>,
 {
     type Value = Arr;
     type InputGradient = Arr;
-
     fn forward(&self) {
         if self.counter.forward() == ForwardAction::Cached {
             return;
         }
 
-        self.lhs.forward();
-        self.rhs.forward();
+        self.operand.forward();
 
-        numerics::mat_mul(
-            1.0,
-            self.lhs.value().deref(),
-            self.rhs.value().deref(),
-            0.0,
-            self.value.borrow_mut().deref_mut(),
-        );
+        let operand_value = self.operand.value();
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, (operand_value.cols(), operand_value.rows()));
+        value.assign(&operand_value.t());
     }
-
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        let beta = match self.counter.backward() {
-            BackwardAction::Set => 0.0,
-            BackwardAction::Increment => 1.0,
-        };
-
-        {
-            let rhs_value = self.rhs.value();
-            let lhs_value = self.lhs.value();
+        if !self.needs_gradient {
+            return;
+        }
 
-            let mut lhs_gradient = self.lhs_gradient.borrow_mut();
-            let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+        fit_shape(&mut self.gradient.borrow_mut(), self.operand.value().dim());
 
-            numerics::mat_mul(1.0, gradient, &rhs_value.t(), beta, &mut lhs_gradient);
-            numerics::mat_mul(
-                1.0,
-                &lhs_value.t(),
-                gradient.deref(),
-                beta,
-                &mut rhs_gradient,
-            );
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                self.gradient.borrow_mut().assign(&gradient.t());
+            }
+            BackwardAction::Increment => {
+                self.gradient.borrow_mut().slice_add_assign(&gradient.t());
+            }
         }
 
         if self.counter.recurse_backward() {
-            self.lhs.backward(&self.lhs_gradient.borrow());
-            self.rhs.backward(&self.rhs_gradient.borrow());
+            self.operand.backward(&self.gradient.borrow());
         }
     }
 
@@ -1145,235 +10238,309 @@ where
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
+
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
-            self.lhs.zero_gradient();
-            self.rhs.zero_gradient();
+            self.operand.zero_gradient();
             self.counter.clear();
         }
     }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.t().boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        Ok(builder.step(Op::Transpose, vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Transpose").operand(&*self.operand)
+    }
+    fn flops(&self) -> Option<usize> {
+        Some(0)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.gradient.borrow()]
+    }
+}
+
+/// Normalize the exponentials of all elements of `operand` to sum to one.
+pub(crate) fn softmax(dest: &mut Arr, operand: &Arr) {
+    fit_shape(dest, operand.dim());
+
+    let max = operand
+        .fast_slice()
+        .iter()
+        .fold(Float::MIN, |x, y| x.max(*y));
+    numerics::softmax_exp_slice(dest.fast_slice_mut(), operand.fast_slice(), max);
+    let denominator = dest.scalar_sum();
+    dest.map_inplace(|x| *x /= denominator);
+}
+
+/// Replace each row of `values` by its softmax. Rows with no finite
+/// entries, such as fully masked attention scores, are set to zero.
+fn softmax_rows(values: &mut Arr) {
+    let cols = values.cols().max(1);
+    // The vectorized exponential writes to a separate slice.
+    let mut row_values = Vec::with_capacity(cols);
+
+    for row in values.fast_slice_mut().chunks_mut(cols) {
+        let max = row.iter().fold(Float::NEG_INFINITY, |x, y| x.max(*y));
+
+        if max == Float::NEG_INFINITY {
+            for x in row.iter_mut() {
+                *x = 0.0;
+            }
+            continue;
+        }
+
+        row_values.clear();
+        row_values.extend_from_slice(row);
+        numerics::softmax_exp_slice(row, &row_values, max);
+
+        let denominator: Float = row.iter().sum();
+
+        for x in row.iter_mut() {
+            *x /= denominator;
+        }
+    }
 }
 
 #[derive(Debug)]
-pub struct VectorDotNode<LHS, RHS> {
+pub struct SoftmaxNode<OP> {
     value: RefCell<Arr>,
-    lhs_gradient: RefCell<Arr>,
-    rhs_gradient: RefCell<Arr>,
-    lhs: Rc<LHS>,
-    rhs: Rc<RHS>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<LHS, RHS> VectorDotNode<LHS, RHS>
+impl<OP> SoftmaxNode<OP>
 where
-    LHS: Node<Value = Arr, InputGradient = Arr>,
-    RHS: Node<Value = Arr, InputGradient = Arr>,
+    OP: Node<Value = Arr>,
 {
-    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
-        let (value, lhs_gradient, rhs_gradient, needs_gradient) = {
-            let lhs_value = lhs.value();
-            let rhs_value = rhs.value();
-
-            let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
-
-            assert_eq!(
-                lhs_value.shape(),
-                rhs_value.shape(),
-                "LHS and RHS must be the same shape for vector dot product."
-            );
-
-            let mut value = Arr::zeros((lhs_value.shape()[0], 1));
-
-            for (result, lhs, rhs) in izip!(
-                value.as_slice_mut().unwrap(),
-                lhs_value
-                    .genrows()
-                    .into_iter()
-                    .map(|x| x.into_slice().unwrap()),
-                rhs_value
-                    .genrows()
-                    .into_iter()
-                    .map(|x| x.into_slice().unwrap())
-            ) {
-                *result = numerics::simd_dot(lhs, rhs);
-            }
-
-            let lhs_gradient = lhs_value.deref() * 0.0;
-            let rhs_gradient = rhs_value.deref() * 0.0;
+    pub fn new(operand: Rc<OP>) -> Self {
+        let value = {
+            let max = operand
+                .value()
+                .deref()
+                .as_slice()
+                .unwrap()
+                .iter()
+                .fold(Float::MIN, |x, y| x.max(*y));
+            let numerator = operand.value().map(|x| numerics::exp(x - max));
+            let denominator = numerator.scalar_sum();
 
-            (value, lhs_gradient, rhs_gradient, needs_gradient)
+            numerator / denominator
         };
 
-        VectorDotNode {
+        let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
+
+        SoftmaxNode {
             value: RefCell::new(value),
-            lhs_gradient: RefCell::new(lhs_gradient),
-            rhs_gradient: RefCell::new(rhs_gradient),
-            lhs: lhs,
-            rhs: rhs,
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl<LHS, RHS> Node for VectorDotNode<LHS, RHS>
+impl<OP> Node for SoftmaxNode<OP>
 where
-    LHS: Node<Value = Arr, InputGradient = Arr>,
-    RHS: Node<Value = Arr, InputGradient = Arr>,
+    OP: Node<Value = Arr, InputGradient = Arr>,
 {
     type Value = Arr;
     type InputGradient = Arr;
-
     fn forward(&self) {
         if self.counter.forward() == ForwardAction::Cached {
             return;
         }
 
-        self.lhs.forward();
-        self.rhs.forward();
-
-        let lhs_value = self.lhs.value();
-        let rhs_value = self.rhs.value();
-
-        for (result, lhs, rhs) in izip!(
-            self.value.borrow_mut().as_slice_mut().unwrap(),
-            lhs_value
-                .genrows()
-                .into_iter()
-                .map(|x| x.into_slice().unwrap()),
-            rhs_value
-                .genrows()
-                .into_iter()
-                .map(|x| x.into_slice().unwrap())
-        ) {
-            *result = numerics::simd_dot(lhs, rhs);
-        }
+        self.operand.forward();
+        softmax(&mut self.value.borrow_mut(), &self.operand.value());
     }
-
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        let lhs_value = self.lhs.value();
-        let rhs_value = self.rhs.value();
+        if !self.needs_gradient {
+            return;
+        }
 
-        match self.counter.backward() {
-            BackwardAction::Set => {
-                let mut lhs_grad = self.lhs_gradient.borrow_mut();
-                let mut rhs_grad = self.rhs_gradient.borrow_mut();
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
 
-                for (backward_row, rhs_row, &gradient) in izip!(
-                    lhs_grad
-                        .genrows_mut()
-                        .into_iter()
-                        .map(|x| x.into_slice().unwrap()),
-                    rhs_value
-                        .genrows()
-                        .into_iter()
-                        .map(|x| x.into_slice().unwrap()),
-                    gradient.as_slice().unwrap()
-                ) {
-                    numerics::simd_scaled_assign(backward_row, rhs_row, gradient)
-                }
-                for (backward_row, lhs_row, &gradient) in izip!(
-                    rhs_grad
-                        .genrows_mut()
-                        .into_iter()
-                        .map(|x| x.into_slice().unwrap()),
-                    lhs_value
-                        .genrows()
-                        .into_iter()
-                        .map(|x| x.into_slice().unwrap()),
-                    gradient.as_slice().unwrap()
-                ) {
-                    numerics::simd_scaled_assign(backward_row, lhs_row, gradient)
-                }
-            }
-            BackwardAction::Increment => {
-                let mut lhs_grad = self.lhs_gradient.borrow_mut();
-                let mut rhs_grad = self.rhs_gradient.borrow_mut();
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
 
-                for (backward_row, rhs_row, &gradient) in izip!(
-                    lhs_grad
-                        .genrows_mut()
-                        .into_iter()
-                        .map(|x| x.into_slice().unwrap()),
-                    rhs_value
-                        .genrows()
-                        .into_iter()
-                        .map(|x| x.into_slice().unwrap()),
-                    gradient.as_slice().unwrap()
-                ) {
-                    numerics::simd_scaled_add(backward_row, rhs_row, gradient)
-                }
-                for (backward_row, lhs_row, &gradient) in izip!(
-                    rhs_grad
-                        .genrows_mut()
-                        .into_iter()
-                        .map(|x| x.into_slice().unwrap()),
-                    lhs_value
-                        .genrows()
-                        .into_iter()
-                        .map(|x| x.into_slice().unwrap()),
-                    gradient.as_slice().unwrap()
-                ) {
-                    numerics::simd_scaled_add(backward_row, lhs_row, gradient)
-                }
+        {
+            // The product with the Jacobian, diag(value) - value^T value,
+            // without materializing it.
+            let value = self.value.borrow();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            let value = value.fast_slice();
+            let gradient = gradient.fast_slice();
+            let dot = numerics::simd_dot(gradient, value);
+
+            for (dest, &grad, &value) in izip!(
+                operand_gradient.fast_slice_mut().iter_mut(),
+                gradient,
+                value
+            ) {
+                *dest = beta * *dest + value * (grad - dot);
             }
         }
 
         if self.counter.recurse_backward() {
-            self.lhs.backward(&self.lhs_gradient.borrow());
-            self.rhs.backward(&self.rhs_gradient.borrow());
+            self.operand.backward(&self.operand_gradient.borrow());
         }
     }
-
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
-
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
-
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
-            self.lhs.zero_gradient();
-            self.rhs.zero_gradient();
+            self.operand.zero_gradient();
             self.counter.clear();
         }
     }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.softmax().boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        Ok(builder.step(Op::Softmax, vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Softmax").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
 }
 
+/// Scaled dot-product attention: `softmax(Q K^T * scale) V`, with the
+/// softmax taken over each row of scores. Where the optional mask is
+/// zero, keys are not attended to; rows with no attended keys are zero.
 #[derive(Debug)]
-pub struct SquareNode<OP> {
+pub struct AttentionNode<Q, K, V> {
     value: RefCell<Arr>,
-    operand_gradient: RefCell<Arr>,
-    operand: Rc<OP>,
+    probabilities: RefCell<Arr>,
+    score_gradient: RefCell<Arr>,
+    query_gradient: RefCell<Arr>,
+    key_gradient: RefCell<Arr>,
+    value_gradient: RefCell<Arr>,
+    queries: Rc<Q>,
+    keys: Rc<K>,
+    values: Rc<V>,
+    mask: Option<Rc<InputNode>>,
+    scale: Float,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<OP> SquareNode<OP>
+/// Compute the attention probabilities of `queries` over `keys`.
+fn attention_probabilities(
+    probabilities: &mut Arr,
+    queries: &Arr,
+    keys: &Arr,
+    mask: Option<&Arr>,
+    scale: Float,
+) {
+    assert_eq!(
+        queries.cols(),
+        keys.cols(),
+        "Queries and keys must have the same number of columns."
+    );
+
+    fit_shape(probabilities, (queries.rows(), keys.rows()));
+    numerics::mat_mul(scale, queries, &keys.t(), 0.0, probabilities);
+
+    if let Some(mask) = mask {
+        assert_eq!(
+            mask.dim(),
+            probabilities.dim(),
+            "The mask must have a row for each query and a column for each key."
+        );
+
+        for (score, &mask) in probabilities.iter_mut().zip(mask.iter()) {
+            if mask == 0.0 {
+                *score = Float::NEG_INFINITY;
+            }
+        }
+    }
+
+    softmax_rows(probabilities);
+}
+
+impl<Q, K, V> AttentionNode<Q, K, V>
 where
-    OP: Node<Value = Arr>,
+    Q: Node<Value = Arr>,
+    K: Node<Value = Arr>,
+    V: Node<Value = Arr>,
 {
-    pub fn new(operand: Rc<OP>) -> Self {
-        let value = operand.value().map(|x| x.powi(2));
-        let gradient = &value * 0.0;
-        let needs_gradient = operand.needs_gradient();
+    pub fn new(queries: Rc<Q>, keys: Rc<K>, values: Rc<V>, mask: Option<Rc<InputNode>>) -> Self {
+        let scale = 1.0 / (queries.value().cols() as Float).sqrt();
 
-        SquareNode {
+        let mut probabilities = Arr::zeros((0, 0));
+        {
+            let mask = mask.as_ref().map(|mask| mask.value.borrow());
+            attention_probabilities(
+                &mut probabilities,
+                &queries.value(),
+                &keys.value(),
+                mask.as_deref(),
+                scale,
+            );
+        }
+
+        assert_eq!(
+            keys.value().rows(),
+            values.value().rows(),
+            "Keys and values must have the same number of rows."
+        );
+        let value = probabilities.dot(values.value().deref());
+
+        let needs_gradient =
+            queries.needs_gradient() || keys.needs_gradient() || values.needs_gradient();
+        let score_gradient = gradient_buffer(probabilities.dim(), needs_gradient);
+        let query_gradient = gradient_buffer(queries.value().dim(), needs_gradient);
+        let key_gradient = gradient_buffer(keys.value().dim(), needs_gradient);
+        let value_gradient = gradient_buffer(values.value().dim(), needs_gradient);
+
+        AttentionNode {
+            score_gradient: RefCell::new(score_gradient),
+            query_gradient: RefCell::new(query_gradient),
+            key_gradient: RefCell::new(key_gradient),
+            value_gradient: RefCell::new(value_gradient),
             value: RefCell::new(value),
-            operand_gradient: RefCell::new(gradient),
-            operand: operand,
+            probabilities: RefCell::new(probabilities),
+            queries: queries,
+            keys: keys,
+            values: values,
+            mask: mask,
+            scale: scale,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl<OP> Node for SquareNode<OP>
+impl<Q, K, V> Node for AttentionNode<Q, K, V>
 where
-    OP: Node<Value = Arr, InputGradient = Arr>,
+    Q: Node<Value = Arr, InputGradient = Arr>,
+    K: Node<Value = Arr, InputGradient = Arr>,
+    V: Node<Value = Arr, InputGradient = Arr>,
 {
     type Value = Arr;
     type InputGradient = Arr;
@@ -1381,55 +10548,163 @@ where
         if self.counter.forward() == ForwardAction::Cached {
             return;
         }
-        self.operand.forward();
 
-        let mut dest = self.value.borrow_mut();
+        self.queries.forward();
+        self.keys.forward();
+        self.values.forward();
 
-        dest.assign(self.operand.value().deref());
-        dest.map_inplace(|x| *x = x.powi(2));
-    }
+        let mut probabilities = self.probabilities.borrow_mut();
+        let mask = self.mask.as_ref().map(|mask| mask.value.borrow());
 
+        attention_probabilities(
+            &mut probabilities,
+            &self.queries.value(),
+            &self.keys.value(),
+            mask.as_deref(),
+            self.scale,
+        );
+
+        let values = self.values.value();
+        let mut value = self.value.borrow_mut();
+        fit_shape(&mut value, (probabilities.rows(), values.cols()));
+        numerics::mat_mul(1.0, probabilities.deref(), values.deref(), 0.0, value.deref_mut());
+    }
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => for (dest, operand_val, grad_val) in izip!(
-                self.operand_gradient.borrow_mut().iter_mut(),
-                self.operand.value().iter(),
-                gradient.iter()
-            ) {
-                *dest = operand_val * 2.0 * grad_val;
-            },
-            BackwardAction::Increment => for (dest, operand_val, grad_val) in izip!(
-                self.operand_gradient.borrow_mut().iter_mut(),
-                self.operand.value().iter(),
-                gradient.iter()
-            ) {
-                *dest += operand_val * 2.0 * grad_val;
-            },
+        if !self.needs_gradient {
+            return;
+        }
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let probabilities = self.probabilities.borrow();
+            let queries = self.queries.value();
+            let keys = self.keys.value();
+            let values = self.values.value();
+
+            let mut score_gradient = self.score_gradient.borrow_mut();
+            let mut query_gradient = self.query_gradient.borrow_mut();
+            let mut key_gradient = self.key_gradient.borrow_mut();
+            let mut value_gradient = self.value_gradient.borrow_mut();
+            fit_shape(&mut score_gradient, probabilities.dim());
+            fit_shape(&mut query_gradient, queries.dim());
+            fit_shape(&mut key_gradient, keys.dim());
+            fit_shape(&mut value_gradient, values.dim());
+
+            numerics::mat_mul(
+                1.0,
+                &probabilities.t(),
+                gradient.deref(),
+                beta,
+                value_gradient.deref_mut(),
+            );
+
+            // The gradient of the probabilities, taken through
+            // the softmax of each row.
+            numerics::mat_mul(
+                1.0,
+                gradient.deref(),
+                &values.t(),
+                0.0,
+                score_gradient.deref_mut(),
+            );
+
+            for (mut grad_row, probability_row) in score_gradient
+                .genrows_mut()
+                .into_iter()
+                .zip(probabilities.genrows())
+            {
+                let dot = grad_row.dot(&probability_row);
+
+                for (grad, &probability) in grad_row.iter_mut().zip(probability_row.iter()) {
+                    *grad = probability * (*grad - dot);
+                }
+            }
+
+            numerics::mat_mul(
+                self.scale,
+                score_gradient.deref(),
+                keys.deref(),
+                beta,
+                query_gradient.deref_mut(),
+            );
+            numerics::mat_mul(
+                self.scale,
+                &score_gradient.t(),
+                queries.deref(),
+                beta,
+                key_gradient.deref_mut(),
+            );
         }
 
         if self.counter.recurse_backward() {
-            self.operand.backward(&self.operand_gradient.borrow());
+            self.queries.backward(&self.query_gradient.borrow());
+            self.keys.backward(&self.key_gradient.borrow());
+            self.values.backward(&self.value_gradient.borrow());
         }
     }
-
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
-
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
-
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
-            self.operand.zero_gradient();
+            self.queries.zero_gradient();
+            self.keys.zero_gradient();
+            self.values.zero_gradient();
             self.counter.clear();
         }
     }
+    fn describe(&self) -> Description {
+        let description = Description::new("Attention")
+            .operand(&*self.queries)
+            .operand(&*self.keys)
+            .operand(&*self.values)
+            .attribute("scale", Attribute::Float(self.scale));
+
+        match self.mask {
+            Some(ref mask) => description.operand(&**mask),
+            None => description,
+        }
+    }
+    fn flops(&self) -> Option<usize> {
+        // The scores, their softmax, and the weighted sum of the values.
+        let (queries, keys) = self.probabilities.borrow().dim();
+        let d = self.queries.value().cols();
+        let value_cols = self.values.value().cols();
+        Some(2 * queries * keys * d + queries * keys + 2 * queries * keys * value_cols)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.query_gradient.borrow(), self.key_gradient.borrow(), self.value_gradient.borrow()]
+    }
+}
+
+/// The logarithm of the softmax of all elements of `operand`.
+pub(crate) fn log_softmax(dest: &mut Arr, operand: &Arr) {
+    fit_shape(dest, operand.dim());
+    dest.assign(operand);
+
+    let operand_slice = operand.as_slice().unwrap();
+    let max = operand_slice.iter().fold(Float::MIN, |x, y| x.max(*y));
+
+    let denominator = max + numerics::softmax_exp_sum(operand_slice, max).ln();
+
+    dest.as_slice_mut()
+        .unwrap()
+        .iter_mut()
+        .for_each(|x| *x -= denominator);
 }
 
 #[derive(Debug)]
-pub struct LogNode<OP> {
+pub struct LogSoftmaxNode<OP> {
     value: RefCell<Arr>,
     operand_gradient: RefCell<Arr>,
     operand: Rc<OP>,
@@ -1437,16 +10712,29 @@ pub struct LogNode<OP> {
     counter: PassCounter,
 }
 
-impl<OP> LogNode<OP>
+impl<OP> LogSoftmaxNode<OP>
 where
     OP: Node<Value = Arr>,
 {
     pub fn new(operand: Rc<OP>) -> Self {
-        let value = operand.value().map(|&x| numerics::ln(x));
-        let gradient = &value * 0.0;
+        let value = {
+            let operand_value = operand.value();
+            let operand_slice = operand_value.deref().as_slice().unwrap();
+            let max = operand_slice.iter().fold(Float::MIN, |x, y| x.max(*y));
+
+            let denominator = max + operand_slice
+                .iter()
+                .map(|&x| numerics::exp(x - max))
+                .sum::<Float>()
+                .ln();
+
+            operand_value.deref() - denominator
+        };
+
         let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
 
-        LogNode {
+        LogSoftmaxNode {
             value: RefCell::new(value),
             operand_gradient: RefCell::new(gradient),
             operand: operand,
@@ -1454,9 +10742,16 @@ where
             counter: PassCounter::default(),
         }
     }
+
+    /// An additional method for zeroing the counter for use in the
+    /// log-softmax loss, where the actuall log-softmax layer is skipped
+    /// when backpropagating.
+    pub fn zero_counter(&self) {
+        self.counter.clear();
+    }
 }
 
-impl<OP> Node for LogNode<OP>
+impl<OP> Node for LogSoftmaxNode<OP>
 where
     OP: Node<Value = Arr, InputGradient = Arr>,
 {
@@ -1468,54 +10763,100 @@ where
         }
 
         self.operand.forward();
+        log_softmax(&mut self.value.borrow_mut(), &self.operand.value());
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
 
-        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
 
-        dest.assign(self.operand.value().deref());
-        dest.map_inplace(|x| *x = numerics::ln(*x));
-    }
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
 
-    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => for (dest, operand_val, grad_val) in izip!(
-                self.operand_gradient.borrow_mut().iter_mut(),
-                self.operand.value().iter(),
-                gradient.iter()
-            ) {
-                *dest = grad_val / operand_val;
-            },
-            BackwardAction::Increment => for (dest, operand_val, grad_val) in izip!(
-                self.operand_gradient.borrow_mut().iter_mut(),
-                self.operand.value().iter(),
-                gradient.iter()
-            ) {
-                *dest += grad_val / operand_val;
-            },
+        {
+            let value = self.value.borrow();
+            let value_slice = value.as_slice().expect("Can't get value slice.");
+
+            let gradient_slice = gradient
+                .as_slice()
+                .expect("Can't get input gradient slice.");
+            let mut downstream_gradient = self.operand_gradient.borrow_mut();
+            let downstream_gradient_slice = downstream_gradient
+                .as_slice_mut()
+                .expect("Can't get output gradient slice");
+
+            let gradient_sum = numerics::simd_sum(gradient_slice);
+
+            for (out_grad, in_grad, &val) in
+                izip!(downstream_gradient_slice, gradient_slice, value_slice)
+            {
+                *out_grad = beta * *out_grad + in_grad - numerics::exp(val) * gradient_sum;
+            }
         }
 
         if self.counter.recurse_backward() {
             self.operand.backward(&self.operand_gradient.borrow());
         }
     }
-
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
-
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
-
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
             self.operand.zero_gradient();
             self.counter.clear();
         }
     }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.log_softmax().boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        Ok(builder.step(Op::LogSoftmax, vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("LogSoftmax").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// The logarithm of the softmax of each row of `operand`.
+pub(crate) fn log_softmax_rows(dest: &mut Arr, operand: &Arr) {
+    fit_shape(dest, operand.dim());
+
+    let cols = operand.cols().max(1);
+
+    for (dest_row, row) in dest
+        .as_slice_mut()
+        .unwrap()
+        .chunks_mut(cols)
+        .zip(operand.as_slice().unwrap().chunks(cols))
+    {
+        let max = row.iter().fold(Float::MIN, |x, y| x.max(*y));
+        let denominator = max + numerics::softmax_exp_sum(row, max).ln();
+
+        for (dest, &x) in dest_row.iter_mut().zip(row) {
+            *dest = x - denominator;
+        }
+    }
 }
 
+/// The log-softmax of each row of its operand, for batches of logits.
 #[derive(Debug)]
-pub struct TanhNode<OP> {
+pub struct LogSoftmaxRowsNode<OP> {
     value: RefCell<Arr>,
     operand_gradient: RefCell<Arr>,
     operand: Rc<OP>,
@@ -1523,16 +10864,18 @@ pub struct TanhNode<OP> {
     counter: PassCounter,
 }
 
-impl<OP> TanhNode<OP>
+impl<OP> LogSoftmaxRowsNode<OP>
 where
     OP: Node<Value = Arr>,
 {
     pub fn new(operand: Rc<OP>) -> Self {
-        let value = operand.value().map(|&x| numerics::tanh(x));
-        let gradient = &value * 0.0;
+        let mut value = Arr::zeros(operand.value().dim());
+        log_softmax_rows(&mut value, &operand.value());
+
         let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
 
-        TanhNode {
+        LogSoftmaxRowsNode {
             value: RefCell::new(value),
             operand_gradient: RefCell::new(gradient),
             operand: operand,
@@ -1540,9 +10883,15 @@ where
             counter: PassCounter::default(),
         }
     }
+
+    /// Zero the counter when used inside a loss that skips this node
+    /// when backpropagating, as `LogSoftmaxNode::zero_counter`.
+    pub fn zero_counter(&self) {
+        self.counter.clear();
+    }
 }
 
-impl<OP> Node for TanhNode<OP>
+impl<OP> Node for LogSoftmaxRowsNode<OP>
 where
     OP: Node<Value = Arr, InputGradient = Arr>,
 {
@@ -1554,83 +10903,130 @@ where
         }
 
         self.operand.forward();
-
-        let mut dest = self.value.borrow_mut();
-        numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), |x| {
-            numerics::tanh(x)
-        });
+        log_softmax_rows(&mut self.value.borrow_mut(), &self.operand.value());
     }
-
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => for (dest, value, grad_val) in izip!(
-                self.operand_gradient.borrow_mut().as_slice_mut().unwrap(),
-                self.value().as_slice().unwrap(),
-                gradient.as_slice().unwrap()
-            ) {
-                *dest = grad_val * (1.0 - value.powi(2));
-            },
-            BackwardAction::Increment => for (dest, value, grad_val) in izip!(
-                self.operand_gradient.borrow_mut().as_slice_mut().unwrap(),
-                self.value().as_slice().unwrap(),
-                gradient.as_slice().unwrap()
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let value = self.value.borrow();
+            let cols = value.cols().max(1);
+            let mut downstream_gradient = self.operand_gradient.borrow_mut();
+
+            for (out_row, in_row, value_row) in izip!(
+                downstream_gradient.fast_slice_mut().chunks_mut(cols),
+                gradient.fast_slice().chunks(cols),
+                value.fast_slice().chunks(cols)
             ) {
-                *dest += grad_val * (1.0 - value.powi(2));
-            },
+                let gradient_sum = numerics::simd_sum(in_row);
+
+                for (out_grad, in_grad, &val) in izip!(out_row, in_row, value_row) {
+                    *out_grad = beta * *out_grad + in_grad - numerics::exp(val) * gradient_sum;
+                }
+            }
         }
 
         if self.counter.recurse_backward() {
             self.operand.backward(&self.operand_gradient.borrow());
         }
     }
-
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
-
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
-
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
             self.operand.zero_gradient();
             self.counter.clear();
         }
     }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.log_softmax_rows().boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        Ok(builder.step(Op::LogSoftmaxRows, vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("LogSoftmaxRows").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+fn logit_adjusted_softmax(dest: &mut Arr, operand: &Arr, log_prior: &Arr) {
+    // Checked on every pass, as the logits may change shape.
+    assert_eq!(
+        log_prior.dim(),
+        (1, operand.cols()),
+        "The log-prior must be a row with one entry per class."
+    );
+
+    fit_shape(dest, operand.dim());
+
+    for (mut dest_row, row) in dest.genrows_mut().into_iter().zip(operand.genrows()) {
+        for (dest, &x, &prior) in izip!(dest_row.iter_mut(), row.iter(), log_prior.iter()) {
+            *dest = x + prior;
+        }
+    }
+
+    softmax_rows(dest);
 }
 
+/// The softmax of each row of its operand after adding a constant
+/// `(1, classes)` log-prior to every row, as in logit adjustment for
+/// long-tailed classification. Only the logits receive a gradient.
 #[derive(Debug)]
-pub struct SigmoidNode<T> {
+pub struct LogitAdjustedSoftmaxNode<OP> {
     value: RefCell<Arr>,
     operand_gradient: RefCell<Arr>,
-    operand: Rc<T>,
+    operand: Rc<OP>,
+    log_prior: Rc<InputNode>,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<T> SigmoidNode<T>
+impl<OP> LogitAdjustedSoftmaxNode<OP>
 where
-    T: Node<Value = Arr>,
+    OP: Node<Value = Arr>,
 {
-    pub fn new(operand: Rc<T>) -> Self {
-        let value = operand.value().deref().map(|&x| numerics::sigmoid(x));
-        let gradient = &value * 0.0;
+    pub fn new(operand: Rc<OP>, log_prior: Rc<InputNode>) -> Self {
+        let mut value = Arr::zeros(operand.value().dim());
+        logit_adjusted_softmax(&mut value, &operand.value(), &log_prior.value());
+
         let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(value.dim(), needs_gradient);
 
-        SigmoidNode {
+        LogitAdjustedSoftmaxNode {
             value: RefCell::new(value),
             operand_gradient: RefCell::new(gradient),
             operand: operand,
+            log_prior: log_prior,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl<T> Node for SigmoidNode<T>
+impl<OP> Node for LogitAdjustedSoftmaxNode<OP>
 where
-    T: Node<Value = Arr, InputGradient = Arr>,
+    OP: Node<Value = Arr, InputGradient = Arr>,
 {
     type Value = Arr;
     type InputGradient = Arr;
@@ -1640,95 +11036,136 @@ where
         }
 
         self.operand.forward();
+        self.log_prior.forward();
 
-        {
-            let mut dest = self.value.borrow_mut();
-
-            numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), |x| {
-                numerics::sigmoid(x)
-            });
-        }
+        logit_adjusted_softmax(
+            &mut self.value.borrow_mut(),
+            &self.operand.value(),
+            &self.log_prior.value(),
+        );
     }
-
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => {
-                let mut operand_gradient = self.operand_gradient.borrow_mut();
+        if !self.needs_gradient {
+            return;
+        }
 
-                numerics::map_assign_binary(
-                    &mut operand_gradient,
-                    self.value.borrow().deref(),
-                    gradient,
-                    |sigmoid, grad| grad * sigmoid * (1.0 - sigmoid),
-                );
-            }
-            BackwardAction::Increment => {
-                let mut operand_gradient = self.operand_gradient.borrow_mut();
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
 
-                numerics::map_inplace_assign_binary(
-                    &mut operand_gradient,
-                    self.value.borrow().deref(),
-                    gradient,
-                    |dest, sigmoid, grad| *dest += grad * sigmoid * (1.0 - sigmoid),
-                );
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let value = self.value.borrow();
+            let cols = value.cols().max(1);
+            let mut downstream_gradient = self.operand_gradient.borrow_mut();
+
+            // The prior shifts the logits by a constant, so the Jacobian
+            // is that of the softmax: `y * (g - sum(g * y))` per row.
+            for (out_row, in_row, value_row) in izip!(
+                downstream_gradient.fast_slice_mut().chunks_mut(cols),
+                gradient.fast_slice().chunks(cols),
+                value.fast_slice().chunks(cols)
+            ) {
+                let weighted_sum = numerics::simd_dot(in_row, value_row);
+
+                for (out_grad, in_grad, &val) in izip!(out_row, in_row, value_row) {
+                    *out_grad = beta * *out_grad + val * (in_grad - weighted_sum);
+                }
             }
         }
 
         if self.counter.recurse_backward() {
-            self.operand.backward(&self.operand_gradient.borrow())
+            self.operand.backward(&self.operand_gradient.borrow());
         }
     }
-
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
-
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
-
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
             self.operand.zero_gradient();
             self.counter.clear();
         }
     }
+    fn describe(&self) -> Description {
+        Description::new("LogitAdjustedSoftmax")
+            .operand(&*self.operand)
+            .operand(&*self.log_prior)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
 }
 
+/// The log-probability of one chosen column per row under the softmax
+/// of that row, as an `(n, 1)` column: the log-likelihood of the actions
+/// taken by a policy, as in REINFORCE. The chosen indices are constant.
 #[derive(Debug)]
-pub struct ReluNode<T> {
+pub struct LogProbNode<OP> {
     value: RefCell<Arr>,
+    log_probabilities: RefCell<Arr>,
     operand_gradient: RefCell<Arr>,
-    operand: Rc<T>,
+    operand: Rc<OP>,
+    chosen: Rc<IndexInputNode>,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<T> ReluNode<T>
+fn log_prob(dest: &mut Arr, log_probabilities: &mut Arr, operand: &Arr, chosen: &[usize]) {
+    assert_eq!(
+        operand.rows(),
+        chosen.len(),
+        "There must be one chosen index per row."
+    );
+
+    log_softmax_rows(log_probabilities, operand);
+    fit_shape(dest, (operand.rows(), 1));
+
+    for (dest, row, &idx) in izip!(dest.iter_mut(), log_probabilities.genrows(), chosen) {
+        *dest = row[idx];
+    }
+}
+
+impl<OP> LogProbNode<OP>
 where
-    T: Node<Value = Arr>,
+    OP: Node<Value = Arr>,
 {
-    pub fn new(operand: Rc<T>) -> Self {
-        let value = operand
-            .value()
-            .deref()
-            .map(|&x| if x < 0.0 { 0.0 } else { x });
-        let gradient = &value * 0.0;
+    pub fn new(operand: Rc<OP>, chosen: Rc<IndexInputNode>) -> Self {
+        let mut value = Arr::zeros((operand.value().rows(), 1));
+        let mut log_probabilities = Arr::zeros(operand.value().dim());
+        log_prob(
+            &mut value,
+            &mut log_probabilities,
+            &operand.value(),
+            &chosen.value(),
+        );
+
         let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(log_probabilities.dim(), needs_gradient);
 
-        ReluNode {
+        LogProbNode {
             value: RefCell::new(value),
+            log_probabilities: RefCell::new(log_probabilities),
             operand_gradient: RefCell::new(gradient),
             operand: operand,
+            chosen: chosen,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl<T> Node for ReluNode<T>
+impl<OP> Node for LogProbNode<OP>
 where
-    T: Node<Value = Arr, InputGradient = Arr>,
+    OP: Node<Value = Arr, InputGradient = Arr>,
 {
     type Value = Arr;
     type InputGradient = Arr;
@@ -1738,98 +11175,142 @@ where
         }
 
         self.operand.forward();
-
-        let mut dest = self.value.borrow_mut();
-
-        numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), |x| {
-            if x < 0.0 {
-                0.0
-            } else {
-                x
-            }
-        });
+        log_prob(
+            &mut self.value.borrow_mut(),
+            &mut self.log_probabilities.borrow_mut(),
+            &self.operand.value(),
+            &self.chosen.value(),
+        );
     }
-
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => {
-                let mut operand_gradient = self.operand_gradient.borrow_mut();
+        if !self.needs_gradient {
+            return;
+        }
 
-                numerics::map_assign_binary(
-                    &mut operand_gradient,
-                    self.value.borrow().deref(),
-                    gradient,
-                    |x, grad| if x <= 0.0 { 0.0 } else { grad },
-                );
-            }
-            BackwardAction::Increment => {
-                let mut operand_gradient = self.operand_gradient.borrow_mut();
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
 
-                numerics::map_inplace_assign_binary(
-                    &mut operand_gradient,
-                    self.value.borrow().deref(),
-                    gradient,
-                    |dest, x, grad| *dest += if x <= 0.0 { 0.0 } else { grad },
-                );
+        {
+            let log_probabilities = self.log_probabilities.borrow();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            fit_shape(&mut operand_gradient, log_probabilities.dim());
+
+            // The gradient of the chosen log-probability is the one-hot
+            // row of the chosen index less the probabilities.
+            for (mut grad_row, row, &grad, &idx) in izip!(
+                operand_gradient.genrows_mut(),
+                log_probabilities.genrows(),
+                gradient.iter(),
+                self.chosen.value().iter()
+            ) {
+                for (out_grad, &log_probability) in grad_row.iter_mut().zip(row) {
+                    *out_grad = beta * *out_grad - grad * numerics::exp(log_probability);
+                }
+
+                grad_row[idx] += grad;
             }
         }
 
         if self.counter.recurse_backward() {
-            self.operand.backward(&self.operand_gradient.borrow())
+            self.operand.backward(&self.operand_gradient.borrow());
         }
     }
-
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
-
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
-
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
             self.operand.zero_gradient();
             self.counter.clear();
         }
     }
+    fn describe(&self) -> Description {
+        Description::new("LogProb")
+            .operand(&*self.operand)
+            .operand(&*self.chosen)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
 }
 
+/// Computes the `(n, n)` matrix of KL divergences `KL(p_i || p_j)`
+/// between every pair of rows of its `(n, d)` operand, each row
+/// interpreted as a probability distribution.
+///
+/// Probabilities are clamped to at least the stability epsilon before
+/// taking logarithms.
 #[derive(Debug)]
-pub struct NegNode<T> {
+pub struct RowKlMatrixNode<OP> {
     value: RefCell<Arr>,
+    log_value: RefCell<Arr>,
     operand_gradient: RefCell<Arr>,
-    operand: Rc<T>,
+    operand: Rc<OP>,
+    epsilon: Float,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<T> NegNode<T>
+/// Write `KL(p_i || p_j) = sum_k p_ik ln(p_ik) - sum_k p_ik ln(p_jk)` into
+/// `dest`, keeping the clamped logarithms of the operand in `log_value`.
+fn row_kl_matrix(dest: &mut Arr, log_value: &mut Arr, operand: &Arr, epsilon: Float) {
+    numerics::map_assign(log_value, operand, |p| numerics::ln(p.max(epsilon)));
+    numerics::mat_mul(-1.0, operand, &log_value.t(), 0.0, dest);
+
+    for (mut dest_row, row, log_row) in
+        izip!(dest.genrows_mut(), operand.genrows(), log_value.genrows())
+    {
+        let negative_entropy = numerics::simd_dot(row.fast_slice(), log_row.fast_slice());
+        dest_row.map_inplace(|x| *x += negative_entropy);
+    }
+}
+
+impl<OP> RowKlMatrixNode<OP>
 where
-    T: Node<Value = Arr>,
+    OP: Node<Value = Arr>,
 {
-    pub fn new(operand: Rc<T>) -> Self {
-        let value = -operand.value().deref();
-        let gradient = &value * 0.0;
+    pub fn new(operand: Rc<OP>) -> Self {
+        let epsilon = stability().epsilon;
+        let (value, log_value, operand_shape) = {
+            let operand_value = operand.value();
+            let rows = operand_value.rows();
+
+            let mut value = Arr::zeros((rows, rows));
+            let mut log_value = Arr::zeros(operand_value.dim());
+            row_kl_matrix(&mut value, &mut log_value, &operand_value, epsilon);
+
+            (value, log_value, operand_value.dim())
+        };
+
         let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(operand_shape, needs_gradient);
 
-        NegNode {
+        RowKlMatrixNode {
             value: RefCell::new(value),
+            log_value: RefCell::new(log_value),
             operand_gradient: RefCell::new(gradient),
             operand: operand,
+            epsilon: epsilon,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl<T> Node for NegNode<T>
+impl<OP> Node for RowKlMatrixNode<OP>
 where
-    T: Node<Value = Arr, InputGradient = Arr>,
+    OP: Node<Value = Arr, InputGradient = Arr>,
 {
     type Value = Arr;
     type InputGradient = Arr;
-
     fn forward(&self) {
         if self.counter.forward() == ForwardAction::Cached {
             return;
@@ -1837,37 +11318,76 @@ where
 
         self.operand.forward();
 
-        let mut dest = self.value.borrow_mut();
+        let operand_value = self.operand.value();
+        let rows = operand_value.rows();
+        let mut value = self.value.borrow_mut();
+        let mut log_value = self.log_value.borrow_mut();
+        fit_shape(&mut value, (rows, rows));
+        fit_shape(&mut log_value, operand_value.dim());
 
-        dest.assign(self.operand.value().deref());
-        dest.map_inplace(|x| *x = -*x);
+        row_kl_matrix(&mut value, &mut log_value, &operand_value, self.epsilon);
     }
-
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => for (dest, grad_val) in izip!(
-                self.operand_gradient.borrow_mut().iter_mut(),
-                gradient.iter()
-            ) {
-                *dest = -grad_val;
-            },
-            BackwardAction::Increment => for (dest, grad_val) in izip!(
-                self.operand_gradient.borrow_mut().iter_mut(),
-                gradient.iter()
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let operand_value = self.operand.value();
+            let log_value = self.log_value.borrow();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            // Through ln(p_jk) of the second argument.
+            numerics::mat_mul(
+                -1.0,
+                gradient.deref(),
+                log_value.deref(),
+                beta,
+                operand_gradient.deref_mut(),
+            );
+
+            // Through p_ik ln(p_ik) of the first argument, and through
+            // ln(p_ik) when row i is the second argument. Clamped
+            // elements have no gradient through their logarithm.
+            let second_argument = gradient.t().dot(operand_value.deref());
+
+            for (mut grad_row, row, log_row, second_row, incoming_row) in izip!(
+                operand_gradient.genrows_mut(),
+                operand_value.genrows(),
+                log_value.genrows(),
+                second_argument.genrows(),
+                gradient.genrows()
             ) {
-                *dest += -grad_val;
-            },
+                let incoming = incoming_row.scalar_sum();
+
+                for (grad, &p, &log_p, &second) in izip!(
+                    grad_row.fast_slice_mut(),
+                    row.fast_slice(),
+                    log_row.fast_slice(),
+                    second_row.fast_slice()
+                ) {
+                    *grad += incoming * log_p;
+                    if p > self.epsilon {
+                        *grad += incoming - second / p;
+                    }
+                }
+            }
         }
 
         if self.counter.recurse_backward() {
-            self.operand.backward(&self.operand_gradient.borrow());
+            self.operand.backward(&self.operand_gradient.borrow())
         }
     }
-
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
-
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
@@ -1877,37 +11397,117 @@ where
             self.counter.clear();
         }
     }
+    fn describe(&self) -> Description {
+        Description::new("RowKlMatrix").operand(&*self.operand)
+    }
+    fn flops(&self) -> Option<usize> {
+        let (n, d) = self.operand.value().dim();
+        Some(2 * n * n * d + 2 * n * d)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
 }
 
+/// Computes `log(softmax)` of the weight of `x_pos` within the
+/// negatives, `x_pos - logsumexp(x_negs)`, for each row of its operand,
+/// yielding an `(n, 1)` column.
 #[derive(Debug)]
-pub struct ExpNode<OP> {
+pub struct LogRatioNode<OP> {
     value: RefCell<Arr>,
     operand_gradient: RefCell<Arr>,
     operand: Rc<OP>,
+    positives: Vec<usize>,
+    negatives: Vec<Vec<usize>>,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<OP> ExpNode<OP>
+/// The numerically stable `logsumexp` of the elements of `row` at `indices`.
+fn indexed_logsumexp(row: &[Float], indices: &[usize], scratch: &mut Vec<Float>) -> Float {
+    scratch.clear();
+    scratch.extend(indices.iter().map(|&idx| row[idx]));
+
+    let max = scratch.iter().fold(Float::MIN, |x, y| x.max(*y));
+
+    max + numerics::softmax_exp_sum(scratch, max).ln()
+}
+
+fn log_ratios(dest: &mut Arr, operand: &Arr, positives: &[usize], negatives: &[Vec<usize>]) {
+    let mut scratch = Vec::new();
+
+    for (dest, row, &positive, negatives) in izip!(
+        dest.fast_slice_mut(),
+        operand.genrows(),
+        positives,
+        negatives
+    ) {
+        let row = row.fast_slice();
+        *dest = row[positive] - indexed_logsumexp(row, negatives, &mut scratch);
+    }
+}
+
+impl<OP> LogRatioNode<OP>
 where
     OP: Node<Value = Arr>,
 {
-    pub fn new(operand: Rc<OP>) -> Self {
-        let value = operand.value().deref().map(|&x| numerics::exp(x));
-        let gradient = &value * 0.0;
+    pub fn new(operand: Rc<OP>, positives: &[usize], negatives: &[Vec<usize>]) -> Self {
+        let (value, operand_shape) = {
+            let operand_value = operand.value();
+            let (rows, cols) = operand_value.dim();
+
+            assert_eq!(
+                positives.len(),
+                rows,
+                "Expected one positive per row, got {} for {} rows.",
+                positives.len(),
+                rows
+            );
+            assert_eq!(
+                negatives.len(),
+                rows,
+                "Expected one set of negatives per row, got {} for {} rows.",
+                negatives.len(),
+                rows
+            );
+            assert!(
+                negatives.iter().all(|negatives| !negatives.is_empty()),
+                "Every row needs at least one negative."
+            );
+            assert!(
+                positives
+                    .iter()
+                    .chain(negatives.iter().flat_map(|x| x.iter()))
+                    .all(|&idx| idx < cols),
+                "Index out of bounds for {} columns.",
+                cols
+            );
+
+            let mut value = Arr::zeros((rows, 1));
+            log_ratios(&mut value, &operand_value, positives, negatives);
+
+            (value, operand_value.dim())
+        };
+
         let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(operand_shape, needs_gradient);
 
-        ExpNode {
+        LogRatioNode {
             value: RefCell::new(value),
             operand_gradient: RefCell::new(gradient),
             operand: operand,
+            positives: positives.to_vec(),
+            negatives: negatives.to_vec(),
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl<OP> Node for ExpNode<OP>
+impl<OP> Node for LogRatioNode<OP>
 where
     OP: Node<Value = Arr, InputGradient = Arr>,
 {
@@ -1919,30 +11519,65 @@ where
         }
 
         self.operand.forward();
-        let mut dest = self.value.borrow_mut();
 
-        dest.assign(self.operand.value().deref());
-        dest.map_inplace(|x| *x = numerics::exp(*x));
+        let operand_value = self.operand.value();
+        assert_eq!(
+            operand_value.rows(),
+            self.positives.len(),
+            "Expected one positive per row, got {} for {} rows.",
+            self.positives.len(),
+            operand_value.rows()
+        );
+
+        log_ratios(
+            &mut self.value.borrow_mut(),
+            &operand_value,
+            &self.positives,
+            &self.negatives,
+        );
     }
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => for (dest, self_val, grad_val) in izip!(
-                self.operand_gradient.borrow_mut().iter_mut(),
-                self.value.borrow().iter(),
-                gradient.iter()
-            ) {
-                *dest = self_val * grad_val;
-            },
-            BackwardAction::Increment => for (dest, self_val, grad_val) in izip!(
-                self.operand_gradient.borrow_mut().iter_mut(),
-                self.value.borrow().iter(),
-                gradient.iter()
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let operand_value = self.operand.value();
+            let value = self.value.borrow();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            for (mut grad_row, value_row, &grad, &ratio, &positive, negatives) in izip!(
+                operand_gradient.genrows_mut(),
+                operand_value.genrows(),
+                gradient.fast_slice(),
+                value.fast_slice(),
+                &self.positives,
+                &self.negatives
             ) {
-                *dest += self_val * grad_val;
-            },
+                let grad_row = grad_row.fast_slice_mut();
+                let value_row = value_row.fast_slice();
+
+                grad_row.iter_mut().for_each(|x| *x *= beta);
+                grad_row[positive] += grad;
+
+                // The softmax weight of each negative, recovered from
+                // the ratio as exp(x_neg - x_pos + ratio).
+                let shift = ratio - value_row[positive];
+                for &idx in negatives {
+                    grad_row[idx] -= grad * numerics::exp(value_row[idx] + shift);
+                }
+            }
         }
+
         if self.counter.recurse_backward() {
-            self.operand.backward(&self.operand_gradient.borrow());
+            self.operand.backward(&self.operand_gradient.borrow())
         }
     }
     fn value(&self) -> Bor<Self::Value> {
@@ -1951,46 +11586,81 @@ where
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
-
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
             self.operand.zero_gradient();
             self.counter.clear();
         }
     }
+    fn describe(&self) -> Description {
+        Description::new("LogRatio").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+fn row_entropy(row: &[Float], epsilon: Float) -> Float {
+    -row.iter()
+        .map(|&p| p * numerics::ln(p.max(epsilon)))
+        .sum::<Float>()
 }
 
+/// Computes the entropy of each row of its operand, interpreted as a
+/// probability distribution, yielding an `(n, 1)` column.
+///
+/// Probabilities are clamped to at least the stability epsilon before
+/// taking logarithms.
 #[derive(Debug)]
-pub struct TransposeNode<OP> {
+pub struct EntropyNode<OP> {
     value: RefCell<Arr>,
-    gradient: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
     operand: Rc<OP>,
+    epsilon: Float,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<OP> TransposeNode<OP>
+impl<OP> EntropyNode<OP>
 where
     OP: Node<Value = Arr>,
 {
     pub fn new(operand: Rc<OP>) -> Self {
+        let epsilon = stability().epsilon;
+        let (value, operand_shape) = {
+            let operand_value = operand.value();
+
+            let mut value = Arr::zeros((operand_value.rows(), 1));
+            for (dest, row) in value
+                .as_slice_mut()
+                .unwrap()
+                .iter_mut()
+                .zip(operand_value.genrows())
+            {
+                *dest = row_entropy(row.fast_slice(), epsilon);
+            }
+
+            (value, operand_value.dim())
+        };
+
         let needs_gradient = operand.needs_gradient();
-        let mut value = Arr::zeros((operand.value().cols(), operand.value().rows()));
-        value.assign(&operand.value().t());
-        let value = RefCell::new(value);
-        let gradient = RefCell::new(operand.value().deref() * 0.0);
+        let gradient = gradient_buffer(operand_shape, needs_gradient);
 
-        TransposeNode {
-            value: value,
-            gradient: gradient,
+        EntropyNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
             operand: operand,
+            epsilon: epsilon,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl<OP> Node for TransposeNode<OP>
+impl<OP> Node for EntropyNode<OP>
 where
     OP: Node<Value = Arr, InputGradient = Arr>,
 {
@@ -2002,20 +11672,53 @@ where
         }
 
         self.operand.forward();
-        self.value.borrow_mut().assign(&self.operand.value().t());
+
+        let operand_value = self.operand.value();
+        let mut dest = self.value.borrow_mut();
+        fit_shape(&mut dest, (operand_value.rows(), 1));
+
+        for (dest, row) in dest
+            .fast_slice_mut()
+            .iter_mut()
+            .zip(operand_value.genrows())
+        {
+            *dest = row_entropy(row.fast_slice(), self.epsilon);
+        }
     }
+
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => {
-                self.gradient.borrow_mut().assign(&gradient.t());
-            }
-            BackwardAction::Increment => {
-                self.gradient.borrow_mut().slice_add_assign(&gradient.t());
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let operand_value = self.operand.value();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            for (mut grad_row, value_row, &grad) in izip!(
+                operand_gradient.genrows_mut(),
+                operand_value.genrows(),
+                gradient.fast_slice()
+            ) {
+                for (dest, &p) in grad_row
+                    .fast_slice_mut()
+                    .iter_mut()
+                    .zip(value_row.fast_slice())
+                {
+                    *dest = beta * *dest - grad * (numerics::ln(p.max(self.epsilon)) + 1.0);
+                }
             }
         }
 
         if self.counter.recurse_backward() {
-            self.operand.backward(&self.gradient.borrow());
+            self.operand.backward(&self.operand_gradient.borrow())
         }
     }
 
@@ -2033,53 +11736,88 @@ where
             self.counter.clear();
         }
     }
+    fn describe(&self) -> Description {
+        Description::new("Entropy").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
 }
 
+/// Computes the smooth maximum `ln(mean(exp(beta * x))) / beta` of each
+/// row of its operand, yielding an `(n, 1)` column.
+///
+/// Larger `beta` moves the result towards the row maximum; as `beta`
+/// goes to zero, it approaches the row mean. Averaging rather than
+/// summing the exponentials keeps the small `beta` limit finite.
 #[derive(Debug)]
-pub struct SoftmaxNode<OP> {
+pub struct SoftMaxPoolNode<OP> {
     value: RefCell<Arr>,
-    jacobian: RefCell<Arr>,
+    weights: RefCell<Arr>,
     operand_gradient: RefCell<Arr>,
     operand: Rc<OP>,
+    beta: Float,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<OP> SoftmaxNode<OP>
+/// The smooth maximum of `row` at temperature `beta`, writing the
+/// softmax of `beta * row`, its gradient, to `weights`.
+fn soft_max_pool_row(row: &[Float], beta: Float, weights: &mut [Float]) -> Float {
+    let max = row.iter().fold(Float::MIN, |x, &y| x.max(y));
+
+    for (weight, &x) in weights.iter_mut().zip(row) {
+        *weight = numerics::exp(beta * (x - max));
+    }
+
+    let sum = weights.iter().sum::<Float>();
+    weights.iter_mut().for_each(|weight| *weight /= sum);
+
+    max + (numerics::ln(sum) - numerics::ln(row.len() as Float)) / beta
+}
+
+impl<OP> SoftMaxPoolNode<OP>
 where
     OP: Node<Value = Arr>,
 {
-    pub fn new(operand: Rc<OP>) -> Self {
-        let value = {
-            let max = operand
-                .value()
-                .deref()
-                .as_slice()
-                .unwrap()
-                .iter()
-                .fold(std::f32::MIN, |x, y| x.max(*y));
-            let numerator = operand.value().map(|x| numerics::exp(x - max));
-            let denominator = numerator.scalar_sum();
+    pub fn new(operand: Rc<OP>, beta: Float) -> Self {
+        assert!(beta > 0.0, "Beta must be positive.");
 
-            numerator / denominator
+        let (value, weights) = {
+            let operand_value = operand.value();
+
+            let mut value = Arr::zeros((operand_value.rows(), 1));
+            let mut weights = Arr::zeros(operand_value.dim());
+            for (dest, row, mut weights) in izip!(
+                value.fast_slice_mut(),
+                operand_value.genrows(),
+                weights.genrows_mut()
+            ) {
+                *dest = soft_max_pool_row(row.fast_slice(), beta, weights.fast_slice_mut());
+            }
+
+            (value, weights)
         };
 
-        let gradient = &value * 0.0;
         let needs_gradient = operand.needs_gradient();
-        let dim = value.shape()[1];
+        let gradient = gradient_buffer(weights.dim(), needs_gradient);
 
-        SoftmaxNode {
+        SoftMaxPoolNode {
             value: RefCell::new(value),
-            jacobian: RefCell::new(ndarray::Array2::zeros((dim, dim))),
+            weights: RefCell::new(weights),
             operand_gradient: RefCell::new(gradient),
             operand: operand,
+            beta: beta,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl<OP> Node for SoftmaxNode<OP>
+impl<OP> Node for SoftMaxPoolNode<OP>
 where
     OP: Node<Value = Arr, InputGradient = Arr>,
 {
@@ -2091,127 +11829,158 @@ where
         }
 
         self.operand.forward();
-        let mut dest = self.value.borrow_mut();
-        dest.slice_assign(self.operand.value().deref());
 
-        let max = self
-            .operand
-            .value()
-            .fast_slice()
-            .iter()
-            .fold(std::f32::MIN, |x, y| x.max(*y));
-        dest.map_inplace(|x| *x = numerics::exp(*x - max));
-        let denominator = dest.scalar_sum();
-        dest.map_inplace(|x| *x /= denominator);
+        let operand_value = self.operand.value();
+        let mut dest = self.value.borrow_mut();
+        let mut weights = self.weights.borrow_mut();
+        fit_shape(&mut dest, (operand_value.rows(), 1));
+        fit_shape(&mut weights, operand_value.dim());
+
+        for (dest, row, mut weights) in izip!(
+            dest.fast_slice_mut(),
+            operand_value.genrows(),
+            weights.genrows_mut()
+        ) {
+            *dest = soft_max_pool_row(row.fast_slice(), self.beta, weights.fast_slice_mut());
+        }
     }
+
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        // TODO: accumulate gradients
-        let value = self.value.borrow();
-        let mut jacobian = self.jacobian.borrow_mut();
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
 
         let beta = match self.counter.backward() {
             BackwardAction::Set => 0.0,
             BackwardAction::Increment => 1.0,
         };
 
-        for (row_idx, (mut row, row_val)) in jacobian
-            .genrows_mut()
-            .into_iter()
-            .zip(value.iter())
-            .enumerate()
         {
-            for (col_idx, (grad, col_val)) in row
-                .as_slice_mut()
-                .unwrap()
-                .iter_mut()
-                .zip(value.as_slice().unwrap())
-                .enumerate()
-            {
-                if row_idx == col_idx {
-                    *grad = row_val * (1.0 - col_val);
-                } else {
-                    *grad = -row_val * col_val;
+            let weights = self.weights.borrow();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            for (mut grad_row, weight_row, &grad) in izip!(
+                operand_gradient.genrows_mut(),
+                weights.genrows(),
+                gradient.fast_slice()
+            ) {
+                for (dest, &weight) in grad_row
+                    .fast_slice_mut()
+                    .iter_mut()
+                    .zip(weight_row.fast_slice())
+                {
+                    *dest = beta * *dest + grad * weight;
                 }
             }
         }
 
-        {
-            numerics::mat_mul(
-                1.0,
-                gradient,
-                jacobian.deref_mut(),
-                beta,
-                self.operand_gradient.borrow_mut().deref_mut(),
-            );
-        }
-
         if self.counter.recurse_backward() {
-            self.operand.backward(&self.operand_gradient.borrow());
+            self.operand.backward(&self.operand_gradient.borrow())
         }
     }
+
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
+
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
+
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
             self.operand.zero_gradient();
             self.counter.clear();
         }
     }
+    fn describe(&self) -> Description {
+        Description::new("SoftMaxPool")
+            .operand(&*self.operand)
+            .attribute("beta", Attribute::Float(self.beta))
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// Order in which `SumNode` adds up the elements of its operand.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reduction {
+    /// Let `ndarray` pick the order. This is the fastest, but the order
+    /// is an implementation detail that may change between versions.
+    Unordered,
+    /// Add the elements one by one in row-major order. The result
+    /// is reproducible, but rounding error grows linearly with the
+    /// number of elements, and the loop does not vectorize.
+    Sequential,
+    /// Split the row-major elements in halves recursively, adding blocks
+    /// of at most 8 elements sequentially. The result is reproducible and
+    /// rounding error only grows logarithmically with the number of
+    /// elements, at a small cost in speed over `Unordered`.
+    Pairwise,
+}
+
+pub(crate) fn reduce(value: &Arr, reduction: Reduction) -> Float {
+    match reduction {
+        Reduction::Unordered => value.scalar_sum(),
+        Reduction::Sequential => value.iter().fold(0.0, |sum, &x| sum + x),
+        Reduction::Pairwise => match value.as_slice() {
+            Some(slice) => numerics::pairwise_sum(slice),
+            None => numerics::pairwise_sum(&value.iter().cloned().collect::<Vec<_>>()),
+        },
+    }
 }
 
 #[derive(Debug)]
-pub struct LogSoftmaxNode<OP> {
+pub struct SumNode<OP> {
     value: RefCell<Arr>,
     operand_gradient: RefCell<Arr>,
     operand: Rc<OP>,
+    reduction: Reduction,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<OP> LogSoftmaxNode<OP>
+impl<OP> SumNode<OP>
 where
     OP: Node<Value = Arr>,
 {
-    pub fn new(operand: Rc<OP>) -> Self {
-        let value = {
-            let operand_value = operand.value();
-            let operand_slice = operand_value.deref().as_slice().unwrap();
-            let max = operand_slice.iter().fold(std::f32::MIN, |x, y| x.max(*y));
-
-            let denominator = max + operand_slice
-                .iter()
-                .map(|&x| numerics::exp(x - max))
-                .sum::<f32>()
-                .ln();
-
-            operand_value.deref() - denominator
-        };
+    pub fn new(operand: Rc<OP>, reduction: Reduction) -> Self {
+        let mut value = arena::zeros((1, 1));
+        value.fill(reduce(&operand.value(), reduction));
 
-        let gradient = &value * 0.0;
         let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(operand.value().dim(), needs_gradient);
 
-        LogSoftmaxNode {
+        SumNode {
             value: RefCell::new(value),
             operand_gradient: RefCell::new(gradient),
             operand: operand,
+            reduction: reduction,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 
-    /// An additional method for zeroing the counter for use in the
-    /// log-softmax loss, where the actuall log-softmax layer is skipped
-    /// when backpropagating.
-    pub fn zero_counter(&self) {
-        self.counter.clear();
+    fn compute(&self) {
+        let mut dest = self.value.borrow_mut();
+        dest[(0, 0)] = reduce(&self.operand.value(), self.reduction);
     }
 }
 
-impl<OP> Node for LogSoftmaxNode<OP>
+impl<OP> Drop for SumNode<OP> {
+    fn drop(&mut self) {
+        arena::recycle(self.value.get_mut());
+        arena::recycle(self.operand_gradient.get_mut());
+    }
+}
+
+impl<OP> Node for SumNode<OP>
 where
     OP: Node<Value = Arr, InputGradient = Arr>,
 {
@@ -2223,49 +11992,53 @@ where
         }
 
         self.operand.forward();
-        let mut dest = self.value.borrow_mut();
-        dest.assign(self.operand.value().deref());
 
-        let operand_value = self.operand.value();
-        let operand_slice = operand_value.deref().as_slice().unwrap();
-        let max = operand_slice.iter().fold(std::f32::MIN, |x, y| x.max(*y));
+        self.compute();
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
 
-        let denominator = max + numerics::softmax_exp_sum(operand_slice, max).ln();
+        self.accumulate(gradient);
 
-        dest.as_slice_mut()
-            .unwrap()
-            .iter_mut()
-            .for_each(|x| *x -= denominator);
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
     }
-    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        let beta = match self.counter.backward() {
-            BackwardAction::Set => 0.0,
-            BackwardAction::Increment => 1.0,
-        };
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, uses: usize) {
+        if self.counter.forward_many(uses) == ForwardAction::Cached {
+            return;
+        }
 
-        {
-            let value = self.value.borrow();
-            let value_slice = value.as_slice().expect("Can't get value slice.");
+        self.compute();
+    }
+    fn accumulate(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
 
-            let gradient_slice = gradient
-                .as_slice()
-                .expect("Can't get input gradient slice.");
-            let mut downstream_gradient = self.operand_gradient.borrow_mut();
-            let downstream_gradient_slice = downstream_gradient
-                .as_slice_mut()
-                .expect("Can't get output gradient slice");
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
 
-            let gradient_sum = numerics::simd_sum(gradient_slice);
+        debug_assert!(gradient.len() == 1, "Input gradient must be a scalar.");
 
-            for (out_grad, in_grad, &val) in
-                izip!(downstream_gradient_slice, gradient_slice, value_slice)
-            {
-                *out_grad = beta * *out_grad + in_grad - numerics::exp(val) * gradient_sum;
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                self.operand_gradient.borrow_mut().fill(gradient[(0, 0)]);
+            }
+            BackwardAction::Increment => {
+                self.operand_gradient
+                    .borrow_mut()
+                    .slice_add_assign(gradient[(0, 0)]);
             }
         }
-
+    }
+    fn propagate(&self) {
         if self.counter.recurse_backward() {
-            self.operand.backward(&self.operand_gradient.borrow());
+            self.operand.accumulate(&self.operand_gradient.borrow());
         }
     }
     fn value(&self) -> Bor<Self::Value> {
@@ -2274,16 +12047,61 @@ where
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
+
     fn zero_gradient(&self) {
         if !self.counter.is_zero() {
             self.operand.zero_gradient();
             self.counter.clear();
         }
     }
+    fn tangent(&self, builder: &mut TangentBuilder) -> Result<Tangent, String> {
+        Ok(builder
+            .tangent(&self.operand)?
+            .map(|x| x.scalar_sum_ordered(self.reduction).boxed()))
+    }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.clone_node(&self.operand)?;
+        Ok(Some(operand.scalar_sum_ordered(self.reduction).boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        Ok(builder.step(Op::Sum(self.reduction), vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Sum").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
+}
+
+/// The penalty each non-positive element contributes to a
+/// `LogBarrierNode`, in place of the infinite `-ln(x)`.
+const LOG_BARRIER_PENALTY: Float = 1e6;
+
+fn log_barrier(value: &Arr) -> Float {
+    value
+        .iter()
+        .map(|&x| {
+            if x > 0.0 {
+                -numerics::ln(x)
+            } else {
+                LOG_BARRIER_PENALTY
+            }
+        })
+        .sum()
 }
 
+/// Computes the log-barrier `-sum(ln(x))` of its operand, yielding a
+/// scalar.
+///
+/// Rather than producing NaNs, every non-positive element contributes
+/// a large finite penalty to the value and a zero gradient.
 #[derive(Debug)]
-pub struct SumNode<OP> {
+pub struct LogBarrierNode<OP> {
     value: RefCell<Arr>,
     operand_gradient: RefCell<Arr>,
     operand: Rc<OP>,
@@ -2291,21 +12109,21 @@ pub struct SumNode<OP> {
     counter: PassCounter,
 }
 
-impl<OP> SumNode<OP>
+impl<OP> LogBarrierNode<OP>
 where
     OP: Node<Value = Arr>,
 {
     pub fn new(operand: Rc<OP>) -> Self {
         let value = {
             let mut value = Arr::zeros((1, 1));
-            value.fill(operand.value().scalar_sum());
+            value.fill(log_barrier(&operand.value()));
             value
         };
 
-        let gradient = operand.value().deref() * 0.0;
         let needs_gradient = operand.needs_gradient();
+        let gradient = gradient_buffer(operand.value().dim(), needs_gradient);
 
-        SumNode {
+        LogBarrierNode {
             value: RefCell::new(value),
             operand_gradient: RefCell::new(gradient),
             operand: operand,
@@ -2315,7 +12133,7 @@ where
     }
 }
 
-impl<OP> Node for SumNode<OP>
+impl<OP> Node for LogBarrierNode<OP>
 where
     OP: Node<Value = Arr, InputGradient = Arr>,
 {
@@ -2329,19 +12147,34 @@ where
         self.operand.forward();
 
         let mut dest = self.value.borrow_mut();
-        dest[(0, 0)] = self.operand.value().scalar_sum();
+        dest[(0, 0)] = log_barrier(&self.operand.value());
     }
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if !self.needs_gradient {
+            return;
+        }
+
+        fit_shape(&mut self.operand_gradient.borrow_mut(), self.operand.value().dim());
+
         debug_assert!(gradient.len() == 1, "Input gradient must be a scalar.");
 
-        match self.counter.backward() {
-            BackwardAction::Set => {
-                self.operand_gradient.borrow_mut().fill(gradient[(0, 0)]);
-            }
-            BackwardAction::Increment => {
-                self.operand_gradient
-                    .borrow_mut()
-                    .slice_add_assign(gradient[(0, 0)]);
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let grad = gradient[(0, 0)];
+            let operand_value = self.operand.value();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            for (dest, &x) in operand_gradient
+                .fast_slice_mut()
+                .iter_mut()
+                .zip(operand_value.fast_slice())
+            {
+                let barrier_gradient = if x > 0.0 { -grad / x } else { 0.0 };
+                *dest = beta * *dest + barrier_gradient;
             }
         }
 
@@ -2362,6 +12195,15 @@ where
             self.counter.clear();
         }
     }
+    fn describe(&self) -> Description {
+        Description::new("LogBarrier").operand(&*self.operand)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
+    fn gradients(&self) -> Vec<Ref<Arr>> {
+        vec![self.operand_gradient.borrow()]
+    }
 }
 
 /// An input node for integer indices into `ParameterNode`s, used
@@ -2388,6 +12230,12 @@ impl Node for IndexInputNode {
     type InputGradient = Arr;
     fn forward(&self) {}
     fn backward(&self, _: &Ref<Self::InputGradient>) {}
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, _: usize) {}
+    fn accumulate(&self, _: &Ref<Self::InputGradient>) {}
+    fn propagate(&self) {}
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
@@ -2395,6 +12243,12 @@ impl Node for IndexInputNode {
         false
     }
     fn zero_gradient(&self) {}
+    fn describe(&self) -> Description {
+        Description::new("IndexInput")
+    }
+    fn flops(&self) -> Option<usize> {
+        Some(0)
+    }
 }
 
 #[derive(Debug)]
@@ -2408,15 +12262,13 @@ pub struct IndexNode<OP> {
     counter: PassCounter,
 }
 
-impl<OP> IndexNode<OP>
-where
-    OP: Node<Value = Arr>,
-{
-    pub fn new(operand: Rc<OP>, index: Rc<IndexInputNode>) -> Self {
-        let value = operand.value().select(Axis(0), &index.value()[..]);
-        let grad = &value * 0.0;
-        let idx_value = index.value().clone();
+impl IndexNode<ParameterNode> {
+    pub fn new(operand: Rc<ParameterNode>, index: Rc<IndexInputNode>) -> Self {
+        let mut value = Arr::zeros((index.value().len(), operand.value.shape().1));
+        operand.value.read_rows(&index.value()[..], &mut value);
         let needs_gradient = operand.needs_gradient();
+        let grad = gradient_buffer(value.dim(), needs_gradient);
+        let idx_value = index.value().clone();
 
         IndexNode {
             value: RefCell::new(value),
@@ -2428,6 +12280,18 @@ where
             counter: PassCounter::default(),
         }
     }
+
+    fn compute(&self) {
+        let mut idx_value = self.index_value.borrow_mut();
+        idx_value.clear();
+        idx_value.extend_from_slice(&self.index.value()[..]);
+
+        let mut arr_value = self.value.borrow_mut();
+        let cols = arr_value.cols();
+        fit_shape(&mut arr_value, (idx_value.len(), cols));
+
+        self.operand.value.read_rows(&idx_value, &mut arr_value);
+    }
 }
 
 impl Node for IndexNode<ParameterNode> {
@@ -2438,35 +12302,34 @@ impl Node for IndexNode<ParameterNode> {
             return;
         }
 
-        let operand_value = self.operand.value();
-
-        let mut idx_value = self.index_value.borrow_mut();
-        idx_value.clear();
-        idx_value.extend_from_slice(&self.index.value()[..]);
-
-        let mut arr_value = self.value.borrow_mut();
-
-        debug_assert_eq!(
-            arr_value.shape()[0],
-            idx_value.len(),
-            "Result of indexing operation must maintain consistent shape between iterations."
-        );
-
-        for (&idx, mut row) in idx_value.iter().zip(arr_value.genrows_mut()) {
-            let new_val = operand_value.subview(Axis(0), idx);
-
-            row.slice_assign(&new_val);
-        }
+        self.compute();
     }
 
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        self.accumulate(gradient);
+    }
+    fn linearizable(&self) -> bool {
+        true
+    }
+    fn evaluate(&self, uses: usize) {
+        if self.counter.forward_many(uses) == ForwardAction::Cached {
+            return;
+        }
+
+        self.compute();
+    }
+    fn accumulate(&self, gradient: &Ref<Self::InputGradient>) {
+        // Every use pushes its own sparse entry, and entries are summed
+        // when the gradient is read, so there is nothing to set: later
+        // uses in the same pass increment the earlier ones.
         self.counter.backward();
         self.operand
             .gradient
             .borrow_mut()
             .accumulate_gradient((&self.index_value.borrow()[..], gradient.deref()));
     }
-
+    // The gradient goes straight into the parameter.
+    fn propagate(&self) {}
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
@@ -2480,13 +12343,38 @@ impl Node for IndexNode<ParameterNode> {
             self.counter.clear();
         }
     }
+    fn clone_architecture(&self, builder: &mut CloneBuilder) -> Result<Cloned, String> {
+        let operand = builder.parameter(&self.operand);
+        let index = builder.copy_index(&self.index);
+        Ok(Some(operand.index(&index).boxed()))
+    }
+    fn compile(&self, builder: &mut InferenceBuilder) -> Result<usize, String> {
+        let operand = builder.compile(&self.operand)?;
+        let indices = self.index.value.borrow().to_vec();
+        Ok(builder.step(Op::Index(indices), vec![operand], &self.value.borrow()))
+    }
+    fn describe(&self) -> Description {
+        Description::new("Index")
+            .operand(&*self.operand)
+            .operand(&*self.index)
+    }
+    fn flops(&self) -> Option<usize> {
+        Some(0)
+    }
+    fn pass_counter(&self) -> Option<&PassCounter> {
+        Some(&self.counter)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use bincode;
+    use serde_json;
+
     use nn;
 
     use super::*;
+    use DataInput;
 
     #[test]
     fn test_sub_counter() {
@@ -2500,4 +12388,175 @@ mod tests {
         z.backward(1.0);
         assert_eq!(y.node.counter.backward_count.get(), 3);
     }
+
+    #[test]
+    fn test_zero_gradient_after_evaluation() {
+        let x = ParameterNode::new(nn::xavier_normal(2, 3));
+        let input = InputNode::new(nn::xavier_normal(2, 3));
+        let y = x.clone() * input.clone();
+        let mut loss = (y.clone() + y.clone()).scalar_sum();
+
+        for _ in 0..3 {
+            // Evaluation only.
+            input.set_value(&nn::xavier_normal(2, 3));
+            loss.forward();
+            loss.zero_gradient();
+            assert!(y.node.counter.is_zero());
+
+            // Training.
+            let value = nn::xavier_normal(2, 3);
+            input.set_value(&value);
+            loss.forward();
+            loss.backward(1.0);
+            assert_eq!(x.gradient(), value * 2.0);
+            loss.zero_gradient();
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Not fully backpropagated.")]
+    fn test_zero_gradient_after_partial_backward() {
+        let x = ParameterNode::new(nn::xavier_normal(2, 3));
+        let mut y = x.square();
+        let z = y.clone() + y.clone();
+
+        z.forward();
+        // Only one of the two uses of `y` is backpropagated.
+        y.backward(1.0);
+        z.zero_gradient();
+    }
+
+    #[test]
+    fn test_reference_ops_share_nodes() {
+        let x = ParameterNode::new(nn::xavier_normal(1, 1));
+
+        let y = &x - &x;
+        let mut z = &y + &y;
+        z.forward();
+        z.backward(1.0);
+
+        let y_clone = x.clone() - x.clone();
+        let mut z_clone = y_clone.clone() + y_clone.clone();
+        z_clone.forward();
+        z_clone.backward(1.0);
+
+        assert_eq!(y.node.counter.forward_count.get(), 2);
+        assert_eq!(y.node.counter.backward_count.get(), 2);
+        assert_eq!(
+            y.node.counter.forward_count.get(),
+            y_clone.node.counter.forward_count.get()
+        );
+
+        let w = -&(&x * 2.0) / &(2.0 + &x) - x.clone();
+        w.forward();
+        assert_eq!(x.parameters().len(), 1);
+        assert_eq!(w.parameters().len(), 1);
+    }
+
+    fn deserialization_error(parameter: &HogwildParameter) -> String {
+        let bytes = bincode::serialize(parameter).unwrap();
+
+        bincode::deserialize::<HogwildParameter>(&bytes)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn hogwild_parameter_round_trip() {
+        let parameter = HogwildParameter::new(nn::xavier_normal(3, 4));
+        let bytes = bincode::serialize(&parameter).unwrap();
+        let deserialized: HogwildParameter = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(deserialized.value(), parameter.value());
+        assert_eq!(deserialized.squared_gradients(), parameter.squared_gradients());
+    }
+
+    #[test]
+    fn hogwild_parameter_bytes_round_trip() {
+        let parameter = HogwildParameter::new(nn::xavier_normal(3, 4));
+        *parameter.squared_gradients.borrow_mut() = nn::xavier_normal(3, 4);
+        *parameter.moments.borrow_mut() = nn::xavier_normal(3, 4);
+        parameter.num_updates.set(17);
+
+        let deserialized = HogwildParameter::from_bytes(&parameter.to_bytes()).unwrap();
+
+        assert_eq!(deserialized.value(), parameter.value());
+        assert_eq!(deserialized.squared_gradients(), parameter.squared_gradients());
+        assert_eq!(
+            deserialized.moments.borrow().deref(),
+            parameter.moments.borrow().deref()
+        );
+        assert_eq!(deserialized.num_updates.get(), 17);
+
+        assert!(HogwildParameter::from_bytes(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn half_precision_parameter_round_trip() {
+        let parameter = HogwildParameter::half(nn::xavier_normal(3, 4));
+        let bytes = bincode::serialize(&parameter).unwrap();
+        let deserialized: HogwildParameter = bincode::deserialize(&bytes).unwrap();
+
+        assert!(deserialized.is_half());
+        assert_eq!(deserialized.full_value(), parameter.full_value());
+
+        let rows = ParameterNode::shared(Arc::new(deserialized)).index(&IndexInputNode::new(&[2]));
+        rows.forward();
+        assert_eq!(
+            rows.value().deref(),
+            &parameter.full_value().select(Axis(0), &[2])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no dense value")]
+    fn half_precision_parameter_value() {
+        HogwildParameter::half(nn::xavier_normal(3, 4)).value();
+    }
+
+    #[test]
+    fn hogwild_parameter_shape_mismatch() {
+        let parameter = HogwildParameter::new(nn::xavier_normal(3, 4));
+        *parameter.squared_gradients.borrow_mut() = Arr::zeros((3, 5));
+
+        assert_eq!(
+            deserialization_error(&parameter),
+            "invalid HogwildParameter: squared_gradients has shape (3, 5), \
+             but value has shape (3, 4)"
+        );
+
+        let parameter = HogwildParameter::new(nn::xavier_normal(3, 4));
+        *parameter.moments.borrow_mut() = Arr::zeros((2, 4));
+
+        assert_eq!(
+            deserialization_error(&parameter),
+            "invalid HogwildParameter: moments has shape (2, 4), but value has shape (3, 4)"
+        );
+    }
+
+    #[test]
+    fn hogwild_parameter_non_finite() {
+        let parameter = HogwildParameter::new(nn::xavier_normal(3, 4));
+        parameter.value.borrow_mut()[(2, 1)] = Float::NAN;
+
+        assert_eq!(
+            deserialization_error(&parameter),
+            "invalid HogwildParameter: value has a non-finite entry at (2, 1)"
+        );
+    }
+
+    #[test]
+    fn hogwild_parameter_missing_moments() {
+        let parameter = HogwildParameter::new(nn::xavier_normal(3, 4));
+        let mut json = serde_json::to_value(&parameter).unwrap();
+        json.as_object_mut().unwrap().remove("moments");
+
+        let deserialized: HogwildParameter = serde_json::from_value(json).unwrap();
+
+        assert_eq!(deserialized.value(), parameter.value());
+        assert_eq!(deserialized.optimizer_buffer_len(), 3 * 4);
+        assert_eq!(unsafe { deserialized.moments_mut() }, &Arr::zeros((3, 4)));
+        assert_eq!(deserialized.optimizer_buffer_len(), 2 * 3 * 4);
+    }
 }
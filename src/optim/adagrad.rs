@@ -1,18 +1,18 @@
 use super::barrier::{SynchronizationBarrier, SynchronizationBarrierGuard};
-use super::Optimizer;
+use super::{deduplicate, Optimizer};
 use numerics::{ArraySlice, ArraySliceMut};
-use {numerics, ParameterNode, Variable};
+use {numerics, Float, ParameterNode, Variable};
 
 use ndarray::Axis;
 
 /// Adagrad optimizer, scaled the learning rate by the inverse of previously
 /// accumulated gradients.
 pub struct Adagrad {
-    learning_rate: f32,
-    l2: f32,
+    learning_rate: Float,
+    l2: Float,
     parameters: Vec<Variable<ParameterNode>>,
-    clamp: Option<(f32, f32)>,
-    eps: f32,
+    clamp: Option<(Float, Float)>,
+    eps: Float,
     sync_barrier: Option<SynchronizationBarrierGuard>,
 }
 
@@ -22,7 +22,7 @@ impl Adagrad {
         Adagrad {
             learning_rate: 0.05,
             l2: 0.0,
-            parameters: parameters,
+            parameters: deduplicate(parameters),
             clamp: None,
             eps: 1e-10,
             sync_barrier: None,
@@ -30,7 +30,7 @@ impl Adagrad {
     }
 
     /// Set the learning rate.
-    pub fn learning_rate(mut self, learning_rate: f32) -> Self {
+    pub fn learning_rate(mut self, learning_rate: Float) -> Self {
         self.learning_rate = learning_rate;
         self
     }
@@ -41,28 +41,44 @@ impl Adagrad {
         self
     }
 
-    /// Set the clamp bounds.
-    pub fn clamp(mut self, min: f32, max: f32) -> Self {
+    /// Set the clamp bounds. Sparse gradients of rows repeated within
+    /// a step are summed first, and the sum is clamped.
+    pub fn clamp(mut self, min: Float, max: Float) -> Self {
         self.clamp = Some((min, max));
         self
     }
 
     /// Set the L2 penalty.
-    pub fn l2_penalty(mut self, l2_penalty: f32) -> Self {
+    pub fn l2_penalty(mut self, l2_penalty: Float) -> Self {
         self.l2 = l2_penalty;
         self
     }
 
     /// Decay weights.
-    pub fn decay_weights(&mut self, penalty: f32) {
+    pub fn decay_weights(&mut self, penalty: Float) {
+        let decay = |x: &mut Float| *x -= x.signum() * penalty * numerics::pow2(*x);
+
         for parameter in &self.parameters {
+            if !parameter.node.value.is_dense() {
+                for row in 0..parameter.node.value.shape().0 {
+                    unsafe {
+                        parameter
+                            .node
+                            .value
+                            .update_row(row, |row| row.iter_mut().for_each(decay))
+                    };
+                }
+
+                continue;
+            }
+
             let mut param_value = unsafe { parameter.node.value.value_mut() };
 
             param_value
                 .as_slice_mut()
                 .unwrap()
                 .iter_mut()
-                .for_each(|x| *x -= x.signum() * penalty * numerics::pow2(*x));
+                .for_each(decay);
         }
     }
 
@@ -71,6 +87,8 @@ impl Adagrad {
 
         let mut sink = parameter.node.gradient.borrow_mut();
 
+        sink.sparse_gradient.coalesce();
+
         if let Some((min, max)) = self.clamp {
             sink.clamp(min, max);
         }
@@ -79,6 +97,8 @@ impl Adagrad {
         let squared_gradient = unsafe { parameter.node.value.squared_gradient_mut() };
 
         if sink.has_dense {
+            parameter.node.value.assert_dense_updates();
+
             for (value, &gradient, squared_gradient) in izip!(
                 param_value.fast_slice_mut(),
                 sink.dense_gradient().fast_slice(),
@@ -88,6 +108,8 @@ impl Adagrad {
                 *squared_gradient += numerics::pow2(gradient);
                 *value -= learning_rate / (self.eps + squared_gradient.sqrt()) * gradient;
             }
+
+            parameter.node.value.record_dense_update();
         }
 
         sink.sparse_gradient
@@ -95,18 +117,24 @@ impl Adagrad {
             .iter()
             .for_each(|(ref index_vec, ref grad)| {
                 for (grad_idx, &param_idx) in index_vec.iter().enumerate() {
+                    parameter.node.value.record_row_update(param_idx);
+
                     let grad_row = grad.subview(Axis(0), grad_idx);
-                    let mut param_row = param_value.subview_mut(Axis(0), param_idx);
                     let mut squared_row = squared_gradient.subview_mut(Axis(0), param_idx);
 
-                    for (value, &gradient, squared_gradient) in izip!(
-                        param_row.fast_slice_mut(),
-                        grad_row.into_slice().unwrap(),
-                        squared_row.fast_slice_mut()
-                    ) {
-                        let gradient = gradient + *value * self.l2;
-                        *squared_gradient += numerics::pow2(gradient);
-                        *value -= learning_rate / (self.eps + squared_gradient.sqrt()) * gradient;
+                    unsafe {
+                        parameter.node.value.update_row(param_idx, |param_row| {
+                            for (value, &gradient, squared_gradient) in izip!(
+                                param_row.iter_mut(),
+                                grad_row.into_slice().unwrap(),
+                                squared_row.fast_slice_mut()
+                            ) {
+                                let gradient = gradient + *value * self.l2;
+                                *squared_gradient += numerics::pow2(gradient);
+                                *value -=
+                                    learning_rate / (self.eps + squared_gradient.sqrt()) * gradient;
+                            }
+                        });
                     }
                 }
             });
@@ -1,6 +1,6 @@
 use super::barrier::{SynchronizationBarrier, SynchronizationBarrierGuard};
-use super::Optimizer;
-use {numerics, Arr, ParameterNode, Variable};
+use super::{deduplicate, Optimizer};
+use {numerics, Arr, Float, ParameterNode, Variable};
 
 use ndarray::Axis;
 
@@ -13,13 +13,13 @@ struct AdamParameters<'params> {
 
 /// ADAM optimizer.
 pub struct Adam {
-    learning_rate: f32,
-    l2: f32,
-    beta_m: f32,
-    beta_v: f32,
-    eps: f32,
+    learning_rate: Float,
+    l2: Float,
+    beta_m: Float,
+    beta_v: Float,
+    eps: Float,
     parameters: Vec<Variable<ParameterNode>>,
-    clamp: Option<(f32, f32)>,
+    clamp: Option<(Float, Float)>,
     sync_barrier: Option<SynchronizationBarrierGuard>,
 }
 
@@ -32,14 +32,14 @@ impl Adam {
             beta_m: 0.9,
             beta_v: 0.999,
             eps: 1.0e-8,
-            parameters: parameters,
+            parameters: deduplicate(parameters),
             clamp: None,
             sync_barrier: None,
         }
     }
 
     /// Set the learning rate.
-    pub fn learning_rate(mut self, learning_rate: f32) -> Self {
+    pub fn learning_rate(mut self, learning_rate: Float) -> Self {
         self.learning_rate = learning_rate;
         self
     }
@@ -50,14 +50,15 @@ impl Adam {
         self
     }
 
-    /// Set the clamp bounds.
-    pub fn clamp(mut self, min: f32, max: f32) -> Self {
+    /// Set the clamp bounds. Sparse gradients of rows repeated within
+    /// a step are summed first, and the sum is clamped.
+    pub fn clamp(mut self, min: Float, max: Float) -> Self {
         self.clamp = Some((min, max));
         self
     }
 
     /// Set the L2 penalty.
-    pub fn l2_penalty(mut self, l2_penalty: f32) -> Self {
+    pub fn l2_penalty(mut self, l2_penalty: Float) -> Self {
         self.l2 = l2_penalty;
         self
     }
@@ -72,7 +73,7 @@ impl Adam {
     }
 
     #[inline(always)]
-    fn update(&self, value: &mut f32, gradient: f32, m: &mut f32, v: &mut f32, t: &i32) {
+    fn update(&self, value: &mut Float, gradient: Float, m: &mut Float, v: &mut Float, t: &i32) {
         // Apply L2 to gradient.
         let gradient = gradient + *value * self.l2;
 
@@ -88,6 +89,7 @@ impl Adam {
 
     fn do_step(&self, parameter: &Variable<ParameterNode>) {
         let mut sink = parameter.node.gradient.borrow_mut();
+        sink.sparse_gradient.coalesce();
 
         if let Some((min, max)) = self.clamp {
             sink.clamp(min, max);
@@ -99,6 +101,8 @@ impl Adam {
         *param.t = param.t.saturating_add(1);
 
         if sink.has_dense {
+            parameter.node.value.assert_dense_updates();
+
             for (value, &gradient, m, v) in izip!(
                 param.value.as_slice_mut().unwrap(),
                 sink.dense_gradient().as_slice().unwrap(),
@@ -107,22 +111,31 @@ impl Adam {
             ) {
                 self.update(value, gradient, m, v, param.t);
             }
+
+            parameter.node.value.record_dense_update();
         }
 
+        let t = *param.t;
+
         for &(ref index_vec, ref grad) in sink.sparse_gradient.as_slice() {
             for (grad_idx, &param_idx) in index_vec.iter().enumerate() {
-                let mut value_row = param.value.subview_mut(Axis(0), param_idx);
+                parameter.node.value.record_row_update(param_idx);
+
                 let grad_row = grad.subview(Axis(0), grad_idx);
                 let mut m_row = param.m.subview_mut(Axis(0), param_idx);
                 let mut v_row = param.v.subview_mut(Axis(0), param_idx);
 
-                for (value, &gradient, m, v) in izip!(
-                    value_row.as_slice_mut().unwrap(),
-                    grad_row.into_slice().unwrap(),
-                    m_row.as_slice_mut().unwrap(),
-                    v_row.as_slice_mut().unwrap(),
-                ) {
-                    self.update(value, gradient, m, v, param.t);
+                unsafe {
+                    parameter.node.value.update_row(param_idx, |value_row| {
+                        for (value, &gradient, m, v) in izip!(
+                            value_row.iter_mut(),
+                            grad_row.into_slice().unwrap(),
+                            m_row.as_slice_mut().unwrap(),
+                            v_row.as_slice_mut().unwrap(),
+                        ) {
+                            self.update(value, gradient, m, v, &t);
+                        }
+                    });
                 }
             }
         }
@@ -0,0 +1,173 @@
+use std::mem;
+
+use super::deduplicate;
+use {Arr, Float, ParameterNode, Variable};
+
+/// Maintains running (Polyak) averages of parameter values, as used
+/// in stochastic weight averaging.
+///
+/// The averages are held in separate buffers and do not interfere with
+/// optimizer state. Call `update` after each optimization step, and
+/// `swap_in` to temporarily install the averaged values, for example
+/// for evaluation; `swap_out` restores the trained values.
+///
+/// ```rust
+/// # extern crate wyrm;
+/// # use wyrm::*;
+/// # use wyrm::optim::{Optimizer, PolyakAverager, SGD};
+/// # fn main() {
+/// let x = ParameterNode::new(nn::xavier_normal(1, 3));
+/// let mut loss = x.square();
+///
+/// let optimizer = SGD::new(loss.parameters());
+/// let mut averager = PolyakAverager::new(loss.parameters());
+///
+/// for _ in 0..10 {
+///     loss.forward();
+///     loss.backward(1.0);
+///     optimizer.step();
+///     loss.zero_gradient();
+///     averager.update();
+/// }
+///
+/// averager.swap_in();
+/// loss.forward();
+/// averager.swap_out();
+/// # }
+/// ```
+pub struct PolyakAverager {
+    parameters: Vec<Variable<ParameterNode>>,
+    averages: Vec<Arr>,
+    num_updates: usize,
+    swapped_in: bool,
+}
+
+impl PolyakAverager {
+    /// Create an averager for the given parameters, none of which
+    /// may be stored in half precision.
+    pub fn new(parameters: Vec<Variable<ParameterNode>>) -> Self {
+        let parameters = deduplicate(parameters);
+        let averages = parameters
+            .iter()
+            .map(|parameter| {
+                parameter.node.value.assert_dense_updates();
+                parameter.node.value.value() * 0.0
+            })
+            .collect();
+
+        PolyakAverager {
+            parameters: parameters,
+            averages: averages,
+            num_updates: 0,
+            swapped_in: false,
+        }
+    }
+
+    /// Number of parameter values averaged so far.
+    pub fn num_updates(&self) -> usize {
+        self.num_updates
+    }
+
+    /// Add the current parameter values to the running averages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the averages are swapped in.
+    pub fn update(&mut self) {
+        assert!(!self.swapped_in, "Cannot update while averages are swapped in.");
+
+        self.num_updates += 1;
+        let weight = 1.0 / self.num_updates as Float;
+
+        for (parameter, average) in self.parameters.iter().zip(self.averages.iter_mut()) {
+            let value = parameter.node.value.value();
+
+            for (avg, &x) in average
+                .as_slice_mut()
+                .unwrap()
+                .iter_mut()
+                .zip(value.as_slice().unwrap())
+            {
+                *avg += weight * (x - *avg);
+            }
+        }
+    }
+
+    /// Install the averaged values in the parameters, keeping the
+    /// current values to be restored by `swap_out`. Does nothing
+    /// if the averages are already swapped in.
+    pub fn swap_in(&mut self) {
+        if !self.swapped_in {
+            self.swap();
+            self.swapped_in = true;
+        }
+    }
+
+    /// Restore the parameter values replaced by `swap_in`. Does
+    /// nothing if the averages are not swapped in.
+    pub fn swap_out(&mut self) {
+        if self.swapped_in {
+            self.swap();
+            self.swapped_in = false;
+        }
+    }
+
+    fn swap(&mut self) {
+        for (parameter, average) in self.parameters.iter().zip(self.averages.iter_mut()) {
+            let value = unsafe { parameter.node.value.value_mut() };
+            mem::swap(value, average);
+        }
+    }
+
+    /// Return a copy of the averaged values, in the order
+    /// the parameters were given.
+    pub fn averages(&self) -> Vec<Arr> {
+        if self.swapped_in {
+            self.parameters
+                .iter()
+                .map(|parameter| parameter.node.value.value().clone())
+                .collect()
+        } else {
+            self.averages.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nn::xavier_normal;
+    use DataInput;
+
+    #[test]
+    fn average_of_trajectory() {
+        let x = ParameterNode::new(xavier_normal(4, 3));
+        let mut averager = PolyakAverager::new(vec![x.clone()]);
+
+        let trajectory: Vec<_> = (0..25).map(|_| xavier_normal(4, 3)).collect();
+
+        for value in &trajectory {
+            x.set_value(value);
+            averager.update();
+        }
+
+        let mut mean = Arr::zeros((4, 3));
+        for value in &trajectory {
+            mean += value;
+        }
+        mean /= trajectory.len() as Float;
+
+        assert_eq!(averager.num_updates(), 25);
+        assert!(averager.averages()[0].all_close(&mean, 1e-5));
+
+        let last = trajectory[trajectory.len() - 1].clone();
+
+        averager.swap_in();
+        assert!(x.value().all_close(&mean, 1e-5));
+        assert_eq!(averager.averages()[0], *x.value());
+
+        averager.swap_out();
+        assert_eq!(*x.value(), last);
+        assert!(averager.averages()[0].all_close(&mean, 1e-5));
+    }
+}
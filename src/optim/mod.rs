@@ -3,16 +3,66 @@
 //! Contains a number of optimizers.
 mod adagrad;
 mod adam;
+mod averaging;
 mod barrier;
 mod sgd;
 
+use std::collections::HashSet;
+
+use {Float, ParameterNode, Variable};
+
 /// Core trait implemented by all optimizer methods.
 pub trait Optimizer {
     /// Perform a single SGD step.
     fn step(&self);
 }
 
+/// Remove repeated parameter nodes, keeping the first occurrence, so
+/// that a node listed twice is not updated twice per step. Frozen
+/// parameters are removed too.
+fn deduplicate(parameters: Vec<Variable<ParameterNode>>) -> Vec<Variable<ParameterNode>> {
+    let mut seen = HashSet::new();
+
+    parameters
+        .into_iter()
+        .filter(|parameter| !parameter.node.value.is_frozen())
+        .filter(|parameter| seen.insert(&*parameter.node as *const ParameterNode))
+        .collect()
+}
+
+/// Rescale the accumulated gradients of `parameters` so that their joint
+/// L2 norm, including sparse rows, is at most `max_norm`. Returns the norm
+/// before clipping. Call between the backward pass and the optimizer step.
+pub fn clip_gradient_norm(parameters: &[Variable<ParameterNode>], max_norm: Float) -> Float {
+    let norm = parameters
+        .iter()
+        .map(|parameter| parameter.node.gradient.borrow().squared_norm())
+        .sum::<Float>()
+        .sqrt();
+
+    if norm > max_norm {
+        for parameter in parameters {
+            parameter
+                .node
+                .gradient
+                .borrow_mut()
+                .scale(max_norm / norm);
+        }
+    }
+
+    norm
+}
+
+/// Clamp the accumulated gradients of `parameters` to `[min, max]`,
+/// element-wise. Call between the backward pass and the optimizer step.
+pub fn clip_gradient_values(parameters: &[Variable<ParameterNode>], min: Float, max: Float) {
+    for parameter in parameters {
+        parameter.clip_gradient_values(min, max);
+    }
+}
+
 pub use self::adagrad::Adagrad;
 pub use self::adam::Adam;
+pub use self::averaging::PolyakAverager;
 pub use self::barrier::SynchronizationBarrier;
 pub use self::sgd::SGD;
@@ -1,14 +1,14 @@
 use super::barrier::{SynchronizationBarrier, SynchronizationBarrierGuard};
-use super::Optimizer;
-use {numerics, ParameterNode, Variable};
+use super::{deduplicate, Optimizer};
+use {numerics, Float, ParameterNode, Variable};
 
 use ndarray::Axis;
 
 /// Standard stochastic gradient descent optimizer with a fixed learning rate.
 pub struct SGD {
-    learning_rate: f32,
+    learning_rate: Float,
     parameters: Vec<Variable<ParameterNode>>,
-    clamp: Option<(f32, f32)>,
+    clamp: Option<(Float, Float)>,
     sync_barrier: Option<SynchronizationBarrierGuard>,
 }
 
@@ -17,14 +17,14 @@ impl SGD {
     pub fn new(parameters: Vec<Variable<ParameterNode>>) -> Self {
         SGD {
             learning_rate: 0.05,
-            parameters: parameters,
+            parameters: deduplicate(parameters),
             clamp: None,
             sync_barrier: None,
         }
     }
 
     /// Set the learning rate.
-    pub fn learning_rate(mut self, learning_rate: f32) -> Self {
+    pub fn learning_rate(mut self, learning_rate: Float) -> Self {
         self.learning_rate = learning_rate;
         self
     }
@@ -35,8 +35,9 @@ impl SGD {
         self
     }
 
-    /// Set the clamp bounds.
-    pub fn clamp(mut self, min: f32, max: f32) -> Self {
+    /// Set the clamp bounds. Sparse gradients of rows repeated within
+    /// a step are summed first, and the sum is clamped.
+    pub fn clamp(mut self, min: Float, max: Float) -> Self {
         self.clamp = Some((min, max));
         self
     }
@@ -47,24 +48,33 @@ impl SGD {
         let mut sink = parameter.node.gradient.borrow_mut();
         let param_value = unsafe { parameter.node.value.value_mut() };
 
+        sink.sparse_gradient.coalesce();
+
         if let Some((min, max)) = self.clamp {
             sink.clamp(min, max);
         }
 
         if sink.has_dense {
+            parameter.node.value.assert_dense_updates();
             param_value.scaled_add(-self.learning_rate, sink.dense_gradient());
+            parameter.node.value.record_dense_update();
         }
 
         for (ref index_vec, ref grad) in sink.sparse_gradient.as_slice() {
             for (grad_idx, &param_idx) in index_vec.iter().enumerate() {
+                parameter.node.value.record_row_update(param_idx);
+
                 let grad_row = grad.subview(Axis(0), grad_idx);
-                let mut param_row = param_value.subview_mut(Axis(0), param_idx);
 
-                numerics::map_add_assign_slice(
-                    param_row.into_slice().unwrap(),
-                    grad_row.into_slice().unwrap(),
-                    |x| -learning_rate * x,
-                );
+                unsafe {
+                    parameter.node.value.update_row(param_idx, |param_row| {
+                        numerics::map_add_assign_slice(
+                            param_row,
+                            grad_row.into_slice().unwrap(),
+                            |x| -learning_rate * x,
+                        )
+                    });
+                }
             }
         }
     }
@@ -0,0 +1,333 @@
+//! Structured summaries of computation graphs.
+use std::collections::HashSet;
+use std::fmt;
+use std::mem;
+
+use nodes::{topological_order, Description, GraphNode};
+use Float;
+
+/// The number of nodes of a given operation in a graph.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeCount {
+    /// The operation name, as given by `Node::describe`.
+    pub op: String,
+    /// The number of distinct nodes performing the operation.
+    pub count: usize,
+}
+
+/// A parameter node of a graph.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParameterSummary {
+    /// The name of the parameter, if it has one.
+    pub name: Option<String>,
+    /// The `(rows, cols)` shape of the parameter.
+    pub shape: (usize, usize),
+    /// The memory used to store the parameter's value, in bytes.
+    pub bytes: usize,
+}
+
+impl ParameterSummary {
+    /// The number of scalar values in the parameter.
+    pub fn size(&self) -> usize {
+        self.shape.0 * self.shape.1
+    }
+}
+
+/// Machine-readable summary of a computation graph, produced by
+/// `Variable::summary`.
+///
+/// The summary can be serialized with any serde format for logging
+/// alongside experiment results; its `Display` implementation prints
+/// a human-readable table.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModelSummary {
+    /// Node counts by operation, in order of first appearance.
+    pub nodes: Vec<NodeCount>,
+    /// Parameter nodes, in the order in which the graph uses them.
+    pub parameters: Vec<ParameterSummary>,
+    /// Total number of trainable scalar values.
+    pub trainable_parameters: usize,
+    /// Estimated memory held by node values, in bytes.
+    pub value_bytes: usize,
+    /// Estimated memory held by gradient buffers, in bytes.
+    pub gradient_bytes: usize,
+    /// Memory held by the optimizer buffers of the parameters, in bytes.
+    /// Parameters shared between nodes are counted once.
+    pub optimizer_bytes: usize,
+}
+
+impl ModelSummary {
+    pub(crate) fn new(root: &GraphNode) -> Self {
+        let mut nodes: Vec<NodeCount> = Vec::new();
+        let mut parameters = Vec::new();
+        let mut value_bytes = 0;
+        let mut gradient_elements = 0;
+        let mut optimizer_elements = 0;
+        let mut seen_parameters = HashSet::new();
+
+        for node in topological_order(root) {
+            let description = node.description();
+            let (rows, cols) = node.value_shape();
+
+            match nodes.iter().position(|count| count.op == description.op) {
+                Some(idx) => nodes[idx].count += 1,
+                None => nodes.push(NodeCount {
+                    op: description.op.to_owned(),
+                    count: 1,
+                }),
+            }
+
+            match node.parameter() {
+                Some(parameter) => {
+                    parameters.push(ParameterSummary {
+                        name: None,
+                        shape: (rows, cols),
+                        bytes: parameter.value_bytes(),
+                    });
+
+                    // Values of shared parameters are only held once.
+                    if seen_parameters.insert(&**parameter as *const _) {
+                        value_bytes += parameter.value_bytes();
+                        optimizer_elements += parameter.optimizer_buffer_len();
+                    }
+                }
+                None => value_bytes += rows * cols * mem::size_of::<Float>(),
+            }
+
+            // Nodes that propagate gradients keep a buffer the
+            // size of the gradient flowing into them.
+            if node.requires_gradient() {
+                gradient_elements += rows * cols;
+            }
+        }
+
+        ModelSummary {
+            nodes: nodes,
+            trainable_parameters: parameters.iter().map(|x| x.size()).sum(),
+            parameters: parameters,
+            value_bytes,
+            gradient_bytes: gradient_elements * mem::size_of::<Float>(),
+            optimizer_bytes: optimizer_elements * mem::size_of::<Float>(),
+        }
+    }
+
+    /// Total estimated memory of values, gradient buffers, and
+    /// optimizer buffers, in bytes.
+    pub fn total_bytes(&self) -> usize {
+        self.value_bytes + self.gradient_bytes + self.optimizer_bytes
+    }
+}
+
+impl fmt::Display for ModelSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const WIDTH: usize = 48;
+
+        writeln!(f, "{:<32}{:>16}", "Operation", "Count")?;
+        writeln!(f, "{}", "=".repeat(WIDTH))?;
+        for node in &self.nodes {
+            writeln!(f, "{:<32}{:>16}", node.op, node.count)?;
+        }
+        writeln!(f, "{}", "_".repeat(WIDTH))?;
+
+        writeln!(f, "{:<20}{:<16}{:>12}", "Parameter", "Shape", "Size")?;
+        writeln!(f, "{}", "=".repeat(WIDTH))?;
+        for (idx, parameter) in self.parameters.iter().enumerate() {
+            let name = match parameter.name {
+                Some(ref name) => name.clone(),
+                None => format!("parameter_{}", idx),
+            };
+            writeln!(
+                f,
+                "{:<20}{:<16}{:>12}",
+                name,
+                format!("{:?}", parameter.shape),
+                parameter.size()
+            )?;
+        }
+        writeln!(f, "{}", "=".repeat(WIDTH))?;
+
+        writeln!(f, "Trainable parameters: {}", self.trainable_parameters)?;
+        write!(
+            f,
+            "Estimated memory: {} bytes ({} values, {} gradients, {} optimizer)",
+            self.total_bytes(),
+            self.value_bytes,
+            self.gradient_bytes,
+            self.optimizer_bytes
+        )
+    }
+}
+
+/// The estimated forward-pass FLOPs of the nodes of a given operation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FlopCount {
+    /// The operation name, as given by `Node::describe`.
+    pub op: String,
+    /// The estimated FLOPs of all nodes performing the operation.
+    pub flops: usize,
+}
+
+/// Approximate FLOP count of one forward pass through a graph,
+/// produced by `Variable::profile_flops`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FlopProfile {
+    /// FLOPs by operation, in order of first appearance.
+    pub nodes: Vec<FlopCount>,
+    /// Total estimated FLOPs.
+    pub total: usize,
+}
+
+impl FlopProfile {
+    pub(crate) fn new(root: &GraphNode) -> Self {
+        let mut nodes: Vec<FlopCount> = Vec::new();
+
+        for node in topological_order(root) {
+            let description = node.description();
+            let flops = node
+                .flops_node()
+                .unwrap_or_else(|| elementwise_flops(&description, node.value_shape()));
+
+            match nodes.iter().position(|count| count.op == description.op) {
+                Some(idx) => nodes[idx].flops += flops,
+                None => nodes.push(FlopCount {
+                    op: description.op.to_owned(),
+                    flops: flops,
+                }),
+            }
+        }
+
+        FlopProfile {
+            total: nodes.iter().map(|x| x.flops).sum(),
+            nodes: nodes,
+        }
+    }
+
+    /// The estimated FLOPs of the nodes performing `op`.
+    pub fn flops(&self, op: &str) -> usize {
+        self.nodes
+            .iter()
+            .find(|x| x.op == op)
+            .map(|x| x.flops)
+            .unwrap_or(0)
+    }
+}
+
+/// Estimate the FLOPs of a forward pass through an element-wise node
+/// of the given value shape: one per element of the largest of its
+/// value and operands.
+fn elementwise_flops(description: &Description, (rows, cols): (usize, usize)) -> usize {
+    description
+        .operands
+        .iter()
+        .map(|operand| {
+            let (operand_rows, operand_cols) = operand.value_shape();
+            operand_rows * operand_cols
+        })
+        .fold(rows * cols, ::std::cmp::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bincode;
+
+    use nn::layers::attention;
+    use nn::xavier_normal;
+    use {InputNode, ParameterNode};
+
+    #[test]
+    fn two_layer_mlp() {
+        let x = InputNode::new(xavier_normal(1, 3));
+        let hidden = ParameterNode::new(xavier_normal(3, 4));
+        let output = ParameterNode::new(xavier_normal(4, 2));
+
+        let y = x.dot(&hidden)
+            .add_bias()
+            .relu()
+            .dot(&output)
+            .add_bias()
+            .sigmoid();
+
+        let summary = y.summary();
+
+        let shapes: Vec<_> = summary.parameters.iter().map(|x| x.shape).collect();
+        assert_eq!(shapes, vec![(3, 4), (1, 4), (4, 2), (1, 2)]);
+        assert_eq!(summary.trainable_parameters, 12 + 4 + 8 + 2);
+
+        let count = |op: &str| {
+            summary
+                .nodes
+                .iter()
+                .find(|x| x.op == op)
+                .map(|x| x.count)
+                .unwrap_or(0)
+        };
+        assert_eq!(count("Input"), 1);
+        assert_eq!(count("Parameter"), 4);
+        assert_eq!(count("Dot"), 2);
+        assert_eq!(count("Bias"), 2);
+        assert_eq!(count("Relu"), 1);
+        assert_eq!(count("Sigmoid"), 1);
+
+        // Everything but the input holds a gradient buffer.
+        let values = 3 + (12 + 4 + 4 + 4 + 4) + (8 + 2 + 2 + 2 + 2);
+        assert_eq!(summary.value_bytes, values * mem::size_of::<Float>());
+        assert_eq!(
+            summary.gradient_bytes,
+            (values - 3) * mem::size_of::<Float>()
+        );
+
+        // Freshly created parameters carry optimizer buffers.
+        assert_eq!(summary.optimizer_bytes, 2 * 26 * mem::size_of::<Float>());
+
+        let table = summary.to_string();
+        assert!(table.contains("parameter_0"));
+        assert!(table.contains("Trainable parameters: 26"));
+
+        let bytes = bincode::serialize(&summary).unwrap();
+        let deserialized: ModelSummary = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(deserialized, summary);
+    }
+
+    #[test]
+    fn mlp_flops() {
+        let x = InputNode::new(xavier_normal(2, 3));
+        let hidden = ParameterNode::new(xavier_normal(3, 4));
+        let output = ParameterNode::new(xavier_normal(4, 2));
+
+        let y = x.dot(&hidden)
+            .add_bias()
+            .relu()
+            .dot(&output)
+            .add_bias()
+            .sigmoid();
+        // Shared subgraphs are only counted once.
+        let z = &y + &y;
+
+        let profile = z.profile_flops();
+
+        assert_eq!(profile.flops("Dot"), 2 * 2 * 4 * 3 + 2 * 2 * 2 * 4);
+        assert_eq!(profile.flops("Bias"), 2 * 4 + 2 * 2);
+        assert_eq!(profile.flops("Relu"), 2 * 4);
+        assert_eq!(profile.flops("Sigmoid"), 2 * 2);
+        assert_eq!(profile.flops("Add"), 2 * 2);
+        assert_eq!(profile.flops("Parameter"), 0);
+        assert_eq!(profile.total, 48 + 32 + 12 + 8 + 4 + 4);
+    }
+
+    #[test]
+    fn attention_flops() {
+        let queries = InputNode::new(xavier_normal(2, 4));
+        let keys = InputNode::new(xavier_normal(3, 4));
+        let values = InputNode::new(xavier_normal(3, 5));
+
+        let profile = attention(&queries, &keys, &values, None).profile_flops();
+
+        // The scores, their softmax, and the weighted sum of the values.
+        assert_eq!(
+            profile.flops("Attention"),
+            2 * 2 * 3 * 4 + 2 * 3 + 2 * 2 * 3 * 5
+        );
+    }
+}
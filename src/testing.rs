@@ -0,0 +1,171 @@
+//! Utilities for testing the gradients of nodes.
+//!
+//! Every node should have its analytical gradient checked against
+//! central finite differences:
+//!
+//! ```rust
+//! # extern crate wyrm;
+//! # use wyrm::*;
+//! # fn main() {
+//! let x = ParameterNode::new(nn::xavier_normal(3, 4));
+//! let mut loss = x.tanh().square().scalar_sum();
+//!
+//! testing::check_gradients(&mut loss, &x, 1e-3, 1e-2).unwrap();
+//! # }
+//! ```
+use std::error;
+use std::fmt;
+
+use {Arr, DataInput, Float, Node, ParameterNode, Variable};
+
+/// The worst mismatch between the numerical and analytical gradients
+/// found by `check_gradients`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GradError {
+    /// The `(row, column)` index of the parameter element.
+    pub index: (usize, usize),
+    /// The central finite difference estimate of the gradient.
+    pub numerical: Float,
+    /// The gradient accumulated by the backward pass.
+    pub analytical: Float,
+    /// The tolerance that was exceeded.
+    pub tolerance: Float,
+}
+
+impl fmt::Display for GradError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Gradient at {:?} is {}, but finite differences give {} (tolerance {}).",
+            self.index, self.analytical, self.numerical, self.tolerance
+        )
+    }
+}
+
+impl error::Error for GradError {
+    fn description(&self) -> &str {
+        "gradient mismatch"
+    }
+}
+
+/// Compute central finite difference estimates of the gradient of the
+/// sum of `output` with respect to `input`, moving each element of the
+/// input by `epsilon` both ways, together with the analytical gradient.
+pub(crate) fn gradients<T>(
+    input: &Variable<ParameterNode>,
+    output: &mut Variable<T>,
+    epsilon: Float,
+) -> (Arr, Arr)
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    let initial_input = { input.value().clone() };
+    let mut central_difference = &initial_input * 0.0;
+
+    let mut evaluate = |changed_input: &Arr| {
+        output.zero_gradient();
+        input.set_value(changed_input);
+        output.forward();
+        output.backward(1.0);
+        output.value().clone()
+    };
+
+    for (idx, diff) in central_difference.indexed_iter_mut() {
+        let mut changed_input = initial_input.clone();
+
+        changed_input[idx] += epsilon;
+        let positive_difference = evaluate(&changed_input);
+
+        changed_input[idx] -= 2.0 * epsilon;
+        let negative_difference = evaluate(&changed_input);
+
+        *diff = (positive_difference - negative_difference).scalar_sum() / (2.0 * epsilon);
+    }
+
+    evaluate(&initial_input);
+    let gradient = input.gradient();
+    output.zero_gradient();
+
+    (central_difference, gradient)
+}
+
+/// Check the gradient of the sum of `loss` with respect to `parameter`
+/// against central finite differences, moving each element of the
+/// parameter by `epsilon` both ways.
+///
+/// Sparse gradients are added to the dense gradient before comparing.
+/// Fails with the element where the two differ the most if that
+/// difference exceeds `tolerance`. The parameter value is restored, and
+/// the gradients zeroed, before returning.
+pub fn check_gradients<T>(
+    loss: &mut Variable<T>,
+    parameter: &Variable<ParameterNode>,
+    epsilon: Float,
+    tolerance: Float,
+) -> Result<(), GradError>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    let (numerical, analytical) = gradients(parameter, loss, epsilon);
+
+    let worst = numerical
+        .indexed_iter()
+        .zip(analytical.iter())
+        .map(|((index, &numerical), &analytical)| GradError {
+            index: index,
+            numerical: numerical,
+            analytical: analytical,
+            tolerance: tolerance,
+        })
+        .max_by(|x, y| {
+            let x_difference = (x.numerical - x.analytical).abs();
+            let y_difference = (y.numerical - y.analytical).abs();
+
+            // Order non-finite differences last, so they are reported.
+            x_difference
+                .partial_cmp(&y_difference)
+                .unwrap_or_else(|| x_difference.is_nan().cmp(&y_difference.is_nan()))
+        });
+
+    match worst {
+        Some(worst) => {
+            if (worst.numerical - worst.analytical).abs() <= tolerance {
+                Ok(())
+            } else {
+                Err(worst)
+            }
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nn::xavier_normal;
+
+    #[test]
+    fn passes_correct_gradients() {
+        let x = ParameterNode::new(xavier_normal(2, 3));
+        let mut loss = x.tanh().square().scalar_sum();
+
+        assert_eq!(check_gradients(&mut loss, &x, 1e-3, 1e-2), Ok(()));
+    }
+
+    #[test]
+    fn reports_worst_element() {
+        let x = ParameterNode::new(
+            Arr::from_shape_vec((2, 2), vec![0.5, -3.0, 1.0, 2.0]).unwrap(),
+        );
+        let y = ParameterNode::new(xavier_normal(2, 2));
+
+        // The backward pass ignores the gradient with respect to `y`.
+        let mut loss = (x.clone() * y.stop_gradient()).scalar_sum();
+
+        let error = check_gradients(&mut loss, &y, 1e-3, 1e-2).unwrap_err();
+        assert_eq!(error.index, (0, 1));
+        assert!((error.numerical - -3.0).abs() < 1e-2);
+        assert_eq!(error.analytical, 0.0);
+    }
+}